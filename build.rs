@@ -2,6 +2,8 @@ fn main() {
     println!("!cargo:rerun-if-changed=src/shaders.hlsl");
     println!("!cargo:rerun-if-changed=src/vertex_shader.vs_4_0");
     println!("!cargo:rerun-if-changed=src/pixel_shader.ps_4_0");
+    println!("!cargo:rerun-if-changed=src/output.vs.hlsl");
+    println!("!cargo:rerun-if-changed=src/output.ps.hlsl");
     std::fs::copy(
         "src/shaders.hlsl",
         std::env::var("OUT_DIR").unwrap() + "/../../../shaders.hlsl",
@@ -17,4 +19,14 @@ fn main() {
         std::env::var("OUT_DIR").unwrap() + "/../../../pixel_shader.ps_4_0",
     )
     .expect("Copy");
+    std::fs::copy(
+        "src/output.vs.hlsl",
+        std::env::var("OUT_DIR").unwrap() + "/../../../output.vs.hlsl",
+    )
+    .expect("Copy");
+    std::fs::copy(
+        "src/output.ps.hlsl",
+        std::env::var("OUT_DIR").unwrap() + "/../../../output.ps.hlsl",
+    )
+    .expect("Copy");
 }
@@ -0,0 +1,400 @@
+//! Graphics adapter enumeration and device creation with a WARP fallback.
+
+use windows::{
+    core::*, Win32::Foundation::*, Win32::Graphics::Direct3D::*, Win32::Graphics::Direct3D11::*,
+    Win32::Graphics::Dxgi::*,
+};
+
+/// Feature levels tried in order. The shaders are compiled for shader model 5, so nothing below 11_0.
+pub const FEATURE_LEVELS: [D3D_FEATURE_LEVEL; 2] = [D3D_FEATURE_LEVEL_11_1, D3D_FEATURE_LEVEL_11_0];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdapterInfo {
+    /// Position in the DXGI enumeration order.
+    pub index: u32,
+    pub name: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub dedicated_video_memory: usize,
+    pub dedicated_system_memory: usize,
+    pub shared_system_memory: usize,
+    /// Locally unique id, stable while the system is running.
+    pub luid: u64,
+    pub is_software: bool,
+}
+
+impl AdapterInfo {
+    pub fn from_desc(index: u32, desc: &DXGI_ADAPTER_DESC1) -> Self {
+        let name_length = desc.Description.iter().position(|&c| c == 0).unwrap_or(desc.Description.len());
+
+        AdapterInfo {
+            index,
+            name: String::from_utf16_lossy(&desc.Description[..name_length]),
+            vendor_id: desc.VendorId,
+            device_id: desc.DeviceId,
+            dedicated_video_memory: desc.DedicatedVideoMemory,
+            dedicated_system_memory: desc.DedicatedSystemMemory,
+            shared_system_memory: desc.SharedSystemMemory,
+            luid: ((desc.AdapterLuid.HighPart as u32 as u64) << 32) | desc.AdapterLuid.LowPart as u64,
+            is_software: desc.Flags & DXGI_ADAPTER_FLAG_SOFTWARE.0 != 0,
+        }
+    }
+
+    pub fn vendor_name(&self) -> &'static str {
+        vendor_name(self.vendor_id)
+    }
+}
+
+impl std::fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({}, {} MB dedicated, luid {:016x}{})",
+            self.index,
+            self.name,
+            self.vendor_name(),
+            self.dedicated_video_memory / (1024 * 1024),
+            self.luid,
+            if self.is_software { ", software" } else { "" },
+        )
+    }
+}
+
+pub fn vendor_name(vendor_id: u32) -> &'static str {
+    match vendor_id {
+        0x1002 | 0x1022 => "AMD",
+        0x10DE => "NVIDIA",
+        0x8086 => "Intel",
+        0x1414 => "Microsoft",
+        0x5143 => "Qualcomm",
+        0x13B5 => "ARM",
+        _ => "Unknown",
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AdapterPreference {
+    /// The hardware adapter with the most dedicated video memory.
+    #[default]
+    Default,
+    /// Adapter at this position in the enumeration order.
+    Index(u32),
+    /// First adapter whose name contains this text, ignoring case.
+    Name(String),
+    /// The WARP software rasterizer, skipping hardware adapters.
+    Warp,
+}
+
+impl AdapterPreference {
+    /// Parses `warp`, an adapter index or a part of an adapter name.
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if value.is_empty() || value.eq_ignore_ascii_case("default") {
+            AdapterPreference::Default
+        } else if value.eq_ignore_ascii_case("warp") {
+            AdapterPreference::Warp
+        } else if let Ok(index) = value.parse() {
+            AdapterPreference::Index(index)
+        } else {
+            AdapterPreference::Name(value.to_string())
+        }
+    }
+}
+
+/// Position in `adapters` of the adapter the preference names, if it exists.
+pub fn find_preferred(adapters: &[AdapterInfo], preference: &AdapterPreference) -> Option<usize> {
+    match preference {
+        AdapterPreference::Index(index) => adapters.iter().position(|adapter| adapter.index == *index),
+        AdapterPreference::Name(name) => {
+            let name = name.to_lowercase();
+            adapters.iter().position(|adapter| adapter.name.to_lowercase().contains(&name))
+        }
+        AdapterPreference::Default | AdapterPreference::Warp => None,
+    }
+}
+
+/// Positions in `adapters` to try creating a device on, in order. WARP is the fallback after all of them.
+///
+/// An explicitly chosen adapter comes first even when it is a software one. The remaining hardware
+/// adapters follow with the most dedicated video memory first, ties keep the enumeration order.
+pub fn adapter_candidates(adapters: &[AdapterInfo], preference: &AdapterPreference) -> Vec<usize> {
+    if *preference == AdapterPreference::Warp {
+        return Vec::new();
+    }
+
+    let preferred = find_preferred(adapters, preference);
+
+    let mut hardware: Vec<usize> = (0..adapters.len())
+        .filter(|&i| !adapters[i].is_software && Some(i) != preferred)
+        .collect();
+    hardware.sort_by(|&a, &b| adapters[b].dedicated_video_memory.cmp(&adapters[a].dedicated_video_memory));
+
+    preferred.into_iter().chain(hardware).collect()
+}
+
+pub fn enumerate_adapters(factory: &IDXGIFactory2) -> Result<Vec<(IDXGIAdapter1, AdapterInfo)>> {
+    let mut adapters = Vec::new();
+
+    for index in 0.. {
+        let adapter = match unsafe { factory.EnumAdapters1(index) } {
+            Ok(adapter) => adapter,
+            Err(error) if error.code() == DXGI_ERROR_NOT_FOUND => break,
+            Err(error) => return Err(error),
+        };
+
+        let mut desc = DXGI_ADAPTER_DESC1::default();
+        unsafe { adapter.GetDesc1(&mut desc)? };
+
+        adapters.push((adapter, AdapterInfo::from_desc(index, &desc)));
+    }
+
+    Ok(adapters)
+}
+
+pub struct CreatedDevice {
+    pub device: ID3D11Device,
+    pub device_context: ID3D11DeviceContext,
+    pub feature_level: D3D_FEATURE_LEVEL,
+    pub adapter: AdapterInfo,
+    /// Factory owning the device's adapter, swap chains for the device have to come from it.
+    pub factory: IDXGIFactory2,
+}
+
+/// Creates a device on the preferred adapter, falling back to the other hardware adapters and then WARP.
+pub fn create_device(
+    factory: &IDXGIFactory2,
+    preference: &AdapterPreference,
+    flags: D3D11_CREATE_DEVICE_FLAG,
+) -> Result<CreatedDevice> {
+    let adapters = enumerate_adapters(factory)?;
+    let infos: Vec<AdapterInfo> = adapters.iter().map(|(_, info)| info.clone()).collect();
+
+    if matches!(preference, AdapterPreference::Index(_) | AdapterPreference::Name(_))
+        && find_preferred(&infos, preference).is_none()
+    {
+        log::warn!("Adapter {:?} not found, using the default", preference);
+    }
+
+    let mut created = create_with_fallback(
+        &infos,
+        &adapter_candidates(&infos, preference),
+        |i| create_device_on(Some(&adapters[i].0.cast()?), D3D_DRIVER_TYPE_UNKNOWN, flags),
+        || create_device_on(None, D3D_DRIVER_TYPE_WARP, flags),
+    )?;
+
+    if let Some(info) = infos.iter().find(|info| info.luid == created.adapter.luid) {
+        created.adapter.index = info.index;
+    }
+
+    Ok(created)
+}
+
+/// Calls `create` for each of the `candidates` positions in `adapters` until one succeeds, then `warp`.
+pub fn create_with_fallback<T>(
+    adapters: &[AdapterInfo],
+    candidates: &[usize],
+    mut create: impl FnMut(usize) -> Result<T>,
+    warp: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    for &i in candidates {
+        match create(i) {
+            Ok(created) => return Ok(created),
+            Err(error) => log::warn!("Failed to create a device on {}: {}", adapters[i], error),
+        }
+    }
+    warp()
+}
+
+/// The shorter feature level list to retry with after creating a device with [`FEATURE_LEVELS`] failed.
+///
+/// Runtimes without 11.1 support reject the whole list instead of skipping the level.
+pub fn retry_feature_levels(error: HRESULT) -> Option<&'static [D3D_FEATURE_LEVEL]> {
+    if error == E_INVALIDARG { Some(&FEATURE_LEVELS[1..]) } else { None }
+}
+
+fn create_device_on(
+    adapter: Option<&IDXGIAdapter>,
+    driver_type: D3D_DRIVER_TYPE,
+    flags: D3D11_CREATE_DEVICE_FLAG,
+) -> Result<CreatedDevice> {
+    let create = |feature_levels: &[D3D_FEATURE_LEVEL]| {
+        let mut device = None;
+        let mut device_context = None;
+        let mut feature_level = D3D_FEATURE_LEVEL::default();
+
+        unsafe {
+            D3D11CreateDevice(
+                adapter,
+                driver_type,
+                HMODULE::default(),
+                flags,
+                Some(feature_levels),
+                D3D11_SDK_VERSION,
+                Some(&mut device),
+                Some(&mut feature_level),
+                Some(&mut device_context),
+            )
+        }
+        .map(|()| (device.unwrap(), device_context.unwrap(), feature_level))
+    };
+
+    let (device, device_context, feature_level) = match create(&FEATURE_LEVELS) {
+        Err(error) => match retry_feature_levels(error.code()) {
+            Some(feature_levels) => create(feature_levels)?,
+            None => return Err(error),
+        },
+        Ok(created) => created,
+    };
+
+    // Describe the adapter the device actually ended up on, which also covers WARP. The index is
+    // only known for enumerated adapters and is filled in by the caller.
+    let dxgi_adapter: IDXGIAdapter1 = unsafe { device.cast::<IDXGIDevice>()?.GetAdapter()? }.cast()?;
+    let mut desc = DXGI_ADAPTER_DESC1::default();
+    unsafe { dxgi_adapter.GetDesc1(&mut desc)? };
+
+    let factory = unsafe { dxgi_adapter.GetParent()? };
+
+    Ok(CreatedDevice {
+        device,
+        device_context,
+        feature_level,
+        adapter: AdapterInfo::from_desc(0, &desc),
+        factory,
+    })
+}
+
+pub fn feature_level_name(feature_level: D3D_FEATURE_LEVEL) -> &'static str {
+    match feature_level {
+        D3D_FEATURE_LEVEL_11_1 => "11_1",
+        D3D_FEATURE_LEVEL_11_0 => "11_0",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(index: u32, name: &str, dedicated_video_memory: usize, is_software: bool) -> AdapterInfo {
+        AdapterInfo {
+            index,
+            name: name.to_string(),
+            vendor_id: if is_software { 0x1414 } else { 0x10DE },
+            device_id: 0,
+            dedicated_video_memory,
+            dedicated_system_memory: 0,
+            shared_system_memory: 0,
+            luid: index as u64,
+            is_software,
+        }
+    }
+
+    /// An integrated GPU first, a discrete one second and the Basic Render Driver last, as DXGI lists them on a laptop.
+    fn laptop() -> Vec<AdapterInfo> {
+        vec![
+            adapter(0, "Intel(R) UHD Graphics 620", 128 << 20, false),
+            adapter(1, "NVIDIA GeForce MX150", 2048 << 20, false),
+            adapter(2, "Microsoft Basic Render Driver", 0, true),
+        ]
+    }
+
+    #[test]
+    fn parse_preferences() {
+        assert_eq!(AdapterPreference::parse(""), AdapterPreference::Default);
+        assert_eq!(AdapterPreference::parse(" Default "), AdapterPreference::Default);
+        assert_eq!(AdapterPreference::parse("WARP"), AdapterPreference::Warp);
+        assert_eq!(AdapterPreference::parse("1"), AdapterPreference::Index(1));
+        assert_eq!(AdapterPreference::parse("geforce"), AdapterPreference::Name("geforce".to_string()));
+        assert_eq!(AdapterPreference::parse("-1"), AdapterPreference::Name("-1".to_string()));
+    }
+
+    #[test]
+    fn default_prefers_the_most_video_memory() {
+        assert_eq!(adapter_candidates(&laptop(), &AdapterPreference::Default), [1, 0]);
+    }
+
+    #[test]
+    fn ties_keep_the_enumeration_order() {
+        let adapters = [adapter(0, "A", 512, false), adapter(1, "B", 1024, false), adapter(2, "C", 512, false)];
+        assert_eq!(adapter_candidates(&adapters, &AdapterPreference::Default), [1, 0, 2]);
+    }
+
+    #[test]
+    fn select_by_index() {
+        let adapters = laptop();
+
+        assert_eq!(find_preferred(&adapters, &AdapterPreference::Index(0)), Some(0));
+        assert_eq!(adapter_candidates(&adapters, &AdapterPreference::Index(0)), [0, 1]);
+        // An explicitly chosen software adapter is tried, the others stay hardware only.
+        assert_eq!(adapter_candidates(&adapters, &AdapterPreference::Index(2)), [2, 1, 0]);
+    }
+
+    #[test]
+    fn select_by_name_ignores_case() {
+        let adapters = laptop();
+
+        assert_eq!(find_preferred(&adapters, &AdapterPreference::Name("uhd".to_string())), Some(0));
+        assert_eq!(adapter_candidates(&adapters, &AdapterPreference::Name("INTEL".to_string())), [0, 1]);
+    }
+
+    #[test]
+    fn no_match_falls_back_to_the_default_order() {
+        let adapters = laptop();
+
+        assert_eq!(find_preferred(&adapters, &AdapterPreference::Index(7)), None);
+        assert_eq!(find_preferred(&adapters, &AdapterPreference::Name("radeon".to_string())), None);
+        assert_eq!(adapter_candidates(&adapters, &AdapterPreference::Index(7)), [1, 0]);
+        assert_eq!(adapter_candidates(&adapters, &AdapterPreference::Name("radeon".to_string())), [1, 0]);
+    }
+
+    #[test]
+    fn warp_and_software_only_systems_have_no_candidates() {
+        assert!(adapter_candidates(&laptop(), &AdapterPreference::Warp).is_empty());
+        assert!(adapter_candidates(&[adapter(0, "Microsoft Basic Render Driver", 0, true)], &AdapterPreference::Default).is_empty());
+        assert!(adapter_candidates(&[], &AdapterPreference::Default).is_empty());
+    }
+
+    #[test]
+    fn the_first_adapter_that_works_is_used() {
+        let adapters = laptop();
+        let mut tried = Vec::new();
+
+        let created = create_with_fallback(&adapters, &[1, 0], |i| {
+            tried.push(i);
+            if i == 1 { Err(E_FAIL.into()) } else { Ok(i) }
+        }, || Ok(99));
+
+        assert_eq!(created.unwrap(), 0);
+        assert_eq!(tried, [1, 0]);
+    }
+
+    #[test]
+    fn warp_is_the_last_resort() {
+        let adapters = laptop();
+
+        let created = create_with_fallback(&adapters, &[1, 0], |_| Err::<usize, _>(E_FAIL.into()), || Ok(99));
+        assert_eq!(created.unwrap(), 99);
+
+        let created = create_with_fallback(&adapters, &[], |_| Ok(0), || Ok(99));
+        assert_eq!(created.unwrap(), 99);
+
+        let failed = create_with_fallback(&adapters, &[0], |_| Err::<usize, _>(E_FAIL.into()), || Err(DXGI_ERROR_UNSUPPORTED.into()));
+        assert_eq!(failed.unwrap_err().code(), DXGI_ERROR_UNSUPPORTED);
+    }
+
+    #[test]
+    fn feature_levels_are_retried_without_11_1() {
+        assert_eq!(FEATURE_LEVELS, [D3D_FEATURE_LEVEL_11_1, D3D_FEATURE_LEVEL_11_0]);
+        assert_eq!(retry_feature_levels(E_INVALIDARG), Some(&[D3D_FEATURE_LEVEL_11_0][..]));
+        assert_eq!(retry_feature_levels(DXGI_ERROR_UNSUPPORTED), None);
+        assert_eq!(feature_level_name(D3D_FEATURE_LEVEL_11_0), "11_0");
+        assert_eq!(feature_level_name(D3D_FEATURE_LEVEL_10_1), "unknown");
+    }
+
+    #[test]
+    fn display_names_the_vendor() {
+        let adapter = adapter(1, "NVIDIA GeForce MX150", 2048 << 20, false);
+        assert_eq!(adapter.to_string(), "[1] NVIDIA GeForce MX150 (NVIDIA, 2048 MB dedicated, luid 0000000000000001)");
+        assert_eq!(vendor_name(0x1234), "Unknown");
+    }
+}
@@ -0,0 +1,68 @@
+//! Converts OBJ and glTF files into the engine `.mesh` format.
+//!
+//! Usage: `mesh_converter <input.obj|input.gltf|input.glb> <output.mesh> [--lods 1.0,0.5,0.25] [--max-error 0.01] [--no-optimize]`
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use dx11_graphics_engine::mesh_lod::{generate_lod_chain, DEFAULT_LOD_RATIOS};
+use dx11_graphics_engine::mesh_optimizer::{optimize_mesh, OptimizerSettings};
+use dx11_graphics_engine::{gltf_loader, mesh_file, obj_loader};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+
+    let mut input = None;
+    let mut output = None;
+    let mut ratios = DEFAULT_LOD_RATIOS.to_vec();
+    let mut max_error = 0.01;
+    let mut optimize = true;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lods" => {
+                let value = args.next().ok_or("--lods expects a comma separated list of ratios")?;
+                ratios = value.split(',')
+                    .map(|ratio| ratio.trim().parse::<f32>())
+                    .collect::<Result<_, _>>()?;
+            }
+            "--max-error" => {
+                max_error = args.next().ok_or("--max-error expects a value")?.parse()?;
+            }
+            "--no-optimize" => optimize = false,
+            _ if input.is_none() => input = Some(arg),
+            _ if output.is_none() => output = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg).into()),
+        }
+    }
+
+    let (Some(input), Some(output)) = (input, output) else {
+        return Err("usage: mesh_converter <input.obj|input.gltf|input.glb> <output.mesh> [--lods 1.0,0.5] [--max-error 0.01] [--no-optimize]".into());
+    };
+
+    let extension = Path::new(&input).extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    let mut mesh = match extension.as_deref() {
+        Some("obj") => obj_loader::load_obj(&input)?,
+        Some("gltf") | Some("glb") => gltf_loader::load_gltf(&input)?,
+        _ => return Err(format!("unsupported input file '{}'", input).into()),
+    };
+
+    if optimize {
+        let report = optimize_mesh(&mut mesh, &OptimizerSettings::default())?;
+        println!("{}", report);
+    }
+
+    let lods = if ratios.is_empty() { Vec::new() } else { generate_lod_chain(&mesh, &ratios, max_error) };
+    for (index, lod) in lods.iter().enumerate() {
+        println!("LOD {}: {} triangles, error {:.4}", index, lod.indices.len() / 3, lod.error);
+    }
+
+    let mut writer = BufWriter::new(File::create(&output)?);
+    mesh_file::write_mesh_file(&mut writer, &mesh, &lods)?;
+    println!("Wrote {}", output);
+
+    Ok(())
+}
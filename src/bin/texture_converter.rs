@@ -0,0 +1,88 @@
+//! Converts images into block compressed `.dds` files with a full mip chain.
+//!
+//! Usage: `texture_converter <input> <output.dds> [--format bc1|bc3|bc4|bc5|bc7|none] [--quality fast|normal|high] [--linear] [--kaiser] [--wrap] [--alpha-cutoff 0.5] [--no-mips]`
+
+use std::fs::File;
+use std::io::BufWriter;
+
+use dx11_graphics_engine::block_compression::{compress_texture, compression_psnr, decompress_texture, BcFormat, BcQuality};
+use dx11_graphics_engine::dds_loader;
+use dx11_graphics_engine::mip_generator::{MipFilter, MipSettings};
+use dx11_graphics_engine::texture_loader::{load_image, ColorSpace, TextureLoadOptions};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+
+    let mut input = None;
+    let mut output = None;
+    let mut format = Some(BcFormat::Bc7);
+    let mut quality = BcQuality::Normal;
+    let mut options = TextureLoadOptions::default();
+    let mut mips = MipSettings::default();
+    let mut generate_mips = true;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("bc1") => Some(BcFormat::Bc1),
+                    Some("bc3") => Some(BcFormat::Bc3),
+                    Some("bc4") => Some(BcFormat::Bc4),
+                    Some("bc5") => Some(BcFormat::Bc5),
+                    Some("bc7") => Some(BcFormat::Bc7),
+                    Some("none") => None,
+                    _ => return Err("--format expects bc1, bc3, bc4, bc5, bc7 or none".into()),
+                };
+            }
+            "--quality" => {
+                quality = match args.next().as_deref() {
+                    Some("fast") => BcQuality::Fast,
+                    Some("normal") => BcQuality::Normal,
+                    Some("high") => BcQuality::High,
+                    _ => return Err("--quality expects fast, normal or high".into()),
+                };
+            }
+            "--linear" => options.color_space = ColorSpace::Linear,
+            "--kaiser" => mips.filter = MipFilter::Kaiser,
+            "--wrap" => mips.wrap = true,
+            "--alpha-cutoff" => {
+                mips.alpha_cutoff = Some(args.next().ok_or("--alpha-cutoff expects a value")?.parse()?);
+            }
+            "--no-mips" => generate_mips = false,
+            _ if input.is_none() => input = Some(arg),
+            _ if output.is_none() => output = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg).into()),
+        }
+    }
+
+    let (Some(input), Some(output)) = (input, output) else {
+        return Err("usage: texture_converter <input> <output.dds> [--format bc1|bc3|bc4|bc5|bc7|none] [--quality fast|normal|high] [--linear] [--kaiser] [--wrap] [--alpha-cutoff 0.5] [--no-mips]".into());
+    };
+
+    // Two channel and single channel formats hold data, never color.
+    if matches!(format, Some(BcFormat::Bc4) | Some(BcFormat::Bc5)) {
+        options.color_space = ColorSpace::Linear;
+    }
+    options.mips = if generate_mips { Some(mips) } else { None };
+
+    let mut texture = load_image(&input, &options)?;
+    println!("{}: {}x{} {:?}, {} levels", input, texture.width, texture.height, texture.format, texture.mip_levels);
+
+    if let Some(format) = format {
+        // DDS and KTX2 inputs may already be block compressed. Re-encoding them needs the decoded
+        // pixels, which fails with a clear error for BC7 blocks in modes other than 6.
+        if texture.format.is_compressed() {
+            texture = decompress_texture(&texture)
+                .map_err(|error| format!("cannot re-encode {}: {}", input, error))?;
+        }
+        let compressed = compress_texture(&texture, format, quality)?;
+        println!("{:?}: PSNR {:.2} dB", compressed.format, compression_psnr(&texture, &compressed)?);
+        texture = compressed;
+    }
+
+    let mut writer = BufWriter::new(File::create(&output)?);
+    dds_loader::write_dds(&mut writer, &texture)?;
+    println!("Wrote {}", output);
+
+    Ok(())
+}
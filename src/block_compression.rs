@@ -0,0 +1,987 @@
+//! CPU block compression (BC1, BC3, BC4, BC5 and BC7) with a matching decoder.
+//!
+//! BC7 blocks are always written in mode 6 (one subset, RGBA endpoints with p-bits and 4 bit
+//! indices), which handles most color and alpha content well and keeps the encoder simple. The
+//! decoder only understands mode 6 for the same reason, blocks in other modes are reported as
+//! unsupported instead of being decoded wrong.
+
+use crate::texture::{TextureData, TextureFormat};
+use crate::texture_loader::TextureError;
+
+type Block = [[f32; 4]; 16];
+
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+const BC7_MODE_6: u8 = 1 << 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BcFormat {
+    /// RGB with 1 bit alpha, 4 bits per pixel.
+    Bc1,
+    /// RGB with BC4 alpha, 8 bits per pixel.
+    Bc3,
+    /// Single channel, for masks and roughness.
+    Bc4,
+    /// Two channels, for tangent space normal maps.
+    Bc5,
+    /// RGBA with higher quality than BC3, 8 bits per pixel.
+    Bc7,
+}
+
+impl BcFormat {
+    /// Channels that carry data, used for PSNR.
+    pub fn channels(self) -> usize {
+        match self {
+            BcFormat::Bc1 => 3,
+            BcFormat::Bc4 => 1,
+            BcFormat::Bc5 => 2,
+            BcFormat::Bc3 | BcFormat::Bc7 => 4,
+        }
+    }
+
+    pub fn texture_format(self, srgb: bool) -> Option<TextureFormat> {
+        Some(match (self, srgb) {
+            (BcFormat::Bc1, false) => TextureFormat::Bc1Unorm,
+            (BcFormat::Bc1, true) => TextureFormat::Bc1UnormSrgb,
+            (BcFormat::Bc3, false) => TextureFormat::Bc3Unorm,
+            (BcFormat::Bc3, true) => TextureFormat::Bc3UnormSrgb,
+            (BcFormat::Bc4, false) => TextureFormat::Bc4Unorm,
+            (BcFormat::Bc5, false) => TextureFormat::Bc5Unorm,
+            (BcFormat::Bc7, false) => TextureFormat::Bc7Unorm,
+            (BcFormat::Bc7, true) => TextureFormat::Bc7UnormSrgb,
+            (BcFormat::Bc4, true) | (BcFormat::Bc5, true) => return None,
+        })
+    }
+
+    pub fn from_texture_format(format: TextureFormat) -> Option<Self> {
+        Some(match format {
+            TextureFormat::Bc1Unorm | TextureFormat::Bc1UnormSrgb => BcFormat::Bc1,
+            TextureFormat::Bc3Unorm | TextureFormat::Bc3UnormSrgb => BcFormat::Bc3,
+            TextureFormat::Bc4Unorm => BcFormat::Bc4,
+            TextureFormat::Bc5Unorm => BcFormat::Bc5,
+            TextureFormat::Bc7Unorm | TextureFormat::Bc7UnormSrgb => BcFormat::Bc7,
+            _ => return None,
+        })
+    }
+}
+
+/// Trades encoding time for quality.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BcQuality {
+    /// Bounding box endpoints, no refinement.
+    Fast,
+    /// Principal axis endpoints with a few least squares refinements.
+    Normal,
+    /// More refinements and an endpoint search around the best result.
+    High,
+}
+
+impl BcQuality {
+    fn refinements(self) -> usize {
+        match self {
+            BcQuality::Fast => 0,
+            BcQuality::Normal => 2,
+            BcQuality::High => 8,
+        }
+    }
+}
+
+/// Compresses every subresource of an 8 bit texture. sRGB textures keep their encoding and
+/// become the `_SRGB` variant of the target format.
+pub fn compress_texture(texture: &TextureData, format: BcFormat, quality: BcQuality) -> Result<TextureData, TextureError> {
+    let target = format.texture_format(texture.format.is_srgb())
+        .ok_or_else(|| TextureError::Unsupported(format!("{:?} has no sRGB variant", format)))?;
+
+    let (subresources, size) = TextureData::packed_subresources(
+        target,
+        texture.width,
+        texture.height,
+        texture.mip_levels,
+        texture.array_layers,
+    )?;
+    let mut data = Vec::with_capacity(size);
+
+    for layer in 0..texture.array_layers {
+        for level in 0..texture.mip_levels {
+            let source = texture.subresource(level, layer);
+            let pixels = rgba8_pixels(texture.format, texture.subresource_data(level, layer))?;
+
+            for block_y in (0..source.height).step_by(4) {
+                for block_x in (0..source.width).step_by(4) {
+                    let block = read_block(&pixels, source.width, source.height, block_x, block_y);
+                    match format {
+                        BcFormat::Bc1 => data.extend_from_slice(&encode_bc1(&block, quality, true)),
+                        BcFormat::Bc3 => {
+                            data.extend_from_slice(&encode_bc4(&channel(&block, 3), quality));
+                            data.extend_from_slice(&encode_bc1(&block, quality, false));
+                        }
+                        BcFormat::Bc4 => data.extend_from_slice(&encode_bc4(&channel(&block, 0), quality)),
+                        BcFormat::Bc5 => {
+                            data.extend_from_slice(&encode_bc4(&channel(&block, 0), quality));
+                            data.extend_from_slice(&encode_bc4(&channel(&block, 1), quality));
+                        }
+                        BcFormat::Bc7 => data.extend_from_slice(&encode_bc7(&block, quality)),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(TextureData {
+        format: target,
+        width: texture.width,
+        height: texture.height,
+        mip_levels: texture.mip_levels,
+        array_layers: texture.array_layers,
+        is_cube: texture.is_cube,
+        subresources,
+        data,
+    })
+}
+
+/// Decodes a block compressed texture to `Rgba8Unorm` (or `Rgba8UnormSrgb`).
+pub fn decompress_texture(texture: &TextureData) -> Result<TextureData, TextureError> {
+    let format = BcFormat::from_texture_format(texture.format)
+        .ok_or_else(|| TextureError::Unsupported(format!("decompressing {:?}", texture.format)))?;
+    let target = if texture.format.is_srgb() { TextureFormat::Rgba8UnormSrgb } else { TextureFormat::Rgba8Unorm };
+
+    let (subresources, size) = TextureData::packed_subresources(
+        target,
+        texture.width,
+        texture.height,
+        texture.mip_levels,
+        texture.array_layers,
+    )?;
+    let mut data = vec![0u8; size];
+
+    for layer in 0..texture.array_layers {
+        for level in 0..texture.mip_levels {
+            let source = texture.subresource(level, layer);
+            let blocks = texture.subresource_data(level, layer);
+            let destination = &subresources[texture.subresource_index(level, layer)];
+            let blocks_wide = source.width.div_ceil(4) as usize;
+
+            for (block_index, block) in blocks.chunks_exact(texture.format.block_bytes() as usize).enumerate() {
+                let (block_x, block_y) = ((block_index % blocks_wide) * 4, (block_index / blocks_wide) * 4);
+                let pixels = decode_block(format, block).ok_or_else(|| {
+                    let mode = bc7_mode(block).map_or("reserved mode".to_string(), |mode| format!("mode {}", mode));
+                    TextureError::Unsupported(format!(
+                        "BC7 {} block at ({}, {}) of level {} layer {}, only mode 6 blocks can be decoded",
+                        mode, block_x, block_y, level, layer
+                    ))
+                })?;
+
+                for (i, pixel) in pixels.iter().enumerate() {
+                    let (x, y) = (block_x + i % 4, block_y + i / 4);
+                    if x < source.width as usize && y < source.height as usize {
+                        let offset = destination.offset + y * destination.row_pitch as usize + x * 4;
+                        data[offset..offset + 4].copy_from_slice(pixel);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(TextureData {
+        format: target,
+        width: texture.width,
+        height: texture.height,
+        mip_levels: texture.mip_levels,
+        array_layers: texture.array_layers,
+        is_cube: texture.is_cube,
+        subresources,
+        data,
+    })
+}
+
+/// Decodes one block, returns `None` for BC7 modes the decoder does not implement.
+pub fn decode_block(format: BcFormat, block: &[u8]) -> Option<[[u8; 4]; 16]> {
+    let mut pixels = [[0, 0, 0, 255]; 16];
+    match format {
+        BcFormat::Bc1 => pixels = decode_bc1(block, true),
+        BcFormat::Bc3 => {
+            pixels = decode_bc1(&block[8..16], false);
+            for (pixel, alpha) in pixels.iter_mut().zip(decode_bc4(&block[0..8])) {
+                pixel[3] = alpha;
+            }
+        }
+        BcFormat::Bc4 => {
+            for (pixel, red) in pixels.iter_mut().zip(decode_bc4(&block[0..8])) {
+                pixel[0] = red;
+            }
+        }
+        BcFormat::Bc5 => {
+            let red = decode_bc4(&block[0..8]);
+            let green = decode_bc4(&block[8..16]);
+            for i in 0..16 {
+                pixels[i][0] = red[i];
+                pixels[i][1] = green[i];
+            }
+        }
+        BcFormat::Bc7 => pixels = decode_bc7(block)?,
+    }
+    Some(pixels)
+}
+
+/// Peak signal to noise ratio in dB over the first `channels` channels, infinite for identical images.
+pub fn psnr(reference: &[[u8; 4]], decoded: &[[u8; 4]], channels: usize) -> f32 {
+    let mut squared_error = 0.0f64;
+    for (a, b) in reference.iter().zip(decoded) {
+        for channel in 0..channels {
+            let difference = a[channel] as f64 - b[channel] as f64;
+            squared_error += difference * difference;
+        }
+    }
+
+    let mean = squared_error / (reference.len() * channels) as f64;
+    if mean == 0.0 {
+        return f32::INFINITY;
+    }
+    (10.0 * (255.0 * 255.0 / mean).log10()) as f32
+}
+
+/// PSNR between the base levels of an uncompressed texture and its compressed version.
+pub fn compression_psnr(original: &TextureData, compressed: &TextureData) -> Result<f32, TextureError> {
+    let format = BcFormat::from_texture_format(compressed.format)
+        .ok_or_else(|| TextureError::Unsupported(format!("{:?} is not block compressed", compressed.format)))?;
+    let decoded = decompress_texture(compressed)?;
+
+    let reference = rgba8_pixels(original.format, original.subresource_data(0, 0))?;
+    let result = rgba8_pixels(decoded.format, decoded.subresource_data(0, 0))?;
+    Ok(psnr(&reference, &result, format.channels()))
+}
+
+/// Reads any 8 or 16 bit unorm level as RGBA8 without changing its color encoding.
+pub fn rgba8_pixels(format: TextureFormat, data: &[u8]) -> Result<Vec<[u8; 4]>, TextureError> {
+    Ok(match format {
+        TextureFormat::R8Unorm => data.iter().map(|&r| [r, 0, 0, 255]).collect(),
+        TextureFormat::Rg8Unorm => data.chunks_exact(2).map(|p| [p[0], p[1], 0, 255]).collect(),
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {
+            data.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect()
+        }
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+            data.chunks_exact(4).map(|p| [p[2], p[1], p[0], p[3]]).collect()
+        }
+        // The high byte of a little endian 16 bit channel.
+        TextureFormat::Rgba16Unorm => data.chunks_exact(8).map(|p| [p[1], p[3], p[5], p[7]]).collect(),
+        _ => return Err(TextureError::Unsupported(format!("block compression of {:?}", format))),
+    })
+}
+
+/// Gathers a 4x4 block, replicating the last row and column past the edge of the image.
+fn read_block(pixels: &[[u8; 4]], width: u32, height: u32, block_x: u32, block_y: u32) -> Block {
+    let mut block = [[0.0; 4]; 16];
+    for (i, texel) in block.iter_mut().enumerate() {
+        let x = (block_x + i as u32 % 4).min(width - 1);
+        let y = (block_y + i as u32 / 4).min(height - 1);
+        let pixel = pixels[(y * width + x) as usize];
+        *texel = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32, pixel[3] as f32];
+    }
+    block
+}
+
+fn channel(block: &Block, channel: usize) -> [f32; 16] {
+    let mut values = [0.0; 16];
+    for (value, pixel) in values.iter_mut().zip(block) {
+        *value = pixel[channel];
+    }
+    values
+}
+
+fn squared_distance(a: &[f32; 4], b: &[f32; 4], channels: usize) -> f32 {
+    (0..channels).map(|c| (a[c] - b[c]) * (a[c] - b[c])).sum()
+}
+
+/// Endpoints at the extremes of the block along its principal axis, or of its bounding box for
+/// [`BcQuality::Fast`].
+fn initial_endpoints(pixels: &[[f32; 4]], channels: usize, quality: BcQuality) -> ([f32; 4], [f32; 4]) {
+    let mut min = [f32::MAX; 4];
+    let mut max = [f32::MIN; 4];
+    for pixel in pixels {
+        for c in 0..channels {
+            min[c] = min[c].min(pixel[c]);
+            max[c] = max[c].max(pixel[c]);
+        }
+    }
+    if quality == BcQuality::Fast || pixels.len() < 2 {
+        return (min, max);
+    }
+
+    let mut mean = [0.0f32; 4];
+    for pixel in pixels {
+        for c in 0..channels {
+            mean[c] += pixel[c] / pixels.len() as f32;
+        }
+    }
+
+    let mut covariance = [[0.0f32; 4]; 4];
+    for pixel in pixels {
+        for i in 0..channels {
+            for j in 0..channels {
+                covariance[i][j] += (pixel[i] - mean[i]) * (pixel[j] - mean[j]);
+            }
+        }
+    }
+
+    // Power iteration, starting from the bounding box diagonal.
+    let mut axis = [0.0f32; 4];
+    for c in 0..channels {
+        axis[c] = max[c] - min[c];
+    }
+    for _ in 0..8 {
+        let mut next = [0.0f32; 4];
+        for i in 0..channels {
+            for j in 0..channels {
+                next[i] += covariance[i][j] * axis[j];
+            }
+        }
+        let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if length < 1e-6 {
+            break;
+        }
+        axis = next.map(|v| v / length);
+    }
+
+    let length_squared: f32 = axis.iter().map(|v| v * v).sum();
+    if length_squared < 1e-12 {
+        return (min, max);
+    }
+
+    let (mut low, mut high) = (f32::MAX, f32::MIN);
+    for pixel in pixels {
+        let t: f32 = (0..channels).map(|c| (pixel[c] - mean[c]) * axis[c]).sum();
+        low = low.min(t);
+        high = high.max(t);
+    }
+
+    let mut start = [0.0; 4];
+    let mut end = [0.0; 4];
+    for c in 0..channels {
+        start[c] = (mean[c] + axis[c] * low).clamp(0.0, 255.0);
+        end[c] = (mean[c] + axis[c] * high).clamp(0.0, 255.0);
+    }
+    (start, end)
+}
+
+/// Least squares endpoints for pixels interpolated with the given weights (0 is the first endpoint).
+fn refine_endpoints(pixels: &[[f32; 4]], weights: &[f32], channels: usize) -> Option<([f32; 4], [f32; 4])> {
+    let (mut aa, mut ab, mut bb) = (0.0f32, 0.0f32, 0.0f32);
+    let mut ax = [0.0f32; 4];
+    let mut bx = [0.0f32; 4];
+
+    for (pixel, &weight) in pixels.iter().zip(weights) {
+        let (a, b) = (1.0 - weight, weight);
+        aa += a * a;
+        ab += a * b;
+        bb += b * b;
+        for c in 0..channels {
+            ax[c] += a * pixel[c];
+            bx[c] += b * pixel[c];
+        }
+    }
+
+    let determinant = aa * bb - ab * ab;
+    if determinant.abs() < 1e-6 {
+        return None;
+    }
+
+    let mut start = [0.0; 4];
+    let mut end = [0.0; 4];
+    for c in 0..channels {
+        start[c] = ((bb * ax[c] - ab * bx[c]) / determinant).clamp(0.0, 255.0);
+        end[c] = ((aa * bx[c] - ab * ax[c]) / determinant).clamp(0.0, 255.0);
+    }
+    Some((start, end))
+}
+
+/// Picks the nearest palette entry for every pixel, returns the indices and the total error.
+fn assign_indices(pixels: &[[f32; 4]], palette: &[[f32; 4]], channels: usize, indices: &mut [u8]) -> f32 {
+    let mut error = 0.0;
+    for (pixel, index) in pixels.iter().zip(indices.iter_mut()) {
+        let (best, best_error) = palette.iter()
+            .enumerate()
+            .map(|(i, entry)| (i, squared_distance(pixel, entry, channels)))
+            .fold((0, f32::MAX), |best, candidate| if candidate.1 < best.1 { candidate } else { best });
+        *index = best as u8;
+        error += best_error;
+    }
+    error
+}
+
+// BC1
+
+fn to_565(color: &[f32; 4]) -> u16 {
+    let r = (color[0].clamp(0.0, 255.0) * 31.0 / 255.0 + 0.5) as u16;
+    let g = (color[1].clamp(0.0, 255.0) * 63.0 / 255.0 + 0.5) as u16;
+    let b = (color[2].clamp(0.0, 255.0) * 31.0 / 255.0 + 0.5) as u16;
+    (r << 11) | (g << 5) | b
+}
+
+fn from_565(color: u16) -> [u8; 4] {
+    let r = ((color >> 11) & 31) as u8;
+    let g = ((color >> 5) & 63) as u8;
+    let b = (color & 31) as u8;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2), 255]
+}
+
+fn bc1_palette(color0: u16, color1: u16, four_color: bool) -> [[u8; 4]; 4] {
+    let c0 = from_565(color0);
+    let c1 = from_565(color1);
+    let mix = |a: u8, b: u8, wa: u16, wb: u16| ((a as u16 * wa + b as u16 * wb + (wa + wb) / 2) / (wa + wb)) as u8;
+
+    if four_color {
+        [
+            c0,
+            c1,
+            [mix(c0[0], c1[0], 2, 1), mix(c0[1], c1[1], 2, 1), mix(c0[2], c1[2], 2, 1), 255],
+            [mix(c0[0], c1[0], 1, 2), mix(c0[1], c1[1], 1, 2), mix(c0[2], c1[2], 1, 2), 255],
+        ]
+    } else {
+        [
+            c0,
+            c1,
+            [mix(c0[0], c1[0], 1, 1), mix(c0[1], c1[1], 1, 1), mix(c0[2], c1[2], 1, 1), 255],
+            [0, 0, 0, 0],
+        ]
+    }
+}
+
+fn palette_to_f32<const N: usize>(palette: &[[u8; 4]; N]) -> [[f32; 4]; N] {
+    palette.map(|entry| entry.map(|value| value as f32))
+}
+
+/// Encodes the color of a block. With `allow_alpha`, pixels with alpha below 128 use the
+/// transparent entry of the three color mode, BC3 color blocks never do.
+fn encode_bc1(block: &Block, quality: BcQuality, allow_alpha: bool) -> [u8; 8] {
+    let transparent: Vec<bool> = block.iter().map(|pixel| allow_alpha && pixel[3] < 128.0).collect();
+    let three_color = transparent.iter().any(|&t| t);
+    let opaque: Vec<[f32; 4]> = block.iter().zip(&transparent).filter(|(_, &t)| !t).map(|(p, _)| *p).collect();
+
+    if opaque.is_empty() {
+        let mut result = [0u8; 8];
+        result[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        return result;
+    }
+
+    // Index weights towards the second endpoint, in palette order.
+    let weights: &[f32] = if three_color { &[0.0, 1.0, 0.5] } else { &[0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0] };
+
+    let evaluate = |color0: u16, color1: u16, indices: &mut [u8; 16]| -> f32 {
+        let palette = palette_to_f32(&bc1_palette(color0, color1, !three_color));
+        let palette = &palette[..weights.len()];
+        assign_indices(&opaque, palette, 3, &mut indices[..opaque.len()])
+    };
+
+    let (start, end) = initial_endpoints(&opaque, 3, quality);
+    let mut best = (to_565(&start), to_565(&end));
+    let mut best_indices = [0u8; 16];
+    let mut best_error = evaluate(best.0, best.1, &mut best_indices);
+
+    for _ in 0..quality.refinements() {
+        let index_weights: Vec<f32> = best_indices[..opaque.len()].iter().map(|&i| weights[i as usize]).collect();
+        let Some((start, end)) = refine_endpoints(&opaque, &index_weights, 3) else {
+            break;
+        };
+        let candidate = (to_565(&start), to_565(&end));
+        let mut indices = [0u8; 16];
+        let error = evaluate(candidate.0, candidate.1, &mut indices);
+        if error >= best_error {
+            break;
+        }
+        (best, best_indices, best_error) = (candidate, indices, error);
+    }
+
+    if quality == BcQuality::High {
+        // Greedy search of single steps of each 565 channel of both endpoints.
+        let steps: [(u16, u16); 3] = [(11, 31), (5, 63), (0, 31)];
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for endpoint in 0..2 {
+                for &(shift, mask) in &steps {
+                    for delta in [-1i32, 1] {
+                        let color = if endpoint == 0 { best.0 } else { best.1 };
+                        let value = ((color >> shift) & mask) as i32 + delta;
+                        if value < 0 || value > mask as i32 {
+                            continue;
+                        }
+                        let color = (color & !(mask << shift)) | ((value as u16) << shift);
+                        let candidate = if endpoint == 0 { (color, best.1) } else { (best.0, color) };
+                        let mut indices = [0u8; 16];
+                        let error = evaluate(candidate.0, candidate.1, &mut indices);
+                        if error < best_error {
+                            (best, best_indices, best_error) = (candidate, indices, error);
+                            improved = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // The decoder picks the mode from the endpoint order: color0 > color1 selects four colors.
+    let (mut color0, mut color1) = best;
+    let mut opaque_indices = best_indices[..opaque.len()].to_vec();
+    let swap = if three_color { color0 > color1 } else { color0 < color1 };
+    if swap {
+        std::mem::swap(&mut color0, &mut color1);
+        for index in &mut opaque_indices {
+            *index = match *index {
+                0 => 1,
+                1 => 0,
+                2 if !three_color => 3,
+                3 => 2,
+                index => index,
+            };
+        }
+    }
+    if !three_color && color0 == color1 {
+        // Equal endpoints decode in three color mode, where only index 0 and 1 are safe.
+        opaque_indices.iter_mut().for_each(|index| *index = 0);
+    }
+
+    let mut opaque_indices = opaque_indices.into_iter();
+    let mut packed = 0u32;
+    for (i, &is_transparent) in transparent.iter().enumerate() {
+        let index = if is_transparent { 3 } else { opaque_indices.next().unwrap() as u32 };
+        packed |= index << (i * 2);
+    }
+
+    let mut result = [0u8; 8];
+    result[0..2].copy_from_slice(&color0.to_le_bytes());
+    result[2..4].copy_from_slice(&color1.to_le_bytes());
+    result[4..8].copy_from_slice(&packed.to_le_bytes());
+    result
+}
+
+/// With `allow_alpha` the endpoint order selects the three color mode like BC1 does, BC2 and
+/// BC3 always use four colors.
+fn decode_bc1(block: &[u8], allow_alpha: bool) -> [[u8; 4]; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    let palette = bc1_palette(color0, color1, !allow_alpha || color0 > color1);
+
+    let mut pixels = [[0u8; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[((indices >> (i * 2)) & 3) as usize];
+    }
+    pixels
+}
+
+// BC4
+
+fn bc4_palette(red0: u8, red1: u8) -> [f32; 8] {
+    let (r0, r1) = (red0 as f32, red1 as f32);
+    let mut palette = [r0, r1, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    if red0 > red1 {
+        for i in 1..7 {
+            palette[i + 1] = ((((7 - i) as f32 * r0 + i as f32 * r1) / 7.0) + 0.5).floor();
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((((5 - i) as f32 * r0 + i as f32 * r1) / 5.0) + 0.5).floor();
+        }
+        palette[6] = 0.0;
+        palette[7] = 255.0;
+    }
+    palette
+}
+
+fn bc4_error(values: &[f32; 16], red0: u8, red1: u8, indices: &mut [u8; 16]) -> f32 {
+    let palette = bc4_palette(red0, red1);
+    let mut error = 0.0;
+    for (value, index) in values.iter().zip(indices.iter_mut()) {
+        let (best, best_error) = palette.iter()
+            .enumerate()
+            .map(|(i, entry)| (i, (entry - value) * (entry - value)))
+            .fold((0, f32::MAX), |best, candidate| if candidate.1 < best.1 { candidate } else { best });
+        *index = best as u8;
+        error += best_error;
+    }
+    error
+}
+
+fn encode_bc4(values: &[f32; 16], quality: BcQuality) -> [u8; 8] {
+    let min = values.iter().cloned().fold(f32::MAX, f32::min) as u8;
+    let max = values.iter().cloned().fold(f32::MIN, f32::max) as u8;
+
+    let mut candidates = vec![(max, min)];
+    if quality != BcQuality::Fast {
+        // Six value mode with exact 0 and 255, using the range of the remaining values.
+        let inner: Vec<u8> = values.iter().map(|&v| v as u8).filter(|&v| v != 0 && v != 255).collect();
+        if let (Some(&low), Some(&high)) = (inner.iter().min(), inner.iter().max()) {
+            candidates.push((low, high));
+        }
+    }
+    if quality == BcQuality::High {
+        for inset_low in 0..=4u8 {
+            for inset_high in 0..=4u8 {
+                let (low, high) = (min.saturating_add(inset_low), max.saturating_sub(inset_high));
+                if high > low {
+                    candidates.push((high, low));
+                }
+            }
+        }
+    }
+
+    let mut best = (max, min);
+    let mut best_indices = [0u8; 16];
+    let mut best_error = f32::MAX;
+    for (red0, red1) in candidates {
+        let mut indices = [0u8; 16];
+        let error = bc4_error(values, red0, red1, &mut indices);
+        if error < best_error {
+            (best, best_indices, best_error) = ((red0, red1), indices, error);
+        }
+    }
+
+    let mut packed = 0u64;
+    for (i, &index) in best_indices.iter().enumerate() {
+        packed |= (index as u64) << (i * 3);
+    }
+
+    let mut result = [0u8; 8];
+    result[0] = best.0;
+    result[1] = best.1;
+    result[2..8].copy_from_slice(&packed.to_le_bytes()[0..6]);
+    result
+}
+
+fn decode_bc4(block: &[u8]) -> [u8; 16] {
+    let palette = bc4_palette(block[0], block[1]);
+    let mut bytes = [0u8; 8];
+    bytes[0..6].copy_from_slice(&block[2..8]);
+    let packed = u64::from_le_bytes(bytes);
+
+    let mut values = [0u8; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[((packed >> (i * 3)) & 7) as usize] as u8;
+    }
+    values
+}
+
+// BC7 mode 6
+
+/// Endpoint with 7 bits per channel plus a shared p-bit, as stored in the block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Bc7Endpoint {
+    color: [u8; 4],
+    p_bit: u8,
+}
+
+impl Bc7Endpoint {
+    fn quantize(color: &[f32; 4], p_bit: u8) -> Self {
+        Bc7Endpoint {
+            color: color.map(|value| ((value - p_bit as f32) / 2.0).round().clamp(0.0, 127.0) as u8),
+            p_bit,
+        }
+    }
+
+    fn value(&self) -> [u8; 4] {
+        self.color.map(|value| (value << 1) | self.p_bit)
+    }
+}
+
+fn bc7_palette(start: &Bc7Endpoint, end: &Bc7Endpoint) -> [[f32; 4]; 16] {
+    let (a, b) = (start.value(), end.value());
+    let mut palette = [[0.0; 4]; 16];
+    for (entry, &weight) in palette.iter_mut().zip(&BC7_WEIGHTS) {
+        for c in 0..4 {
+            entry[c] = (((64 - weight) * a[c] as u32 + weight * b[c] as u32 + 32) >> 6) as f32;
+        }
+    }
+    palette
+}
+
+fn encode_bc7(block: &Block, quality: BcQuality) -> [u8; 16] {
+    let evaluate = |start: &Bc7Endpoint, end: &Bc7Endpoint, indices: &mut [u8; 16]| -> f32 {
+        assign_indices(block, &bc7_palette(start, end), 4, indices)
+    };
+
+    // A p-bit of 0 turns opaque alpha into 254, so opaque blocks always use 1.
+    let opaque = block.iter().all(|pixel| pixel[3] == 255.0);
+
+    // Tries the p-bits that quantize each endpoint best, or all four combinations for High.
+    let quantize = |start: &[f32; 4], end: &[f32; 4]| -> (Bc7Endpoint, Bc7Endpoint, [u8; 16], f32) {
+        let mut options = Vec::new();
+        if opaque {
+            options.push((Bc7Endpoint::quantize(start, 1), Bc7Endpoint::quantize(end, 1)));
+        } else if quality == BcQuality::High {
+            for p0 in 0..2 {
+                for p1 in 0..2 {
+                    options.push((Bc7Endpoint::quantize(start, p0), Bc7Endpoint::quantize(end, p1)));
+                }
+            }
+        } else {
+            let nearest = |color: &[f32; 4]| {
+                (0..2)
+                    .map(|p| Bc7Endpoint::quantize(color, p))
+                    .min_by(|a, b| {
+                        let error = |e: &Bc7Endpoint| squared_distance(color, &e.value().map(|v| v as f32), 4);
+                        error(a).partial_cmp(&error(b)).unwrap()
+                    })
+                    .unwrap()
+            };
+            options.push((nearest(start), nearest(end)));
+        }
+
+        options.into_iter()
+            .map(|(start, end)| {
+                let mut indices = [0u8; 16];
+                let error = evaluate(&start, &end, &mut indices);
+                (start, end, indices, error)
+            })
+            .min_by(|a, b| a.3.partial_cmp(&b.3).unwrap())
+            .unwrap()
+    };
+
+    let (start, end) = initial_endpoints(block, 4, quality);
+    let mut best = quantize(&start, &end);
+
+    for _ in 0..quality.refinements() {
+        let weights: Vec<f32> = best.2.iter().map(|&i| BC7_WEIGHTS[i as usize] as f32 / 64.0).collect();
+        let Some((start, end)) = refine_endpoints(block, &weights, 4) else {
+            break;
+        };
+        let candidate = quantize(&start, &end);
+        if candidate.3 >= best.3 {
+            break;
+        }
+        best = candidate;
+    }
+
+    let (mut start, mut end, mut indices, _) = best;
+
+    // The anchor index (pixel 0) is stored without its top bit, so it has to be below 8.
+    if indices[0] >= 8 {
+        std::mem::swap(&mut start, &mut end);
+        indices.iter_mut().for_each(|index| *index = 15 - *index);
+    }
+
+    let mut writer = BitWriter::default();
+    writer.write(BC7_MODE_6 as u32, 7);
+    for c in 0..4 {
+        writer.write(start.color[c] as u32, 7);
+        writer.write(end.color[c] as u32, 7);
+    }
+    writer.write(start.p_bit as u32, 1);
+    writer.write(end.p_bit as u32, 1);
+    for (i, &index) in indices.iter().enumerate() {
+        writer.write(index as u32, if i == 0 { 3 } else { 4 });
+    }
+    writer.bytes
+}
+
+/// Mode of a BC7 block, the number of zero bits before the first set bit. `None` for the
+/// reserved all zero mode byte.
+pub fn bc7_mode(block: &[u8]) -> Option<u32> {
+    match block[0].trailing_zeros() {
+        8 => None,
+        mode => Some(mode),
+    }
+}
+
+fn decode_bc7(block: &[u8]) -> Option<[[u8; 4]; 16]> {
+    if block[0] & 0x7f != BC7_MODE_6 {
+        return None;
+    }
+
+    let mut reader = BitReader { bytes: block.try_into().unwrap(), position: 7 };
+    let mut start = Bc7Endpoint { color: [0; 4], p_bit: 0 };
+    let mut end = start;
+    for c in 0..4 {
+        start.color[c] = reader.read(7) as u8;
+        end.color[c] = reader.read(7) as u8;
+    }
+    start.p_bit = reader.read(1) as u8;
+    end.p_bit = reader.read(1) as u8;
+
+    let palette = bc7_palette(&start, &end);
+    let mut pixels = [[0u8; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let index = reader.read(if i == 0 { 3 } else { 4 }) as usize;
+        *pixel = palette[index].map(|value| value as u8);
+    }
+    Some(pixels)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: [u8; 16],
+    position: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: usize) {
+        for bit in 0..bits {
+            if value >> bit & 1 != 0 {
+                self.bytes[self.position / 8] |= 1 << (self.position % 8);
+            }
+            self.position += 1;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8; 16],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: usize) -> u32 {
+        let mut value = 0;
+        for bit in 0..bits {
+            value |= ((self.bytes[self.position / 8] >> (self.position % 8) & 1) as u32) << bit;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_FORMATS: [BcFormat; 5] = [BcFormat::Bc1, BcFormat::Bc3, BcFormat::Bc4, BcFormat::Bc5, BcFormat::Bc7];
+
+    /// Smooth gradients with a little deterministic noise, like a typical photo texture.
+    fn test_image(width: u32, height: u32, alpha: bool) -> TextureData {
+        let mut state = 12345u32;
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let noise = (state >> 16) as i32 % 9 - 4;
+                let value = |base: i32| (base + noise).clamp(0, 255) as u8;
+                data.extend_from_slice(&[
+                    value((x * 255 / width) as i32),
+                    value((y * 255 / height) as i32),
+                    value(((x + y) * 127 / (width + height)) as i32 + 64),
+                    if alpha { value(255 - (x * 200 / width) as i32) } else { 255 },
+                ]);
+            }
+        }
+        TextureData::from_pixels(TextureFormat::Rgba8Unorm, width, height, data)
+    }
+
+    #[test]
+    fn round_trip_psnr_per_format() {
+        // The noise cannot be represented by two endpoints per block, it dominates the error.
+        let thresholds = [
+            (BcFormat::Bc1, false, 31.0),
+            (BcFormat::Bc3, true, 32.0),
+            (BcFormat::Bc4, false, 44.0),
+            (BcFormat::Bc5, false, 44.0),
+            (BcFormat::Bc7, true, 30.0),
+        ];
+        for (format, alpha, minimum) in thresholds {
+            let image = test_image(32, 32, alpha);
+            for quality in [BcQuality::Fast, BcQuality::Normal, BcQuality::High] {
+                let compressed = compress_texture(&image, format, quality).unwrap();
+                assert_eq!(compressed.format, format.texture_format(false).unwrap());
+                assert_eq!(compressed.data.len(), 64 * compressed.format.block_bytes() as usize);
+
+                let psnr = compression_psnr(&image, &compressed).unwrap();
+                assert!(psnr >= minimum, "{:?} {:?}: {:.2} dB < {} dB", format, quality, psnr, minimum);
+            }
+        }
+    }
+
+    #[test]
+    fn higher_quality_is_not_worse() {
+        let image = test_image(16, 16, true);
+        for format in ALL_FORMATS {
+            let psnr = |quality| compression_psnr(&image, &compress_texture(&image, format, quality).unwrap()).unwrap();
+            let (fast, high) = (psnr(BcQuality::Fast), psnr(BcQuality::High));
+            assert!(high + 0.01 >= fast, "{:?}: high {:.2} dB, fast {:.2} dB", format, high, fast);
+        }
+    }
+
+    #[test]
+    fn solid_colors_are_nearly_exact() {
+        let data = [200u8, 100, 50, 255].repeat(16);
+        let image = TextureData::from_pixels(TextureFormat::Rgba8Unorm, 4, 4, data);
+        for format in ALL_FORMATS {
+            let compressed = compress_texture(&image, format, BcQuality::Normal).unwrap();
+            let psnr = compression_psnr(&image, &compressed).unwrap();
+            assert!(psnr >= 40.0, "{:?}: {:.2} dB", format, psnr);
+        }
+    }
+
+    #[test]
+    fn partial_blocks_and_mips() {
+        let image = test_image(6, 5, false);
+        let compressed = compress_texture(&image, BcFormat::Bc1, BcQuality::Normal).unwrap();
+        assert_eq!(compressed.data.len(), 2 * 2 * 8);
+
+        let decoded = decompress_texture(&compressed).unwrap();
+        assert_eq!((decoded.width, decoded.height), (6, 5));
+        assert_eq!(decoded.data.len(), 6 * 5 * 4);
+    }
+
+    #[test]
+    fn srgb_stays_srgb() {
+        let data = test_image(8, 8, false).data;
+        let image = TextureData::from_pixels(TextureFormat::Rgba8UnormSrgb, 8, 8, data);
+        let compressed = compress_texture(&image, BcFormat::Bc7, BcQuality::Fast).unwrap();
+        assert_eq!(compressed.format, TextureFormat::Bc7UnormSrgb);
+        assert_eq!(decompress_texture(&compressed).unwrap().format, TextureFormat::Rgba8UnormSrgb);
+        assert!(compress_texture(&image, BcFormat::Bc5, BcQuality::Fast).is_err());
+    }
+
+    #[test]
+    fn bc1_punch_through_alpha() {
+        let mut data = [255u8, 255, 255, 255].repeat(16);
+        data[3] = 0;
+        let image = TextureData::from_pixels(TextureFormat::Rgba8Unorm, 4, 4, data);
+        let compressed = compress_texture(&image, BcFormat::Bc1, BcQuality::Normal).unwrap();
+        let decoded = decode_block(BcFormat::Bc1, &compressed.data).unwrap();
+        assert_eq!(decoded[0][3], 0);
+        assert!(decoded[1..].iter().all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn bc7_modes() {
+        let image = test_image(4, 4, true);
+        let compressed = compress_texture(&image, BcFormat::Bc7, BcQuality::Normal).unwrap();
+        assert_eq!(bc7_mode(&compressed.data), Some(6));
+
+        let mut block = [0u8; 16];
+        assert_eq!(bc7_mode(&block), None);
+        assert_eq!(decode_block(BcFormat::Bc7, &block), None);
+        for mode in (0..8).filter(|&mode| mode != 6) {
+            block[0] = 1 << mode;
+            assert_eq!(bc7_mode(&block), Some(mode));
+            assert_eq!(decode_block(BcFormat::Bc7, &block), None);
+        }
+    }
+
+    #[test]
+    fn unsupported_bc7_blocks_are_reported() {
+        let image = test_image(8, 4, true);
+        let mut compressed = compress_texture(&image, BcFormat::Bc7, BcQuality::Fast).unwrap();
+        // Second block switched to mode 5.
+        compressed.data[16] = 1 << 5;
+
+        match decompress_texture(&compressed) {
+            Err(TextureError::Unsupported(message)) => {
+                assert!(message.contains("mode 5"), "{}", message);
+                assert!(message.contains("(4, 0)"), "{}", message);
+            }
+            other => panic!("unexpected result {:?}", other.map(|texture| texture.format)),
+        }
+    }
+
+    #[test]
+    fn psnr_of_identical_and_different_images() {
+        let a = [[10u8, 20, 30, 40]; 4];
+        assert_eq!(psnr(&a, &a, 4), f32::INFINITY);
+        let mut b = a;
+        b[0][0] = 11;
+        // One channel off by one in 16 samples: MSE 1/16.
+        assert!((psnr(&a, &b, 4) - 10.0 * (255.0f32 * 255.0 * 16.0).log10()).abs() < 1e-3);
+        // Channels past `channels` are ignored.
+        b[0] = [10, 20, 30, 0];
+        assert_eq!(psnr(&a, &b, 3), f32::INFINITY);
+    }
+}
@@ -0,0 +1,236 @@
+//! Output color spaces and the encoding of linear scene colors for them.
+//!
+//! The scene is rendered in linear Rec.709 with 1.0 as paper white. `output.ps.hlsl` mirrors
+//! [`encode_color`], keep the two in sync.
+
+use serde::{Deserialize, Serialize};
+
+use crate::mip_generator::linear_to_srgb;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputColorSpace {
+    /// 8 bit sRGB, the fallback every display supports.
+    Srgb,
+    /// 10 bit Rec.2020 primaries with the ST.2084 (PQ) transfer function.
+    Hdr10,
+    /// 16 bit float linear Rec.709 where 1.0 is 80 nits.
+    ScRgb,
+}
+
+impl OutputColorSpace {
+    pub fn is_hdr(self) -> bool {
+        self != OutputColorSpace::Srgb
+    }
+
+    /// Value of `OutputConstants::mode` in `output.ps.hlsl`.
+    pub fn shader_mode(self) -> u32 {
+        match self {
+            OutputColorSpace::Srgb => 0,
+            OutputColorSpace::Hdr10 => 1,
+            OutputColorSpace::ScRgb => 2,
+        }
+    }
+}
+
+/// Picks the color space to output, HDR only when the display is in HDR mode.
+pub fn negotiate(preferred: OutputColorSpace, display_hdr: bool) -> OutputColorSpace {
+    if preferred.is_hdr() && display_hdr { preferred } else { OutputColorSpace::Srgb }
+}
+
+/// Luminance scRGB maps to 1.0.
+pub const SCRGB_REFERENCE_NITS: f32 = 80.0;
+/// Luminance of the PQ curve's maximum code value.
+pub const PQ_MAX_NITS: f32 = 10000.0;
+
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+/// ST.2084 inverse EOTF, absolute luminance in nits to a 0..1 signal.
+pub fn pq_encode(nits: f32) -> f32 {
+    let y = (nits / PQ_MAX_NITS).clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+/// ST.2084 EOTF, a 0..1 signal to absolute luminance in nits.
+pub fn pq_decode(signal: f32) -> f32 {
+    let e = signal.clamp(0.0, 1.0).powf(1.0 / PQ_M2);
+    let y = ((e - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * e)).powf(1.0 / PQ_M1);
+    y * PQ_MAX_NITS
+}
+
+/// Converts linear Rec.709 primaries to Rec.2020 (ITU-R BT.2087), both with a D65 white point.
+pub fn rec709_to_rec2020(color: [f32; 3]) -> [f32; 3] {
+    const M: [[f32; 3]; 3] = [
+        [0.627404, 0.329282, 0.0433136],
+        [0.069097, 0.91954, 0.0113612],
+        [0.0163916, 0.0880132, 0.895595],
+    ];
+
+    M.map(|row| row[0] * color[0] + row[1] * color[1] + row[2] * color[2])
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputEncoding {
+    pub color_space: OutputColorSpace,
+    /// Luminance scene value 1.0 is shown at on HDR displays.
+    pub paper_white_nits: f32,
+    /// Brightest luminance the display can show, HDR output is clipped to it.
+    pub max_nits: f32,
+}
+
+impl Default for OutputEncoding {
+    fn default() -> Self {
+        OutputEncoding { color_space: OutputColorSpace::Srgb, paper_white_nits: 200.0, max_nits: 1000.0 }
+    }
+}
+
+/// Constant buffer of `output.ps.hlsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OutputConstants {
+    pub mode: u32,
+    pub paper_white_nits: f32,
+    pub max_nits: f32,
+    pub _padding: f32,
+}
+
+impl OutputEncoding {
+    pub fn constants(&self) -> OutputConstants {
+        OutputConstants {
+            mode: self.color_space.shader_mode(),
+            paper_white_nits: self.paper_white_nits,
+            max_nits: self.max_nits,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Encodes a linear scene color into the values written to the back buffer.
+pub fn encode_color(color: [f32; 3], encoding: &OutputEncoding) -> [f32; 3] {
+    match encoding.color_space {
+        OutputColorSpace::Srgb => color.map(|c| linear_to_srgb(c.clamp(0.0, 1.0))),
+        OutputColorSpace::Hdr10 => rec709_to_rec2020(color)
+            .map(|c| pq_encode((c * encoding.paper_white_nits).clamp(0.0, encoding.max_nits))),
+        OutputColorSpace::ScRgb => {
+            let max = encoding.max_nits / SCRGB_REFERENCE_NITS;
+            color.map(|c| (c * encoding.paper_white_nits / SCRGB_REFERENCE_NITS).min(max))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not within {} of {}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn pq_reference_points() {
+        assert_close(pq_encode(0.0), 0.0, 1e-6);
+        assert_close(pq_encode(100.0), 0.5081, 1e-3);
+        assert_close(pq_encode(1000.0), 0.7518, 1e-3);
+        assert_close(pq_encode(PQ_MAX_NITS), 1.0, 1e-6);
+
+        assert_close(pq_decode(0.0), 0.0, 1e-3);
+        assert_close(pq_decode(0.5081), 100.0, 0.2);
+        assert_close(pq_decode(1.0), PQ_MAX_NITS, 0.5);
+    }
+
+    #[test]
+    fn pq_clamps_out_of_range_values() {
+        assert_eq!(pq_encode(-5.0), pq_encode(0.0));
+        assert_eq!(pq_encode(20000.0), pq_encode(PQ_MAX_NITS));
+        assert_eq!(pq_decode(1.5), pq_decode(1.0));
+        assert_eq!(pq_decode(-0.5), pq_decode(0.0));
+    }
+
+    #[test]
+    fn pq_round_trips() {
+        for nits in [0.01, 0.1, 1.0, 10.0, 80.0, 100.0, 203.0, 1000.0, 4000.0, 10000.0] {
+            assert_close(pq_decode(pq_encode(nits)), nits, nits * 1e-3 + 1e-3);
+        }
+        for step in 0..=100 {
+            let signal = step as f32 / 100.0;
+            assert_close(pq_encode(pq_decode(signal)), signal, 1e-4);
+        }
+    }
+
+    #[test]
+    fn pq_is_monotonic() {
+        let signals: Vec<f32> = (0..=1000).map(|step| pq_encode(step as f32 * 10.0)).collect();
+        assert!(signals.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn rec709_white_maps_to_rec2020_white() {
+        for (actual, expected) in rec709_to_rec2020([1.0, 1.0, 1.0]).into_iter().zip([1.0, 1.0, 1.0]) {
+            assert_close(actual, expected, 1e-5);
+        }
+        assert_eq!(rec709_to_rec2020([0.0, 0.0, 0.0]), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn rec709_primaries_stay_inside_rec2020() {
+        for primary in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] {
+            let converted = rec709_to_rec2020(primary);
+            assert!(converted.iter().all(|&c| c > 0.0 && c < 1.0), "{:?}", converted);
+        }
+    }
+
+    #[test]
+    fn negotiate_needs_an_hdr_display() {
+        assert_eq!(negotiate(OutputColorSpace::Hdr10, true), OutputColorSpace::Hdr10);
+        assert_eq!(negotiate(OutputColorSpace::ScRgb, true), OutputColorSpace::ScRgb);
+        assert_eq!(negotiate(OutputColorSpace::Hdr10, false), OutputColorSpace::Srgb);
+        assert_eq!(negotiate(OutputColorSpace::Srgb, true), OutputColorSpace::Srgb);
+    }
+
+    #[test]
+    fn encode_srgb_clamps_and_applies_the_curve() {
+        let encoding = OutputEncoding::default();
+
+        let encoded = encode_color([0.0, 1.0, 2.0], &encoding);
+        assert_eq!(encoded[0], 0.0);
+        assert_close(encoded[1], 1.0, 1e-6);
+        assert_eq!(encoded[2], encoded[1]);
+        assert_close(encode_color([0.5, 0.5, 0.5], &encoding)[0], 0.7354, 1e-3);
+        assert_eq!(encode_color([-1.0, 0.0, 0.0], &encoding)[0], 0.0);
+    }
+
+    #[test]
+    fn encode_hdr10_puts_paper_white_at_its_luminance() {
+        let encoding = OutputEncoding { color_space: OutputColorSpace::Hdr10, paper_white_nits: 100.0, max_nits: 1000.0 };
+
+        for c in encode_color([1.0, 1.0, 1.0], &encoding) {
+            assert_close(c, pq_encode(100.0), 1e-4);
+        }
+        for c in encode_color([50.0, 50.0, 50.0], &encoding) {
+            assert_close(c, pq_encode(1000.0), 1e-6);
+        }
+        assert_eq!(encode_color([0.0, 0.0, 0.0], &encoding), [pq_encode(0.0); 3]);
+    }
+
+    #[test]
+    fn encode_scrgb_scales_to_80_nits() {
+        let encoding = OutputEncoding { color_space: OutputColorSpace::ScRgb, paper_white_nits: 160.0, max_nits: 400.0 };
+
+        assert_eq!(encode_color([1.0, 0.5, 0.0], &encoding), [2.0, 1.0, 0.0]);
+        assert_eq!(encode_color([10.0, 10.0, 10.0], &encoding), [5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn constants_match_the_shader_modes() {
+        let encoding = OutputEncoding { color_space: OutputColorSpace::ScRgb, paper_white_nits: 160.0, max_nits: 400.0 };
+
+        assert_eq!(encoding.constants(), OutputConstants { mode: 2, paper_white_nits: 160.0, max_nits: 400.0, _padding: 0.0 });
+        assert_eq!(OutputColorSpace::Srgb.shader_mode(), 0);
+        assert_eq!(OutputColorSpace::Hdr10.shader_mode(), 1);
+        assert_eq!(std::mem::size_of::<OutputConstants>(), 16);
+    }
+}
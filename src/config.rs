@@ -0,0 +1,668 @@
+//! Engine configuration from a TOML file with command line overrides.
+//!
+//! Precedence from lowest to highest: built in defaults, the config file, command line flags.
+//! Any key can be overridden with `--section.key=value` or `--section.key value`.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::adapter::AdapterPreference;
+use crate::color_space::OutputColorSpace;
+use crate::debug_layer::{DebugLayerSettings, MessageSeverity};
+use crate::depth_buffer::{DepthFormat, DepthSettings};
+use crate::display_mode::{DisplayMode, DisplaySettings, VideoMode};
+use crate::gamepad::{Deadzone, GamepadSettings};
+use crate::imgui_glfw_support::HiDpiMode;
+use crate::logging::{LogConfig, LogFilter};
+use crate::swap_chain::PresentConfig;
+
+/// Looked up in the working directory when no `--config` is given.
+pub const DEFAULT_CONFIG_PATH: &str = "engine.toml";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    pub window: WindowConfig,
+    pub ui: UiConfig,
+    pub graphics: GraphicsConfig,
+    pub log: LoggingConfig,
+    pub input: InputConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: String,
+    /// Client area size in pixels, 0 takes `screen_fraction` of the primary monitor.
+    pub width: u32,
+    pub height: u32,
+    pub screen_fraction: f32,
+    /// Saves the window's placement on exit and restores it on start, in place of the size above.
+    pub remember_placement: bool,
+    pub placement_file: PathBuf,
+    pub mode: DisplayMode,
+    /// Monitor to go fullscreen on by name, empty for the one the window is on.
+    pub monitor: String,
+    /// Exclusive fullscreen video mode, 0 keeps the desktop resolution or refresh rate.
+    pub fullscreen_width: u32,
+    pub fullscreen_height: u32,
+    pub refresh_rate: u32,
+    /// Shows the window, a hidden one still renders, e.g. to replay recorded input.
+    pub visible: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            title: "Test application!!!".to_string(),
+            width: 0,
+            height: 0,
+            screen_fraction: 0.8,
+            remember_placement: true,
+            placement_file: PathBuf::from("window_placement.toml"),
+            mode: DisplayMode::Windowed,
+            monitor: String::new(),
+            fullscreen_width: 0,
+            fullscreen_height: 0,
+            refresh_rate: 0,
+            visible: true,
+        }
+    }
+}
+
+impl WindowConfig {
+    /// The window size on a monitor of `monitor_size`.
+    pub fn size(&self, monitor_size: (u32, u32)) -> (u32, u32) {
+        let fraction = |monitor: u32| (monitor as f32 * self.screen_fraction).round() as u32;
+        (
+            if self.width > 0 { self.width } else { fraction(monitor_size.0) },
+            if self.height > 0 { self.height } else { fraction(monitor_size.1) },
+        )
+    }
+
+    pub fn display_settings(&self) -> DisplaySettings {
+        DisplaySettings {
+            mode: self.mode,
+            monitor: Some(self.monitor.clone()).filter(|monitor| !monitor.is_empty()),
+            video_mode: (self.fullscreen_width > 0).then_some(VideoMode {
+                width: self.fullscreen_width,
+                height: self.fullscreen_height,
+                refresh_rate: self.refresh_rate,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HiDpiSetting {
+    Default,
+    Rounded,
+    Locked,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    pub hidpi: HiDpiSetting,
+    /// Scale used when `hidpi` is `locked`.
+    pub locked_scale: f64,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig { hidpi: HiDpiSetting::Default, locked_scale: 1.0 }
+    }
+}
+
+impl UiConfig {
+    pub fn hidpi_mode(&self) -> HiDpiMode {
+        match self.hidpi {
+            HiDpiSetting::Default => HiDpiMode::Default,
+            HiDpiSetting::Rounded => HiDpiMode::Rounded,
+            HiDpiSetting::Locked => HiDpiMode::Locked(self.locked_scale),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphicsConfig {
+    /// `default`, `warp`, an adapter index or a part of its name.
+    pub adapter: String,
+    /// Defaults to whether the `debug-layer` feature is enabled.
+    pub debug_layer: Option<bool>,
+    pub debug_severity: MessageSeverity,
+    pub break_on: Option<MessageSeverity>,
+    /// 0 presents immediately, 1 waits for every vertical blank.
+    pub sync_interval: u32,
+    pub allow_tearing: bool,
+    pub buffer_count: u32,
+    pub max_frame_latency: u32,
+    pub color_space: OutputColorSpace,
+    pub depth_format: DepthFormat,
+    pub reversed_z: bool,
+}
+
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        let present = PresentConfig::default();
+        let depth = DepthSettings::default();
+        let debug_layer = DebugLayerSettings::default();
+
+        GraphicsConfig {
+            adapter: "default".to_string(),
+            debug_layer: None,
+            debug_severity: debug_layer.min_severity,
+            break_on: debug_layer.break_on,
+            sync_interval: present.sync_interval,
+            allow_tearing: present.allow_tearing,
+            buffer_count: present.buffer_count,
+            max_frame_latency: present.max_frame_latency,
+            color_space: present.color_space,
+            depth_format: depth.format,
+            reversed_z: depth.reversed_z,
+        }
+    }
+}
+
+impl GraphicsConfig {
+    pub fn adapter_preference(&self) -> AdapterPreference {
+        AdapterPreference::parse(&self.adapter)
+    }
+
+    pub fn debug_layer_settings(&self) -> DebugLayerSettings {
+        DebugLayerSettings {
+            enabled: self.debug_layer.unwrap_or(DebugLayerSettings::default().enabled),
+            min_severity: self.debug_severity,
+            break_on: self.break_on,
+        }
+    }
+
+    pub fn present_config(&self) -> PresentConfig {
+        PresentConfig {
+            sync_interval: self.sync_interval,
+            allow_tearing: self.allow_tearing,
+            buffer_count: self.buffer_count,
+            max_frame_latency: self.max_frame_latency,
+            color_space: self.color_space,
+        }
+    }
+
+    pub fn depth_settings(&self) -> DepthSettings {
+        DepthSettings { format: self.depth_format, reversed_z: self.reversed_z }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Levels per module, `info,window=warn`.
+    pub filter: String,
+    pub stdout: bool,
+    pub file: Option<PathBuf>,
+    pub max_file_bytes: u64,
+    pub max_files: u32,
+    pub history: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        let log = LogConfig::default();
+        LoggingConfig {
+            filter: log.filter,
+            stdout: log.stdout,
+            file: log.file,
+            max_file_bytes: log.max_file_bytes,
+            max_files: log.max_files,
+            history: log.history,
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn log_config(&self) -> LogConfig {
+        LogConfig {
+            filter: self.filter.clone(),
+            stdout: self.stdout,
+            file: self.file.clone(),
+            max_file_bytes: self.max_file_bytes,
+            max_files: self.max_files,
+            history: self.history,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Action bindings, changes made in the bindings window are saved here.
+    pub bindings_file: PathBuf,
+    /// Extra gamepad mappings in the SDL_GameControllerDB format.
+    pub gamepad_mappings: Option<PathBuf>,
+    /// Stick and trigger travel ignored around the rest position, from 0 to 1.
+    pub stick_deadzone: f32,
+    pub trigger_deadzone: f32,
+    /// Records the window events of every frame to this file.
+    pub record: Option<PathBuf>,
+    /// Replays window events recorded earlier in place of live input.
+    pub replay: Option<PathBuf>,
+    /// Closes the window once the replay has run out, always the case for a hidden window.
+    pub exit_after_replay: bool,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        let gamepad = GamepadSettings::default();
+        InputConfig {
+            bindings_file: PathBuf::from("bindings.toml"),
+            gamepad_mappings: None,
+            stick_deadzone: gamepad.stick_deadzone.inner,
+            trigger_deadzone: gamepad.trigger_deadzone.inner,
+            record: None,
+            replay: None,
+            exit_after_replay: false,
+        }
+    }
+}
+
+impl InputConfig {
+    pub fn gamepad_settings(&self) -> GamepadSettings {
+        GamepadSettings { stick_deadzone: Deadzone::new(self.stick_deadzone), trigger_deadzone: Deadzone::new(self.trigger_deadzone) }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    pub key: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(String),
+    /// A command line argument that is not a known flag, or a flag missing its value.
+    Argument(String),
+    Invalid(Vec<ValidationError>),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, error) => write!(f, "Failed to read {}: {}", path.display(), error),
+            ConfigError::Parse(message) => write!(f, "Invalid config: {}", message.trim_end()),
+            ConfigError::Argument(message) => write!(f, "Invalid argument: {}", message),
+            ConfigError::Invalid(errors) => {
+                write!(f, "Invalid config:")?;
+                for error in errors {
+                    write!(f, "\n  {}: {}", error.key, error.message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl EngineConfig {
+    /// Reads `path`, or [`DEFAULT_CONFIG_PATH`] when it exists, and applies `overrides`.
+    pub fn load(path: Option<&Path>, overrides: &[(String, String)]) -> Result<Self, ConfigError> {
+        let text = match path {
+            Some(path) => std::fs::read_to_string(path).map_err(|error| ConfigError::Io(path.to_path_buf(), error))?,
+            None => std::fs::read_to_string(DEFAULT_CONFIG_PATH).unwrap_or_default(),
+        };
+
+        Self::from_toml(&text, overrides)
+    }
+
+    /// Parses a config file's contents and applies `overrides` as `(section.key, value)` pairs on top.
+    pub fn from_toml(text: &str, overrides: &[(String, String)]) -> Result<Self, ConfigError> {
+        let mut table: toml::Table = toml::from_str(text).map_err(|error| ConfigError::Parse(error.to_string()))?;
+
+        let defaults = toml::Table::try_from(EngineConfig::default()).unwrap();
+        for (key, value) in overrides {
+            set_key(&mut table, &defaults, key, value)?;
+        }
+
+        let config: EngineConfig = table.try_into().map_err(|error: toml::de::Error| ConfigError::Parse(error.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        let mut check = |valid: bool, key: &'static str, message: String| {
+            if !valid {
+                errors.push(ValidationError { key, message });
+            }
+        };
+
+        const MAX_SIZE: u32 = 16384;
+        check(self.window.width <= MAX_SIZE, "window.width", format!("must be at most {}", MAX_SIZE));
+        check(self.window.height <= MAX_SIZE, "window.height", format!("must be at most {}", MAX_SIZE));
+        check(
+            self.window.screen_fraction > 0.0 && self.window.screen_fraction <= 1.0,
+            "window.screen_fraction",
+            "must be above 0 and at most 1".to_string(),
+        );
+        check(
+            (self.window.fullscreen_width == 0) == (self.window.fullscreen_height == 0),
+            "window.fullscreen_width",
+            "must be set together with window.fullscreen_height".to_string(),
+        );
+        check(self.ui.locked_scale > 0.0, "ui.locked_scale", "must be above 0".to_string());
+
+        let graphics = &self.graphics;
+        check(
+            graphics.sync_interval <= PresentConfig::MAX_SYNC_INTERVAL,
+            "graphics.sync_interval",
+            format!("must be at most {}", PresentConfig::MAX_SYNC_INTERVAL),
+        );
+        check(
+            (PresentConfig::MIN_BUFFER_COUNT..=PresentConfig::MAX_BUFFER_COUNT).contains(&graphics.buffer_count),
+            "graphics.buffer_count",
+            format!("must be between {} and {}", PresentConfig::MIN_BUFFER_COUNT, PresentConfig::MAX_BUFFER_COUNT),
+        );
+        check(
+            (1..=PresentConfig::MAX_FRAME_LATENCY).contains(&graphics.max_frame_latency),
+            "graphics.max_frame_latency",
+            format!("must be between 1 and {}", PresentConfig::MAX_FRAME_LATENCY),
+        );
+
+        for (deadzone, key) in [(self.input.stick_deadzone, "input.stick_deadzone"), (self.input.trigger_deadzone, "input.trigger_deadzone")] {
+            check((0.0..1.0).contains(&deadzone), key, "must be at least 0 and below 1".to_string());
+        }
+
+        check(
+            self.input.record.is_none() || self.input.record != self.input.replay,
+            "input.record",
+            "must not be the file being replayed".to_string(),
+        );
+
+        if let Err(message) = LogFilter::parse(&self.log.filter) {
+            check(false, "log.filter", message);
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(ConfigError::Invalid(errors)) }
+    }
+}
+
+/// Sets `section.key` in `table`. Values are parsed as TOML, except for keys whose default is a
+/// string, so `--graphics.adapter=0` stays the text `0`.
+fn set_key(table: &mut toml::Table, defaults: &toml::Table, key: &str, value: &str) -> Result<(), ConfigError> {
+    let Some((section, name)) = key.split_once('.') else {
+        return Err(ConfigError::Argument(format!("'{}' is not a section.key", key)));
+    };
+
+    let default = defaults.get(section).and_then(|section| section.get(name));
+    let value = match default {
+        Some(toml::Value::String(_)) => toml::Value::String(value.to_string()),
+        _ => parse_value(value),
+    };
+
+    let section = table
+        .entry(section)
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        .as_table_mut()
+        .ok_or_else(|| ConfigError::Argument(format!("'{}' is not a section", section)))?;
+    section.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// A TOML literal, or the text itself when it is not one.
+fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+/// What the command line asks for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandLine {
+    pub config_path: Option<PathBuf>,
+    /// `(section.key, value)` in the order given, later ones win.
+    pub overrides: Vec<(String, String)>,
+    pub list_adapters: bool,
+}
+
+impl CommandLine {
+    /// Parses the arguments after the program name. Besides `--section.key` the older short flags
+    /// are accepted: `--adapter`, `--warp`, `--debug-layer`, `--no-debug-layer`, `--debug-severity`,
+    /// `--break-on`, `--log`, `--log-file`, `--no-stdout-log`, `--record`, `--replay` and `--headless`.
+    pub fn parse(args: &[String]) -> Result<Self, ConfigError> {
+        let mut command_line = CommandLine::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                return Err(ConfigError::Argument(arg.clone()));
+            };
+            let mut value = |flag: &str| args.next().cloned().ok_or_else(|| ConfigError::Argument(format!("--{} needs a value", flag)));
+
+            let (key, value) = match flag {
+                "config" => {
+                    command_line.config_path = Some(value(flag)?.into());
+                    continue;
+                }
+                "list-adapters" => {
+                    command_line.list_adapters = true;
+                    continue;
+                }
+                "adapter" => ("graphics.adapter", value(flag)?),
+                "warp" => ("graphics.adapter", "warp".to_string()),
+                "debug-layer" => ("graphics.debug_layer", "true".to_string()),
+                "no-debug-layer" => ("graphics.debug_layer", "false".to_string()),
+                "debug-severity" => ("graphics.debug_severity", severity(flag, value(flag)?)?),
+                "break-on" => ("graphics.break_on", severity(flag, value(flag)?)?),
+                "log" => ("log.filter", value(flag)?),
+                "log-file" => ("log.file", value(flag)?),
+                "no-stdout-log" => ("log.stdout", "false".to_string()),
+                "record" => ("input.record", value(flag)?),
+                "replay" => ("input.replay", value(flag)?),
+                "headless" => ("window.visible", "false".to_string()),
+                flag if flag.contains('.') => match flag.split_once('=') {
+                    Some((key, inline)) => (key, inline.to_string()),
+                    None => (flag, value(flag)?),
+                },
+                _ => return Err(ConfigError::Argument(arg.clone())),
+            };
+
+            command_line.overrides.push((key.to_string(), value));
+        }
+
+        Ok(command_line)
+    }
+}
+
+/// Accepts a debug layer severity in any case, the config file only takes the lowercase names.
+fn severity(flag: &str, value: String) -> Result<String, ConfigError> {
+    MessageSeverity::parse(&value)
+        .map(|severity| severity.name().to_string())
+        .ok_or_else(|| ConfigError::Argument(format!("--{} {} is not a debug layer severity", flag, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn overrides(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn invalid_keys(error: ConfigError) -> Vec<&'static str> {
+        match error {
+            ConfigError::Invalid(errors) => errors.into_iter().map(|error| error.key).collect(),
+            error => panic!("expected a validation error, got {}", error),
+        }
+    }
+
+    #[test]
+    fn empty_file_gives_the_defaults() {
+        assert_eq!(EngineConfig::from_toml("", &[]).unwrap(), EngineConfig::default());
+        assert!(EngineConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn file_overrides_defaults() {
+        let config = EngineConfig::from_toml("[graphics]\nsync_interval = 0\nadapter = \"warp\"\n\n[window]\nwidth = 1280\n", &[]).unwrap();
+
+        assert_eq!(config.graphics.sync_interval, 0);
+        assert_eq!(config.graphics.adapter_preference(), AdapterPreference::Warp);
+        assert_eq!(config.window.width, 1280);
+        // Keys the file leaves out keep their defaults, also within a section it mentions.
+        assert_eq!(config.window.screen_fraction, WindowConfig::default().screen_fraction);
+        assert_eq!(config.graphics.buffer_count, GraphicsConfig::default().buffer_count);
+        assert_eq!(config.log, LoggingConfig::default());
+    }
+
+    #[test]
+    fn command_line_overrides_file() {
+        let file = "[graphics]\nsync_interval = 0\nbuffer_count = 3\n";
+        let config = EngineConfig::from_toml(file, &overrides(&[("graphics.sync_interval", "2"), ("window.visible", "false")])).unwrap();
+
+        assert_eq!(config.graphics.sync_interval, 2);
+        assert_eq!(config.graphics.buffer_count, 3);
+        assert!(!config.window.visible);
+    }
+
+    #[test]
+    fn later_overrides_win() {
+        let config = EngineConfig::from_toml("", &overrides(&[("graphics.adapter", "1"), ("graphics.adapter", "warp")])).unwrap();
+        assert_eq!(config.graphics.adapter, "warp");
+    }
+
+    #[test]
+    fn string_keys_keep_numbers_as_text() {
+        let config = EngineConfig::from_toml("", &overrides(&[("graphics.adapter", "0"), ("window.title", "true")])).unwrap();
+
+        assert_eq!(config.graphics.adapter_preference(), AdapterPreference::Index(0));
+        assert_eq!(config.window.title, "true");
+    }
+
+    #[test]
+    fn overrides_parse_enums_and_options() {
+        let config = EngineConfig::from_toml(
+            "",
+            &overrides(&[
+                ("graphics.color_space", "hdr10"),
+                ("graphics.break_on", "error"),
+                ("graphics.depth_format", "d24_unorm_s8_uint"),
+                ("window.mode", "borderless"),
+                ("input.record", "frames.log"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.graphics.present_config().color_space, OutputColorSpace::Hdr10);
+        assert_eq!(config.graphics.debug_layer_settings().break_on, Some(MessageSeverity::Error));
+        assert_eq!(config.graphics.depth_settings().format, DepthFormat::D24UnormS8Uint);
+        assert_eq!(config.window.display_settings().mode, DisplayMode::Borderless);
+        assert_eq!(config.input.record, Some(PathBuf::from("frames.log")));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(matches!(EngineConfig::from_toml("[graphics]\nvsync = true\n", &[]), Err(ConfigError::Parse(_))));
+        assert!(matches!(EngineConfig::from_toml("[audio]\nvolume = 1\n", &[]), Err(ConfigError::Parse(_))));
+        assert!(matches!(EngineConfig::from_toml("", &overrides(&[("graphics.vsync", "true")])), Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        assert!(matches!(EngineConfig::from_toml("[graphics\n", &[]), Err(ConfigError::Parse(_))));
+        assert!(matches!(EngineConfig::from_toml("[graphics]\nsync_interval = \"fast\"\n", &[]), Err(ConfigError::Parse(_))));
+        assert!(matches!(EngineConfig::from_toml("", &overrides(&[("adapter", "1")])), Err(ConfigError::Argument(_))));
+        assert!(matches!(EngineConfig::from_toml("window = 1\n", &overrides(&[("window.width", "1")])), Err(ConfigError::Argument(_))));
+    }
+
+    #[test]
+    fn validation_reports_every_invalid_key() {
+        let file = "[graphics]\nsync_interval = 5\nbuffer_count = 1\nmax_frame_latency = 0\n\n[window]\nscreen_fraction = 0.0\nfullscreen_width = 1920\n";
+        let keys = invalid_keys(EngineConfig::from_toml(file, &[]).unwrap_err());
+
+        assert_eq!(
+            keys,
+            ["window.screen_fraction", "window.fullscreen_width", "graphics.sync_interval", "graphics.buffer_count", "graphics.max_frame_latency"]
+        );
+    }
+
+    #[test]
+    fn validation_checks_input_and_logging() {
+        let keys = invalid_keys(
+            EngineConfig::from_toml(
+                "",
+                &overrides(&[
+                    ("input.stick_deadzone", "1.0"),
+                    ("input.record", "same.log"),
+                    ("input.replay", "same.log"),
+                    ("log.filter", "loud"),
+                    ("ui.locked_scale", "0.0"),
+                ]),
+            )
+            .unwrap_err(),
+        );
+
+        assert_eq!(keys, ["ui.locked_scale", "input.stick_deadzone", "input.record", "log.filter"]);
+    }
+
+    #[test]
+    fn validation_error_lists_keys_and_messages() {
+        let error = EngineConfig::from_toml("", &overrides(&[("window.width", "20000")])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid config:\n  window.width: must be at most 16384");
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let path = Path::new("does/not/exist.toml");
+        assert!(matches!(EngineConfig::load(Some(path), &[]), Err(ConfigError::Io(..))));
+    }
+
+    #[test]
+    fn command_line_flags() {
+        let command_line = CommandLine::parse(&args(&[
+            "--config", "custom.toml", "--warp", "--graphics.sync_interval=0", "--window.width", "800", "--log", "debug", "--list-adapters",
+        ]))
+        .unwrap();
+
+        assert_eq!(command_line.config_path, Some(PathBuf::from("custom.toml")));
+        assert!(command_line.list_adapters);
+        assert_eq!(
+            command_line.overrides,
+            overrides(&[("graphics.adapter", "warp"), ("graphics.sync_interval", "0"), ("window.width", "800"), ("log.filter", "debug")])
+        );
+    }
+
+    #[test]
+    fn command_line_errors() {
+        assert!(matches!(CommandLine::parse(&args(&["config.toml"])), Err(ConfigError::Argument(_))));
+        assert!(matches!(CommandLine::parse(&args(&["--fullscreen"])), Err(ConfigError::Argument(_))));
+        assert!(matches!(CommandLine::parse(&args(&["--adapter"])), Err(ConfigError::Argument(_))));
+        assert!(matches!(CommandLine::parse(&args(&["--window.width"])), Err(ConfigError::Argument(_))));
+        assert!(matches!(CommandLine::parse(&args(&["--break-on", "fatal"])), Err(ConfigError::Argument(_))));
+    }
+
+    #[test]
+    fn severity_flags_take_any_case() {
+        let command_line = CommandLine::parse(&args(&["--debug-severity", "Info", "--break-on", "ERROR"])).unwrap();
+        assert_eq!(command_line.overrides, overrides(&[("graphics.debug_severity", "info"), ("graphics.break_on", "error")]));
+
+        let config = EngineConfig::from_toml("", &command_line.overrides).unwrap();
+        assert_eq!(config.graphics.debug_layer_settings().min_severity, MessageSeverity::Info);
+        assert_eq!(config.graphics.debug_layer_settings().break_on, Some(MessageSeverity::Error));
+    }
+
+    #[test]
+    fn window_size_from_the_monitor() {
+        let window = WindowConfig::default();
+        assert_eq!(window.size((1920, 1080)), (1536, 864));
+        assert_eq!(WindowConfig { width: 800, ..window }.size((1920, 1080)), (800, 864));
+    }
+}
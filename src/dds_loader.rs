@@ -0,0 +1,379 @@
+//! DirectDraw Surface (`.dds`) container parsing, including the DX10 extension header.
+
+use std::io::Write;
+
+use crate::texture::{TextureData, TextureFormat};
+use crate::texture_loader::TextureError;
+
+pub const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+const HEADER_SIZE: usize = 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+const DDSD_DEPTH: u32 = 0x800000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xfc00;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+const DX10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const DX10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+const DX10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+fn four_cc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// `DXGI_FORMAT` values of the formats the engine knows.
+const DXGI_FORMATS: [(u32, TextureFormat); 28] = [
+    (2, TextureFormat::Rgba32Float),
+    (10, TextureFormat::Rgba16Float),
+    (11, TextureFormat::Rgba16Unorm),
+    (24, TextureFormat::Rgb10A2Unorm),
+    (26, TextureFormat::Rg11B10Float),
+    (28, TextureFormat::Rgba8Unorm),
+    (29, TextureFormat::Rgba8UnormSrgb),
+    (34, TextureFormat::Rg16Float),
+    (41, TextureFormat::R32Float),
+    (49, TextureFormat::Rg8Unorm),
+    (54, TextureFormat::R16Float),
+    (61, TextureFormat::R8Unorm),
+    (71, TextureFormat::Bc1Unorm),
+    (72, TextureFormat::Bc1UnormSrgb),
+    (74, TextureFormat::Bc2Unorm),
+    (75, TextureFormat::Bc2UnormSrgb),
+    (77, TextureFormat::Bc3Unorm),
+    (78, TextureFormat::Bc3UnormSrgb),
+    (80, TextureFormat::Bc4Unorm),
+    (81, TextureFormat::Bc4Snorm),
+    (83, TextureFormat::Bc5Unorm),
+    (84, TextureFormat::Bc5Snorm),
+    (87, TextureFormat::Bgra8Unorm),
+    (91, TextureFormat::Bgra8UnormSrgb),
+    (95, TextureFormat::Bc6hUfloat),
+    (96, TextureFormat::Bc6hSfloat),
+    (98, TextureFormat::Bc7Unorm),
+    (99, TextureFormat::Bc7UnormSrgb),
+];
+
+/// Maps a `DXGI_FORMAT` value stored in a DX10 header.
+pub fn format_from_dxgi(dxgi_format: u32) -> Option<TextureFormat> {
+    DXGI_FORMATS.iter().find(|(value, _)| *value == dxgi_format).map(|(_, format)| *format)
+}
+
+pub fn dxgi_from_format(format: TextureFormat) -> u32 {
+    DXGI_FORMATS.iter().find(|(_, known)| *known == format).map(|(value, _)| *value).unwrap()
+}
+
+/// Maps the legacy `DDS_PIXELFORMAT` of files written without a DX10 header.
+fn format_from_pixel_format(pixel_format: &[u8]) -> Option<TextureFormat> {
+    let flags = read_u32(pixel_format, 4);
+    let code = read_u32(pixel_format, 8);
+    let bit_count = read_u32(pixel_format, 12);
+    let masks = [
+        read_u32(pixel_format, 16),
+        read_u32(pixel_format, 20),
+        read_u32(pixel_format, 24),
+        read_u32(pixel_format, 28),
+    ];
+
+    if flags & DDPF_FOURCC != 0 {
+        return Some(match code {
+            _ if code == four_cc(b"DXT1") => TextureFormat::Bc1Unorm,
+            _ if code == four_cc(b"DXT2") || code == four_cc(b"DXT3") => TextureFormat::Bc2Unorm,
+            _ if code == four_cc(b"DXT4") || code == four_cc(b"DXT5") => TextureFormat::Bc3Unorm,
+            _ if code == four_cc(b"ATI1") || code == four_cc(b"BC4U") => TextureFormat::Bc4Unorm,
+            _ if code == four_cc(b"BC4S") => TextureFormat::Bc4Snorm,
+            _ if code == four_cc(b"ATI2") || code == four_cc(b"BC5U") => TextureFormat::Bc5Unorm,
+            _ if code == four_cc(b"BC5S") => TextureFormat::Bc5Snorm,
+            // D3DFORMAT values stored directly in the four character code.
+            36 => TextureFormat::Rgba16Unorm,
+            111 => TextureFormat::R16Float,
+            112 => TextureFormat::Rg16Float,
+            113 => TextureFormat::Rgba16Float,
+            114 => TextureFormat::R32Float,
+            116 => TextureFormat::Rgba32Float,
+            _ => return None,
+        });
+    }
+
+    let has_alpha = flags & DDPF_ALPHAPIXELS != 0;
+    match (flags & (DDPF_RGB | DDPF_LUMINANCE), bit_count, masks) {
+        (DDPF_RGB, 32, [0xff, 0xff00, 0xff0000, alpha]) if alpha == 0xff000000 || !has_alpha => {
+            Some(TextureFormat::Rgba8Unorm)
+        }
+        (DDPF_RGB, 32, [0xff0000, 0xff00, 0xff, alpha]) if alpha == 0xff000000 || !has_alpha => {
+            Some(TextureFormat::Bgra8Unorm)
+        }
+        (DDPF_RGB, 32, [0x3ff, 0xffc00, 0x3ff00000, 0xc0000000]) => Some(TextureFormat::Rgb10A2Unorm),
+        (DDPF_LUMINANCE, 8, [0xff, 0, 0, 0]) => Some(TextureFormat::R8Unorm),
+        (DDPF_LUMINANCE, 16, [0xff, 0, 0, 0xff00]) => Some(TextureFormat::Rg8Unorm),
+        _ => None,
+    }
+}
+
+/// Parses a DDS file, the subresources are stored in Direct3D order already so the data is copied as is.
+pub fn parse_dds(bytes: &[u8]) -> Result<TextureData, TextureError> {
+    if bytes.len() < 4 + HEADER_SIZE || bytes[0..4] != DDS_MAGIC {
+        return Err(TextureError::Decode("not a DDS file".into()));
+    }
+
+    let header = &bytes[4..4 + HEADER_SIZE];
+    if read_u32(header, 0) as usize != HEADER_SIZE {
+        return Err(TextureError::Decode("invalid DDS header size".into()));
+    }
+
+    let flags = read_u32(header, 4);
+    let height = read_u32(header, 8);
+    let width = read_u32(header, 12);
+    let mip_levels = if flags & DDSD_MIPMAPCOUNT != 0 { read_u32(header, 24).max(1) } else { 1 };
+    let pixel_format = &header[72..104];
+    let caps2 = read_u32(header, 108);
+
+    if width == 0 || height == 0 {
+        return Err(TextureError::Decode("DDS texture has no size".into()));
+    }
+
+    let mut data_offset = 4 + HEADER_SIZE;
+    let (format, array_layers, is_cube) = if read_u32(pixel_format, 8) == four_cc(b"DX10") {
+        let dx10 = bytes.get(data_offset..data_offset + DX10_HEADER_SIZE)
+            .ok_or_else(|| TextureError::Decode("truncated DX10 header".into()))?;
+        data_offset += DX10_HEADER_SIZE;
+
+        let dxgi_format = read_u32(dx10, 0);
+        let format = format_from_dxgi(dxgi_format)
+            .ok_or_else(|| TextureError::Unsupported(format!("DXGI format {}", dxgi_format)))?;
+
+        match read_u32(dx10, 4) {
+            DX10_RESOURCE_DIMENSION_TEXTURE2D => (),
+            DX10_RESOURCE_DIMENSION_TEXTURE3D => return Err(TextureError::Unsupported("volume textures".into())),
+            _ => return Err(TextureError::Unsupported("DDS resource dimension".into())),
+        }
+
+        let is_cube = read_u32(dx10, 8) & DX10_RESOURCE_MISC_TEXTURECUBE != 0;
+        let array_size = read_u32(dx10, 12).max(1);
+        let array_layers = if is_cube { array_size.checked_mul(6) } else { Some(array_size) }
+            .ok_or_else(|| TextureError::Decode(format!("DDS array size {} is too large", array_size)))?;
+        (format, array_layers, is_cube)
+    } else {
+        if flags & DDSD_DEPTH != 0 || caps2 & DDSCAPS2_VOLUME != 0 {
+            return Err(TextureError::Unsupported("volume textures".into()));
+        }
+
+        let format = format_from_pixel_format(pixel_format)
+            .ok_or_else(|| TextureError::Unsupported("DDS pixel format".into()))?;
+
+        let is_cube = caps2 & DDSCAPS2_CUBEMAP != 0;
+        if is_cube && caps2 & DDSCAPS2_CUBEMAP_ALLFACES != DDSCAPS2_CUBEMAP_ALLFACES {
+            return Err(TextureError::Unsupported("cube maps with missing faces".into()));
+        }
+        (format, if is_cube { 6 } else { 1 }, is_cube)
+    };
+
+    // Check the header against the file size before allocating anything for it.
+    let size = TextureData::packed_size(format, width, height, mip_levels, array_layers)?;
+    if size > bytes.len() - data_offset {
+        return Err(TextureError::Decode("truncated DDS pixel data".into()));
+    }
+    let data = &bytes[data_offset..data_offset + size];
+    let (subresources, _) = TextureData::packed_subresources(format, width, height, mip_levels, array_layers)?;
+
+    Ok(TextureData {
+        format,
+        width,
+        height,
+        mip_levels,
+        array_layers,
+        is_cube,
+        subresources,
+        data: data.to_vec(),
+    })
+}
+
+/// Writes `texture` with a DX10 header, which can describe every [`TextureFormat`].
+pub fn write_dds(writer: &mut impl Write, texture: &TextureData) -> Result<(), TextureError> {
+    let mut header = [0u32; HEADER_SIZE / 4];
+    header[0] = HEADER_SIZE as u32;
+    header[1] = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT | DDSD_LINEARSIZE;
+    header[2] = texture.height;
+    header[3] = texture.width;
+    header[4] = texture.subresources[0].slice_pitch;
+    header[6] = texture.mip_levels;
+    // DDS_PIXELFORMAT
+    header[18] = 32;
+    header[19] = DDPF_FOURCC;
+    header[20] = four_cc(b"DX10");
+    header[26] = DDSCAPS_TEXTURE
+        | if texture.mip_levels > 1 { DDSCAPS_COMPLEX | DDSCAPS_MIPMAP } else { 0 }
+        | if texture.is_cube || texture.array_layers > 1 { DDSCAPS_COMPLEX } else { 0 };
+    header[27] = if texture.is_cube { DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALLFACES } else { 0 };
+
+    let dx10 = [
+        dxgi_from_format(texture.format),
+        DX10_RESOURCE_DIMENSION_TEXTURE2D,
+        if texture.is_cube { DX10_RESOURCE_MISC_TEXTURECUBE } else { 0 },
+        if texture.is_cube { texture.array_layers / 6 } else { texture.array_layers },
+        0,
+    ];
+
+    writer.write_all(&DDS_MAGIC)?;
+    for value in header.iter().chain(&dx10) {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    // Subresources may have padding between them, write only their contents.
+    for layer in 0..texture.array_layers {
+        for level in 0..texture.mip_levels {
+            writer.write_all(texture.subresource_data(level, layer))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(format: TextureFormat, width: u32, height: u32, mip_levels: u32, array_layers: u32, is_cube: bool) -> TextureData {
+        let (subresources, size) = TextureData::packed_subresources(format, width, height, mip_levels, array_layers).unwrap();
+        TextureData {
+            format,
+            width,
+            height,
+            mip_levels,
+            array_layers,
+            is_cube,
+            subresources,
+            data: (0..size).map(|index| index as u8).collect(),
+        }
+    }
+
+    fn write(texture: &TextureData) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_dds(&mut bytes, texture).unwrap();
+        bytes
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Offsets into the file, including the magic.
+    const MIP_COUNT: usize = 4 + 24;
+    const FOUR_CC: usize = 4 + 80;
+    const DX10_ARRAY_SIZE: usize = 4 + HEADER_SIZE + 12;
+
+    fn decode_error(bytes: &[u8]) -> String {
+        match parse_dds(bytes) {
+            Err(error) => error.to_string(),
+            Ok(_) => panic!("invalid DDS file was accepted"),
+        }
+    }
+
+    #[test]
+    fn round_trip_dx10() {
+        for original in [
+            texture(TextureFormat::Rgba8UnormSrgb, 8, 4, 4, 1, false),
+            texture(TextureFormat::Bc7Unorm, 16, 16, 5, 3, false),
+            texture(TextureFormat::Bc1Unorm, 8, 8, 4, 12, true),
+            texture(TextureFormat::Rgba16Float, 3, 5, 1, 1, false),
+        ] {
+            let parsed = parse_dds(&write(&original)).unwrap();
+            assert_eq!(parsed, original);
+        }
+    }
+
+    #[test]
+    fn legacy_pixel_formats() {
+        let original = texture(TextureFormat::Bc3Unorm, 8, 8, 1, 1, false);
+        let mut bytes = write(&original);
+        // Drop the DX10 header and describe the format with a four character code instead.
+        set_u32(&mut bytes, FOUR_CC, four_cc(b"DXT5"));
+        bytes.drain(4 + HEADER_SIZE..4 + HEADER_SIZE + DX10_HEADER_SIZE);
+        assert_eq!(parse_dds(&bytes).unwrap(), original);
+
+        let original = texture(TextureFormat::Bgra8Unorm, 2, 2, 1, 1, false);
+        let mut bytes = write(&original);
+        let pixel_format = 4 + 72;
+        set_u32(&mut bytes, pixel_format + 4, DDPF_RGB | DDPF_ALPHAPIXELS);
+        set_u32(&mut bytes, pixel_format + 8, 0);
+        set_u32(&mut bytes, pixel_format + 12, 32);
+        for (index, mask) in [0xff0000, 0xff00, 0xff, 0xff000000].into_iter().enumerate() {
+            set_u32(&mut bytes, pixel_format + 16 + index * 4, mask);
+        }
+        bytes.drain(4 + HEADER_SIZE..4 + HEADER_SIZE + DX10_HEADER_SIZE);
+        assert_eq!(parse_dds(&bytes).unwrap(), original);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let bytes = write(&texture(TextureFormat::Rgba8Unorm, 4, 4, 1, 1, false));
+        assert!(decode_error(&bytes[..100]).contains("not a DDS file"));
+        assert!(decode_error(b"XXXX").contains("not a DDS file"));
+
+        let mut bad = bytes.clone();
+        set_u32(&mut bad, 4, 100);
+        assert!(decode_error(&bad).contains("header size"));
+
+        let mut bad = bytes.clone();
+        set_u32(&mut bad, 4 + 12, 0);
+        assert!(decode_error(&bad).contains("no size"));
+
+        let mut bad = bytes.clone();
+        set_u32(&mut bad, 4 + HEADER_SIZE, 1234);
+        assert!(matches!(parse_dds(&bad), Err(TextureError::Unsupported(_))));
+
+        assert!(decode_error(&bytes[..bytes.len() - 1]).contains("truncated"));
+        assert!(decode_error(&bytes[..4 + HEADER_SIZE + 4]).contains("truncated DX10 header"));
+    }
+
+    #[test]
+    fn rejects_mip_counts_above_the_full_chain() {
+        let mut bytes = write(&texture(TextureFormat::Rgba8Unorm, 4, 4, 3, 1, false));
+        set_u32(&mut bytes, MIP_COUNT, 4);
+        assert!(decode_error(&bytes).contains("mip levels"));
+        for mip_levels in [33, 64, u32::MAX] {
+            set_u32(&mut bytes, MIP_COUNT, mip_levels);
+            assert!(decode_error(&bytes).contains("mip levels"));
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_arrays_without_allocating() {
+        let mut bytes = write(&texture(TextureFormat::Rgba8Unorm, 4, 4, 1, 6, true));
+        // array_size * 6 overflows 32 bits.
+        set_u32(&mut bytes, DX10_ARRAY_SIZE, u32::MAX / 3);
+        assert!(decode_error(&bytes).contains("too large"));
+
+        // Fits, but the file holds far less data than the header claims.
+        set_u32(&mut bytes, DX10_ARRAY_SIZE, u32::MAX / 6);
+        assert!(decode_error(&bytes).contains("truncated"));
+    }
+
+    #[test]
+    fn rejects_dimensions_that_overflow() {
+        let mut bytes = write(&texture(TextureFormat::Rgba32Float, 1, 1, 1, 1, false));
+        set_u32(&mut bytes, 4 + 12, u32::MAX);
+        assert!(decode_error(&bytes).contains("too large"));
+    }
+}
@@ -0,0 +1,230 @@
+//! D3D11 debug layer setup, forwarding of its info queue messages and the live object report.
+
+use serde::{Deserialize, Serialize};
+use windows::{core::*, Win32::Graphics::Direct3D11::*};
+
+/// Severity of a debug layer message, ordered from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageSeverity {
+    Message,
+    Info,
+    Warning,
+    Error,
+    Corruption,
+}
+
+impl MessageSeverity {
+    pub const ALL: [MessageSeverity; 5] = [
+        MessageSeverity::Message,
+        MessageSeverity::Info,
+        MessageSeverity::Warning,
+        MessageSeverity::Error,
+        MessageSeverity::Corruption,
+    ];
+
+    pub fn from_d3d11(severity: D3D11_MESSAGE_SEVERITY) -> Self {
+        match severity {
+            D3D11_MESSAGE_SEVERITY_CORRUPTION => MessageSeverity::Corruption,
+            D3D11_MESSAGE_SEVERITY_ERROR => MessageSeverity::Error,
+            D3D11_MESSAGE_SEVERITY_WARNING => MessageSeverity::Warning,
+            D3D11_MESSAGE_SEVERITY_INFO => MessageSeverity::Info,
+            _ => MessageSeverity::Message,
+        }
+    }
+
+    pub fn to_d3d11(self) -> D3D11_MESSAGE_SEVERITY {
+        match self {
+            MessageSeverity::Corruption => D3D11_MESSAGE_SEVERITY_CORRUPTION,
+            MessageSeverity::Error => D3D11_MESSAGE_SEVERITY_ERROR,
+            MessageSeverity::Warning => D3D11_MESSAGE_SEVERITY_WARNING,
+            MessageSeverity::Info => D3D11_MESSAGE_SEVERITY_INFO,
+            MessageSeverity::Message => D3D11_MESSAGE_SEVERITY_MESSAGE,
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "message" => Some(MessageSeverity::Message),
+            "info" => Some(MessageSeverity::Info),
+            "warning" => Some(MessageSeverity::Warning),
+            "error" => Some(MessageSeverity::Error),
+            "corruption" => Some(MessageSeverity::Corruption),
+            _ => None,
+        }
+    }
+
+    pub fn log_level(self) -> log::Level {
+        match self {
+            MessageSeverity::Message => log::Level::Debug,
+            MessageSeverity::Info => log::Level::Info,
+            MessageSeverity::Warning => log::Level::Warn,
+            MessageSeverity::Error | MessageSeverity::Corruption => log::Level::Error,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageSeverity::Message => "message",
+            MessageSeverity::Info => "info",
+            MessageSeverity::Warning => "warning",
+            MessageSeverity::Error => "error",
+            MessageSeverity::Corruption => "corruption",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DebugLayerSettings {
+    /// Creates the device with `D3D11_CREATE_DEVICE_DEBUG`. Needs the Graphics Tools optional feature installed.
+    pub enabled: bool,
+    /// Messages less severe than this are dropped.
+    pub min_severity: MessageSeverity,
+    /// Breaks into an attached debugger on messages this severe or worse.
+    pub break_on: Option<MessageSeverity>,
+}
+
+impl Default for DebugLayerSettings {
+    fn default() -> Self {
+        DebugLayerSettings {
+            enabled: cfg!(feature = "debug-layer"),
+            min_severity: MessageSeverity::Warning,
+            break_on: None,
+        }
+    }
+}
+
+impl DebugLayerSettings {
+    pub fn device_flags(&self) -> D3D11_CREATE_DEVICE_FLAG {
+        if self.enabled { D3D11_CREATE_DEVICE_DEBUG } else { D3D11_CREATE_DEVICE_FLAG(0) }
+    }
+
+    pub fn should_log(&self, severity: MessageSeverity) -> bool {
+        severity >= self.min_severity
+    }
+
+    pub fn should_break(&self, severity: MessageSeverity) -> bool {
+        self.break_on.is_some_and(|break_on| severity >= break_on)
+    }
+}
+
+pub struct DebugLayer {
+    info_queue: ID3D11InfoQueue,
+    debug: ID3D11Debug,
+    settings: DebugLayerSettings,
+}
+
+impl DebugLayer {
+    /// Hooks into the debug layer of `device`, `None` when it was created without one.
+    pub fn new(device: &ID3D11Device, settings: DebugLayerSettings) -> Option<Self> {
+        let debug: ID3D11Debug = device.cast().ok()?;
+        let info_queue: ID3D11InfoQueue = device.cast().ok()?;
+
+        for severity in MessageSeverity::ALL {
+            unsafe { info_queue.SetBreakOnSeverity(severity.to_d3d11(), settings.should_break(severity)).ok()? };
+        }
+
+        Some(DebugLayer { info_queue, debug, settings })
+    }
+
+    /// Logs the messages stored since the last call and clears the queue.
+    pub fn flush_messages(&self) {
+        unsafe {
+            let count = self.info_queue.GetNumStoredMessagesAllowedByRetrievalFilter();
+
+            for index in 0..count {
+                let mut length = 0;
+                if self.info_queue.GetMessage(index, None, &mut length).is_err() {
+                    continue;
+                }
+
+                // The message is followed by its description in the same allocation, keep it aligned.
+                let mut buffer = vec![0u64; length.div_ceil(8)];
+                let message = buffer.as_mut_ptr() as *mut D3D11_MESSAGE;
+                if self.info_queue.GetMessage(index, Some(message), &mut length).is_err() {
+                    continue;
+                }
+
+                let message = &*message;
+                let severity = MessageSeverity::from_d3d11(message.Severity);
+                if !self.settings.should_log(severity) {
+                    continue;
+                }
+
+                // The length includes the terminating nul.
+                let description = std::slice::from_raw_parts(message.pDescription, message.DescriptionByteLength.saturating_sub(1));
+                log::log!(target: "d3d11", severity.log_level(), "#{}: {}", message.ID.0, String::from_utf8_lossy(description));
+            }
+
+            self.info_queue.ClearStoredMessages();
+        }
+    }
+
+    /// Lists the device objects that are still alive. Call it after everything else was released,
+    /// the device itself shows up with the one reference held by the debug interface.
+    pub fn report_live_objects(&self) {
+        let flags = D3D11_RLDO_FLAGS(D3D11_RLDO_DETAIL.0 | D3D11_RLDO_IGNORE_INTERNAL.0);
+        if let Err(error) = unsafe { self.debug.ReportLiveDeviceObjects(flags) } {
+            log::error!("Failed to report the live device objects: {}", error);
+        }
+
+        // The report is emitted as info queue messages.
+        self.flush_messages();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severity_names_round_trip() {
+        for severity in MessageSeverity::ALL {
+            assert_eq!(MessageSeverity::parse(severity.name()), Some(severity));
+            assert_eq!(MessageSeverity::parse(&severity.name().to_uppercase()), Some(severity));
+        }
+        assert_eq!(MessageSeverity::parse("Warning"), Some(MessageSeverity::Warning));
+        assert_eq!(MessageSeverity::parse("warn"), None);
+        assert_eq!(MessageSeverity::parse(""), None);
+    }
+
+    #[test]
+    fn severity_d3d11_round_trip() {
+        for severity in MessageSeverity::ALL {
+            assert_eq!(MessageSeverity::from_d3d11(severity.to_d3d11()), severity);
+        }
+        assert_eq!(MessageSeverity::Corruption.to_d3d11(), D3D11_MESSAGE_SEVERITY_CORRUPTION);
+        assert_eq!(MessageSeverity::Message.to_d3d11(), D3D11_MESSAGE_SEVERITY_MESSAGE);
+        // Unknown values from newer runtimes are the least severe.
+        assert_eq!(MessageSeverity::from_d3d11(D3D11_MESSAGE_SEVERITY(42)), MessageSeverity::Message);
+    }
+
+    #[test]
+    fn severities_are_ordered() {
+        assert!(MessageSeverity::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn log_threshold() {
+        let settings = DebugLayerSettings { enabled: true, min_severity: MessageSeverity::Warning, break_on: None };
+
+        assert!(!settings.should_log(MessageSeverity::Message));
+        assert!(!settings.should_log(MessageSeverity::Info));
+        assert!(settings.should_log(MessageSeverity::Warning));
+        assert!(settings.should_log(MessageSeverity::Corruption));
+
+        let everything = DebugLayerSettings { min_severity: MessageSeverity::Message, ..settings };
+        assert!(MessageSeverity::ALL.into_iter().all(|severity| everything.should_log(severity)));
+    }
+
+    #[test]
+    fn break_threshold() {
+        let settings = DebugLayerSettings { enabled: true, min_severity: MessageSeverity::Warning, break_on: None };
+        assert!(!MessageSeverity::ALL.into_iter().any(|severity| settings.should_break(severity)));
+
+        let on_error = DebugLayerSettings { break_on: Some(MessageSeverity::Error), ..settings };
+        assert!(!on_error.should_break(MessageSeverity::Warning));
+        assert!(on_error.should_break(MessageSeverity::Error));
+        assert!(on_error.should_break(MessageSeverity::Corruption));
+    }
+}
@@ -0,0 +1,190 @@
+//! Depth stencil target of the main render pass.
+
+use directx_math::*;
+use serde::{Deserialize, Serialize};
+use windows::{core::*, Win32::Graphics::Direct3D11::*, Win32::Graphics::Dxgi::Common::*};
+
+use crate::pipeline_state::DepthStencilDesc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepthFormat {
+    D32Float,
+    D24UnormS8Uint,
+}
+
+impl DepthFormat {
+    pub fn dxgi_format(self) -> DXGI_FORMAT {
+        match self {
+            DepthFormat::D32Float => DXGI_FORMAT_D32_FLOAT,
+            DepthFormat::D24UnormS8Uint => DXGI_FORMAT_D24_UNORM_S8_UINT,
+        }
+    }
+
+    pub fn has_stencil(self) -> bool {
+        self == DepthFormat::D24UnormS8Uint
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthSettings {
+    pub format: DepthFormat,
+    /// Maps the far plane to 0 and the near plane to 1, which spreads float precision evenly over distance.
+    pub reversed_z: bool,
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        DepthSettings { format: DepthFormat::D32Float, reversed_z: false }
+    }
+}
+
+impl DepthSettings {
+    /// Depth of the far plane, which the buffer is cleared to.
+    pub fn clear_depth(&self) -> f32 {
+        if self.reversed_z { 0.0 } else { 1.0 }
+    }
+
+    /// Adapts a depth stencil description written for conventional depth to these settings.
+    pub fn depth_stencil(&self, desc: DepthStencilDesc) -> DepthStencilDesc {
+        if self.reversed_z { desc.reversed() } else { desc }
+    }
+
+    /// Left handed perspective projection matching the depth direction. `far_z` may be infinite.
+    pub fn perspective(&self, fov_y: f32, aspect_ratio: f32, near_z: f32, far_z: f32) -> XMMATRIX {
+        if far_z.is_infinite() {
+            // The finite projection's limit, depth goes to 1 - near / z, or near / z when reversed.
+            let mut matrix = XMFLOAT4X4::default();
+            XMStoreFloat4x4(&mut matrix, XMMatrixPerspectiveFovLH(fov_y, aspect_ratio, near_z, near_z * 2.0));
+            (matrix.m[2][2], matrix.m[3][2]) = if self.reversed_z { (0.0, near_z) } else { (1.0, -near_z) };
+            return XMLoadFloat4x4(&matrix);
+        }
+
+        if self.reversed_z {
+            XMMatrixPerspectiveFovLH(fov_y, aspect_ratio, far_z, near_z)
+        } else {
+            XMMatrixPerspectiveFovLH(fov_y, aspect_ratio, near_z, far_z)
+        }
+    }
+}
+
+pub struct DepthBuffer {
+    pub texture: ID3D11Texture2D,
+    pub view: ID3D11DepthStencilView,
+    pub settings: DepthSettings,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DepthBuffer {
+    pub fn new(device: &ID3D11Device, width: u32, height: u32, settings: DepthSettings) -> Result<Self> {
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: width,
+            Height: height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: settings.format.dxgi_format(),
+            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: D3D11_BIND_DEPTH_STENCIL.0 as u32,
+            ..Default::default()
+        };
+
+        let mut texture = None;
+        unsafe { device.CreateTexture2D(&desc, None, Some(&mut texture))? };
+        let texture = texture.unwrap();
+
+        let mut view = None;
+        unsafe { device.CreateDepthStencilView(&texture, None, Some(&mut view))? };
+
+        Ok(DepthBuffer { texture, view: view.unwrap(), settings, width, height })
+    }
+
+    /// Recreates the buffer when the size changed.
+    pub fn resize(&mut self, device: &ID3D11Device, width: u32, height: u32) -> Result<()> {
+        if (width, height) != (self.width, self.height) {
+            *self = DepthBuffer::new(device, width, height, self.settings)?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self, context: &ID3D11DeviceContext) {
+        let flags = if self.settings.format.has_stencil() {
+            D3D11_CLEAR_DEPTH.0 | D3D11_CLEAR_STENCIL.0
+        } else {
+            D3D11_CLEAR_DEPTH.0
+        };
+
+        unsafe { context.ClearDepthStencilView(&self.view, flags, self.settings.clear_depth(), 0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler_cache::ComparisonFunc;
+
+    const CONVENTIONAL: DepthSettings = DepthSettings { format: DepthFormat::D32Float, reversed_z: false };
+    const REVERSED: DepthSettings = DepthSettings { format: DepthFormat::D32Float, reversed_z: true };
+
+    /// Depth buffer value of a point `z` in front of the camera.
+    fn depth(projection: XMMATRIX, z: f32) -> f32 {
+        XMVectorGetZ(XMVector3TransformCoord(XMVectorSet(0.0, 0.0, z, 1.0), projection))
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn cleared_to_the_far_plane() {
+        assert_eq!(CONVENTIONAL.clear_depth(), 1.0);
+        assert_eq!(REVERSED.clear_depth(), 0.0);
+        assert_eq!(DepthSettings::default().clear_depth(), 1.0);
+    }
+
+    #[test]
+    fn reversed_depth_test_flips_the_comparison() {
+        assert_eq!(CONVENTIONAL.depth_stencil(DepthStencilDesc::DEFAULT), DepthStencilDesc::DEFAULT);
+
+        let reversed = REVERSED.depth_stencil(DepthStencilDesc::DEFAULT);
+        assert_eq!(reversed.depth_func, ComparisonFunc::Greater);
+        assert!(reversed.depth_test && reversed.depth_write);
+
+        let less_equal = DepthStencilDesc { depth_func: ComparisonFunc::LessEqual, ..DepthStencilDesc::READ_ONLY };
+        assert_eq!(REVERSED.depth_stencil(less_equal).depth_func, ComparisonFunc::GreaterEqual);
+        assert!(!REVERSED.depth_stencil(less_equal).depth_write);
+        assert_eq!(REVERSED.depth_stencil(DepthStencilDesc::DISABLED), DepthStencilDesc::DISABLED);
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far() {
+        let conventional = CONVENTIONAL.perspective(XM_PIDIV4, 16.0 / 9.0, 0.1, 100.0);
+        assert_close(depth(conventional, 0.1), 0.0);
+        assert_close(depth(conventional, 100.0), 1.0);
+
+        let reversed = REVERSED.perspective(XM_PIDIV4, 16.0 / 9.0, 0.1, 100.0);
+        assert_close(depth(reversed, 0.1), 1.0);
+        assert_close(depth(reversed, 100.0), 0.0);
+        assert!(depth(reversed, 1.0) > depth(reversed, 10.0));
+    }
+
+    #[test]
+    fn infinite_perspective() {
+        let conventional = CONVENTIONAL.perspective(XM_PIDIV4, 1.0, 0.5, f32::INFINITY);
+        assert_close(depth(conventional, 0.5), 0.0);
+        assert_close(depth(conventional, 5.0), 0.9);
+        assert!(depth(conventional, 1.0e6) < 1.0);
+
+        let reversed = REVERSED.perspective(XM_PIDIV4, 1.0, 0.5, f32::INFINITY);
+        assert_close(depth(reversed, 0.5), 1.0);
+        assert_close(depth(reversed, 5.0), 0.1);
+        assert!(depth(reversed, 1.0e6) > 0.0);
+
+        // Only depth changes, x and y are projected as with a finite far plane.
+        let finite = REVERSED.perspective(XM_PIDIV4, 1.0, 0.5, 100.0);
+        let point = XMVectorSet(1.0, 2.0, 5.0, 1.0);
+        assert_close(XMVectorGetX(XMVector3TransformCoord(point, reversed)), XMVectorGetX(XMVector3TransformCoord(point, finite)));
+        assert_close(XMVectorGetY(XMVector3TransformCoord(point, reversed)), XMVectorGetY(XMVector3TransformCoord(point, finite)));
+    }
+}
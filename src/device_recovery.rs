@@ -0,0 +1,475 @@
+//! Detection of lost devices and recreation of GPU resources from their CPU side descriptions.
+
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use windows::{core::*, Win32::Graphics::Dxgi::*};
+
+/// Attempts at creating a new device before recovery gives up.
+pub const MAX_RECOVERY_ATTEMPTS: u32 = 3;
+
+/// Why the device stopped working, from `GetDeviceRemovedReason`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceLostReason {
+    /// The GPU took too long on a command and was reset by the OS.
+    Hung,
+    /// The adapter was physically removed, disabled or had its driver updated.
+    Removed,
+    /// A badly formed command was sent.
+    Reset,
+    DriverInternalError,
+    InvalidCall,
+    Unknown(HRESULT),
+}
+
+impl DeviceLostReason {
+    pub fn from_hresult(code: HRESULT) -> Self {
+        match code {
+            DXGI_ERROR_DEVICE_HUNG => DeviceLostReason::Hung,
+            DXGI_ERROR_DEVICE_REMOVED => DeviceLostReason::Removed,
+            DXGI_ERROR_DEVICE_RESET => DeviceLostReason::Reset,
+            DXGI_ERROR_DRIVER_INTERNAL_ERROR => DeviceLostReason::DriverInternalError,
+            DXGI_ERROR_INVALID_CALL => DeviceLostReason::InvalidCall,
+            code => DeviceLostReason::Unknown(code),
+        }
+    }
+}
+
+/// Whether an error means the device is gone and everything created on it has to be recreated.
+pub fn is_device_lost(code: HRESULT) -> bool {
+    code == DXGI_ERROR_DEVICE_REMOVED || code == DXGI_ERROR_DEVICE_RESET
+}
+
+/// Runs `attempt` until it succeeds, fails for another reason than a lost device, or `max_attempts` ran out.
+/// The attempt number starting at 1 is passed in.
+pub fn retry_while_lost<T>(max_attempts: u32, mut attempt: impl FnMut(u32) -> Result<T>) -> Result<T> {
+    let mut number = 1;
+    loop {
+        match attempt(number) {
+            Err(error) if is_device_lost(error.code()) && number < max_attempts => number += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Wait after the first failed recovery, doubled for every further failure.
+pub const RECOVERY_BACKOFF: Duration = Duration::from_millis(250);
+pub const MAX_RECOVERY_BACKOFF: Duration = Duration::from_secs(8);
+/// Failed recoveries in a row before giving up on the device.
+pub const MAX_FAILED_RECOVERIES: u32 = 6;
+
+/// Spaces out recoveries that failed, so an adapter that is still gone isn't polled every frame.
+#[derive(Debug, Default)]
+pub struct RecoveryBackoff {
+    failures: u32,
+    next_attempt: Option<Instant>,
+}
+
+impl RecoveryBackoff {
+    /// Wait after `failures` failed recoveries in a row.
+    pub fn delay(failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::ZERO;
+        }
+        RECOVERY_BACKOFF.saturating_mul(1 << (failures - 1).min(16)).min(MAX_RECOVERY_BACKOFF)
+    }
+
+    /// Whether the next recovery may be attempted at `now`.
+    pub fn ready(&self, now: Instant) -> bool {
+        self.next_attempt.is_none_or(|next_attempt| now >= next_attempt)
+    }
+
+    /// Records a recovery that failed at `now`, returns whether another one should be attempted.
+    pub fn failed(&mut self, now: Instant) -> bool {
+        self.failures += 1;
+        self.next_attempt = Some(now + Self::delay(self.failures));
+        self.failures < MAX_FAILED_RECOVERIES
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+/// Resources are recreated stage by stage, so later stages may use objects of earlier ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ResourceStage {
+    /// Buffers and textures.
+    Resources,
+    /// Views of resources from the first stage.
+    Views,
+    /// Shaders, input layouts and state objects.
+    Pipelines,
+}
+
+/// A GPU object that can be created again from data kept on the CPU.
+pub trait DeviceResource<D> {
+    fn create(&mut self, device: &D) -> Result<()>;
+    /// Drops the GPU object, keeping what is needed to create it again.
+    fn release(&mut self);
+}
+
+/// Creates a GPU object on a device from its description.
+type Create<S, T, D> = Box<dyn Fn(&D, &S) -> Result<T>>;
+
+/// A GPU object together with the description it is created from.
+pub struct Recreatable<S, T, D> {
+    pub source: S,
+    resource: Option<T>,
+    create: Create<S, T, D>,
+}
+
+impl<S, T, D> Recreatable<S, T, D> {
+    pub fn new(device: &D, source: S, create: impl Fn(&D, &S) -> Result<T> + 'static) -> Result<Self> {
+        let resource = create(device, &source)?;
+        Ok(Recreatable { source, resource: Some(resource), create: Box::new(create) })
+    }
+
+    /// The GPU object, `None` between losing the device and recreating it.
+    pub fn get(&self) -> Option<&T> {
+        self.resource.as_ref()
+    }
+}
+
+impl<S, T, D> DeviceResource<D> for Recreatable<S, T, D> {
+    fn create(&mut self, device: &D) -> Result<()> {
+        self.resource = Some((self.create)(device, &self.source)?);
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.resource = None;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(u32);
+
+struct RegistryEntry<D> {
+    id: ResourceId,
+    stage: ResourceStage,
+    name: String,
+    resource: Weak<RefCell<dyn DeviceResource<D>>>,
+}
+
+/// Everything that has to be recreated when the device is lost. Entries are weak, a resource
+/// dropped by its owner is forgotten.
+pub struct ResourceRegistry<D> {
+    entries: Vec<RegistryEntry<D>>,
+    next_id: u32,
+}
+
+impl<D> Default for ResourceRegistry<D> {
+    fn default() -> Self {
+        ResourceRegistry { entries: Vec::new(), next_id: 0 }
+    }
+}
+
+impl<D: 'static> ResourceRegistry<D> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, stage: ResourceStage, name: &str, resource: &Rc<RefCell<impl DeviceResource<D> + 'static>>) -> ResourceId {
+        let resource: Rc<RefCell<dyn DeviceResource<D>>> = resource.clone();
+        let id = ResourceId(self.next_id);
+        self.next_id += 1;
+        self.entries.push(RegistryEntry { id, stage, name: name.to_string(), resource: Rc::downgrade(&resource) });
+        id
+    }
+
+    /// Creates a [`Recreatable`] on `device` and registers it.
+    pub fn create<S: 'static, T: 'static>(
+        &mut self,
+        device: &D,
+        stage: ResourceStage,
+        name: &str,
+        source: S,
+        create: impl Fn(&D, &S) -> Result<T> + 'static,
+    ) -> Result<Rc<RefCell<Recreatable<S, T, D>>>> {
+        let resource = Rc::new(RefCell::new(Recreatable::new(device, source, create)?));
+        self.register(stage, name, &resource);
+        Ok(resource)
+    }
+
+    pub fn unregister(&mut self, id: ResourceId) {
+        self.entries.retain(|entry| entry.id != id);
+    }
+
+    /// Number of registered resources that are still alive.
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.resource.strong_count() > 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ids in recreation order, by stage and then by registration.
+    pub fn creation_order(&self) -> Vec<ResourceId> {
+        let mut entries: Vec<&RegistryEntry<D>> = self.entries.iter().collect();
+        // The sort is stable, registration order is kept within a stage.
+        entries.sort_by_key(|entry| entry.stage);
+        entries.into_iter().map(|entry| entry.id).collect()
+    }
+
+    /// Releases every resource, in the reverse of the creation order.
+    pub fn release_all(&mut self) {
+        self.prune();
+        for id in self.creation_order().into_iter().rev() {
+            if let Some(resource) = self.resource(id) {
+                resource.borrow_mut().release();
+            }
+        }
+    }
+
+    /// Recreates every resource on `device` in creation order, stopping at the first failure.
+    pub fn recreate_all(&mut self, device: &D) -> Result<()> {
+        self.prune();
+        for id in self.creation_order() {
+            if let Some(resource) = self.resource(id) {
+                if let Err(error) = resource.borrow_mut().create(device) {
+                    log::error!("Failed to recreate {}: {}", self.name(id).unwrap_or_default(), error);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn name(&self, id: ResourceId) -> Option<&str> {
+        self.entries.iter().find(|entry| entry.id == id).map(|entry| entry.name.as_str())
+    }
+
+    fn resource(&self, id: ResourceId) -> Option<Rc<RefCell<dyn DeviceResource<D>>>> {
+        self.entries.iter().find(|entry| entry.id == id).and_then(|entry| entry.resource.upgrade())
+    }
+
+    fn prune(&mut self) {
+        self.entries.retain(|entry| entry.resource.strong_count() > 0);
+    }
+}
+
+/// A device without a GPU that fails on request, for exercising recovery.
+#[derive(Debug, Default)]
+pub struct HeadlessDevice {
+    /// Which device this is, counting from 1.
+    pub generation: u32,
+    /// Resource creations left before every further one fails with `DXGI_ERROR_DEVICE_REMOVED`.
+    pub fail_after: Option<u32>,
+    /// Creating a resource with one of these names fails with the given error.
+    pub failures: Vec<(String, HRESULT)>,
+    /// Names of the created resources, in order.
+    pub created: RefCell<Vec<String>>,
+    creations: Cell<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeadlessResource {
+    pub name: String,
+    pub generation: u32,
+}
+
+impl HeadlessDevice {
+    pub fn new(generation: u32) -> Self {
+        HeadlessDevice { generation, ..Default::default() }
+    }
+
+    pub fn create_resource(&self, name: &str) -> Result<HeadlessResource> {
+        if self.fail_after.is_some_and(|fail_after| self.creations.get() >= fail_after) {
+            return Err(DXGI_ERROR_DEVICE_REMOVED.into());
+        }
+        if let Some((_, code)) = self.failures.iter().find(|(failing, _)| failing == name) {
+            return Err((*code).into());
+        }
+
+        self.creations.set(self.creations.get() + 1);
+        self.created.borrow_mut().push(name.to_string());
+        Ok(HeadlessResource { name: name.to_string(), generation: self.generation })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::Foundation::{E_FAIL, E_OUTOFMEMORY};
+
+    type Resource = Rc<RefCell<Recreatable<String, HeadlessResource, HeadlessDevice>>>;
+
+    fn create(registry: &mut ResourceRegistry<HeadlessDevice>, device: &HeadlessDevice, stage: ResourceStage, name: &str) -> Resource {
+        registry.create(device, stage, name, name.to_string(), |device, name| device.create_resource(name)).unwrap()
+    }
+
+    #[test]
+    fn lost_device_errors() {
+        assert!(is_device_lost(DXGI_ERROR_DEVICE_REMOVED));
+        assert!(is_device_lost(DXGI_ERROR_DEVICE_RESET));
+        assert!(!is_device_lost(DXGI_ERROR_DEVICE_HUNG));
+        assert!(!is_device_lost(E_OUTOFMEMORY));
+
+        assert_eq!(DeviceLostReason::from_hresult(DXGI_ERROR_DEVICE_HUNG), DeviceLostReason::Hung);
+        assert_eq!(DeviceLostReason::from_hresult(DXGI_ERROR_DRIVER_INTERNAL_ERROR), DeviceLostReason::DriverInternalError);
+        assert_eq!(DeviceLostReason::from_hresult(E_FAIL), DeviceLostReason::Unknown(E_FAIL));
+    }
+
+    #[test]
+    fn retry_stops_at_the_first_success() {
+        let result = retry_while_lost(3, |attempt| if attempt < 2 { Err(DXGI_ERROR_DEVICE_REMOVED.into()) } else { Ok(attempt) });
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn retry_gives_up_after_max_attempts() {
+        let mut attempts = 0;
+        let result: Result<()> = retry_while_lost(MAX_RECOVERY_ATTEMPTS, |_| {
+            attempts += 1;
+            Err(DXGI_ERROR_DEVICE_RESET.into())
+        });
+
+        assert_eq!(result.unwrap_err().code(), DXGI_ERROR_DEVICE_RESET);
+        assert_eq!(attempts, MAX_RECOVERY_ATTEMPTS);
+    }
+
+    #[test]
+    fn retry_does_not_repeat_other_errors() {
+        let mut attempts = 0;
+        let result: Result<()> = retry_while_lost(MAX_RECOVERY_ATTEMPTS, |_| {
+            attempts += 1;
+            Err(E_OUTOFMEMORY.into())
+        });
+
+        assert_eq!(result.unwrap_err().code(), E_OUTOFMEMORY);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        assert_eq!(RecoveryBackoff::delay(0), Duration::ZERO);
+        assert_eq!(RecoveryBackoff::delay(1), RECOVERY_BACKOFF);
+        assert_eq!(RecoveryBackoff::delay(2), RECOVERY_BACKOFF * 2);
+        assert_eq!(RecoveryBackoff::delay(3), RECOVERY_BACKOFF * 4);
+        assert_eq!(RecoveryBackoff::delay(20), MAX_RECOVERY_BACKOFF);
+        assert_eq!(RecoveryBackoff::delay(u32::MAX), MAX_RECOVERY_BACKOFF);
+    }
+
+    #[test]
+    fn backoff_waits_between_attempts_and_gives_up() {
+        let start = Instant::now();
+        let mut backoff = RecoveryBackoff::default();
+        assert!(backoff.ready(start));
+
+        assert!(backoff.failed(start));
+        assert!(!backoff.ready(start));
+        assert!(!backoff.ready(start + RECOVERY_BACKOFF / 2));
+        assert!(backoff.ready(start + RECOVERY_BACKOFF));
+
+        for failure in 2..MAX_FAILED_RECOVERIES {
+            assert!(backoff.failed(start), "gave up after {} failures", failure);
+        }
+        assert!(!backoff.failed(start));
+        assert_eq!(backoff.failures(), MAX_FAILED_RECOVERIES);
+    }
+
+    #[test]
+    fn recreation_goes_by_stage_then_registration() {
+        let device = HeadlessDevice::new(1);
+        let mut registry = ResourceRegistry::new();
+        let _pipeline = create(&mut registry, &device, ResourceStage::Pipelines, "pipeline");
+        let _view = create(&mut registry, &device, ResourceStage::Views, "view");
+        let _texture = create(&mut registry, &device, ResourceStage::Resources, "texture");
+        let _buffer = create(&mut registry, &device, ResourceStage::Resources, "buffer");
+
+        let order: Vec<&str> = registry.creation_order().into_iter().map(|id| registry.name(id).unwrap()).collect();
+        assert_eq!(order, ["texture", "buffer", "view", "pipeline"]);
+
+        let recovered = HeadlessDevice::new(2);
+        registry.recreate_all(&recovered).unwrap();
+        assert_eq!(*recovered.created.borrow(), ["texture", "buffer", "view", "pipeline"]);
+    }
+
+    #[test]
+    fn recreated_resources_live_on_the_new_device() {
+        let device = HeadlessDevice::new(1);
+        let mut registry = ResourceRegistry::new();
+        let texture = create(&mut registry, &device, ResourceStage::Resources, "texture");
+        assert_eq!(texture.borrow().get().unwrap().generation, 1);
+
+        registry.release_all();
+        assert!(texture.borrow().get().is_none());
+
+        registry.recreate_all(&HeadlessDevice::new(2)).unwrap();
+        assert_eq!(texture.borrow().get(), Some(&HeadlessResource { name: "texture".to_string(), generation: 2 }));
+        assert_eq!(texture.borrow().source, "texture");
+    }
+
+    #[test]
+    fn dropped_and_unregistered_resources_are_skipped() {
+        let device = HeadlessDevice::new(1);
+        let mut registry = ResourceRegistry::new();
+        let kept = create(&mut registry, &device, ResourceStage::Resources, "kept");
+        let dropped = create(&mut registry, &device, ResourceStage::Resources, "dropped");
+        let unregistered = Rc::new(RefCell::new(Recreatable::new(&device, "unregistered".to_string(), |device: &HeadlessDevice, name: &String| device.create_resource(name)).unwrap()));
+        let id = registry.register(ResourceStage::Views, "unregistered", &unregistered);
+        assert_eq!(registry.len(), 3);
+
+        drop(dropped);
+        registry.unregister(id);
+        assert_eq!(registry.len(), 1);
+
+        let recovered = HeadlessDevice::new(2);
+        registry.recreate_all(&recovered).unwrap();
+        assert_eq!(*recovered.created.borrow(), ["kept"]);
+        assert_eq!(kept.borrow().get().unwrap().generation, 2);
+        assert_eq!(unregistered.borrow().get().unwrap().generation, 1);
+    }
+
+    #[test]
+    fn recreation_stops_at_the_first_failure() {
+        let device = HeadlessDevice::new(1);
+        let mut registry = ResourceRegistry::new();
+        let texture = create(&mut registry, &device, ResourceStage::Resources, "texture");
+        let view = create(&mut registry, &device, ResourceStage::Views, "view");
+        let pipeline = create(&mut registry, &device, ResourceStage::Pipelines, "pipeline");
+        registry.release_all();
+
+        let recovered = HeadlessDevice { failures: vec![("view".to_string(), E_OUTOFMEMORY)], ..HeadlessDevice::new(2) };
+        assert_eq!(registry.recreate_all(&recovered).unwrap_err().code(), E_OUTOFMEMORY);
+
+        assert!(texture.borrow().get().is_some());
+        assert!(view.borrow().get().is_none());
+        assert!(pipeline.borrow().get().is_none());
+        assert_eq!(*recovered.created.borrow(), ["texture"]);
+    }
+
+    #[test]
+    fn recovery_retries_on_a_device_lost_again() {
+        let device = HeadlessDevice::new(1);
+        let mut registry = ResourceRegistry::new();
+        let texture = create(&mut registry, &device, ResourceStage::Resources, "texture");
+        let _view = create(&mut registry, &device, ResourceStage::Views, "view");
+
+        // The first new device is lost after one creation, the second one works.
+        let devices = retry_while_lost(MAX_RECOVERY_ATTEMPTS, |attempt| {
+            registry.release_all();
+            let fail_after = if attempt == 1 { Some(1) } else { None };
+            let recovered = HeadlessDevice { fail_after, ..HeadlessDevice::new(attempt + 1) };
+            registry.recreate_all(&recovered)?;
+            Ok(recovered)
+        })
+        .unwrap();
+
+        assert_eq!(devices.generation, 3);
+        assert_eq!(texture.borrow().get().unwrap().generation, 3);
+    }
+
+    #[test]
+    fn headless_device_fails_after_the_given_count() {
+        let device = HeadlessDevice { fail_after: Some(2), ..HeadlessDevice::new(1) };
+
+        assert!(device.create_resource("a").is_ok());
+        assert!(device.create_resource("b").is_ok());
+        assert_eq!(device.create_resource("c").unwrap_err().code(), DXGI_ERROR_DEVICE_REMOVED);
+        assert_eq!(*device.created.borrow(), ["a", "b"]);
+    }
+}
@@ -0,0 +1,260 @@
+//! Windowed, borderless and exclusive fullscreen display modes, and picking the monitor and video mode for them.
+
+use serde::{Deserialize, Serialize};
+
+use crate::window_placement::{best_monitor, MonitorRect};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    /// An undecorated window covering the whole monitor at its desktop video mode.
+    Borderless,
+    /// The monitor is switched to the chosen video mode and owned by the window.
+    Exclusive,
+}
+
+impl DisplayMode {
+    pub const ALL: [DisplayMode; 3] = [DisplayMode::Windowed, DisplayMode::Borderless, DisplayMode::Exclusive];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DisplayMode::Windowed => "Windowed",
+            DisplayMode::Borderless => "Borderless",
+            DisplayMode::Exclusive => "Exclusive fullscreen",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: u32,
+}
+
+impl VideoMode {
+    pub fn from_glfw(mode: &glfw::VidMode) -> Self {
+        VideoMode { width: mode.width, height: mode.height, refresh_rate: mode.refresh_rate }
+    }
+}
+
+impl std::fmt::Display for VideoMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}x{} @ {} Hz", self.width, self.height, self.refresh_rate)
+    }
+}
+
+/// A connected monitor with its full bounds and the video modes it supports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MonitorModes {
+    pub name: Option<String>,
+    pub position: (i32, i32),
+    /// The desktop video mode.
+    pub current: VideoMode,
+    pub modes: Vec<VideoMode>,
+}
+
+impl MonitorModes {
+    /// `None` for a monitor glfw can't query the video mode of.
+    pub fn from_glfw(monitor: &glfw::Monitor) -> Option<Self> {
+        let current = VideoMode::from_glfw(&monitor.get_video_mode()?);
+
+        // glfw lists modes with different color depths separately.
+        let mut modes: Vec<VideoMode> = monitor.get_video_modes().iter().map(VideoMode::from_glfw).collect();
+        modes.sort_by_key(|mode| std::cmp::Reverse((mode.width * mode.height, mode.width, mode.refresh_rate)));
+        modes.dedup();
+
+        Some(MonitorModes { name: monitor.get_name(), position: monitor.get_pos(), current, modes })
+    }
+
+    pub fn rect(&self) -> MonitorRect {
+        MonitorRect { name: self.name.clone(), position: self.position, size: (self.current.width, self.current.height) }
+    }
+}
+
+/// What the window is shown as.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DisplaySettings {
+    pub mode: DisplayMode,
+    /// Monitor to go fullscreen on, `None` for the one the window is on.
+    pub monitor: Option<String>,
+    /// Video mode for exclusive fullscreen, `None` keeps the desktop one.
+    pub video_mode: Option<VideoMode>,
+}
+
+/// Index of the monitor to go fullscreen on: the one named `preferred` when it is connected, else the
+/// one the window overlaps most, else the one closest to the window. `None` without monitors.
+pub fn pick_monitor(monitors: &[MonitorRect], preferred: Option<&str>, window_position: (i32, i32), window_size: (u32, u32)) -> Option<usize> {
+    if let Some(index) = preferred.and_then(|name| monitors.iter().position(|monitor| monitor.name.as_deref() == Some(name))) {
+        return Some(index);
+    }
+
+    best_monitor(monitors, window_position, window_size).or_else(|| {
+        let center = (
+            window_position.0 as i64 + window_size.0 as i64 / 2,
+            window_position.1 as i64 + window_size.1 as i64 / 2,
+        );
+
+        // Distance from the window's center to the closest point of each monitor.
+        let distance = |monitor: &MonitorRect| {
+            let (left, top) = (monitor.position.0 as i64, monitor.position.1 as i64);
+            let dx = center.0.clamp(left, left + monitor.size.0 as i64) - center.0;
+            let dy = center.1.clamp(top, top + monitor.size.1 as i64) - center.1;
+            dx * dx + dy * dy
+        };
+
+        monitors.iter().enumerate().min_by_key(|(_, monitor)| distance(monitor)).map(|(index, _)| index)
+    })
+}
+
+/// Where the monitor at `index` of the `known_count` known ones is in glfw's `connected` list. The
+/// lists line up while no monitor was connected or disconnected since, else it is found by name.
+pub fn connected_monitor(index: usize, name: Option<&str>, known_count: usize, connected: &[Option<String>]) -> Option<usize> {
+    if connected.len() == known_count {
+        return (index < connected.len()).then_some(index);
+    }
+
+    let name = name?;
+    connected.iter().position(|connected| connected.as_deref() == Some(name))
+}
+
+/// The supported mode closest to `requested`: the same size if there is one, then the closest
+/// refresh rate, preferring the higher one. A refresh rate of 0 asks for the desktop one.
+pub fn pick_video_mode(modes: &[VideoMode], current: VideoMode, requested: Option<VideoMode>) -> VideoMode {
+    let Some(requested) = requested else {
+        return current;
+    };
+    let refresh_rate = if requested.refresh_rate == 0 { current.refresh_rate } else { requested.refresh_rate };
+
+    modes
+        .iter()
+        .min_by_key(|mode| {
+            (
+                mode.width.abs_diff(requested.width) + mode.height.abs_diff(requested.height),
+                mode.refresh_rate.abs_diff(refresh_rate),
+                std::cmp::Reverse(mode.refresh_rate),
+            )
+        })
+        .copied()
+        .unwrap_or(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, position: (i32, i32), size: (u32, u32)) -> MonitorRect {
+        MonitorRect { name: Some(name.to_string()), position, size }
+    }
+
+    /// Primary at the origin, one monitor to its left and one above it, so both have negative coordinates.
+    fn three_monitors() -> Vec<MonitorRect> {
+        vec![
+            monitor("Primary", (0, 0), (1920, 1080)),
+            monitor("Left", (-2560, -360), (2560, 1440)),
+            monitor("Top", (0, -1200), (1920, 1200)),
+        ]
+    }
+
+    fn mode(width: u32, height: u32, refresh_rate: u32) -> VideoMode {
+        VideoMode { width, height, refresh_rate }
+    }
+
+    #[test]
+    fn preferred_monitor_wins_when_connected() {
+        let monitors = three_monitors();
+
+        assert_eq!(pick_monitor(&monitors, Some("Top"), (100, 100), (800, 600)), Some(2));
+        assert_eq!(pick_monitor(&monitors, Some("Gone"), (100, 100), (800, 600)), Some(0));
+        assert_eq!(pick_monitor(&monitors, None, (100, 100), (800, 600)), Some(0));
+    }
+
+    #[test]
+    fn window_on_negative_coordinates() {
+        let monitors = three_monitors();
+
+        assert_eq!(pick_monitor(&monitors, None, (-2000, -300), (800, 600)), Some(1));
+        assert_eq!(pick_monitor(&monitors, None, (200, -900), (800, 600)), Some(2));
+    }
+
+    #[test]
+    fn window_spanning_monitors_picks_the_largest_overlap() {
+        let monitors = three_monitors();
+
+        // 300 pixels of width on the left monitor, 500 on the primary.
+        assert_eq!(pick_monitor(&monitors, None, (-300, 100), (800, 600)), Some(0));
+        assert_eq!(pick_monitor(&monitors, None, (-500, 100), (800, 600)), Some(1));
+        // Straddling the primary and the top monitor, mostly above.
+        assert_eq!(pick_monitor(&monitors, None, (100, -400), (800, 600)), Some(2));
+    }
+
+    #[test]
+    fn window_off_every_monitor_picks_the_closest() {
+        let monitors = three_monitors();
+
+        assert_eq!(pick_monitor(&monitors, None, (3000, 100), (800, 600)), Some(0));
+        assert_eq!(pick_monitor(&monitors, None, (-4000, 0), (800, 600)), Some(1));
+        assert_eq!(pick_monitor(&monitors, None, (500, -3000), (800, 600)), Some(2));
+        assert_eq!(pick_monitor(&monitors, None, (-3000, 2000), (100, 100)), Some(1));
+    }
+
+    #[test]
+    fn no_monitors() {
+        assert_eq!(pick_monitor(&[], Some("Primary"), (0, 0), (800, 600)), None);
+    }
+
+    #[test]
+    fn unnamed_monitors_never_match_a_name() {
+        let monitors = [MonitorRect { name: None, position: (0, 0), size: (1920, 1080) }, monitor("Second", (1920, 0), (1920, 1080))];
+
+        assert_eq!(pick_monitor(&monitors, Some(""), (2000, 0), (100, 100)), Some(1));
+    }
+
+    #[test]
+    fn connected_monitor_by_index_then_by_name() {
+        let connected = [Some("Dell".to_string()), Some("Dell".to_string()), None];
+
+        // Two monitors of the same model only differ by their position in the list.
+        assert_eq!(connected_monitor(1, Some("Dell"), 3, &connected), Some(1));
+        assert_eq!(connected_monitor(2, None, 3, &connected), Some(2));
+        assert_eq!(connected_monitor(3, None, 3, &connected), None);
+
+        // One was connected or disconnected since the monitors were listed.
+        assert_eq!(connected_monitor(1, Some("Dell"), 2, &connected), Some(0));
+        assert_eq!(connected_monitor(0, Some("LG"), 4, &connected), None);
+        assert_eq!(connected_monitor(2, None, 4, &connected), None);
+    }
+
+    #[test]
+    fn video_mode_defaults_to_the_desktop_one() {
+        let current = mode(2560, 1440, 144);
+        assert_eq!(pick_video_mode(&[mode(1920, 1080, 60)], current, None), current);
+        assert_eq!(pick_video_mode(&[], current, Some(mode(1920, 1080, 60))), current);
+    }
+
+    #[test]
+    fn video_mode_prefers_the_size_then_the_refresh_rate() {
+        let modes = [mode(2560, 1440, 144), mode(2560, 1440, 60), mode(1920, 1080, 144), mode(1920, 1080, 120), mode(1920, 1080, 60)];
+        let current = modes[0];
+
+        assert_eq!(pick_video_mode(&modes, current, Some(mode(1920, 1080, 60))), mode(1920, 1080, 60));
+        assert_eq!(pick_video_mode(&modes, current, Some(mode(1920, 1080, 100))), mode(1920, 1080, 120));
+        assert_eq!(pick_video_mode(&modes, current, Some(mode(1920, 1080, 0))), mode(1920, 1080, 144));
+        assert_eq!(pick_video_mode(&modes, current, Some(mode(1920, 1200, 60))), mode(1920, 1080, 60));
+    }
+
+    #[test]
+    fn equally_close_refresh_rates_prefer_the_higher() {
+        let modes = [mode(1920, 1080, 60), mode(1920, 1080, 120)];
+        assert_eq!(pick_video_mode(&modes, modes[0], Some(mode(1920, 1080, 90))), mode(1920, 1080, 120));
+    }
+
+    #[test]
+    fn monitor_rect_uses_the_desktop_mode() {
+        let monitor = MonitorModes { name: Some("Left".to_string()), position: (-2560, -360), current: mode(2560, 1440, 144), modes: Vec::new() };
+        assert_eq!(monitor.rect(), MonitorRect { name: Some("Left".to_string()), position: (-2560, -360), size: (2560, 1440) });
+        assert_eq!(mode(2560, 1440, 144).to_string(), "2560x1440 @ 144 Hz");
+    }
+}
@@ -0,0 +1,504 @@
+//! Gamepads through GLFW's standard mappings: connection events, deadzones and analog axes.
+
+use glfw::{GamepadAxis, GamepadButton, JoystickId};
+use imgui::Key as ImguiKey;
+
+pub const BUTTON_COUNT: usize = 15;
+pub const AXIS_COUNT: usize = 6;
+
+/// The buttons in GLFW's order.
+pub const BUTTONS: [GamepadButton; BUTTON_COUNT] = [
+    GamepadButton::ButtonA,
+    GamepadButton::ButtonB,
+    GamepadButton::ButtonX,
+    GamepadButton::ButtonY,
+    GamepadButton::ButtonLeftBumper,
+    GamepadButton::ButtonRightBumper,
+    GamepadButton::ButtonBack,
+    GamepadButton::ButtonStart,
+    GamepadButton::ButtonGuide,
+    GamepadButton::ButtonLeftThumb,
+    GamepadButton::ButtonRightThumb,
+    GamepadButton::ButtonDpadUp,
+    GamepadButton::ButtonDpadRight,
+    GamepadButton::ButtonDpadDown,
+    GamepadButton::ButtonDpadLeft,
+];
+
+const AXES: [GamepadAxis; AXIS_COUNT] = [
+    GamepadAxis::AxisLeftX,
+    GamepadAxis::AxisLeftY,
+    GamepadAxis::AxisRightX,
+    GamepadAxis::AxisRightY,
+    GamepadAxis::AxisLeftTrigger,
+    GamepadAxis::AxisRightTrigger,
+];
+
+const JOYSTICKS: [JoystickId; 16] = [
+    JoystickId::Joystick1,
+    JoystickId::Joystick2,
+    JoystickId::Joystick3,
+    JoystickId::Joystick4,
+    JoystickId::Joystick5,
+    JoystickId::Joystick6,
+    JoystickId::Joystick7,
+    JoystickId::Joystick8,
+    JoystickId::Joystick9,
+    JoystickId::Joystick10,
+    JoystickId::Joystick11,
+    JoystickId::Joystick12,
+    JoystickId::Joystick13,
+    JoystickId::Joystick14,
+    JoystickId::Joystick15,
+    JoystickId::Joystick16,
+];
+
+/// Readings below `inner` are dropped and the rest is rescaled to reach 1 at `outer`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Deadzone {
+    pub inner: f32,
+    pub outer: f32,
+}
+
+impl Deadzone {
+    pub fn new(inner: f32) -> Self {
+        Deadzone { inner, outer: 1.0 }
+    }
+
+    /// For a single axis in -1..1.
+    pub fn apply(&self, value: f32) -> f32 {
+        value.signum() * self.rescale(value.abs())
+    }
+
+    /// For a stick, on its distance from the center so diagonals aren't snapped to the axes.
+    pub fn apply_stick(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let length = (x * x + y * y).sqrt();
+        if length <= self.inner {
+            return [0.0, 0.0];
+        }
+
+        let scale = self.rescale(length) / length;
+        [x * scale, y * scale]
+    }
+
+    fn rescale(&self, magnitude: f32) -> f32 {
+        if magnitude <= self.inner {
+            return 0.0;
+        }
+        ((magnitude - self.inner) / (self.outer - self.inner).max(f32::EPSILON)).min(1.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GamepadSettings {
+    pub stick_deadzone: Deadzone,
+    pub trigger_deadzone: Deadzone,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        GamepadSettings { stick_deadzone: Deadzone::new(0.2), trigger_deadzone: Deadzone::new(0.1) }
+    }
+}
+
+/// Readings as GLFW reports them: sticks in -1..1 with down being positive, triggers from -1
+/// released to 1 fully pressed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RawGamepadState {
+    pub buttons: [bool; BUTTON_COUNT],
+    pub axes: [f32; AXIS_COUNT],
+}
+
+impl Default for RawGamepadState {
+    fn default() -> Self {
+        let mut axes = [0.0; AXIS_COUNT];
+        axes[GamepadAxis::AxisLeftTrigger as usize] = -1.0;
+        axes[GamepadAxis::AxisRightTrigger as usize] = -1.0;
+        RawGamepadState { buttons: [false; BUTTON_COUNT], axes }
+    }
+}
+
+impl RawGamepadState {
+    pub fn from_glfw(state: &glfw::GamepadState) -> Self {
+        RawGamepadState {
+            buttons: BUTTONS.map(|button| state.get_button_state(button) != glfw::Action::Release),
+            axes: AXES.map(|axis| state.get_axis(axis)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Axis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// A gamepad's state after the deadzones. Sticks are in -1..1 with right and up positive,
+/// triggers in 0..1.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadInput {
+    pub buttons: [bool; BUTTON_COUNT],
+    pub left_stick: [f32; 2],
+    pub right_stick: [f32; 2],
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+}
+
+impl GamepadInput {
+    pub fn from_raw(raw: &RawGamepadState, settings: &GamepadSettings) -> Self {
+        let axis = |axis: GamepadAxis| raw.axes[axis as usize];
+        let stick = |x, y| settings.stick_deadzone.apply_stick([axis(x), -axis(y)]);
+        let trigger = |trigger| settings.trigger_deadzone.apply((axis(trigger) + 1.0) * 0.5);
+
+        GamepadInput {
+            buttons: raw.buttons,
+            left_stick: stick(GamepadAxis::AxisLeftX, GamepadAxis::AxisLeftY),
+            right_stick: stick(GamepadAxis::AxisRightX, GamepadAxis::AxisRightY),
+            left_trigger: trigger(GamepadAxis::AxisLeftTrigger),
+            right_trigger: trigger(GamepadAxis::AxisRightTrigger),
+        }
+    }
+
+    pub fn button(&self, button: GamepadButton) -> bool {
+        self.buttons[button as usize]
+    }
+
+    pub fn axis(&self, axis: Axis) -> f32 {
+        match axis {
+            Axis::LeftX => self.left_stick[0],
+            Axis::LeftY => self.left_stick[1],
+            Axis::RightX => self.right_stick[0],
+            Axis::RightY => self.right_stick[1],
+            Axis::LeftTrigger => self.left_trigger,
+            Axis::RightTrigger => self.right_trigger,
+        }
+    }
+
+    /// Both pads at once: buttons held on either and the axis moved furthest.
+    pub fn combine(&self, other: &GamepadInput) -> GamepadInput {
+        let furthest = |a: f32, b: f32| if b.abs() > a.abs() { b } else { a };
+
+        GamepadInput {
+            buttons: std::array::from_fn(|index| self.buttons[index] || other.buttons[index]),
+            left_stick: [furthest(self.left_stick[0], other.left_stick[0]), furthest(self.left_stick[1], other.left_stick[1])],
+            right_stick: [furthest(self.right_stick[0], other.right_stick[0]), furthest(self.right_stick[1], other.right_stick[1])],
+            left_trigger: self.left_trigger.max(other.left_trigger),
+            right_trigger: self.right_trigger.max(other.right_trigger),
+        }
+    }
+}
+
+/// imgui's gamepad keys for `gamepad` with their analog values, in the layout of the standard imgui backends.
+pub fn imgui_keys(gamepad: &GamepadInput) -> [(ImguiKey, f32); 24] {
+    let button = |button| if gamepad.button(button) { 1.0 } else { 0.0 };
+    let [left_x, left_y] = gamepad.left_stick;
+    let [right_x, right_y] = gamepad.right_stick;
+
+    [
+        (ImguiKey::GamepadStart, button(GamepadButton::ButtonStart)),
+        (ImguiKey::GamepadBack, button(GamepadButton::ButtonBack)),
+        (ImguiKey::GamepadFaceDown, button(GamepadButton::ButtonA)),
+        (ImguiKey::GamepadFaceRight, button(GamepadButton::ButtonB)),
+        (ImguiKey::GamepadFaceLeft, button(GamepadButton::ButtonX)),
+        (ImguiKey::GamepadFaceUp, button(GamepadButton::ButtonY)),
+        (ImguiKey::GamepadDpadLeft, button(GamepadButton::ButtonDpadLeft)),
+        (ImguiKey::GamepadDpadRight, button(GamepadButton::ButtonDpadRight)),
+        (ImguiKey::GamepadDpadUp, button(GamepadButton::ButtonDpadUp)),
+        (ImguiKey::GamepadDpadDown, button(GamepadButton::ButtonDpadDown)),
+        (ImguiKey::GamepadL1, button(GamepadButton::ButtonLeftBumper)),
+        (ImguiKey::GamepadR1, button(GamepadButton::ButtonRightBumper)),
+        (ImguiKey::GamepadL2, gamepad.left_trigger),
+        (ImguiKey::GamepadR2, gamepad.right_trigger),
+        (ImguiKey::GamepadL3, button(GamepadButton::ButtonLeftThumb)),
+        (ImguiKey::GamepadR3, button(GamepadButton::ButtonRightThumb)),
+        (ImguiKey::GamepadLStickLeft, (-left_x).max(0.0)),
+        (ImguiKey::GamepadLStickRight, left_x.max(0.0)),
+        (ImguiKey::GamepadLStickUp, left_y.max(0.0)),
+        (ImguiKey::GamepadLStickDown, (-left_y).max(0.0)),
+        (ImguiKey::GamepadRStickLeft, (-right_x).max(0.0)),
+        (ImguiKey::GamepadRStickRight, right_x.max(0.0)),
+        (ImguiKey::GamepadRStickUp, right_y.max(0.0)),
+        (ImguiKey::GamepadRStickDown, (-right_y).max(0.0)),
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GamepadEvent {
+    Connected { id: JoystickId, name: String },
+    Disconnected { id: JoystickId, name: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gamepad {
+    pub id: JoystickId,
+    pub name: String,
+    pub input: GamepadInput,
+}
+
+/// The connected gamepads, polled once per frame.
+#[derive(Clone, Debug, Default)]
+pub struct Gamepads {
+    pub settings: GamepadSettings,
+    pads: Vec<Gamepad>,
+}
+
+impl Gamepads {
+    pub fn new(settings: GamepadSettings) -> Self {
+        Gamepads { settings, pads: Vec::new() }
+    }
+
+    /// Adds mappings in the SDL_GameControllerDB format to the ones built into GLFW.
+    pub fn load_mappings(glfw: &glfw::Glfw, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mappings = std::fs::read_to_string(path)?;
+        if !glfw.update_gamepad_mappings(&mappings) {
+            return Err(format!("Invalid gamepad mappings in {}", path.display()).into());
+        }
+        Ok(())
+    }
+
+    /// Reads every joystick GLFW has a gamepad mapping for and reports the ones that came and went.
+    pub fn poll(&mut self, glfw: &glfw::Glfw) -> Vec<GamepadEvent> {
+        let readings = JOYSTICKS
+            .iter()
+            .filter_map(|id| {
+                let joystick = glfw.get_joystick(*id);
+                if !joystick.is_gamepad() {
+                    return None;
+                }
+                let state = joystick.get_gamepad_state()?;
+                let name = joystick.get_gamepad_name().or_else(|| joystick.get_name()).unwrap_or_default();
+                Some((*id, name, RawGamepadState::from_glfw(&state)))
+            })
+            .collect();
+
+        self.update(readings)
+    }
+
+    /// Replaces the connected gamepads with `readings`.
+    pub fn update(&mut self, readings: Vec<(JoystickId, String, RawGamepadState)>) -> Vec<GamepadEvent> {
+        let mut events: Vec<GamepadEvent> = self
+            .pads
+            .iter()
+            .filter(|pad| !readings.iter().any(|(id, _, _)| *id == pad.id))
+            .map(|pad| GamepadEvent::Disconnected { id: pad.id, name: pad.name.clone() })
+            .collect();
+
+        events.extend(
+            readings
+                .iter()
+                .filter(|(id, _, _)| !self.pads.iter().any(|pad| pad.id == *id))
+                .map(|(id, name, _)| GamepadEvent::Connected { id: *id, name: name.clone() }),
+        );
+
+        self.pads = readings
+            .into_iter()
+            .map(|(id, name, raw)| Gamepad { id, name, input: GamepadInput::from_raw(&raw, &self.settings) })
+            .collect();
+
+        events
+    }
+
+    pub fn gamepads(&self) -> &[Gamepad] {
+        &self.pads
+    }
+
+    /// All connected gamepads as one, `None` without any.
+    pub fn combined(&self) -> Option<GamepadInput> {
+        self.pads.iter().map(|pad| pad.input).reduce(|combined, input| combined.combine(&input))
+    }
+
+    pub fn button(&self, button: GamepadButton) -> bool {
+        self.pads.iter().any(|pad| pad.input.button(button))
+    }
+
+    /// The axis on whichever gamepad moves it furthest, 0 without any.
+    pub fn axis(&self, axis: Axis) -> f32 {
+        self.combined().map_or(0.0, |input| input.axis(axis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{} is not {}", actual, expected);
+    }
+
+    fn buttons(held: &[GamepadButton]) -> [bool; BUTTON_COUNT] {
+        BUTTONS.map(|button| held.contains(&button))
+    }
+
+    fn raw(axes: [(GamepadAxis, f32); 2], held: &[GamepadButton]) -> RawGamepadState {
+        let mut raw = RawGamepadState { buttons: buttons(held), ..RawGamepadState::default() };
+        for (axis, value) in axes {
+            raw.axes[axis as usize] = value;
+        }
+        raw
+    }
+
+    #[test]
+    fn axial_deadzone_drops_small_readings() {
+        let deadzone = Deadzone::new(0.2);
+
+        assert_eq!(deadzone.apply(0.0), 0.0);
+        assert_eq!(deadzone.apply(0.15), 0.0);
+        assert_eq!(deadzone.apply(-0.2), 0.0);
+        assert_close(deadzone.apply(0.6), 0.5);
+        assert_close(deadzone.apply(-0.6), -0.5);
+        assert_close(deadzone.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn deadzone_rescales_to_the_outer_edge() {
+        let deadzone = Deadzone { inner: 0.1, outer: 0.9 };
+
+        assert_close(deadzone.apply(0.5), 0.5);
+        assert_close(deadzone.apply(0.9), 1.0);
+        assert_close(deadzone.apply(1.0), 1.0);
+        assert_close(deadzone.apply(-0.95), -1.0);
+    }
+
+    #[test]
+    fn deadzone_is_continuous_at_the_inner_edge() {
+        let deadzone = Deadzone::new(0.25);
+        assert!(deadzone.apply(0.2501) < 0.001);
+        assert!(deadzone.apply_stick([0.2501, 0.0])[0] < 0.001);
+    }
+
+    #[test]
+    fn degenerate_deadzone_does_not_divide_by_zero() {
+        let deadzone = Deadzone { inner: 0.5, outer: 0.5 };
+        assert_eq!(deadzone.apply(0.6), 1.0);
+        assert_eq!(deadzone.apply(0.4), 0.0);
+    }
+
+    #[test]
+    fn radial_deadzone_keeps_diagonals() {
+        let deadzone = Deadzone::new(0.2);
+
+        // Each axis alone is inside the deadzone, together they are not.
+        let [x, y] = deadzone.apply_stick([0.18, 0.18]);
+        assert!(x > 0.0 && y > 0.0);
+        assert_close(x, y);
+
+        assert_eq!(deadzone.apply_stick([0.1, -0.1]), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn radial_deadzone_rescales_the_length() {
+        let deadzone = Deadzone::new(0.2);
+
+        let [x, y] = deadzone.apply_stick([0.36, 0.48]);
+        assert_close((x * x + y * y).sqrt(), 0.5);
+        assert_close(x / y, 0.75);
+
+        // Readings past the edge, as square gates report on diagonals, are cut to length 1.
+        let [x, y] = deadzone.apply_stick([1.0, 1.0]);
+        assert_close((x * x + y * y).sqrt(), 1.0);
+    }
+
+    #[test]
+    fn raw_readings_are_converted() {
+        let settings = GamepadSettings { stick_deadzone: Deadzone::new(0.0), trigger_deadzone: Deadzone::new(0.0) };
+        let raw = raw([(GamepadAxis::AxisLeftY, -0.5), (GamepadAxis::AxisRightTrigger, 0.0)], &[GamepadButton::ButtonY]);
+
+        let input = GamepadInput::from_raw(&raw, &settings);
+
+        // GLFW's down is positive, up is positive after conversion.
+        assert_close(input.axis(Axis::LeftY), 0.5);
+        assert_close(input.axis(Axis::RightTrigger), 0.5);
+        assert_eq!(input.axis(Axis::LeftTrigger), 0.0);
+        assert!(input.button(GamepadButton::ButtonY));
+        assert!(!input.button(GamepadButton::ButtonA));
+    }
+
+    #[test]
+    fn released_triggers_and_centered_sticks_read_zero() {
+        let input = GamepadInput::from_raw(&RawGamepadState::default(), &GamepadSettings::default());
+        assert_eq!(input, GamepadInput::default());
+    }
+
+    #[test]
+    fn combine_takes_the_furthest_axis_and_any_button() {
+        let left = GamepadInput { left_stick: [0.5, -0.2], left_trigger: 0.3, buttons: buttons(&[GamepadButton::ButtonA]), ..Default::default() };
+        let right = GamepadInput { left_stick: [-0.7, 0.1], left_trigger: 0.1, buttons: buttons(&[GamepadButton::ButtonY]), ..Default::default() };
+
+        let combined = left.combine(&right);
+
+        assert_eq!(combined.left_stick, [-0.7, -0.2]);
+        assert_eq!(combined.left_trigger, 0.3);
+        assert!(combined.button(GamepadButton::ButtonA) && combined.button(GamepadButton::ButtonY));
+    }
+
+    #[test]
+    fn hot_plug_events() {
+        let mut gamepads = Gamepads::new(GamepadSettings::default());
+        let pad = |id, name: &str| (id, name.to_string(), RawGamepadState::default());
+
+        let events = gamepads.update(vec![pad(JoystickId::Joystick1, "Xbox Controller")]);
+        assert_eq!(events, [GamepadEvent::Connected { id: JoystickId::Joystick1, name: "Xbox Controller".to_string() }]);
+
+        // Nothing changes while the same pads stay connected.
+        assert!(gamepads.update(vec![pad(JoystickId::Joystick1, "Xbox Controller")]).is_empty());
+
+        let events = gamepads.update(vec![pad(JoystickId::Joystick1, "Xbox Controller"), pad(JoystickId::Joystick2, "DualSense")]);
+        assert_eq!(events, [GamepadEvent::Connected { id: JoystickId::Joystick2, name: "DualSense".to_string() }]);
+        assert_eq!(gamepads.gamepads().len(), 2);
+
+        let events = gamepads.update(vec![pad(JoystickId::Joystick2, "DualSense")]);
+        assert_eq!(events, [GamepadEvent::Disconnected { id: JoystickId::Joystick1, name: "Xbox Controller".to_string() }]);
+
+        let events = gamepads.update(Vec::new());
+        assert_eq!(events, [GamepadEvent::Disconnected { id: JoystickId::Joystick2, name: "DualSense".to_string() }]);
+        assert!(gamepads.combined().is_none());
+        assert_eq!(gamepads.axis(Axis::LeftX), 0.0);
+    }
+
+    #[test]
+    fn replugging_in_one_poll_reports_both_events() {
+        let mut gamepads = Gamepads::default();
+        gamepads.update(vec![(JoystickId::Joystick1, "Old".to_string(), RawGamepadState::default())]);
+
+        let events = gamepads.update(vec![(JoystickId::Joystick3, "New".to_string(), RawGamepadState::default())]);
+
+        assert_eq!(
+            events,
+            [
+                GamepadEvent::Disconnected { id: JoystickId::Joystick1, name: "Old".to_string() },
+                GamepadEvent::Connected { id: JoystickId::Joystick3, name: "New".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn buttons_and_axes_of_every_pad_count() {
+        let mut gamepads = Gamepads::new(GamepadSettings::default());
+        gamepads.update(vec![
+            (JoystickId::Joystick1, "A".to_string(), raw([(GamepadAxis::AxisLeftX, 0.4), (GamepadAxis::AxisRightX, 0.0)], &[])),
+            (JoystickId::Joystick2, "B".to_string(), raw([(GamepadAxis::AxisLeftX, -0.9), (GamepadAxis::AxisRightX, 0.0)], &[GamepadButton::ButtonStart])),
+        ]);
+
+        assert!(gamepads.button(GamepadButton::ButtonStart));
+        assert!(!gamepads.button(GamepadButton::ButtonA));
+        assert_close(gamepads.axis(Axis::LeftX), -0.875);
+    }
+
+    #[test]
+    fn imgui_keys_split_sticks_by_direction() {
+        let input = GamepadInput { left_stick: [-0.5, 0.25], right_trigger: 0.75, ..Default::default() };
+        let keys = imgui_keys(&input);
+        let value = |key| keys.iter().find(|(other, _)| *other == key).unwrap().1;
+
+        assert_eq!(value(ImguiKey::GamepadLStickLeft), 0.5);
+        assert_eq!(value(ImguiKey::GamepadLStickRight), 0.0);
+        assert_eq!(value(ImguiKey::GamepadLStickUp), 0.25);
+        assert_eq!(value(ImguiKey::GamepadLStickDown), 0.0);
+        assert_eq!(value(ImguiKey::GamepadR2), 0.75);
+        assert_eq!(value(ImguiKey::GamepadFaceDown), 0.0);
+    }
+}
@@ -0,0 +1,143 @@
+//! glTF 2.0 importer (`.gltf` and `.glb`).
+//!
+//! Flattens the default scene into a single [`MeshData`], applying node transforms. Every triangle
+//! primitive becomes a submesh that keeps the glTF material index.
+
+use std::path::Path;
+
+use crate::mesh::{MeshData, MeshVertex, Submesh};
+
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Multiplies two column-major matrices.
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for column in 0..4 {
+        for row in 0..4 {
+            result[column][row] = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+fn transform_point(m: &Matrix, p: [f32; 3]) -> [f32; 3] {
+    [
+        m[0][0] * p[0] + m[1][0] * p[1] + m[2][0] * p[2] + m[3][0],
+        m[0][1] * p[0] + m[1][1] * p[1] + m[2][1] * p[2] + m[3][1],
+        m[0][2] * p[0] + m[1][2] * p[1] + m[2][2] * p[2] + m[3][2],
+    ]
+}
+
+/// Transforms a normal with the cofactor matrix of the upper 3x3, which handles non-uniform scale.
+fn transform_normal(m: &Matrix, n: [f32; 3]) -> [f32; 3] {
+    let column = |i: usize| [m[i][0], m[i][1], m[i][2]];
+    let cross = |a: [f32; 3], b: [f32; 3]| [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+
+    // Rows of the cofactor matrix are the cross products of the columns.
+    let rows = [cross(column(1), column(2)), cross(column(2), column(0)), cross(column(0), column(1))];
+    let result = [
+        rows[0][0] * n[0] + rows[1][0] * n[1] + rows[2][0] * n[2],
+        rows[0][1] * n[0] + rows[1][1] * n[1] + rows[2][1] * n[2],
+        rows[0][2] * n[0] + rows[1][2] * n[1] + rows[2][2] * n[2],
+    ];
+
+    let length = (result[0] * result[0] + result[1] * result[1] + result[2] * result[2]).sqrt();
+    if length > 0.0 {
+        [result[0] / length, result[1] / length, result[2] / length]
+    } else {
+        result
+    }
+}
+
+fn determinant(m: &Matrix) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2])
+        - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+        + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2])
+}
+
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<MeshData, Box<dyn std::error::Error>> {
+    let (document, buffers, _images) = gltf::import(path)?;
+
+    let scene = document.default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or("glTF file has no scenes")?;
+
+    let mut mesh = MeshData::default();
+    for node in scene.nodes() {
+        load_node(&node, &IDENTITY, &buffers, &mut mesh);
+    }
+
+    Ok(mesh)
+}
+
+fn load_node(node: &gltf::Node, parent: &Matrix, buffers: &[gltf::buffer::Data], mesh: &mut MeshData) {
+    let transform = multiply(parent, &node.transform().matrix());
+
+    if let Some(node_mesh) = node.mesh() {
+        // Mirroring transforms flip the winding order.
+        let flip_winding = determinant(&transform) < 0.0;
+
+        for primitive in node_mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()][..]));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+
+            let mut normals = reader.read_normals();
+            let mut uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32());
+
+            let mut part = MeshData::default();
+            for position in positions {
+                part.vertices.push(MeshVertex {
+                    position: transform_point(&transform, position),
+                    normal: normals.as_mut()
+                        .and_then(|normals| normals.next())
+                        .map_or([0.0; 3], |normal| transform_normal(&transform, normal)),
+                    uv: uvs.as_mut().and_then(|uvs| uvs.next()).unwrap_or([0.0; 2]),
+                });
+            }
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..part.vertices.len() as u32).collect(),
+            };
+            for triangle in indices.chunks_exact(3) {
+                if flip_winding {
+                    part.indices.extend_from_slice(&[triangle[0], triangle[2], triangle[1]]);
+                } else {
+                    part.indices.extend_from_slice(triangle);
+                }
+            }
+
+            if normals.is_none() {
+                part.generate_normals();
+            }
+
+            let base_vertex = mesh.vertices.len() as u32;
+            let index_offset = mesh.indices.len() as u32;
+            mesh.vertices.extend(part.vertices);
+            mesh.indices.extend(part.indices.iter().map(|index| base_vertex + index));
+
+            mesh.submeshes.push(Submesh {
+                index_offset,
+                index_count: mesh.indices.len() as u32 - index_offset,
+                material_index: primitive.material().index().map_or(0, |index| index as u32),
+            });
+        }
+    }
+
+    for child in node.children() {
+        load_node(&child, &transform, buffers, mesh);
+    }
+}
@@ -0,0 +1,118 @@
+//! Uploads `.mesh` files into Direct3D 11 buffers.
+
+use windows::{
+    core::*, Win32::Graphics::Direct3D11::*, Win32::Graphics::Dxgi::Common::*,
+};
+
+use crate::mesh::{MeshBounds, Submesh};
+use crate::mesh_file::{MeshFileView, VertexFormat, VertexSemantic};
+
+pub struct GpuMeshLod {
+    pub index_buffer: ID3D11Buffer,
+    pub index_count: u32,
+    pub submeshes: Vec<Submesh>,
+    pub error: f32,
+}
+
+pub struct GpuMesh {
+    pub vertex_buffers: Vec<ID3D11Buffer>,
+    pub vertex_strides: Vec<u32>,
+    pub index_format: DXGI_FORMAT,
+    pub lods: Vec<GpuMeshLod>,
+    pub bounds: MeshBounds,
+    pub input_elements: Vec<D3D11_INPUT_ELEMENT_DESC>,
+}
+
+impl GpuMesh {
+    /// Creates immutable vertex and index buffers directly from the (memory mapped) file data.
+    pub fn new(device: &ID3D11Device, file: &MeshFileView) -> Result<Self> {
+        let vertex_buffers = file.streams.iter()
+            .map(|stream| Self::create_buffer(device, stream.data, D3D11_BIND_VERTEX_BUFFER))
+            .collect::<Result<Vec<_>>>()?;
+
+        let lods = file.lods.iter()
+            .map(|lod| {
+                Ok(GpuMeshLod {
+                    index_buffer: Self::create_buffer(device, lod.index_data, D3D11_BIND_INDEX_BUFFER)?,
+                    index_count: lod.index_count,
+                    submeshes: lod.submeshes.clone(),
+                    error: lod.error,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let input_elements = file.attributes.iter()
+            .map(|attribute| D3D11_INPUT_ELEMENT_DESC {
+                SemanticName: match attribute.semantic {
+                    VertexSemantic::Position => s!("POSITION"),
+                    VertexSemantic::Normal => s!("NORMAL"),
+                    VertexSemantic::TexCoord => s!("TEXCOORD"),
+                    VertexSemantic::Color => s!("COLOR"),
+                    VertexSemantic::Tangent => s!("TANGENT"),
+                },
+                SemanticIndex: attribute.semantic_index as u32,
+                Format: match attribute.format {
+                    VertexFormat::Float2 => DXGI_FORMAT_R32G32_FLOAT,
+                    VertexFormat::Float3 => DXGI_FORMAT_R32G32B32_FLOAT,
+                    VertexFormat::Float4 => DXGI_FORMAT_R32G32B32A32_FLOAT,
+                    VertexFormat::UNorm8x4 => DXGI_FORMAT_R8G8B8A8_UNORM,
+                },
+                InputSlot: attribute.stream as u32,
+                AlignedByteOffset: attribute.offset,
+                InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA,
+                InstanceDataStepRate: 0,
+            })
+            .collect();
+
+        Ok(GpuMesh {
+            vertex_buffers,
+            vertex_strides: file.streams.iter().map(|stream| stream.stride).collect(),
+            index_format: if file.index_32 { DXGI_FORMAT_R32_UINT } else { DXGI_FORMAT_R16_UINT },
+            lods,
+            bounds: file.bounds,
+            input_elements,
+        })
+    }
+
+    fn create_buffer(device: &ID3D11Device, data: &[u8], bind_flags: D3D11_BIND_FLAG) -> Result<ID3D11Buffer> {
+        let buffer_info = D3D11_BUFFER_DESC {
+            ByteWidth: data.len() as u32,
+            Usage: D3D11_USAGE_IMMUTABLE,
+            BindFlags: bind_flags.0 as u32,
+            ..Default::default()
+        };
+
+        let resource_data = D3D11_SUBRESOURCE_DATA {
+            pSysMem: data.as_ptr().cast(),
+            ..Default::default()
+        };
+
+        let mut buffer = None;
+        unsafe {
+            device.CreateBuffer(
+                &buffer_info,
+                Some(&resource_data),
+                Some(&mut buffer),
+            )?;
+        }
+
+        Ok(buffer.unwrap())
+    }
+
+    /// Binds the vertex streams and the index buffer of `lod` to the input assembler.
+    pub fn bind(&self, context: &ID3D11DeviceContext, lod: usize) {
+        let buffers: Vec<Option<ID3D11Buffer>> = self.vertex_buffers.iter().cloned().map(Some).collect();
+        let offsets = vec![0u32; buffers.len()];
+
+        unsafe {
+            context.IASetVertexBuffers(
+                0,
+                buffers.len() as u32,
+                Some(buffers.as_ptr()),
+                Some(self.vertex_strides.as_ptr()),
+                Some(offsets.as_ptr()),
+            );
+            context.IASetIndexBuffer(&self.lods[lod].index_buffer, self.index_format, 0);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Creates Direct3D 11 textures and shader resource views from [`TextureData`].
+
+use windows::{
+    core::*, Win32::Graphics::Direct3D::*, Win32::Graphics::Direct3D11::*, Win32::Graphics::Dxgi::Common::*,
+};
+
+use crate::texture::{TextureData, TextureFormat};
+
+pub fn dxgi_format(format: TextureFormat) -> DXGI_FORMAT {
+    match format {
+        TextureFormat::R8Unorm => DXGI_FORMAT_R8_UNORM,
+        TextureFormat::Rg8Unorm => DXGI_FORMAT_R8G8_UNORM,
+        TextureFormat::Rgba8Unorm => DXGI_FORMAT_R8G8B8A8_UNORM,
+        TextureFormat::Rgba8UnormSrgb => DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+        TextureFormat::Bgra8Unorm => DXGI_FORMAT_B8G8R8A8_UNORM,
+        TextureFormat::Bgra8UnormSrgb => DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+        TextureFormat::Rgb10A2Unorm => DXGI_FORMAT_R10G10B10A2_UNORM,
+        TextureFormat::Rg11B10Float => DXGI_FORMAT_R11G11B10_FLOAT,
+        TextureFormat::R16Float => DXGI_FORMAT_R16_FLOAT,
+        TextureFormat::Rg16Float => DXGI_FORMAT_R16G16_FLOAT,
+        TextureFormat::Rgba16Unorm => DXGI_FORMAT_R16G16B16A16_UNORM,
+        TextureFormat::Rgba16Float => DXGI_FORMAT_R16G16B16A16_FLOAT,
+        TextureFormat::R32Float => DXGI_FORMAT_R32_FLOAT,
+        TextureFormat::Rgba32Float => DXGI_FORMAT_R32G32B32A32_FLOAT,
+        TextureFormat::Bc1Unorm => DXGI_FORMAT_BC1_UNORM,
+        TextureFormat::Bc1UnormSrgb => DXGI_FORMAT_BC1_UNORM_SRGB,
+        TextureFormat::Bc2Unorm => DXGI_FORMAT_BC2_UNORM,
+        TextureFormat::Bc2UnormSrgb => DXGI_FORMAT_BC2_UNORM_SRGB,
+        TextureFormat::Bc3Unorm => DXGI_FORMAT_BC3_UNORM,
+        TextureFormat::Bc3UnormSrgb => DXGI_FORMAT_BC3_UNORM_SRGB,
+        TextureFormat::Bc4Unorm => DXGI_FORMAT_BC4_UNORM,
+        TextureFormat::Bc4Snorm => DXGI_FORMAT_BC4_SNORM,
+        TextureFormat::Bc5Unorm => DXGI_FORMAT_BC5_UNORM,
+        TextureFormat::Bc5Snorm => DXGI_FORMAT_BC5_SNORM,
+        TextureFormat::Bc6hUfloat => DXGI_FORMAT_BC6H_UF16,
+        TextureFormat::Bc6hSfloat => DXGI_FORMAT_BC6H_SF16,
+        TextureFormat::Bc7Unorm => DXGI_FORMAT_BC7_UNORM,
+        TextureFormat::Bc7UnormSrgb => DXGI_FORMAT_BC7_UNORM_SRGB,
+    }
+}
+
+pub struct GpuTexture {
+    pub texture: ID3D11Texture2D,
+    pub view: ID3D11ShaderResourceView,
+    pub format: DXGI_FORMAT,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl GpuTexture {
+    /// Creates an immutable texture with every subresource of `data` and a view over all of them.
+    pub fn new(device: &ID3D11Device, data: &TextureData) -> Result<Self> {
+        let format = dxgi_format(data.format);
+
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: data.width,
+            Height: data.height,
+            MipLevels: data.mip_levels,
+            ArraySize: data.array_layers,
+            Format: format,
+            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+            Usage: D3D11_USAGE_IMMUTABLE,
+            BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+            MiscFlags: if data.is_cube { D3D11_RESOURCE_MISC_TEXTURECUBE.0 as u32 } else { 0 },
+            ..Default::default()
+        };
+
+        let sub_resources: Vec<D3D11_SUBRESOURCE_DATA> = data.subresources.iter()
+            .map(|subresource| D3D11_SUBRESOURCE_DATA {
+                pSysMem: data.data[subresource.offset..].as_ptr().cast(),
+                SysMemPitch: subresource.row_pitch,
+                SysMemSlicePitch: subresource.slice_pitch,
+            })
+            .collect();
+
+        let mut texture = None;
+        unsafe {
+            device.CreateTexture2D(
+                &desc,
+                Some(sub_resources.as_ptr()),
+                Some(&mut texture),
+            )?;
+        }
+        let texture = texture.unwrap();
+
+        let mut srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+            Format: format,
+            ..Default::default()
+        };
+        match (data.is_cube, data.array_layers) {
+            (true, 6) => {
+                srv_desc.ViewDimension = D3D_SRV_DIMENSION_TEXTURECUBE;
+                srv_desc.Anonymous.TextureCube.MipLevels = data.mip_levels;
+            }
+            (true, layers) => {
+                srv_desc.ViewDimension = D3D_SRV_DIMENSION_TEXTURECUBEARRAY;
+                srv_desc.Anonymous.TextureCubeArray.MipLevels = data.mip_levels;
+                srv_desc.Anonymous.TextureCubeArray.NumCubes = layers / 6;
+            }
+            (false, 1) => {
+                srv_desc.ViewDimension = D3D_SRV_DIMENSION_TEXTURE2D;
+                srv_desc.Anonymous.Texture2D.MipLevels = data.mip_levels;
+            }
+            (false, layers) => {
+                srv_desc.ViewDimension = D3D_SRV_DIMENSION_TEXTURE2DARRAY;
+                srv_desc.Anonymous.Texture2DArray.MipLevels = data.mip_levels;
+                srv_desc.Anonymous.Texture2DArray.ArraySize = layers;
+            }
+        }
+
+        let mut view = None;
+        unsafe {
+            device.CreateShaderResourceView(
+                &texture,
+                Some(&srv_desc),
+                Some(&mut view),
+            )?;
+        }
+
+        Ok(GpuTexture {
+            texture,
+            view: view.unwrap(),
+            format,
+            width: data.width,
+            height: data.height,
+        })
+    }
+}
@@ -3,7 +3,6 @@
 
 //! This crate offers a DirectX 11 renderer for the [imgui-rs](https://docs.rs/imgui/*/imgui/) rust bindings.
 
-use core::ptr::null;
 use core::{mem, slice};
 
 use imgui::internal::RawWrapper;
@@ -17,6 +16,10 @@ use windows::Win32::Graphics::Direct3D11::*;
 use windows::Win32::Graphics::Dxgi::Common::*;
 use windows::Win32::Graphics::Dxgi::*;
 
+use crate::gpu_texture::GpuTexture;
+use crate::pipeline_state::{BlendDesc, DepthStencilDesc, PipelineCache, RasterizerDesc};
+use crate::sampler_cache::{AddressMode, SamplerCache, SamplerDesc, SamplerFilter};
+use crate::texture::{TextureData, TextureFormat};
 use crate::window_application::WindowApplication;
 
 const FONT_TEX_ID: usize = !0;
@@ -56,11 +59,17 @@ impl Renderer {
     /// `device` must be a valid [`ID3D11Device`] pointer.
     ///
     /// [`ID3D11Device`]: https://docs.rs/winapi/0.3/x86_64-pc-windows-msvc/winapi/um/d3d11/struct.ID3D11Device.html
-    pub unsafe fn new(im_ctx: &mut imgui::Context, device: &ID3D11Device, context: &ID3D11DeviceContext) -> Result<Self> {
+    pub unsafe fn new(
+        im_ctx: &mut imgui::Context,
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        samplers: &mut SamplerCache,
+        pipelines: &mut PipelineCache,
+    ) -> Result<Self> {
         let (vertex_shader, input_layout, constant_buffer) = Self::create_vertex_shader(device)?;
         let pixel_shader = Self::create_pixel_shader(device)?;
-        let (blend_state, rasterizer_state, depth_stencil_state) = Self::create_device_objects(device)?;
-        let (font_resource_view, font_sampler) = Self::create_font_texture(im_ctx.fonts(), device)?;
+        let (blend_state, rasterizer_state, depth_stencil_state) = Self::create_device_objects(pipelines)?;
+        let (font_resource_view, font_sampler) = Self::create_font_texture(im_ctx.fonts(), device, samplers)?;
         let vertex_buffer = Self::create_vertex_buffer(device, 0)?;
         let index_buffer = Self::create_index_buffer(device, 0)?;
 
@@ -88,19 +97,32 @@ impl Renderer {
         })
     }
 
-    /// The textures registry of this renderer.
+    /// Recreates every device object on `device` after the previous device was lost.
     ///
-    /// The texture slot at !0 is reserved for the font texture, therefore the
-    /// renderer will ignore any texture inserted into said slot.
-    #[inline]
-    pub fn textures_mut(&mut self) -> &mut Textures<ID3D11ShaderResourceView> {
-        &mut self.textures
+    /// Textures in the registry belong to the old device and are removed, their owners have to
+    /// insert them again.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be a valid [`ID3D11Device`] pointer.
+    pub unsafe fn recreate_device_objects(
+        &mut self,
+        im_ctx: &mut imgui::Context,
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        samplers: &mut SamplerCache,
+        pipelines: &mut PipelineCache,
+    ) -> Result<()> {
+        *self = Self::new(im_ctx, device, context, samplers, pipelines)?;
+        Ok(())
     }
 
-    /// The textures registry of this renderer.
-    #[inline]
-    pub fn textures(&self) -> &Textures<ID3D11ShaderResourceView> {
-        &self.textures
+    /// Uploads the font atlas again after its fonts were changed, e.g. for a new DPI scale.
+    pub fn rebuild_font_texture(&mut self, im_ctx: &mut imgui::Context, samplers: &mut SamplerCache) -> Result<()> {
+        let (font_resource_view, font_sampler) = unsafe { Self::create_font_texture(im_ctx.fonts(), &self.device, samplers)? };
+        self.font_resource_view = font_resource_view;
+        self.font_sampler = font_sampler;
+        Ok(())
     }
 
     /// Renders the given [`Ui`] with this renderer.
@@ -140,7 +162,7 @@ impl Renderer {
 
     unsafe fn render_impl(&self, draw_data: &DrawData) -> Result<()> {
         let clip_off = draw_data.display_pos;
-        // let clip_scale = draw_data.framebuffer_scale;
+        let clip_scale = draw_data.framebuffer_scale;
         let mut global_vertex_offset = 0;
         let mut global_index_offset = 0;
         let mut last_tex = TextureId::from(FONT_TEX_ID);
@@ -154,8 +176,8 @@ impl Renderer {
                         cmd_params: DrawCmdParams { clip_rect, texture_id, idx_offset, vtx_offset },
                     } => {
 
-                        let clip_min = [(clip_rect[0] - clip_off[0]) as i32, (clip_rect[1] - clip_off[1]) as i32];
-                        let clip_max = [(clip_rect[2] - clip_off[0]) as i32, (clip_rect[3] - clip_off[1]) as i32];
+                        let clip_min = [((clip_rect[0] - clip_off[0]) * clip_scale[0]) as i32, ((clip_rect[1] - clip_off[1]) * clip_scale[1]) as i32];
+                        let clip_max = [((clip_rect[2] - clip_off[0]) * clip_scale[0]) as i32, ((clip_rect[3] - clip_off[1]) * clip_scale[1]) as i32];
                         if clip_max[0] <= clip_min[0] || clip_max[1] <= clip_min[1] {
                             continue;
                         }
@@ -170,31 +192,24 @@ impl Renderer {
 
 
 
-                        let texture = self.textures
-                            .get(texture_id)
-                            .ok_or(DXGI_ERROR_INVALID_CALL)?
-                            .clone();
+                        if texture_id != last_tex {
+                            let texture = if texture_id.id() == FONT_TEX_ID {
+                                self.font_resource_view.clone()
+                            } else {
+                                self.textures
+                                    .get(texture_id)
+                                    .ok_or(DXGI_ERROR_INVALID_CALL)?
+                                    .clone()
+                            };
+                            context.PSSetShaderResources(0, Some(&[Some(texture)]));
+                            last_tex = texture_id;
+                        }
 
-                        context.PSSetShaderResources(0, Some(&[Some(texture)]));
                         context.DrawIndexed(
                             count as u32,
                             (idx_offset + global_index_offset) as u32,
                             (vtx_offset + global_vertex_offset) as i32,
                         );
-
-
-                        // if texture_id != last_tex {
-                        //     let texture = if texture_id.id() == FONT_TEX_ID {
-                        //         self.font_resource_view.clone()
-                        //     } else {
-                        //         self.textures
-                        //             .get(texture_id)
-                        //             .ok_or(DXGI_ERROR_INVALID_CALL)?
-                        //             .clone()
-                        //     };
-                        //     context.PSSetShaderResources(0, Some(&[Some(texture)]));
-                        //     last_tex = texture_id;
-                        // }
                     },
                     DrawCmd::ResetRenderState => self.setup_render_state(draw_data),
                     DrawCmd::RawCallback { callback, raw_cmd } => {
@@ -213,8 +228,8 @@ impl Renderer {
         let vp = D3D11_VIEWPORT {
             TopLeftX: 0.0,
             TopLeftY: 0.0,
-            Width: draw_data.display_size[0],
-            Height: draw_data.display_size[1],
+            Width: draw_data.display_size[0] * draw_data.framebuffer_scale[0],
+            Height: draw_data.display_size[1] * draw_data.framebuffer_scale[1],
             MinDepth: 0.0,
             MaxDepth: 1.0,
         };
@@ -390,89 +405,25 @@ impl Renderer {
     unsafe fn create_font_texture(
         fonts: &mut imgui::FontAtlas,
         device: &ID3D11Device,
+        samplers: &mut SamplerCache,
     ) -> Result<(ID3D11ShaderResourceView, ID3D11SamplerState)> {
         let fa_tex = fonts.build_rgba32_texture();
 
-        let desc = D3D11_TEXTURE2D_DESC {
-            Width: fa_tex.width,
-            Height: fa_tex.height,
-            MipLevels: 1,
-            ArraySize: 1,
-            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
-            Usage: D3D11_USAGE_DEFAULT,
-            BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
-            ..Default::default()
-        };
-        let sub_resource = D3D11_SUBRESOURCE_DATA {
-            pSysMem: fa_tex.data.as_ptr().cast(),
-            SysMemPitch: desc.Width * 4,
-            SysMemSlicePitch: 0,
-        };
-
-        let mut texture = None;
-        unsafe { 
-            device.CreateTexture2D(
-                &desc,
-                Some(&sub_resource),
-                Some(&mut texture),
-            ).unwrap();
-        }
-
-
-        // let texture = None;
-        // let texture = device.CreateTexture2D(&desc, Some(&sub_resource), texture)
-        //     .map(|()| texture.unwrap().as_ref().unwrap().as_ref().unwrap())?;
-
-        let mut srv_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
-            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-            ViewDimension: D3D11_SRV_DIMENSION_TEXTURE2D,
-            ..Default::default()
-        };
-        srv_desc.Anonymous.Texture2D.MipLevels = desc.MipLevels;
-        srv_desc.Anonymous.Texture2D.MostDetailedMip = 0;
-
-        let mut font_texture_view = None;
-        unsafe { 
-            device.CreateShaderResourceView(
-                &texture.unwrap(),
-                Some(&srv_desc),
-                Some(&mut font_texture_view),
-            ).unwrap();
-        }
-
-
-        // let font_texture_view = None;
-        // let font_texture_view = device.CreateShaderResourceView(&texture.unwrap(), Some(&srv_desc), font_texture_view)
-        //     .map(|()| font_texture_view.unwrap().as_ref().unwrap().as_ref().unwrap())?;
+        // A single level, mips would blend neighboring glyphs of the tightly packed atlas. Fonts
+        // are rebuilt at the content scale instead, so the atlas is never minified much.
+        let font_atlas = TextureData::from_pixels(
+            TextureFormat::Rgba8Unorm,
+            fa_tex.width,
+            fa_tex.height,
+            fa_tex.data.to_vec(),
+        );
+        let font_texture = GpuTexture::new(device, &font_atlas)?;
 
         fonts.tex_id = TextureId::from(FONT_TEX_ID);
 
-        let desc = D3D11_SAMPLER_DESC {
-            Filter: D3D11_FILTER_MIN_MAG_MIP_LINEAR,
-            AddressU: D3D11_TEXTURE_ADDRESS_WRAP,
-            AddressV: D3D11_TEXTURE_ADDRESS_WRAP,
-            AddressW: D3D11_TEXTURE_ADDRESS_WRAP,
-            MipLODBias: 0.0,
-            ComparisonFunc: D3D11_COMPARISON_ALWAYS,
-            MinLOD: 0.0,
-            MaxLOD: 0.0,
-            ..Default::default()
-        };
-
-        let mut font_sampler = None;
-        unsafe { 
-            device.CreateSamplerState(
-                &desc,
-                Some(&mut font_sampler),
-            ).unwrap();
-        }
-
-        // let font_sampler = None;
-        // let font_sampler = device.CreateSamplerState(&desc, font_sampler)
-        //     .map(|()| font_sampler.unwrap().as_ref().unwrap().as_ref().unwrap())?;
+        let font_sampler = samplers.get(&SamplerDesc::new(SamplerFilter::Bilinear, AddressMode::Wrap).with_lod_range(0.0, 0.0))?;
 
-        Ok((font_texture_view.unwrap(), font_sampler.unwrap()))
+        Ok((font_texture.view, font_sampler))
     }
 
     unsafe fn create_vertex_shader(
@@ -510,7 +461,7 @@ impl Renderer {
 
         let local_layout = [
             D3D11_INPUT_ELEMENT_DESC {
-                SemanticName: PCSTR(b"POSITION\0".as_ptr()),
+                SemanticName: s!("POSITION"),
                 SemanticIndex: 0,
                 Format: DXGI_FORMAT_R32G32_FLOAT,
                 InputSlot: 0,
@@ -519,7 +470,7 @@ impl Renderer {
                 InstanceDataStepRate: 0,
             },
             D3D11_INPUT_ELEMENT_DESC {
-                SemanticName: PCSTR(b"TEXCOORD\0".as_ptr()),
+                SemanticName: s!("TEXCOORD"),
                 SemanticIndex: 0,
                 Format: DXGI_FORMAT_R32G32_FLOAT,
                 InputSlot: 0,
@@ -528,7 +479,7 @@ impl Renderer {
                 InstanceDataStepRate: 0,
             },
             D3D11_INPUT_ELEMENT_DESC {
-                SemanticName: PCSTR(b"COLOR\0".as_ptr()),
+                SemanticName: s!("COLOR"),
                 SemanticIndex: 0,
                 Format: DXGI_FORMAT_R8G8B8A8_UNORM,
                 InputSlot: 0,
@@ -609,90 +560,13 @@ impl Renderer {
     }
 
     unsafe fn create_device_objects(
-        device: &ID3D11Device,
+        pipelines: &mut PipelineCache,
     ) -> Result<(ID3D11BlendState, ID3D11RasterizerState, ID3D11DepthStencilState)> {
-        let desc = D3D11_BLEND_DESC {
-            AlphaToCoverageEnable: false.into(),
-            IndependentBlendEnable: true.into(),
-            RenderTarget: [D3D11_RENDER_TARGET_BLEND_DESC {
-                BlendEnable: true.into(),
-                SrcBlend: D3D11_BLEND_SRC_ALPHA,
-                DestBlend: D3D11_BLEND_INV_SRC_ALPHA,
-                BlendOp: D3D11_BLEND_OP_ADD,
-                SrcBlendAlpha: D3D11_BLEND_ONE,
-                DestBlendAlpha: D3D11_BLEND_INV_SRC_ALPHA,
-                BlendOpAlpha: D3D11_BLEND_OP_ADD,
-                RenderTargetWriteMask: D3D11_COLOR_WRITE_ENABLE_ALL.0 as u8,
-            }; 8],
-        };
+        let (_, blend_state) = pipelines.blend_state(&BlendDesc::ALPHA)?;
+        let (_, rasterizer_state) = pipelines.rasterizer_state(&RasterizerDesc::SCISSOR)?;
+        let (_, depth_stencil_state) = pipelines.depth_stencil_state(&DepthStencilDesc::DISABLED)?;
 
-
-        let mut blend_state = None;
-
-        unsafe { 
-            device.CreateBlendState(
-                &desc,
-                Some(&mut blend_state),
-            ).unwrap();
-        }
-
-        // let blend_state = None;
-        // let blend_state = device.CreateBlendState(&desc, blend_state)
-        //     .map(|()| blend_state.unwrap().as_ref().unwrap().as_ref().unwrap())?;
-
-        let desc = D3D11_RASTERIZER_DESC {
-            FillMode: D3D11_FILL_SOLID,
-            CullMode: D3D11_CULL_NONE,
-            DepthClipEnable: true.into(),
-            ScissorEnable: true.into(),
-            ..Default::default()
-        };
-
-        let mut rasterizer_state = None;
-
-        unsafe { 
-            device.CreateRasterizerState(
-                &desc,
-                Some(&mut rasterizer_state),
-            ).unwrap();
-        }
-
-
-        // let rasterizer_state = None;
-        // let rasterizer_state = device.CreateRasterizerState(&desc, rasterizer_state)
-        //     .map(|()| rasterizer_state.unwrap().as_ref().unwrap().as_ref().unwrap())?;
-
-        let stencil_op_desc = D3D11_DEPTH_STENCILOP_DESC {
-            StencilFailOp: D3D11_STENCIL_OP_KEEP,
-            StencilDepthFailOp: D3D11_STENCIL_OP_KEEP,
-            StencilPassOp: D3D11_STENCIL_OP_KEEP,
-            StencilFunc: D3D11_COMPARISON_ALWAYS,
-        };
-        let desc = D3D11_DEPTH_STENCIL_DESC {
-            DepthEnable: false.into(),
-            DepthWriteMask: D3D11_DEPTH_WRITE_MASK_ALL,
-            DepthFunc: D3D11_COMPARISON_ALWAYS,
-            StencilEnable: false.into(),
-            StencilReadMask: 0,
-            StencilWriteMask: 0,
-            FrontFace: stencil_op_desc,
-            BackFace: stencil_op_desc,
-        };
-
-        let mut depth_stencil_state = None;
-
-        unsafe { 
-            device.CreateDepthStencilState(
-                &desc,
-                Some(&mut depth_stencil_state),
-            ).unwrap();
-        }
-
-        // let depth_stencil_state = None;
-        // let depth_stencil_state = device.CreateDepthStencilState(&desc, depth_stencil_state)
-        //     .map(|()| depth_stencil_state.unwrap().as_ref().unwrap().as_ref().unwrap())?;
-
-        Ok((blend_state.unwrap(), rasterizer_state.unwrap(), depth_stencil_state.unwrap()))
+        Ok((blend_state, rasterizer_state, depth_stencil_state))
     }
 }
 
@@ -740,31 +614,6 @@ struct StateBackup {
     input_layout: Option<ID3D11InputLayout>,
 }
 
-#[derive(Default)]
-struct BackupState {
-    scissor_rects_count: u32,
-    viewports_count: u32,
-    scissor_rects: RECT,
-    viewports: D3D11_VIEWPORT,
-    rasterizer_state: Option<ID3D11RasterizerState>,
-}
-
-
-impl BackupState {
-    unsafe fn backup(ctx: ID3D11DeviceContext) -> Self {
-        let mut old = Self::default();
-
-        old.scissor_rects_count = D3D11_VIEWPORT_AND_SCISSORRECT_OBJECT_COUNT_PER_PIPELINE;
-        old.viewports_count = D3D11_VIEWPORT_AND_SCISSORRECT_OBJECT_COUNT_PER_PIPELINE;
-
-        ctx.RSGetScissorRects(&mut old.scissor_rects_count, Some(&mut old.scissor_rects));
-        ctx.RSGetViewports(&mut old.viewports_count, Some(&mut old.viewports));
-        old.rasterizer_state = ctx.RSGetState().ok();
-
-        old
-    }
-}
-
 impl StateBackup {
     unsafe fn backup(context: Option<ID3D11DeviceContext>) -> Self {
         let mut result = Self::default();
@@ -5,10 +5,9 @@
 //!
 //! 1. Initialize a `GlfwPlatform`
 //! 2. Attach it to a glfw `Window`
-//! 3. Optionally, enable platform clipboard integration
-//! 4. Pass events to the platform (every frame)
-//! 5. Call frame preparation (every frame)
-//! 6. Call render preperation (every frame)
+//! 3. Pass events to the platform (every frame)
+//! 4. Call frame preparation (every frame)
+//! 5. Call render preperation (every frame)
 //!
 //! ## Examples
 //!
@@ -18,11 +17,22 @@ use glfw::{
     Action, Cursor, CursorMode, Key as GlfwKey, Modifiers, MouseButton, StandardCursor, Window,
     WindowEvent,
 };
-use imgui::{BackendFlags, ConfigFlags, Context, ImString, Io, Key, Ui};
+use imgui::{BackendFlags, ConfigFlags, Context, Io, Key, Ui};
+
+use crate::gamepad::{self, GamepadInput};
 
 pub struct GlfwPlatform {
     hidpi_mode: ActiveHiDpiMode,
     hidpi_factor: f64,
+    /// Framebuffer pixels per glfw screen coordinate, 1 on Windows and 2 on a Retina display.
+    pixels_per_screen_coordinate: f64,
+    /// In screen coordinates.
+    window_size: (i32, i32),
+    framebuffer_size: (i32, i32),
+    /// The last cursor position in screen coordinates, `None` while it is outside the window.
+    cursor_pos: Option<[f64; 2]>,
+    /// Which of [`MODIFIER_KEYS`] are held.
+    modifier_keys_down: [bool; 8],
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -47,33 +57,6 @@ pub enum HiDpiMode {
     Locked(f64),
 }
 
-struct Clipboard {
-    window_ptr: *mut glfw::ffi::GLFWwindow,
-}
-
-impl imgui::ClipboardBackend for Clipboard {
-    fn set(&mut self, s: &str) {
-        unsafe {
-            glfw::with_c_str(s, |s| {
-                glfw::ffi::glfwSetClipboardString(self.window_ptr, s);
-            });
-        }
-    }
-    fn get(&mut self) -> std::option::Option<std::string::String> {
-        unsafe {
-            let s = glfw::ffi::glfwGetClipboardString(self.window_ptr);
-            let s = std::ffi::CStr::from_ptr(s);
-            let bytes = s.to_bytes();
-            if !bytes.is_empty() {
-                let v = String::from_utf8_lossy(bytes);
-                Some(v.to_string())
-            } else {
-                None
-            }
-        }
-    }
-}
-
 impl HiDpiMode {
     fn apply(&self, hidpi_factor: f64) -> (ActiveHiDpiMode, f64) {
         match *self {
@@ -84,11 +67,159 @@ impl HiDpiMode {
     }
 }
 
+/// The left and right modifier keys and the imgui modifier each of them holds.
+const MODIFIER_KEYS: [(GlfwKey, Key); 8] = [
+    (GlfwKey::LeftShift, Key::ModShift),
+    (GlfwKey::RightShift, Key::ModShift),
+    (GlfwKey::LeftControl, Key::ModCtrl),
+    (GlfwKey::RightControl, Key::ModCtrl),
+    (GlfwKey::LeftAlt, Key::ModAlt),
+    (GlfwKey::RightAlt, Key::ModAlt),
+    (GlfwKey::LeftSuper, Key::ModSuper),
+    (GlfwKey::RightSuper, Key::ModSuper),
+];
+
+const MODIFIERS: [(Key, Modifiers); 4] = [
+    (Key::ModShift, Modifiers::Shift),
+    (Key::ModCtrl, Modifiers::Control),
+    (Key::ModAlt, Modifiers::Alt),
+    (Key::ModSuper, Modifiers::Super),
+];
+
+fn to_imgui_mouse_button(button: MouseButton) -> Option<imgui::MouseButton> {
+    match button {
+        MouseButton::Button1 => Some(imgui::MouseButton::Left),
+        MouseButton::Button2 => Some(imgui::MouseButton::Right),
+        MouseButton::Button3 => Some(imgui::MouseButton::Middle),
+        MouseButton::Button4 => Some(imgui::MouseButton::Extra1),
+        MouseButton::Button5 => Some(imgui::MouseButton::Extra2),
+        _ => None,
+    }
+}
+
+/// imgui has no keys past F12 and none for the international `World1` and `World2` keys.
+fn to_imgui_key(key: GlfwKey) -> Option<Key> {
+    match key {
+        GlfwKey::Tab => Some(Key::Tab),
+        GlfwKey::Left => Some(Key::LeftArrow),
+        GlfwKey::Right => Some(Key::RightArrow),
+        GlfwKey::Up => Some(Key::UpArrow),
+        GlfwKey::Down => Some(Key::DownArrow),
+        GlfwKey::PageUp => Some(Key::PageUp),
+        GlfwKey::PageDown => Some(Key::PageDown),
+        GlfwKey::Home => Some(Key::Home),
+        GlfwKey::End => Some(Key::End),
+        GlfwKey::Insert => Some(Key::Insert),
+        GlfwKey::Delete => Some(Key::Delete),
+        GlfwKey::Backspace => Some(Key::Backspace),
+        GlfwKey::Space => Some(Key::Space),
+        GlfwKey::Enter => Some(Key::Enter),
+        GlfwKey::Escape => Some(Key::Escape),
+        GlfwKey::LeftControl => Some(Key::LeftCtrl),
+        GlfwKey::LeftShift => Some(Key::LeftShift),
+        GlfwKey::LeftAlt => Some(Key::LeftAlt),
+        GlfwKey::LeftSuper => Some(Key::LeftSuper),
+        GlfwKey::RightControl => Some(Key::RightCtrl),
+        GlfwKey::RightShift => Some(Key::RightShift),
+        GlfwKey::RightAlt => Some(Key::RightAlt),
+        GlfwKey::RightSuper => Some(Key::RightSuper),
+        GlfwKey::Menu => Some(Key::Menu),
+        GlfwKey::Num0 => Some(Key::Alpha0),
+        GlfwKey::Num1 => Some(Key::Alpha1),
+        GlfwKey::Num2 => Some(Key::Alpha2),
+        GlfwKey::Num3 => Some(Key::Alpha3),
+        GlfwKey::Num4 => Some(Key::Alpha4),
+        GlfwKey::Num5 => Some(Key::Alpha5),
+        GlfwKey::Num6 => Some(Key::Alpha6),
+        GlfwKey::Num7 => Some(Key::Alpha7),
+        GlfwKey::Num8 => Some(Key::Alpha8),
+        GlfwKey::Num9 => Some(Key::Alpha9),
+        GlfwKey::A => Some(Key::A),
+        GlfwKey::B => Some(Key::B),
+        GlfwKey::C => Some(Key::C),
+        GlfwKey::D => Some(Key::D),
+        GlfwKey::E => Some(Key::E),
+        GlfwKey::F => Some(Key::F),
+        GlfwKey::G => Some(Key::G),
+        GlfwKey::H => Some(Key::H),
+        GlfwKey::I => Some(Key::I),
+        GlfwKey::J => Some(Key::J),
+        GlfwKey::K => Some(Key::K),
+        GlfwKey::L => Some(Key::L),
+        GlfwKey::M => Some(Key::M),
+        GlfwKey::N => Some(Key::N),
+        GlfwKey::O => Some(Key::O),
+        GlfwKey::P => Some(Key::P),
+        GlfwKey::Q => Some(Key::Q),
+        GlfwKey::R => Some(Key::R),
+        GlfwKey::S => Some(Key::S),
+        GlfwKey::T => Some(Key::T),
+        GlfwKey::U => Some(Key::U),
+        GlfwKey::V => Some(Key::V),
+        GlfwKey::W => Some(Key::W),
+        GlfwKey::X => Some(Key::X),
+        GlfwKey::Y => Some(Key::Y),
+        GlfwKey::Z => Some(Key::Z),
+        GlfwKey::F1 => Some(Key::F1),
+        GlfwKey::F2 => Some(Key::F2),
+        GlfwKey::F3 => Some(Key::F3),
+        GlfwKey::F4 => Some(Key::F4),
+        GlfwKey::F5 => Some(Key::F5),
+        GlfwKey::F6 => Some(Key::F6),
+        GlfwKey::F7 => Some(Key::F7),
+        GlfwKey::F8 => Some(Key::F8),
+        GlfwKey::F9 => Some(Key::F9),
+        GlfwKey::F10 => Some(Key::F10),
+        GlfwKey::F11 => Some(Key::F11),
+        GlfwKey::F12 => Some(Key::F12),
+        GlfwKey::Apostrophe => Some(Key::Apostrophe),
+        GlfwKey::Comma => Some(Key::Comma),
+        GlfwKey::Minus => Some(Key::Minus),
+        GlfwKey::Period => Some(Key::Period),
+        GlfwKey::Slash => Some(Key::Slash),
+        GlfwKey::Semicolon => Some(Key::Semicolon),
+        GlfwKey::Equal => Some(Key::Equal),
+        GlfwKey::LeftBracket => Some(Key::LeftBracket),
+        GlfwKey::Backslash => Some(Key::Backslash),
+        GlfwKey::RightBracket => Some(Key::RightBracket),
+        GlfwKey::GraveAccent => Some(Key::GraveAccent),
+        GlfwKey::CapsLock => Some(Key::CapsLock),
+        GlfwKey::ScrollLock => Some(Key::ScrollLock),
+        GlfwKey::NumLock => Some(Key::NumLock),
+        GlfwKey::PrintScreen => Some(Key::PrintScreen),
+        GlfwKey::Pause => Some(Key::Pause),
+        GlfwKey::Kp0 => Some(Key::Keypad0),
+        GlfwKey::Kp1 => Some(Key::Keypad1),
+        GlfwKey::Kp2 => Some(Key::Keypad2),
+        GlfwKey::Kp3 => Some(Key::Keypad3),
+        GlfwKey::Kp4 => Some(Key::Keypad4),
+        GlfwKey::Kp5 => Some(Key::Keypad5),
+        GlfwKey::Kp6 => Some(Key::Keypad6),
+        GlfwKey::Kp7 => Some(Key::Keypad7),
+        GlfwKey::Kp8 => Some(Key::Keypad8),
+        GlfwKey::Kp9 => Some(Key::Keypad9),
+        GlfwKey::KpDecimal => Some(Key::KeypadDecimal),
+        GlfwKey::KpDivide => Some(Key::KeypadDivide),
+        GlfwKey::KpMultiply => Some(Key::KeypadMultiply),
+        GlfwKey::KpSubtract => Some(Key::KeypadSubtract),
+        GlfwKey::KpAdd => Some(Key::KeypadAdd),
+        GlfwKey::KpEnter => Some(Key::KeypadEnter),
+        GlfwKey::KpEqual => Some(Key::KeypadEqual),
+        _ => None,
+    }
+}
+
+/// imgui-rs only wraps the digital `AddKeyEvent`.
+fn add_key_analog_event(io: &mut Io, key: Key, down: bool, value: f32) {
+    unsafe {
+        imgui::sys::ImGuiIO_AddKeyAnalogEvent(io as *mut Io as *mut imgui::sys::ImGuiIO, key as _, down, value);
+    }
+}
+
 impl GlfwPlatform {
     /// Initializes a glfw platform instance and configures imgui.
     ///
     /// * backend flgs are updated
-    /// * keys are configured
     /// * platform name is set
     pub fn init(imgui: &mut Context) -> GlfwPlatform {
         let io = imgui.io_mut();
@@ -96,111 +227,183 @@ impl GlfwPlatform {
 
         io.backend_flags.insert(BackendFlags::HAS_MOUSE_CURSORS);
         io.backend_flags.insert(BackendFlags::HAS_SET_MOUSE_POS);
-        // io.backend_flags.insert(BackendFlags::HAS_MOUSE_CURSORS);
-        // io.backend_flags.insert(BackendFlags::HAS_SET_MOUSE_POS);
-        io[Key::Tab] = GlfwKey::Tab as _;
-        io[Key::LeftArrow] = GlfwKey::Left as _;
-        io[Key::RightArrow] = GlfwKey::Right as _;
-        io[Key::UpArrow] = GlfwKey::Up as _;
-        io[Key::DownArrow] = GlfwKey::Down as _;
-        io[Key::PageUp] = GlfwKey::PageUp as _;
-        io[Key::PageDown] = GlfwKey::PageDown as _;
-        io[Key::Home] = GlfwKey::Home as _;
-        io[Key::End] = GlfwKey::End as _;
-        io[Key::Insert] = GlfwKey::Insert as _;
-        io[Key::Delete] = GlfwKey::Delete as _;
-        io[Key::Backspace] = GlfwKey::Backspace as _;
-        io[Key::Space] = GlfwKey::Space as _;
-        io[Key::Enter] = GlfwKey::Enter as _;
-        io[Key::Escape] = GlfwKey::Escape as _;
-        // io[Key::KeyPadEnter] = GlfwKey::KpEnter as _;
-        io[Key::A] = GlfwKey::A as _;
-        io[Key::C] = GlfwKey::C as _;
-        io[Key::V] = GlfwKey::V as _;
-        io[Key::X] = GlfwKey::X as _;
-        io[Key::Y] = GlfwKey::Y as _;
-        io[Key::Z] = GlfwKey::Z as _;
         imgui.set_platform_name(Some("glfw".to_string()));
         GlfwPlatform {
             hidpi_mode: ActiveHiDpiMode::Default,
             hidpi_factor: 1.0,
+            pixels_per_screen_coordinate: 1.0,
+            window_size: (0, 0),
+            framebuffer_size: (0, 0),
+            cursor_pos: None,
+            modifier_keys_down: [false; 8],
         }
     }
 
-    /// Adds platform clipboard integration for the provided window. The caller **must** ensure that
-    /// the `Window` outlives the imgui `Context` **and** that any imgui functions that may access
-    /// the clipboard are called from the **main thread** (the thread that's executing the event polling).
-    pub unsafe fn set_clipboard_backend(&self, imgui: &mut Context, window: &Window) {
-        use glfw::Context;
-        let window_ptr = window.window_ptr();
-        imgui.set_clipboard_backend(Clipboard { window_ptr });
-    }
-
     /// Attaches the platform instance to a glfw window.
     ///
     /// * framebuffer sacle (i.e. DPI factor) is set
     /// * display size is set
     pub fn attach_window(&mut self, io: &mut Io, window: &Window, hidpi_mode: HiDpiMode) {
         let (scale_factor_x, _scale_factor_y) = window.get_content_scale();
-        let (hidpi_mode, hidpi_factor) = hidpi_mode.apply(scale_factor_x as _);
+        self.attach(io, hidpi_mode, scale_factor_x as _, window.get_size(), window.get_framebuffer_size());
+    }
+
+    /// [`GlfwPlatform::attach_window`] with the window's content scale and sizes given.
+    pub fn attach(&mut self, io: &mut Io, hidpi_mode: HiDpiMode, content_scale: f64, window_size: (i32, i32), framebuffer_size: (i32, i32)) {
+        let (hidpi_mode, hidpi_factor) = hidpi_mode.apply(content_scale);
         self.hidpi_mode = hidpi_mode;
         self.hidpi_factor = hidpi_factor;
         io.display_framebuffer_scale = [hidpi_factor as f32, hidpi_factor as f32];
-        let (width, height) = window.get_size();
-        io.display_size = [width as f32, height as f32];
+        self.window_size = window_size;
+        self.framebuffer_size = framebuffer_size;
+        self.update_display_size(io);
+    }
+
+    /// Returns the current DPI factor.
+    ///
+    /// The value might not be the same as glfw's content scale (depends on the used DPI mode)
+    pub fn hidpi_factor(&self) -> f64 {
+        self.hidpi_factor
+    }
+
+    /// Scales a position in glfw screen coordinates to imgui's, which are framebuffer pixels
+    /// divided by the DPI factor.
+    pub fn scale_pos_from_glfw(&self, pos: [f64; 2]) -> [f32; 2] {
+        let scale = self.pixels_per_screen_coordinate / self.hidpi_factor;
+        [(pos[0] * scale) as f32, (pos[1] * scale) as f32]
+    }
+
+    /// Scales a position in imgui's coordinates to glfw screen coordinates.
+    pub fn scale_pos_for_glfw(&self, pos: [f32; 2]) -> [f64; 2] {
+        let scale = self.hidpi_factor / self.pixels_per_screen_coordinate;
+        [pos[0] as f64 * scale, pos[1] as f64 * scale]
+    }
+
+    fn update_display_size(&mut self, io: &mut Io) {
+        let pixels_per_screen_coordinate = self.pixels_per_screen_coordinate;
+        // Both are 0 while minimized, the ratio is kept for when the window comes back.
+        if self.window_size.0 > 0 && self.framebuffer_size.0 > 0 {
+            self.pixels_per_screen_coordinate = self.framebuffer_size.0 as f64 / self.window_size.0 as f64;
+        }
+        let [width, height] = self.scale_pos_from_glfw([self.window_size.0 as f64, self.window_size.1 as f64]);
+        io.display_size = [width, height];
+
+        if self.pixels_per_screen_coordinate != pixels_per_screen_coordinate {
+            self.rescale_cursor(io);
+        }
+    }
+
+    fn set_hidpi_factor(&mut self, io: &mut Io, hidpi_factor: f64) {
+        if hidpi_factor == self.hidpi_factor {
+            return;
+        }
+
+        self.hidpi_factor = hidpi_factor;
+        io.display_framebuffer_scale = [hidpi_factor as f32, hidpi_factor as f32];
+        self.update_display_size(io);
+        self.rescale_cursor(io);
+    }
+
+    /// The cursor didn't move, but its position in imgui's coordinates did.
+    fn rescale_cursor(&self, io: &mut Io) {
+        if let Some(pos) = self.cursor_pos {
+            io.add_mouse_pos_event(self.scale_pos_from_glfw(pos));
+        }
     }
 
     /// Handles a glfw window event
     ///
+    /// * window size / DPI factor changes are applied
     /// * keyboard state is updated
     /// * mouse state is updated
-    pub fn handle_event(&self, io: &mut Io, _window: &Window, event: &WindowEvent) {
+    pub fn handle_event(&mut self, io: &mut Io, event: &WindowEvent) {
         match *event {
             WindowEvent::Key(key, _scancode, action, modifiers) => {
-                if key as i32 >= 0 {
-                    io.keys_down[key as usize] = action != Action::Release;
+                let down = action != Action::Release;
+                let modifier_key = MODIFIER_KEYS.iter().position(|(modifier_key, _)| *modifier_key == key);
+                if let Some(index) = modifier_key {
+                    self.modifier_keys_down[index] = down;
+                }
+
+                // Some platforms leave a modifier key out of the modifiers of its own event, so
+                // those follow the keys held instead.
+                for (modifier, flag) in MODIFIERS {
+                    let held = match modifier_key {
+                        Some(index) if MODIFIER_KEYS[index].1 == modifier => MODIFIER_KEYS
+                            .iter()
+                            .zip(self.modifier_keys_down)
+                            .any(|((_, other), down)| *other == modifier && down),
+                        _ => modifiers.contains(flag),
+                    };
+                    io.add_key_event(modifier, held);
+                }
+
+                if let Some(key) = to_imgui_key(key) {
+                    io.add_key_event(key, down);
                 }
-                io.key_shift = modifiers.contains(Modifiers::Shift);
-                io.key_ctrl = modifiers.contains(Modifiers::Control);
-                io.key_alt = modifiers.contains(Modifiers::Alt);
-                io.key_super = modifiers.contains(Modifiers::Super);
             }
             WindowEvent::Size(width, height) => {
-                io.display_size = [width as _, height as _];
+                self.window_size = (width, height);
+                self.update_display_size(io);
             }
-            WindowEvent::Char(ch) => {
-                // Exclude the backspace key
-                if ch != '\u{7f}' {
-                    io.add_input_character(ch);
-                }
+            WindowEvent::FramebufferSize(width, height) => {
+                self.framebuffer_size = (width, height);
+                self.update_display_size(io);
+            }
+            WindowEvent::ContentScale(scale_x, _scale_y) => {
+                let hidpi_factor = match self.hidpi_mode {
+                    ActiveHiDpiMode::Default => scale_x as f64,
+                    ActiveHiDpiMode::Rounded => (scale_x as f64).round(),
+                    ActiveHiDpiMode::Locked => return,
+                };
+                self.set_hidpi_factor(io, hidpi_factor);
+            }
+            // Exclude the backspace key
+            WindowEvent::Char(ch) if ch != '\u{7f}' => {
+                io.add_input_character(ch);
             }
             WindowEvent::CursorPos(x, y) => {
-                io.mouse_pos = [x as _, y as _];
+                self.cursor_pos = Some([x, y]);
+                io.add_mouse_pos_event(self.scale_pos_from_glfw([x, y]));
+            }
+            WindowEvent::CursorEnter(false) => {
+                self.cursor_pos = None;
+                // imgui's "no mouse" position
+                io.add_mouse_pos_event([-f32::MAX, -f32::MAX]);
             }
             WindowEvent::Scroll(x, y) => {
-                io.mouse_wheel_h = x as _;
-                io.mouse_wheel = y as _;
+                io.add_mouse_wheel_event([x as _, y as _]);
             }
             WindowEvent::MouseButton(button, action, _modifiers) => {
-                let pressed = action == Action::Press;
-                match button {
-                    MouseButton::Button1 => io.mouse_down[0] = pressed,
-                    MouseButton::Button2 => io.mouse_down[1] = pressed,
-                    MouseButton::Button3 => io.mouse_down[2] = pressed,
-                    _ => (),
+                if let Some(button) = to_imgui_mouse_button(button) {
+                    io.add_mouse_button_event(button, action != Action::Release);
                 }
             }
-            WindowEvent::Focus(newly_focused) => {
-                if !newly_focused {
-                    // Set focus-lost to avoid stuck keys (like 'alt'
-                    // when alt-tabbing)
-                    io.app_focus_lost = true;
-                }
+            WindowEvent::Focus(false) => {
+                // Set focus-lost to avoid stuck keys (like 'alt'
+                // when alt-tabbing)
+                io.app_focus_lost = true;
+                self.modifier_keys_down = [false; 8];
             }
             _ => {}
         }
     }
 
+    /// Feeds gamepad navigation, call every frame with the connected gamepads combined or `None`
+    /// without any.
+    pub fn handle_gamepad(&self, io: &mut Io, gamepad: Option<&GamepadInput>) {
+        if gamepad.is_some() {
+            io.backend_flags.insert(BackendFlags::HAS_GAMEPAD);
+        } else {
+            io.backend_flags.remove(BackendFlags::HAS_GAMEPAD);
+        }
+
+        // Released too when the last gamepad is gone, so nothing stays held.
+        for (key, value) in gamepad::imgui_keys(&gamepad.copied().unwrap_or_default()) {
+            add_key_analog_event(io, key, value > 0.0, value);
+        }
+    }
+
     /// Prepare the window for the next frame.
     ///
     /// Call before calling the imgui-rs `Context::frame` function.
@@ -208,8 +411,8 @@ impl GlfwPlatform {
     /// * mouse cursor is repositioned if requested by imgui
     pub fn prepare_frame(&self, io: &mut Io, window: &mut Window) -> Result<(), String> {
         if io.want_set_mouse_pos {
-            let [x, y] = io.mouse_pos;
-            window.set_cursor_pos(x as _, y as _);
+            let [x, y] = self.scale_pos_for_glfw(io.mouse_pos);
+            window.set_cursor_pos(x, y);
             Ok(())
         } else {
             Ok(())
@@ -249,4 +452,211 @@ impl GlfwPlatform {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use glfw::GamepadButton;
+    use imgui::MouseButton as ImguiMouseButton;
+
+    /// imgui allows one context at a time, tests creating one take turns.
+    static CONTEXT: Mutex<()> = Mutex::new(());
+
+    struct Harness {
+        imgui: Context,
+        platform: GlfwPlatform,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let mut imgui = Context::create();
+            imgui.set_ini_filename(None);
+            imgui.io_mut().display_size = [800.0, 600.0];
+            // Every queued event is applied in the next frame, also several of the same key.
+            imgui.io_mut().config_input_trickle_event_queue = false;
+            imgui.fonts().build_rgba32_texture();
+            let platform = GlfwPlatform::init(&mut imgui);
+            Harness { imgui, platform }
+        }
+
+        /// Feeds `events` and runs a frame, then passes imgui's state to `check`.
+        fn frame<T>(&mut self, events: &[WindowEvent], check: impl FnOnce(&Ui) -> T) -> T {
+            for event in events {
+                self.platform.handle_event(self.imgui.io_mut(), event);
+            }
+            self.imgui.io_mut().update_delta_time(std::time::Duration::from_millis(16));
+            let ui = self.imgui.frame();
+            let result = check(ui);
+            self.imgui.render();
+            result
+        }
+    }
+
+    fn with_harness(test: impl FnOnce(&mut Harness)) {
+        let _guard = CONTEXT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        test(&mut Harness::new());
+    }
+
+    fn key(key: GlfwKey, action: Action, modifiers: Modifiers) -> WindowEvent {
+        WindowEvent::Key(key, 0, action, modifiers)
+    }
+
+    fn press(glfw_key: GlfwKey) -> WindowEvent {
+        key(glfw_key, Action::Press, Modifiers::empty())
+    }
+
+    fn modifiers(ui: &Ui) -> [bool; 4] {
+        let io = ui.io();
+        [io.key_ctrl, io.key_shift, io.key_alt, io.key_super]
+    }
+
+    #[test]
+    fn keys_are_translated() {
+        let keys = [
+            (GlfwKey::F1, Key::F1),
+            (GlfwKey::F12, Key::F12),
+            (GlfwKey::Kp0, Key::Keypad0),
+            (GlfwKey::Kp7, Key::Keypad7),
+            (GlfwKey::KpEnter, Key::KeypadEnter),
+            (GlfwKey::KpDecimal, Key::KeypadDecimal),
+            (GlfwKey::Semicolon, Key::Semicolon),
+            (GlfwKey::GraveAccent, Key::GraveAccent),
+            (GlfwKey::LeftBracket, Key::LeftBracket),
+            (GlfwKey::Backslash, Key::Backslash),
+            (GlfwKey::Apostrophe, Key::Apostrophe),
+            (GlfwKey::Num5, Key::Alpha5),
+            (GlfwKey::Menu, Key::Menu),
+        ];
+
+        with_harness(|harness| {
+            let events: Vec<WindowEvent> = keys.iter().map(|(glfw_key, _)| press(*glfw_key)).collect();
+            let down = harness.frame(&events, |ui| keys.map(|(_, key)| ui.is_key_down(key)));
+            assert_eq!(down, [true; 13]);
+
+            let up = harness.frame(&[key(GlfwKey::F1, Action::Release, Modifiers::empty())], |ui| (ui.is_key_down(Key::F1), ui.is_key_down(Key::F12)));
+            assert_eq!(up, (false, true));
+        });
+    }
+
+    #[test]
+    fn repeat_keeps_the_key_down() {
+        with_harness(|harness| {
+            harness.frame(&[press(GlfwKey::Kp7)], |_| ());
+            let down = harness.frame(&[key(GlfwKey::Kp7, Action::Repeat, Modifiers::empty())], |ui| ui.is_key_down(Key::Keypad7));
+            assert!(down);
+        });
+    }
+
+    #[test]
+    fn keys_imgui_does_not_have_are_ignored() {
+        with_harness(|harness| {
+            let held = harness.frame(&[press(GlfwKey::F13), press(GlfwKey::World1), press(GlfwKey::Unknown)], modifiers);
+            assert_eq!(held, [false; 4]);
+        });
+        assert_eq!(to_imgui_key(GlfwKey::F25), None);
+    }
+
+    #[test]
+    fn left_and_right_modifiers() {
+        with_harness(|harness| {
+            // Some platforms leave the modifier out of its own key event.
+            let held = harness.frame(&[press(GlfwKey::LeftControl)], |ui| (modifiers(ui), ui.is_key_down(Key::LeftCtrl), ui.is_key_down(Key::RightCtrl)));
+            assert_eq!(held, ([true, false, false, false], true, false));
+
+            let held = harness.frame(&[key(GlfwKey::RightControl, Action::Press, Modifiers::Control)], |ui| (ui.is_key_down(Key::LeftCtrl), ui.is_key_down(Key::RightCtrl)));
+            assert_eq!(held, (true, true));
+
+            // Releasing one side keeps the modifier while the other is held.
+            let held = harness.frame(&[key(GlfwKey::LeftControl, Action::Release, Modifiers::Control)], |ui| (modifiers(ui), ui.is_key_down(Key::LeftCtrl)));
+            assert_eq!(held, ([true, false, false, false], false));
+
+            let held = harness.frame(&[key(GlfwKey::RightControl, Action::Release, Modifiers::empty())], modifiers);
+            assert_eq!(held, [false; 4]);
+        });
+    }
+
+    #[test]
+    fn modifiers_follow_other_keys_events() {
+        with_harness(|harness| {
+            let held = harness.frame(&[key(GlfwKey::A, Action::Press, Modifiers::Shift | Modifiers::Alt | Modifiers::Super)], modifiers);
+            assert_eq!(held, [false, true, true, true]);
+
+            let held = harness.frame(&[key(GlfwKey::A, Action::Release, Modifiers::empty())], modifiers);
+            assert_eq!(held, [false; 4]);
+        });
+    }
+
+    #[test]
+    fn all_mouse_buttons() {
+        let buttons = [
+            (MouseButton::Button1, ImguiMouseButton::Left),
+            (MouseButton::Button2, ImguiMouseButton::Right),
+            (MouseButton::Button3, ImguiMouseButton::Middle),
+            (MouseButton::Button4, ImguiMouseButton::Extra1),
+            (MouseButton::Button5, ImguiMouseButton::Extra2),
+        ];
+
+        with_harness(|harness| {
+            let mut events: Vec<WindowEvent> = buttons.iter().map(|(button, _)| WindowEvent::MouseButton(*button, Action::Press, Modifiers::empty())).collect();
+            // glfw has buttons imgui doesn't.
+            events.push(WindowEvent::MouseButton(MouseButton::Button6, Action::Press, Modifiers::empty()));
+            let down = harness.frame(&events, |ui| buttons.map(|(_, button)| ui.is_mouse_down(button)));
+            assert_eq!(down, [true; 5]);
+
+            let events: Vec<WindowEvent> = buttons.iter().map(|(button, _)| WindowEvent::MouseButton(*button, Action::Release, Modifiers::empty())).collect();
+            let down = harness.frame(&events, |ui| buttons.map(|(_, button)| ui.is_mouse_down(button)));
+            assert_eq!(down, [false; 5]);
+        });
+    }
+
+    #[test]
+    fn cursor_and_scroll() {
+        with_harness(|harness| {
+            let (pos, wheel) = harness.frame(&[WindowEvent::CursorPos(123.0, 45.0), WindowEvent::Scroll(-1.0, 2.0)], |ui| (ui.io().mouse_pos, [ui.io().mouse_wheel_h, ui.io().mouse_wheel]));
+            assert_eq!(pos, [123.0, 45.0]);
+            assert_eq!(wheel, [-1.0, 2.0]);
+
+            let pos = harness.frame(&[WindowEvent::CursorEnter(false)], |ui| ui.io().mouse_pos);
+            assert_eq!(pos, [-f32::MAX, -f32::MAX]);
+        });
+    }
+
+    #[test]
+    fn losing_focus_releases_keys() {
+        with_harness(|harness| {
+            let down = harness.frame(&[press(GlfwKey::Z), press(GlfwKey::LeftShift), WindowEvent::Focus(false)], |ui| (ui.is_key_down(Key::Z), ui.io().key_shift));
+            assert_eq!(down, (false, false));
+        });
+    }
+
+    #[test]
+    fn gamepad_keys_are_analog() {
+        with_harness(|harness| {
+            let gamepad = GamepadInput { left_stick: [-0.5, 0.0], buttons: gamepad::BUTTONS.map(|button| button == GamepadButton::ButtonA), ..Default::default() };
+            harness.platform.handle_gamepad(harness.imgui.io_mut(), Some(&gamepad));
+            let state = harness.frame(&[], |ui| {
+                (ui.io().backend_flags.contains(BackendFlags::HAS_GAMEPAD), ui.is_key_down(Key::GamepadFaceDown), ui.is_key_down(Key::GamepadLStickLeft))
+            });
+            assert_eq!(state, (true, true, true));
+
+            harness.platform.handle_gamepad(harness.imgui.io_mut(), None);
+            let state = harness.frame(&[], |ui| (ui.io().backend_flags.contains(BackendFlags::HAS_GAMEPAD), ui.is_key_down(Key::GamepadFaceDown)));
+            assert_eq!(state, (false, false));
+        });
+    }
+
+    #[test]
+    fn display_size_follows_the_window() {
+        with_harness(|harness| {
+            harness.platform.attach(harness.imgui.io_mut(), HiDpiMode::Default, 1.0, (800, 600), (800, 600));
+            let size = harness.frame(&[WindowEvent::Size(1024, 768), WindowEvent::FramebufferSize(1024, 768)], |ui| ui.io().display_size);
+            assert_eq!(size, [1024.0, 768.0]);
+
+            // A Retina display, twice the pixels per screen coordinate at a content scale of 2.
+            let size = harness.frame(&[WindowEvent::FramebufferSize(2048, 1536), WindowEvent::ContentScale(2.0, 2.0)], |ui| ui.io().display_size);
+            assert_eq!(size, [1024.0, 768.0]);
+        });
+    }
+}
@@ -0,0 +1,740 @@
+//! Named input actions bound to keys, mouse buttons and gamepad buttons, with modifier chords.
+//!
+//! Bindings are written as text, `enter`, `ctrl+shift+s`, `mouse_right` or `gamepad_a`, and kept in a
+//! TOML file mapping each action to its list of bindings.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use glfw::{Action, GamepadButton, Key, Modifiers, MouseButton, WindowEvent};
+
+pub const TOGGLE_FULLSCREEN: &str = "toggle_fullscreen";
+pub const QUIT: &str = "quit";
+pub const CAMERA_FORWARD: &str = "camera_forward";
+pub const CAMERA_BACK: &str = "camera_back";
+pub const CAMERA_LEFT: &str = "camera_left";
+pub const CAMERA_RIGHT: &str = "camera_right";
+pub const CAMERA_UP: &str = "camera_up";
+pub const CAMERA_DOWN: &str = "camera_down";
+pub const CAMERA_FAST: &str = "camera_fast";
+pub const CAMERA_LOOK: &str = "camera_look";
+
+const KEY_NAMES: [(Key, &str); 120] = [
+    (Key::Space, "space"),
+    (Key::Apostrophe, "apostrophe"),
+    (Key::Comma, "comma"),
+    (Key::Minus, "minus"),
+    (Key::Period, "period"),
+    (Key::Slash, "slash"),
+    (Key::Num0, "0"),
+    (Key::Num1, "1"),
+    (Key::Num2, "2"),
+    (Key::Num3, "3"),
+    (Key::Num4, "4"),
+    (Key::Num5, "5"),
+    (Key::Num6, "6"),
+    (Key::Num7, "7"),
+    (Key::Num8, "8"),
+    (Key::Num9, "9"),
+    (Key::Semicolon, "semicolon"),
+    (Key::Equal, "equal"),
+    (Key::A, "a"),
+    (Key::B, "b"),
+    (Key::C, "c"),
+    (Key::D, "d"),
+    (Key::E, "e"),
+    (Key::F, "f"),
+    (Key::G, "g"),
+    (Key::H, "h"),
+    (Key::I, "i"),
+    (Key::J, "j"),
+    (Key::K, "k"),
+    (Key::L, "l"),
+    (Key::M, "m"),
+    (Key::N, "n"),
+    (Key::O, "o"),
+    (Key::P, "p"),
+    (Key::Q, "q"),
+    (Key::R, "r"),
+    (Key::S, "s"),
+    (Key::T, "t"),
+    (Key::U, "u"),
+    (Key::V, "v"),
+    (Key::W, "w"),
+    (Key::X, "x"),
+    (Key::Y, "y"),
+    (Key::Z, "z"),
+    (Key::LeftBracket, "left_bracket"),
+    (Key::Backslash, "backslash"),
+    (Key::RightBracket, "right_bracket"),
+    (Key::GraveAccent, "grave_accent"),
+    (Key::World1, "world_1"),
+    (Key::World2, "world_2"),
+    (Key::Escape, "escape"),
+    (Key::Enter, "enter"),
+    (Key::Tab, "tab"),
+    (Key::Backspace, "backspace"),
+    (Key::Insert, "insert"),
+    (Key::Delete, "delete"),
+    (Key::Right, "right"),
+    (Key::Left, "left"),
+    (Key::Down, "down"),
+    (Key::Up, "up"),
+    (Key::PageUp, "page_up"),
+    (Key::PageDown, "page_down"),
+    (Key::Home, "home"),
+    (Key::End, "end"),
+    (Key::CapsLock, "caps_lock"),
+    (Key::ScrollLock, "scroll_lock"),
+    (Key::NumLock, "num_lock"),
+    (Key::PrintScreen, "print_screen"),
+    (Key::Pause, "pause"),
+    (Key::F1, "f1"),
+    (Key::F2, "f2"),
+    (Key::F3, "f3"),
+    (Key::F4, "f4"),
+    (Key::F5, "f5"),
+    (Key::F6, "f6"),
+    (Key::F7, "f7"),
+    (Key::F8, "f8"),
+    (Key::F9, "f9"),
+    (Key::F10, "f10"),
+    (Key::F11, "f11"),
+    (Key::F12, "f12"),
+    (Key::F13, "f13"),
+    (Key::F14, "f14"),
+    (Key::F15, "f15"),
+    (Key::F16, "f16"),
+    (Key::F17, "f17"),
+    (Key::F18, "f18"),
+    (Key::F19, "f19"),
+    (Key::F20, "f20"),
+    (Key::F21, "f21"),
+    (Key::F22, "f22"),
+    (Key::F23, "f23"),
+    (Key::F24, "f24"),
+    (Key::F25, "f25"),
+    (Key::Kp0, "kp_0"),
+    (Key::Kp1, "kp_1"),
+    (Key::Kp2, "kp_2"),
+    (Key::Kp3, "kp_3"),
+    (Key::Kp4, "kp_4"),
+    (Key::Kp5, "kp_5"),
+    (Key::Kp6, "kp_6"),
+    (Key::Kp7, "kp_7"),
+    (Key::Kp8, "kp_8"),
+    (Key::Kp9, "kp_9"),
+    (Key::KpDecimal, "kp_decimal"),
+    (Key::KpDivide, "kp_divide"),
+    (Key::KpMultiply, "kp_multiply"),
+    (Key::KpSubtract, "kp_subtract"),
+    (Key::KpAdd, "kp_add"),
+    (Key::KpEnter, "kp_enter"),
+    (Key::KpEqual, "kp_equal"),
+    (Key::LeftShift, "left_shift"),
+    (Key::LeftControl, "left_control"),
+    (Key::LeftAlt, "left_alt"),
+    (Key::LeftSuper, "left_super"),
+    (Key::RightShift, "right_shift"),
+    (Key::RightControl, "right_control"),
+    (Key::RightAlt, "right_alt"),
+    (Key::RightSuper, "right_super"),
+    (Key::Menu, "menu"),
+];
+
+const MOUSE_BUTTON_NAMES: [(MouseButton, &str); 8] = [
+    (MouseButton::Button1, "mouse_left"),
+    (MouseButton::Button2, "mouse_right"),
+    (MouseButton::Button3, "mouse_middle"),
+    (MouseButton::Button4, "mouse_4"),
+    (MouseButton::Button5, "mouse_5"),
+    (MouseButton::Button6, "mouse_6"),
+    (MouseButton::Button7, "mouse_7"),
+    (MouseButton::Button8, "mouse_8"),
+];
+
+const GAMEPAD_BUTTON_NAMES: [(GamepadButton, &str); 15] = [
+    (GamepadButton::ButtonA, "gamepad_a"),
+    (GamepadButton::ButtonB, "gamepad_b"),
+    (GamepadButton::ButtonX, "gamepad_x"),
+    (GamepadButton::ButtonY, "gamepad_y"),
+    (GamepadButton::ButtonLeftBumper, "gamepad_left_bumper"),
+    (GamepadButton::ButtonRightBumper, "gamepad_right_bumper"),
+    (GamepadButton::ButtonBack, "gamepad_back"),
+    (GamepadButton::ButtonStart, "gamepad_start"),
+    (GamepadButton::ButtonGuide, "gamepad_guide"),
+    (GamepadButton::ButtonLeftThumb, "gamepad_left_thumb"),
+    (GamepadButton::ButtonRightThumb, "gamepad_right_thumb"),
+    (GamepadButton::ButtonDpadUp, "gamepad_dpad_up"),
+    (GamepadButton::ButtonDpadRight, "gamepad_dpad_right"),
+    (GamepadButton::ButtonDpadDown, "gamepad_dpad_down"),
+    (GamepadButton::ButtonDpadLeft, "gamepad_dpad_left"),
+];
+
+const MODIFIER_NAMES: [(Modifiers, &str); 4] = [
+    (Modifiers::Control, "ctrl"),
+    (Modifiers::Shift, "shift"),
+    (Modifiers::Alt, "alt"),
+    (Modifiers::Super, "super"),
+];
+
+fn find_value<T: Copy>(names: &[(T, &str)], name: &str) -> Option<T> {
+    names.iter().find(|(_, other)| *other == name).map(|(value, _)| *value)
+}
+
+fn find_name<T: PartialEq>(names: &[(T, &'static str)], value: T) -> &'static str {
+    names.iter().find(|(other, _)| *other == value).map_or("unknown", |(_, name)| *name)
+}
+
+/// A single key or button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(Key),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl Input {
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase();
+
+        find_value(&KEY_NAMES, &name).map(Input::Key)
+            .or_else(|| find_value(&MOUSE_BUTTON_NAMES, &name).map(Input::Mouse))
+            .or_else(|| find_value(&GAMEPAD_BUTTON_NAMES, &name).map(Input::Gamepad))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Input::Key(key) => find_name(&KEY_NAMES, key),
+            Input::Mouse(button) => find_name(&MOUSE_BUTTON_NAMES, button),
+            Input::Gamepad(button) => find_name(&GAMEPAD_BUTTON_NAMES, button),
+        }
+    }
+
+    /// The modifier this key is one of the sides of, empty for everything else.
+    pub fn modifier(self) -> Modifiers {
+        match self {
+            Input::Key(Key::LeftControl | Key::RightControl) => Modifiers::Control,
+            Input::Key(Key::LeftShift | Key::RightShift) => Modifiers::Shift,
+            Input::Key(Key::LeftAlt | Key::RightAlt) => Modifiers::Alt,
+            Input::Key(Key::LeftSuper | Key::RightSuper) => Modifiers::Super,
+            _ => Modifiers::empty(),
+        }
+    }
+}
+
+/// An input together with the modifiers that have to be held for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub modifiers: Modifiers,
+    pub input: Input,
+}
+
+impl Binding {
+    /// Parses `+` separated modifiers followed by the input, `ctrl+shift+s`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let input = parts.pop().unwrap_or_default();
+        let input = Input::parse(input).ok_or_else(|| format!("Unknown input '{}'", input))?;
+
+        let mut modifiers = Modifiers::empty();
+        for part in parts {
+            let modifier = match part.to_ascii_lowercase().as_str() {
+                "control" => Modifiers::Control,
+                name => find_value(&MODIFIER_NAMES, name).ok_or_else(|| format!("Unknown modifier '{}'", part))?,
+            };
+            modifiers |= modifier;
+        }
+
+        Ok(Binding { modifiers, input })
+    }
+
+    /// Whether the binding applies with `modifiers` held.
+    pub fn matches(&self, modifiers: Modifiers) -> bool {
+        modifiers.contains(self.modifiers)
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (modifier, name) in MODIFIER_NAMES {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.input.name())
+    }
+}
+
+/// Which inputs are down, fed with window events and gamepad polling.
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+    down: HashSet<Input>,
+    /// Inputs that went down this frame in order, so a press and release within one frame still counts.
+    went_down: Vec<Input>,
+}
+
+impl InputState {
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match *event {
+            WindowEvent::Key(key, _, action, _) => self.set(Input::Key(key), action != Action::Release),
+            WindowEvent::MouseButton(button, action, _) => self.set(Input::Mouse(button), action != Action::Release),
+            // Everything let go while the window is in the background.
+            WindowEvent::Focus(false) => self.down.clear(),
+            _ => {}
+        }
+    }
+
+    pub fn set(&mut self, input: Input, down: bool) {
+        if down {
+            if self.down.insert(input) {
+                self.went_down.push(input);
+            }
+        } else {
+            self.down.remove(&input);
+        }
+    }
+
+    /// Down now, or pressed at some point during this frame.
+    pub fn is_down(&self, input: Input) -> bool {
+        self.down.contains(&input) || self.went_down.contains(&input)
+    }
+
+    /// The modifiers held through either side's key.
+    pub fn modifiers(&self) -> Modifiers {
+        self.down.iter().fold(Modifiers::empty(), |modifiers, input| modifiers | input.modifier())
+    }
+
+    /// The first input other than a modifier key pressed this frame, with the modifiers held.
+    /// Used to pick a new binding.
+    pub fn captured_binding(&self) -> Option<Binding> {
+        let input = self.went_down.iter().find(|input| input.modifier().is_empty())?;
+        Some(Binding { modifiers: self.modifiers(), input: *input })
+    }
+
+    /// Call after the frame's actions were updated.
+    pub fn end_frame(&mut self) {
+        self.went_down.clear();
+    }
+}
+
+/// Whether the UI takes a press so it doesn't reach the actions. Keys get through while a binding
+/// is being picked, mouse presses on the UI never do, so clicking its buttons, like the one
+/// cancelling the capture, doesn't become the binding.
+pub fn taken_by_ui(event: &WindowEvent, want_capture_keyboard: bool, want_capture_mouse: bool, capturing: bool) -> bool {
+    match *event {
+        WindowEvent::Key(_, _, Action::Press, _) => want_capture_keyboard && !capturing,
+        WindowEvent::MouseButton(_, Action::Press, _) => want_capture_mouse,
+        _ => false,
+    }
+}
+
+/// An action's state for the current frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ActionState {
+    pub held: bool,
+    /// Started being held this frame.
+    pub pressed: bool,
+    /// Stopped being held this frame.
+    pub released: bool,
+}
+
+/// Actions with their bindings and their state in the current frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ActionMap {
+    pub bindings: BTreeMap<String, Vec<Binding>>,
+    states: BTreeMap<String, ActionState>,
+}
+
+impl ActionMap {
+    /// The engine's built in actions and their default bindings.
+    pub fn with_defaults() -> Self {
+        let defaults: [(&str, &[&str]); 10] = [
+            (TOGGLE_FULLSCREEN, &["enter", "alt+enter"]),
+            (QUIT, &["escape"]),
+            (CAMERA_FORWARD, &["w", "up"]),
+            (CAMERA_BACK, &["s", "down"]),
+            (CAMERA_LEFT, &["a", "left"]),
+            (CAMERA_RIGHT, &["d", "right"]),
+            (CAMERA_UP, &["e", "gamepad_right_bumper"]),
+            (CAMERA_DOWN, &["q", "gamepad_left_bumper"]),
+            (CAMERA_FAST, &["left_shift", "gamepad_left_thumb"]),
+            (CAMERA_LOOK, &["mouse_right"]),
+        ];
+
+        let mut map = ActionMap::default();
+        for (action, bindings) in defaults {
+            map.bindings.insert(action.to_string(), bindings.iter().map(|binding| Binding::parse(binding).unwrap()).collect());
+        }
+        map
+    }
+
+    /// The default bindings with those in `path` replacing them per action, the defaults alone
+    /// when the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut map = Self::with_defaults();
+        if !path.exists() {
+            return Ok(map);
+        }
+
+        let text = std::fs::read_to_string(path)?;
+        map.merge_toml(&text)?;
+        Ok(map)
+    }
+
+    /// Replaces the bindings of the actions listed in `text`, `action = ["ctrl+s", "gamepad_a"]`.
+    pub fn merge_toml(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file: BTreeMap<String, Vec<String>> = toml::from_str(text)?;
+
+        for (action, bindings) in file {
+            let bindings = bindings
+                .iter()
+                .map(|binding| Binding::parse(binding))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|error| format!("{}: {}", action, error))?;
+            self.bindings.insert(action, bindings);
+        }
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> String {
+        let file: BTreeMap<&String, Vec<String>> = self
+            .bindings
+            .iter()
+            .map(|(action, bindings)| (action, bindings.iter().map(Binding::to_string).collect()))
+            .collect();
+        toml::to_string(&file).unwrap()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, self.to_toml())?;
+        Ok(())
+    }
+
+    /// Adds `binding` to `action` unless it is already bound there.
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.bindings.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(action) {
+            bindings.retain(|other| *other != binding);
+        }
+    }
+
+    /// Whether `binding` is active. A binding whose input is down and whose modifiers are held is
+    /// still inactive when another binding of the same input needs more of the held modifiers,
+    /// so `ctrl+s` doesn't also trigger what `s` is bound to.
+    pub fn is_active(&self, binding: &Binding, input: &InputState) -> bool {
+        let modifiers = input.modifiers();
+        if !input.is_down(binding.input) || !binding.matches(modifiers) {
+            return false;
+        }
+
+        !self.bindings.values().flatten().any(|other| {
+            other.input == binding.input && other.matches(modifiers) && other.modifiers.contains(binding.modifiers) && other.modifiers != binding.modifiers
+        })
+    }
+
+    /// Updates every action's state from `input`, call once per frame before reading them.
+    pub fn update(&mut self, input: &InputState) {
+        let held: Vec<(String, bool)> = self
+            .bindings
+            .iter()
+            .map(|(action, bindings)| (action.clone(), bindings.iter().any(|binding| self.is_active(binding, input))))
+            .collect();
+
+        self.states.retain(|action, _| self.bindings.contains_key(action));
+        for (action, held) in held {
+            let state = self.states.entry(action).or_default();
+            *state = ActionState { held, pressed: held && !state.held, released: !held && state.held };
+        }
+    }
+
+    pub fn state(&self, action: &str) -> ActionState {
+        self.states.get(action).copied().unwrap_or_default()
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.state(action).pressed
+    }
+
+    pub fn held(&self, action: &str) -> bool {
+        self.state(action).held
+    }
+
+    pub fn released(&self, action: &str) -> bool {
+        self.state(action).released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(input: &mut InputState, key: Key) {
+        input.handle_event(&WindowEvent::Key(key, 0, Action::Press, Modifiers::empty()));
+    }
+
+    fn release(input: &mut InputState, key: Key) {
+        input.handle_event(&WindowEvent::Key(key, 0, Action::Release, Modifiers::empty()));
+    }
+
+    /// Updates `map` from `input` and starts the next frame, like the window's loop does.
+    fn frame(map: &mut ActionMap, input: &mut InputState) {
+        map.update(input);
+        input.end_frame();
+    }
+
+    fn binding(text: &str) -> Binding {
+        Binding::parse(text).unwrap()
+    }
+
+    fn map(bindings: &[(&str, &[&str])]) -> ActionMap {
+        let mut map = ActionMap::default();
+        for (action, texts) in bindings {
+            for text in texts.iter() {
+                map.bind(action, binding(text));
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn pressed_held_released() {
+        let mut map = map(&[("jump", &["space"])]);
+        let mut input = InputState::default();
+
+        press(&mut input, Key::Space);
+        frame(&mut map, &mut input);
+        assert_eq!(map.state("jump"), ActionState { held: true, pressed: true, released: false });
+
+        // Key repeats don't press again.
+        input.handle_event(&WindowEvent::Key(Key::Space, 0, Action::Repeat, Modifiers::empty()));
+        frame(&mut map, &mut input);
+        assert_eq!(map.state("jump"), ActionState { held: true, pressed: false, released: false });
+
+        release(&mut input, Key::Space);
+        frame(&mut map, &mut input);
+        assert_eq!(map.state("jump"), ActionState { held: false, pressed: false, released: true });
+
+        frame(&mut map, &mut input);
+        assert_eq!(map.state("jump"), ActionState::default());
+    }
+
+    #[test]
+    fn press_and_release_within_a_frame_still_counts() {
+        let mut map = map(&[("jump", &["space"])]);
+        let mut input = InputState::default();
+
+        press(&mut input, Key::Space);
+        release(&mut input, Key::Space);
+        frame(&mut map, &mut input);
+        assert!(map.pressed("jump"));
+
+        frame(&mut map, &mut input);
+        assert!(map.released("jump"));
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut map = map(&[("jump", &["space"])]);
+        let mut input = InputState::default();
+        press(&mut input, Key::Space);
+        frame(&mut map, &mut input);
+
+        input.handle_event(&WindowEvent::Focus(false));
+        frame(&mut map, &mut input);
+
+        assert!(map.released("jump"));
+        assert!(!input.is_down(Input::Key(Key::Space)));
+    }
+
+    #[test]
+    fn mouse_and_gamepad_inputs() {
+        let mut map = map(&[("look", &["mouse_right"]), ("confirm", &["gamepad_a"])]);
+        let mut input = InputState::default();
+
+        input.handle_event(&WindowEvent::MouseButton(MouseButton::Button2, Action::Press, Modifiers::empty()));
+        input.set(Input::Gamepad(GamepadButton::ButtonA), true);
+        frame(&mut map, &mut input);
+
+        assert!(map.held("look"));
+        assert!(map.held("confirm"));
+    }
+
+    #[test]
+    fn chords_need_their_modifiers() {
+        let mut map = map(&[("save", &["ctrl+s"])]);
+        let mut input = InputState::default();
+
+        press(&mut input, Key::S);
+        frame(&mut map, &mut input);
+        assert!(!map.held("save"));
+        release(&mut input, Key::S);
+
+        // Either side of a modifier works.
+        press(&mut input, Key::RightControl);
+        press(&mut input, Key::S);
+        frame(&mut map, &mut input);
+        assert!(map.pressed("save"));
+        assert_eq!(input.modifiers(), Modifiers::Control);
+    }
+
+    #[test]
+    fn chords_shadow_plain_bindings_of_the_same_input() {
+        let mut map = map(&[("save", &["ctrl+s"]), ("back", &["s"]), ("save_as", &["ctrl+shift+s"])]);
+        let mut input = InputState::default();
+
+        press(&mut input, Key::LeftControl);
+        press(&mut input, Key::S);
+        frame(&mut map, &mut input);
+        assert!(map.held("save"));
+        assert!(!map.held("back"));
+
+        press(&mut input, Key::LeftShift);
+        frame(&mut map, &mut input);
+        assert!(map.held("save_as"));
+        assert!(!map.held("save"));
+        assert!(!map.held("back"));
+    }
+
+    #[test]
+    fn extra_modifiers_still_match_plain_bindings() {
+        let mut map = map(&[("forward", &["w"])]);
+        let mut input = InputState::default();
+
+        press(&mut input, Key::LeftShift);
+        press(&mut input, Key::W);
+        frame(&mut map, &mut input);
+
+        assert!(map.held("forward"));
+    }
+
+    #[test]
+    fn captured_binding_skips_modifier_keys() {
+        let mut input = InputState::default();
+        press(&mut input, Key::LeftControl);
+        press(&mut input, Key::LeftAlt);
+        assert_eq!(input.captured_binding(), None);
+
+        press(&mut input, Key::F5);
+        assert_eq!(input.captured_binding(), Some(binding("ctrl+alt+f5")));
+
+        input.end_frame();
+        assert_eq!(input.captured_binding(), None);
+    }
+
+    #[test]
+    fn cancelling_a_capture_does_not_bind_the_click() {
+        let mut input = InputState::default();
+        let click = WindowEvent::MouseButton(MouseButton::Button1, Action::Press, Modifiers::empty());
+
+        // Pressing "Cancel" while capturing, with the mouse over the UI.
+        if !taken_by_ui(&click, false, true, true) {
+            input.handle_event(&click);
+        }
+        assert_eq!(input.captured_binding(), None);
+
+        // A click outside of the UI is captured.
+        if !taken_by_ui(&click, false, false, true) {
+            input.handle_event(&click);
+        }
+        assert_eq!(input.captured_binding(), Some(binding("mouse_left")));
+    }
+
+    #[test]
+    fn keys_reach_a_capture_while_the_ui_has_focus() {
+        let key = WindowEvent::Key(Key::F5, 0, Action::Press, Modifiers::empty());
+        assert!(taken_by_ui(&key, true, false, false));
+        assert!(!taken_by_ui(&key, true, false, true));
+        assert!(!taken_by_ui(&key, false, true, false));
+
+        // Releases always get through, so nothing stays held.
+        let release = WindowEvent::MouseButton(MouseButton::Button1, Action::Release, Modifiers::empty());
+        assert!(!taken_by_ui(&release, true, true, false));
+    }
+
+    #[test]
+    fn rebinding() {
+        let mut map = ActionMap::with_defaults();
+        let mut input = InputState::default();
+
+        map.unbind(QUIT, binding("escape"));
+        map.bind(QUIT, binding("ctrl+q"));
+        map.bind(QUIT, binding("ctrl+q"));
+        assert_eq!(map.bindings[QUIT], [binding("ctrl+q")]);
+
+        press(&mut input, Key::Escape);
+        frame(&mut map, &mut input);
+        assert!(!map.held(QUIT));
+
+        press(&mut input, Key::LeftControl);
+        press(&mut input, Key::Q);
+        frame(&mut map, &mut input);
+        assert!(map.pressed(QUIT));
+    }
+
+    #[test]
+    fn parse_and_display_bindings() {
+        assert_eq!(binding("Ctrl + Shift + S"), Binding { modifiers: Modifiers::Control | Modifiers::Shift, input: Input::Key(Key::S) });
+        assert_eq!(binding("control+enter").to_string(), "ctrl+enter");
+        assert_eq!(binding("shift+ctrl+f1").to_string(), "ctrl+shift+f1");
+        assert_eq!(binding("mouse_left").input, Input::Mouse(MouseButton::Button1));
+        assert_eq!(binding("gamepad_a").input, Input::Gamepad(GamepadButton::ButtonA));
+        assert_eq!(Binding::parse("hyper+s"), Err("Unknown modifier 'hyper'".to_string()));
+        assert_eq!(Binding::parse("ctrl+"), Err("Unknown input ''".to_string()));
+        assert_eq!(Binding::parse("ctrl+nothing"), Err("Unknown input 'nothing'".to_string()));
+    }
+
+    #[test]
+    fn every_input_name_round_trips() {
+        for (key, name) in KEY_NAMES {
+            assert_eq!(Input::parse(name), Some(Input::Key(key)), "{}", name);
+            assert_eq!(Input::Key(key).name(), name);
+        }
+        for (button, name) in MOUSE_BUTTON_NAMES {
+            assert_eq!(Input::parse(name), Some(Input::Mouse(button)), "{}", name);
+        }
+        for (button, name) in GAMEPAD_BUTTON_NAMES {
+            assert_eq!(Input::parse(name), Some(Input::Gamepad(button)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn bindings_file_replaces_listed_actions() {
+        let mut map = ActionMap::with_defaults();
+        map.merge_toml("quit = [\"ctrl+q\", \"gamepad_back\"]\ncustom = [\"f2\"]\n").unwrap();
+
+        assert_eq!(map.bindings[QUIT], [binding("ctrl+q"), binding("gamepad_back")]);
+        assert_eq!(map.bindings["custom"], [binding("f2")]);
+        assert_eq!(map.bindings[CAMERA_FORWARD], ActionMap::with_defaults().bindings[CAMERA_FORWARD]);
+    }
+
+    #[test]
+    fn bad_bindings_file_names_the_action() {
+        let mut map = ActionMap::with_defaults();
+
+        let error = map.merge_toml("quit = [\"ctrl+nothing\"]\n").unwrap_err();
+        assert_eq!(error.to_string(), "quit: Unknown input 'nothing'");
+        assert!(map.merge_toml("quit = \"escape\"\n").is_err());
+        assert_eq!(map, ActionMap::with_defaults());
+    }
+
+    #[test]
+    fn bindings_file_round_trips() {
+        let path = std::env::temp_dir().join(format!("input_bindings_{}.toml", std::process::id()));
+        let mut map = ActionMap::with_defaults();
+        map.bind("custom", binding("alt+mouse_middle"));
+
+        map.save(&path).unwrap();
+        let loaded = ActionMap::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, map);
+        assert_eq!(ActionMap::load(&path).unwrap(), ActionMap::with_defaults());
+    }
+}
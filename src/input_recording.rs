@@ -0,0 +1,487 @@
+//! Recording window events to a file and replaying them in place of live input.
+//!
+//! The log is text, one line per frame followed by one line per event of that frame:
+//!
+//! ```text
+//! dx11-input-log 1
+//! frame 1 16666667
+//! key escape 9 press 0
+//! cursor_pos 512.5 300.0
+//! ```
+//!
+//! Frame lines hold the frame number and the delta time in nanoseconds. Events use the names of
+//! [`Input`] for keys and mouse buttons and the raw bits for modifiers. Gamepads are not polled
+//! through window events and are not recorded.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use glfw::{Action, Key, Modifiers, WindowEvent};
+
+use crate::input::Input;
+
+pub const INPUT_LOG_MAGIC: &str = "dx11-input-log";
+pub const INPUT_LOG_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum InputLogError {
+    Io(io::Error),
+    /// A line that doesn't parse, numbered from 1.
+    Format { line: usize, message: String },
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for InputLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            InputLogError::Io(error) => write!(f, "{}", error),
+            InputLogError::Format { line, message } => write!(f, "Invalid input log at line {}: {}", line, message),
+            InputLogError::UnsupportedVersion(version) => {
+                write!(f, "Input log version {} is not supported, expected {}", version, INPUT_LOG_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputLogError {}
+
+impl From<io::Error> for InputLogError {
+    fn from(error: io::Error) -> Self {
+        InputLogError::Io(error)
+    }
+}
+
+/// The events of one frame and the time it took.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+    pub frame: u64,
+    pub delta: Duration,
+    pub events: Vec<WindowEvent>,
+}
+
+/// Whether `event` comes from the user rather than the window itself. Only these are replayed, the
+/// window keeps reporting its own position and size.
+pub fn is_input_event(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::Focus(_)
+            | WindowEvent::MouseButton(..)
+            | WindowEvent::CursorPos(..)
+            | WindowEvent::CursorEnter(_)
+            | WindowEvent::Scroll(..)
+            | WindowEvent::Key(..)
+            | WindowEvent::Char(_)
+            | WindowEvent::CharModifiers(..)
+            | WindowEvent::FileDrop(_)
+    )
+}
+
+fn action_name(action: Action) -> &'static str {
+    match action {
+        Action::Release => "release",
+        Action::Press => "press",
+        Action::Repeat => "repeat",
+    }
+}
+
+fn flag(value: bool) -> u8 {
+    value as u8
+}
+
+/// Formats `event` as a log line without the line break.
+pub fn format_event(event: &WindowEvent) -> String {
+    match event {
+        WindowEvent::Pos(x, y) => format!("pos {} {}", x, y),
+        WindowEvent::Size(width, height) => format!("size {} {}", width, height),
+        WindowEvent::Close => "close".to_string(),
+        WindowEvent::Refresh => "refresh".to_string(),
+        WindowEvent::Focus(focused) => format!("focus {}", flag(*focused)),
+        WindowEvent::Iconify(iconified) => format!("iconify {}", flag(*iconified)),
+        WindowEvent::FramebufferSize(width, height) => format!("framebuffer_size {} {}", width, height),
+        WindowEvent::MouseButton(button, action, modifiers) => {
+            format!("mouse_button {} {} {}", Input::Mouse(*button).name(), action_name(*action), modifiers.bits())
+        }
+        WindowEvent::CursorPos(x, y) => format!("cursor_pos {:?} {:?}", x, y),
+        WindowEvent::CursorEnter(entered) => format!("cursor_enter {}", flag(*entered)),
+        WindowEvent::Scroll(x, y) => format!("scroll {:?} {:?}", x, y),
+        WindowEvent::Key(key, scancode, action, modifiers) => {
+            format!("key {} {} {} {}", Input::Key(*key).name(), scancode, action_name(*action), modifiers.bits())
+        }
+        WindowEvent::Char(character) => format!("char {}", *character as u32),
+        WindowEvent::CharModifiers(character, modifiers) => format!("char_modifiers {} {}", *character as u32, modifiers.bits()),
+        // Paths are separated by tabs, which they are very unlikely to contain.
+        WindowEvent::FileDrop(paths) => {
+            let paths: Vec<String> = paths.iter().map(|path| path.to_string_lossy().into_owned()).collect();
+            format!("file_drop\t{}", paths.join("\t"))
+        }
+        WindowEvent::Maximize(maximized) => format!("maximize {}", flag(*maximized)),
+        WindowEvent::ContentScale(x, y) => format!("content_scale {:?} {:?}", x, y),
+    }
+}
+
+/// Parses a line written by [`format_event`].
+pub fn parse_event(line: &str) -> Result<WindowEvent, String> {
+    if let Some(paths) = line.strip_prefix("file_drop\t").or((line == "file_drop").then_some("")) {
+        return Ok(WindowEvent::FileDrop(paths.split('\t').filter(|path| !path.is_empty()).map(PathBuf::from).collect()));
+    }
+
+    let mut fields = line.split_whitespace();
+    let name = fields.next().ok_or("Empty event")?;
+    let mut next = || fields.next().ok_or_else(|| format!("Missing field in '{}'", line));
+
+    fn number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+        text.parse().map_err(|_| format!("Invalid number '{}'", text))
+    }
+    fn boolean(text: &str) -> Result<bool, String> {
+        match text {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(format!("Invalid flag '{}'", text)),
+        }
+    }
+    fn action(text: &str) -> Result<Action, String> {
+        match text {
+            "release" => Ok(Action::Release),
+            "press" => Ok(Action::Press),
+            "repeat" => Ok(Action::Repeat),
+            _ => Err(format!("Invalid action '{}'", text)),
+        }
+    }
+    fn modifiers(text: &str) -> Result<Modifiers, String> {
+        Modifiers::from_bits(number(text)?).ok_or_else(|| format!("Invalid modifiers '{}'", text))
+    }
+    fn character(text: &str) -> Result<char, String> {
+        char::from_u32(number(text)?).ok_or_else(|| format!("Invalid character '{}'", text))
+    }
+
+    let event = match name {
+        "pos" => WindowEvent::Pos(number(next()?)?, number(next()?)?),
+        "size" => WindowEvent::Size(number(next()?)?, number(next()?)?),
+        "close" => WindowEvent::Close,
+        "refresh" => WindowEvent::Refresh,
+        "focus" => WindowEvent::Focus(boolean(next()?)?),
+        "iconify" => WindowEvent::Iconify(boolean(next()?)?),
+        "framebuffer_size" => WindowEvent::FramebufferSize(number(next()?)?, number(next()?)?),
+        "mouse_button" => {
+            let button = match Input::parse(next()?) {
+                Some(Input::Mouse(button)) => button,
+                _ => return Err(format!("Invalid mouse button in '{}'", line)),
+            };
+            WindowEvent::MouseButton(button, action(next()?)?, modifiers(next()?)?)
+        }
+        "cursor_pos" => WindowEvent::CursorPos(number(next()?)?, number(next()?)?),
+        "cursor_enter" => WindowEvent::CursorEnter(boolean(next()?)?),
+        "scroll" => WindowEvent::Scroll(number(next()?)?, number(next()?)?),
+        "key" => {
+            let key = match next()? {
+                "unknown" => Key::Unknown,
+                name => match Input::parse(name) {
+                    Some(Input::Key(key)) => key,
+                    _ => return Err(format!("Invalid key '{}'", name)),
+                },
+            };
+            WindowEvent::Key(key, number(next()?)?, action(next()?)?, modifiers(next()?)?)
+        }
+        "char" => WindowEvent::Char(character(next()?)?),
+        "char_modifiers" => WindowEvent::CharModifiers(character(next()?)?, modifiers(next()?)?),
+        "maximize" => WindowEvent::Maximize(boolean(next()?)?),
+        "content_scale" => WindowEvent::ContentScale(number(next()?)?, number(next()?)?),
+        _ => return Err(format!("Unknown event '{}'", name)),
+    };
+
+    if fields.next().is_some() {
+        return Err(format!("Too many fields in '{}'", line));
+    }
+    Ok(event)
+}
+
+pub struct InputLogWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> InputLogWriter<W> {
+    /// Writes the header.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "{} {}", INPUT_LOG_MAGIC, INPUT_LOG_VERSION)?;
+        Ok(InputLogWriter { writer })
+    }
+
+    pub fn write_frame(&mut self, frame: &RecordedFrame) -> io::Result<()> {
+        writeln!(self.writer, "frame {} {}", frame.frame, frame.delta.as_nanos())?;
+        for event in &frame.events {
+            writeln!(self.writer, "{}", format_event(event))?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+pub struct InputLogReader<R: BufRead> {
+    lines: std::iter::Peekable<std::iter::Enumerate<io::Lines<R>>>,
+}
+
+impl<R: BufRead> InputLogReader<R> {
+    /// Reads and checks the header.
+    pub fn new(reader: R) -> Result<Self, InputLogError> {
+        let mut lines = reader.lines().enumerate().peekable();
+
+        let header = lines.next().map(|(_, line)| line).transpose()?.unwrap_or_default();
+        let version = header
+            .strip_prefix(INPUT_LOG_MAGIC)
+            .and_then(|version| version.trim().parse().ok())
+            .ok_or_else(|| InputLogError::Format { line: 1, message: "Not an input log".to_string() })?;
+        if version != INPUT_LOG_VERSION {
+            return Err(InputLogError::UnsupportedVersion(version));
+        }
+
+        Ok(InputLogReader { lines })
+    }
+
+    /// The next frame, `None` at the end of the log.
+    pub fn read_frame(&mut self) -> Result<Option<RecordedFrame>, InputLogError> {
+        let Some(line) = self.lines.next() else {
+            return Ok(None);
+        };
+        let (index, line) = line;
+        let line = line?;
+        let format_error = |message: String| InputLogError::Format { line: index + 1, message };
+
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some("frame") {
+            return Err(format_error(format!("Expected a frame, found '{}'", line)));
+        }
+        let frame = fields.next().and_then(|frame| frame.parse().ok()).ok_or_else(|| format_error("Invalid frame number".to_string()))?;
+        let delta = fields.next().and_then(|delta| delta.parse().ok()).ok_or_else(|| format_error("Invalid delta time".to_string()))?;
+
+        let mut events = Vec::new();
+        while let Some((_, next)) = self.lines.peek() {
+            if matches!(next, Ok(next) if next.starts_with("frame ")) {
+                break;
+            }
+
+            let (index, line) = self.lines.next().unwrap();
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push(parse_event(&line).map_err(|message| InputLogError::Format { line: index + 1, message })?);
+        }
+
+        Ok(Some(RecordedFrame { frame, delta: Duration::from_nanos(delta), events }))
+    }
+}
+
+/// Writes every frame to a file.
+pub struct InputRecorder {
+    writer: InputLogWriter<BufWriter<File>>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> Result<Self, InputLogError> {
+        Ok(InputRecorder { writer: InputLogWriter::new(BufWriter::new(File::create(path)?))? })
+    }
+
+    pub fn record(&mut self, frame: &RecordedFrame) -> Result<(), InputLogError> {
+        Ok(self.writer.write_frame(frame)?)
+    }
+
+    pub fn flush(&mut self) -> Result<(), InputLogError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// The frames of a log, handed out one per frame.
+#[derive(Clone, Debug, Default)]
+pub struct InputReplay {
+    frames: VecDeque<RecordedFrame>,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> Result<Self, InputLogError> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read(reader: impl BufRead) -> Result<Self, InputLogError> {
+        let mut reader = InputLogReader::new(reader)?;
+        let mut frames = VecDeque::new();
+        while let Some(frame) = reader.read_frame()? {
+            frames.push_back(frame);
+        }
+        Ok(InputReplay { frames })
+    }
+
+    pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+        self.frames.pop_front()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glfw::MouseButton;
+
+    /// One of every event, with values that need care: negative positions, fractions, modifiers and non-ASCII text.
+    fn every_event() -> Vec<WindowEvent> {
+        vec![
+            WindowEvent::Pos(-1920, 40),
+            WindowEvent::Size(1280, 720),
+            WindowEvent::Close,
+            WindowEvent::Refresh,
+            WindowEvent::Focus(true),
+            WindowEvent::Focus(false),
+            WindowEvent::Iconify(true),
+            WindowEvent::FramebufferSize(2560, 1440),
+            WindowEvent::MouseButton(MouseButton::Button5, Action::Release, Modifiers::Shift | Modifiers::Super),
+            WindowEvent::CursorPos(512.5, -0.1),
+            WindowEvent::CursorEnter(false),
+            WindowEvent::Scroll(0.0, -2.25),
+            WindowEvent::Key(Key::Escape, 9, Action::Press, Modifiers::empty()),
+            WindowEvent::Key(Key::KpEnter, 104, Action::Repeat, Modifiers::Control | Modifiers::Alt),
+            WindowEvent::Key(Key::Unknown, 0, Action::Release, Modifiers::NumLock),
+            WindowEvent::Char('a'),
+            WindowEvent::Char('\u{1F600}'),
+            WindowEvent::CharModifiers('ß', Modifiers::Shift),
+            WindowEvent::FileDrop(vec![PathBuf::from("C:\\models\\a mesh.gltf"), PathBuf::from("/tmp/b.png")]),
+            WindowEvent::FileDrop(Vec::new()),
+            WindowEvent::Maximize(true),
+            WindowEvent::ContentScale(1.5, 1.25),
+        ]
+    }
+
+    #[test]
+    fn every_event_round_trips() {
+        for event in every_event() {
+            let line = format_event(&event);
+            assert!(!line.contains('\n'), "{}", line);
+            assert_eq!(parse_event(&line), Ok(event), "{}", line);
+        }
+    }
+
+    #[test]
+    fn event_lines() {
+        assert_eq!(format_event(&WindowEvent::Key(Key::Escape, 9, Action::Press, Modifiers::empty())), "key escape 9 press 0");
+        assert_eq!(format_event(&WindowEvent::CursorPos(512.5, 300.0)), "cursor_pos 512.5 300.0");
+        assert_eq!(format_event(&WindowEvent::MouseButton(MouseButton::Button1, Action::Press, Modifiers::Control)), "mouse_button mouse_left press 2");
+    }
+
+    #[test]
+    fn only_user_input_is_replayed() {
+        assert!(is_input_event(&WindowEvent::Key(Key::A, 0, Action::Press, Modifiers::empty())));
+        assert!(is_input_event(&WindowEvent::FileDrop(Vec::new())));
+        assert!(!is_input_event(&WindowEvent::Size(1, 1)));
+        assert!(!is_input_event(&WindowEvent::FramebufferSize(1, 1)));
+        assert!(!is_input_event(&WindowEvent::Close));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let errors = [
+            ("", "Empty event"),
+            ("teleport 1 2", "Unknown event 'teleport'"),
+            ("pos 1", "Missing field in 'pos 1'"),
+            ("pos one 2", "Invalid number 'one'"),
+            ("scroll 1.0 up", "Invalid number 'up'"),
+            ("focus yes", "Invalid flag 'yes'"),
+            ("focus 1 1", "Too many fields in 'focus 1 1'"),
+            ("close now", "Too many fields in 'close now'"),
+            ("key escape 9 tap 0", "Invalid action 'tap'"),
+            ("key mouse_left 0 press 0", "Invalid key 'mouse_left'"),
+            ("key escape 9 press 4096", "Invalid modifiers '4096'"),
+            ("mouse_button escape press 0", "Invalid mouse button in 'mouse_button escape press 0'"),
+            ("char 55296", "Invalid character '55296'"),
+            ("file_dropped", "Unknown event 'file_dropped'"),
+        ];
+
+        for (line, message) in errors {
+            assert_eq!(parse_event(line), Err(message.to_string()), "{}", line);
+        }
+    }
+
+    fn recorded_frames() -> Vec<RecordedFrame> {
+        vec![
+            RecordedFrame { frame: 1, delta: Duration::from_nanos(16_666_667), events: every_event() },
+            RecordedFrame { frame: 2, delta: Duration::from_millis(33), events: Vec::new() },
+            RecordedFrame { frame: 3, delta: Duration::ZERO, events: vec![WindowEvent::Close] },
+        ]
+    }
+
+    fn write_log(frames: &[RecordedFrame]) -> String {
+        let mut writer = InputLogWriter::new(Vec::new()).unwrap();
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn log_round_trips() {
+        let frames = recorded_frames();
+        let mut replay = InputReplay::read(write_log(&frames).as_bytes()).unwrap();
+
+        assert_eq!(replay.remaining(), 3);
+        for frame in frames {
+            assert_eq!(replay.next_frame(), Some(frame));
+        }
+        assert_eq!(replay.next_frame(), None);
+    }
+
+    #[test]
+    fn log_starts_with_the_header() {
+        let log = write_log(&[]);
+        assert_eq!(log, format!("{} {}\n", INPUT_LOG_MAGIC, INPUT_LOG_VERSION));
+        assert_eq!(InputReplay::read(log.as_bytes()).unwrap().remaining(), 0);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let log = format!("{} {}\nframe 1 0\n", INPUT_LOG_MAGIC, INPUT_LOG_VERSION + 1);
+
+        let error = InputReplay::read(log.as_bytes()).unwrap_err();
+        assert!(matches!(error, InputLogError::UnsupportedVersion(version) if version == INPUT_LOG_VERSION + 1));
+        assert_eq!(error.to_string(), format!("Input log version 2 is not supported, expected {}", INPUT_LOG_VERSION));
+    }
+
+    #[test]
+    fn missing_header() {
+        for log in ["", "frame 1 0\n", "dx11-input-log one\n"] {
+            let error = InputReplay::read(log.as_bytes()).unwrap_err();
+            assert!(matches!(error, InputLogError::Format { line: 1, .. }), "{:?}", log);
+        }
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let log = format!("{} 1\nframe 1 0\nfocus 1\nfocus 2\n", INPUT_LOG_MAGIC);
+        let error = InputReplay::read(log.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid input log at line 4: Invalid flag '2'");
+
+        let log = format!("{} 1\nfocus 1\n", INPUT_LOG_MAGIC);
+        assert!(matches!(InputReplay::read(log.as_bytes()), Err(InputLogError::Format { line: 2, .. })));
+
+        let log = format!("{} 1\nframe 1 soon\n", INPUT_LOG_MAGIC);
+        assert!(matches!(InputReplay::read(log.as_bytes()), Err(InputLogError::Format { line: 2, .. })));
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        let log = format!("{} 1\nframe 1 0\n\nfocus 1\n\nframe 2 0\n", INPUT_LOG_MAGIC);
+        let mut replay = InputReplay::read(log.as_bytes()).unwrap();
+
+        assert_eq!(replay.next_frame().unwrap().events, [WindowEvent::Focus(true)]);
+        assert_eq!(replay.next_frame().unwrap().frame, 2);
+    }
+}
@@ -0,0 +1,287 @@
+//! Khronos KTX 2.0 (`.ktx2`) container parsing.
+//!
+//! Only textures without supercompression are supported, Basis Universal and Zstandard payloads
+//! have to be transcoded by the asset pipeline first.
+
+use crate::texture::{mip_size, TextureData, TextureFormat};
+use crate::texture_loader::TextureError;
+
+pub const KTX2_IDENTIFIER: [u8; 12] = [0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a];
+
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Maps a `VkFormat` value.
+pub fn format_from_vulkan(vk_format: u32) -> Option<TextureFormat> {
+    Some(match vk_format {
+        9 => TextureFormat::R8Unorm,
+        16 => TextureFormat::Rg8Unorm,
+        37 => TextureFormat::Rgba8Unorm,
+        43 => TextureFormat::Rgba8UnormSrgb,
+        44 => TextureFormat::Bgra8Unorm,
+        50 => TextureFormat::Bgra8UnormSrgb,
+        64 => TextureFormat::Rgb10A2Unorm,
+        76 => TextureFormat::R16Float,
+        83 => TextureFormat::Rg16Float,
+        91 => TextureFormat::Rgba16Unorm,
+        97 => TextureFormat::Rgba16Float,
+        100 => TextureFormat::R32Float,
+        109 => TextureFormat::Rgba32Float,
+        122 => TextureFormat::Rg11B10Float,
+        131 | 133 => TextureFormat::Bc1Unorm,
+        132 | 134 => TextureFormat::Bc1UnormSrgb,
+        135 => TextureFormat::Bc2Unorm,
+        136 => TextureFormat::Bc2UnormSrgb,
+        137 => TextureFormat::Bc3Unorm,
+        138 => TextureFormat::Bc3UnormSrgb,
+        139 => TextureFormat::Bc4Unorm,
+        140 => TextureFormat::Bc4Snorm,
+        141 => TextureFormat::Bc5Unorm,
+        142 => TextureFormat::Bc5Snorm,
+        143 => TextureFormat::Bc6hUfloat,
+        144 => TextureFormat::Bc6hSfloat,
+        145 => TextureFormat::Bc7Unorm,
+        146 => TextureFormat::Bc7UnormSrgb,
+        _ => return None,
+    })
+}
+
+/// Parses a KTX2 file.
+///
+/// KTX2 stores levels from smallest to largest with all layers and faces of a level together,
+/// the images are reordered so every layer (or cube face) has its complete mip chain in a row.
+pub fn parse_ktx2(bytes: &[u8]) -> Result<TextureData, TextureError> {
+    if bytes.len() < HEADER_SIZE || bytes[0..12] != KTX2_IDENTIFIER {
+        return Err(TextureError::Decode("not a KTX2 file".into()));
+    }
+
+    let vk_format = read_u32(bytes, 12);
+    let width = read_u32(bytes, 20);
+    let height = read_u32(bytes, 24).max(1);
+    let depth = read_u32(bytes, 28);
+    let layer_count = read_u32(bytes, 32).max(1);
+    let face_count = read_u32(bytes, 36);
+    let mip_levels = read_u32(bytes, 40).max(1);
+    let supercompression = read_u32(bytes, 44);
+
+    if supercompression != 0 {
+        return Err(TextureError::Unsupported(format!("KTX2 supercompression scheme {}", supercompression)));
+    }
+    if depth > 0 {
+        return Err(TextureError::Unsupported("volume textures".into()));
+    }
+    if width == 0 {
+        return Err(TextureError::Decode("KTX2 texture has no size".into()));
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err(TextureError::Decode("KTX2 face count must be 1 or 6".into()));
+    }
+
+    let format = format_from_vulkan(vk_format)
+        .ok_or_else(|| TextureError::Unsupported(format!("VkFormat {}", vk_format)))?;
+
+    let is_cube = face_count == 6;
+    let array_layers = layer_count.checked_mul(face_count)
+        .ok_or_else(|| TextureError::Decode(format!("KTX2 layer count {} is too large", layer_count)))?;
+
+    // Also rejects mip counts above the full chain, which keeps the level index small.
+    let size = TextureData::packed_size(format, width, height, mip_levels, array_layers)?;
+    let level_index = bytes.get(HEADER_SIZE..HEADER_SIZE + mip_levels as usize * LEVEL_INDEX_ENTRY_SIZE)
+        .ok_or_else(|| TextureError::Decode("truncated KTX2 level index".into()))?;
+
+    // Validate every level range before allocating the texture.
+    let mut levels = Vec::with_capacity(mip_levels as usize);
+    for level in 0..mip_levels {
+        let entry = &level_index[level as usize * LEVEL_INDEX_ENTRY_SIZE..];
+        let level_offset = read_u64(entry, 0);
+        let level_length = read_u64(entry, 8);
+
+        // Cannot fail, `packed_size` checked every level.
+        let image_size = format.slice_pitch(mip_size(width, level), mip_size(height, level)).unwrap() as usize;
+        if Some(level_length) != (image_size as u64).checked_mul(array_layers as u64) {
+            return Err(TextureError::Decode("KTX2 level size does not match its format".into()));
+        }
+
+        let level_data = level_offset.checked_add(level_length)
+            .and_then(|end| bytes.get(usize::try_from(level_offset).ok()?..usize::try_from(end).ok()?))
+            .ok_or_else(|| TextureError::Decode("truncated KTX2 level data".into()))?;
+        levels.push((level_data, image_size));
+    }
+
+    let (subresources, _) = TextureData::packed_subresources(format, width, height, mip_levels, array_layers)?;
+    let mut data = vec![0u8; size];
+
+    for (level, (level_data, image_size)) in levels.into_iter().enumerate() {
+        // Within a level, images are ordered by layer and then by face, which matches the
+        // Direct3D layer index of cube arrays.
+        for (layer, image) in level_data.chunks_exact(image_size).enumerate() {
+            let subresource = &subresources[level + layer * mip_levels as usize];
+            data[subresource.offset..subresource.offset + image_size].copy_from_slice(image);
+        }
+    }
+
+    Ok(TextureData {
+        format,
+        width,
+        height,
+        mip_levels,
+        array_layers,
+        is_cube,
+        subresources,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+    const VK_FORMAT_BC1_RGBA_UNORM: u32 = 133;
+
+    /// Minimal KTX2 file. `levels` holds the data of each level from the base level down, the
+    /// level data itself is stored smallest level first like KTX2 writers do.
+    fn ktx2(vk_format: u32, width: u32, height: u32, layers: u32, faces: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in [vk_format, 1, width, height, 0, layers, faces, levels.len() as u32, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE, 0);
+
+        let mut offset = bytes.len();
+        let mut offsets = vec![0; levels.len()];
+        for (level, data) in levels.iter().enumerate().rev() {
+            offsets[level] = offset;
+            offset += data.len();
+        }
+        for (level, data) in levels.iter().enumerate() {
+            let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+            bytes[entry..entry + 8].copy_from_slice(&(offsets[level] as u64).to_le_bytes());
+            bytes[entry + 8..entry + 16].copy_from_slice(&(data.len() as u64).to_le_bytes());
+            bytes[entry + 16..entry + 24].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        }
+        for data in levels.iter().rev() {
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn set_u64(bytes: &mut [u8], offset: usize, value: u64) {
+        bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn decode_error(bytes: &[u8]) -> String {
+        match parse_ktx2(bytes) {
+            Err(error) => error.to_string(),
+            Ok(_) => panic!("invalid KTX2 file was accepted"),
+        }
+    }
+
+    fn sample_file() -> Vec<u8> {
+        ktx2(VK_FORMAT_R8G8B8A8_UNORM, 2, 2, 0, 1, &[vec![1; 16], vec![2; 4]])
+    }
+
+    #[test]
+    fn parses_levels_into_direct3d_order() {
+        // Two layers of a 2x2 texture with two levels, every image filled with a distinct value.
+        let base: Vec<u8> = [vec![10; 16], vec![11; 16]].concat();
+        let small: Vec<u8> = [vec![20; 4], vec![21; 4]].concat();
+        let texture = parse_ktx2(&ktx2(VK_FORMAT_R8G8B8A8_UNORM, 2, 2, 2, 1, &[base, small])).unwrap();
+
+        assert_eq!(texture.format, TextureFormat::Rgba8Unorm);
+        assert_eq!((texture.width, texture.height, texture.mip_levels, texture.array_layers), (2, 2, 2, 2));
+        assert!(!texture.is_cube);
+        assert_eq!(texture.subresource_data(0, 0), &[10; 16]);
+        assert_eq!(texture.subresource_data(1, 0), &[20; 4]);
+        assert_eq!(texture.subresource_data(0, 1), &[11; 16]);
+        assert_eq!(texture.subresource_data(1, 1), &[21; 4]);
+    }
+
+    #[test]
+    fn parses_cube_maps() {
+        let faces: Vec<u8> = (0..6).flat_map(|face| vec![face as u8; 8]).collect();
+        let texture = parse_ktx2(&ktx2(VK_FORMAT_BC1_RGBA_UNORM, 4, 4, 0, 6, &[faces])).unwrap();
+        assert_eq!(texture.format, TextureFormat::Bc1Unorm);
+        assert!(texture.is_cube);
+        assert_eq!(texture.array_layers, 6);
+        assert_eq!(texture.subresource_data(0, 5), &[5; 8]);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let bytes = sample_file();
+        assert!(decode_error(&bytes[..HEADER_SIZE - 1]).contains("not a KTX2 file"));
+
+        let mut bad = bytes.clone();
+        set_u32(&mut bad, 44, 2);
+        assert!(decode_error(&bad).contains("supercompression"));
+
+        let mut bad = bytes.clone();
+        set_u32(&mut bad, 28, 4);
+        assert!(decode_error(&bad).contains("volume"));
+
+        let mut bad = bytes.clone();
+        set_u32(&mut bad, 20, 0);
+        assert!(decode_error(&bad).contains("no size"));
+
+        let mut bad = bytes.clone();
+        set_u32(&mut bad, 36, 2);
+        assert!(decode_error(&bad).contains("face count"));
+
+        let mut bad = bytes.clone();
+        set_u32(&mut bad, 12, 1);
+        assert!(decode_error(&bad).contains("VkFormat"));
+    }
+
+    #[test]
+    fn rejects_mip_counts_above_the_full_chain() {
+        let mut bytes = sample_file();
+        for mip_levels in [3, 33, u32::MAX] {
+            set_u32(&mut bytes, 40, mip_levels);
+            assert!(decode_error(&bytes).contains("mip levels"), "{}", mip_levels);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_layers_without_allocating() {
+        let mut bytes = ktx2(VK_FORMAT_R8G8B8A8_UNORM, 1, 1, 1, 6, &[vec![0; 24]]);
+        set_u32(&mut bytes, 32, u32::MAX / 2);
+        assert!(decode_error(&bytes).contains("too large"));
+
+        // The product fits, but the level index does not describe that much data.
+        set_u32(&mut bytes, 32, u32::MAX / 6);
+        assert!(decode_error(&bytes).contains("level size"));
+    }
+
+    #[test]
+    fn rejects_level_ranges_outside_of_the_file() {
+        let bytes = sample_file();
+
+        let mut bad = bytes.clone();
+        set_u64(&mut bad, HEADER_SIZE, bytes.len() as u64 - 8);
+        assert!(decode_error(&bad).contains("truncated KTX2 level data"));
+
+        let mut bad = bytes.clone();
+        set_u64(&mut bad, HEADER_SIZE, u64::MAX - 4);
+        assert!(decode_error(&bad).contains("truncated KTX2 level data"));
+
+        let mut bad = bytes.clone();
+        set_u64(&mut bad, HEADER_SIZE + 8, u64::MAX);
+        assert!(decode_error(&bad).contains("level size"));
+
+        assert!(decode_error(&bytes[..HEADER_SIZE + 8]).contains("truncated KTX2 level index"));
+        assert!(decode_error(&bytes[..bytes.len() - 1]).contains("truncated KTX2 level data"));
+    }
+}
@@ -0,0 +1,31 @@
+//! The parts of the engine that don't need a window: mesh and texture assets with their importers,
+//! processing passes, file formats and GPU upload, the render state descriptions, the swap chain
+//! and device recovery, input and logging. Shared by the engine and the converters in `src/bin`.
+
+pub mod mesh;
+pub mod mesh_optimizer;
+pub mod mesh_simplifier;
+pub mod mesh_lod;
+pub mod mesh_file;
+pub mod obj_loader;
+pub mod gltf_loader;
+pub mod gpu_mesh;
+pub mod texture;
+pub mod texture_loader;
+pub mod dds_loader;
+pub mod ktx2_loader;
+pub mod mip_generator;
+pub mod block_compression;
+pub mod gpu_texture;
+
+pub mod sampler_cache;
+pub mod pipeline_state;
+pub mod depth_buffer;
+pub mod color_space;
+pub mod swap_chain;
+pub mod device_recovery;
+
+pub mod input;
+pub mod gamepad;
+pub mod input_recording;
+pub mod logging;
@@ -0,0 +1,529 @@
+//! Logger behind the `log` macros: per module levels, stdout and rotating file sinks, frame numbers
+//! and a history the UI log viewer reads from.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+static FRAME: AtomicU64 = AtomicU64::new(0);
+static HISTORY: OnceLock<Arc<Mutex<LogHistory>>> = OnceLock::new();
+
+/// Sets the frame number records are tagged with, call once at the start of every frame.
+pub fn set_frame(frame: u64) {
+    FRAME.store(frame, Ordering::Relaxed);
+}
+
+pub fn frame() -> u64 {
+    FRAME.load(Ordering::Relaxed)
+}
+
+/// The records kept for the log viewer, `None` before [`init`].
+pub fn history() -> Option<&'static Arc<Mutex<LogHistory>>> {
+    HISTORY.get()
+}
+
+/// Strips the crate name from module paths, so filters and output can use `window` for `dx11_graphics_engine::window`.
+pub fn short_target(target: &str) -> &str {
+    target
+        .strip_prefix(env!("CARGO_CRATE_NAME"))
+        .and_then(|rest| rest.strip_prefix("::"))
+        .unwrap_or(target)
+}
+
+/// Levels per module in the `env_logger` syntax, `info,window=warn,d3d11=debug`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFilter {
+    pub default: LevelFilter,
+    /// Module prefixes with their levels, the longest matching prefix wins.
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter { default: LevelFilter::Info, modules: Vec::new() }
+    }
+}
+
+impl LogFilter {
+    /// Parses comma separated directives, a bare level sets the default. Unknown levels are an error.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = LogFilter::default();
+
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let level = level.trim().parse().map_err(|_| format!("Unknown log level '{}'", level))?;
+                    filter.modules.push((short_target(module.trim()).to_string(), level));
+                }
+                None => {
+                    filter.default = directive.parse().map_err(|_| format!("Unknown log level '{}'", directive))?;
+                }
+            }
+        }
+
+        Ok(filter)
+    }
+
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        let target = short_target(target);
+
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module || target.strip_prefix(module.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        level <= self.level_for(target)
+    }
+
+    /// The most verbose level any module logs at, for `log::set_max_level`.
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules.iter().map(|(_, level)| *level).fold(self.default, Ord::max)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogRecord {
+    pub frame: u64,
+    /// Milliseconds since the logger was initialized.
+    pub time_ms: u64,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>6}.{:03} #{:<6} {:<5} {}: {}",
+            self.time_ms / 1000,
+            self.time_ms % 1000,
+            self.frame,
+            self.level,
+            self.target,
+            self.message,
+        )
+    }
+}
+
+/// Where formatted records go.
+pub trait LogSink: Send {
+    fn write(&mut self, record: &LogRecord) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct StdoutSink;
+
+impl LogSink for StdoutSink {
+    fn write(&mut self, record: &LogRecord) -> io::Result<()> {
+        writeln!(io::stdout().lock(), "{}", record)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// Path of the `index`th older file, `engine.log` becomes `engine.1.log`.
+pub fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// Writes to a file, moving it aside once it grows past `max_bytes` and keeping `max_files` old ones.
+pub struct RotatingFileSink {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: u32,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFileSink {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, max_files: u32) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+
+        Ok(RotatingFileSink { path, max_bytes, max_files, file, written })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (0..self.max_files).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+
+        // Without old files to keep the current one is simply started over.
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl LogSink for RotatingFileSink {
+    fn write(&mut self, record: &LogRecord) -> io::Result<()> {
+        let line = format!("{}\n", record);
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// What the log viewer shows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogViewFilter {
+    pub max_level: LevelFilter,
+    /// Records of other modules are hidden when not empty.
+    pub target: String,
+    /// Text that has to appear in the message, ignoring case.
+    pub search: String,
+}
+
+impl Default for LogViewFilter {
+    fn default() -> Self {
+        LogViewFilter { max_level: LevelFilter::Trace, target: String::new(), search: String::new() }
+    }
+}
+
+impl LogViewFilter {
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        record.level <= self.max_level
+            && (self.target.is_empty() || record.target.starts_with(&self.target))
+            && (self.search.is_empty() || record.message.to_lowercase().contains(&self.search.to_lowercase()))
+    }
+}
+
+/// The latest records, oldest first.
+#[derive(Debug)]
+pub struct LogHistory {
+    records: VecDeque<LogRecord>,
+    capacity: usize,
+}
+
+impl LogHistory {
+    pub fn new(capacity: usize) -> Self {
+        LogHistory { records: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, record: LogRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &LogRecord> {
+        self.records.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogConfig {
+    pub filter: String,
+    pub stdout: bool,
+    pub file: Option<PathBuf>,
+    pub max_file_bytes: u64,
+    /// Rotated files kept next to the current one.
+    pub max_files: u32,
+    /// Records kept for the log viewer.
+    pub history: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            filter: "info".to_string(),
+            stdout: true,
+            file: None,
+            max_file_bytes: 4 * 1024 * 1024,
+            max_files: 3,
+            history: 2000,
+        }
+    }
+}
+
+struct Logger {
+    filter: LogFilter,
+    sinks: Mutex<Vec<Box<dyn LogSink>>>,
+    history: Arc<Mutex<LogHistory>>,
+    start: Instant,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata.level(), metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let record = LogRecord {
+            frame: frame(),
+            time_ms: self.start.elapsed().as_millis() as u64,
+            level: record.level(),
+            target: short_target(record.target()).to_string(),
+            message: record.args().to_string(),
+        };
+
+        for sink in self.sinks.lock().unwrap().iter_mut() {
+            // There is nowhere left to report a failing sink.
+            let _ = sink.write(&record);
+        }
+        self.history.lock().unwrap().push(record);
+    }
+
+    fn flush(&self) {
+        for sink in self.sinks.lock().unwrap().iter_mut() {
+            let _ = sink.flush();
+        }
+    }
+}
+
+/// Installs the logger, can only be called once.
+pub fn init(config: &LogConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = LogFilter::parse(&config.filter)?;
+
+    let mut sinks: Vec<Box<dyn LogSink>> = Vec::new();
+    if config.stdout {
+        sinks.push(Box::new(StdoutSink));
+    }
+    if let Some(file) = &config.file {
+        sinks.push(Box::new(RotatingFileSink::new(file, config.max_file_bytes, config.max_files)?));
+    }
+
+    let history = HISTORY.get_or_init(|| Arc::new(Mutex::new(LogHistory::new(config.history)))).clone();
+
+    log::set_max_level(filter.max_level());
+    log::set_boxed_logger(Box::new(Logger { filter, sinks: Mutex::new(sinks), history, start: Instant::now() }))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(level: Level, target: &str, message: &str) -> LogRecord {
+        LogRecord { frame: 7, time_ms: 1234, level, target: target.to_string(), message: message.to_string() }
+    }
+
+    /// An empty directory of its own for each test, the tests run in parallel.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("logging_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn filter_parsing() {
+        let filter = LogFilter::parse(" warn , window=debug,d3d11=trace,").unwrap();
+        assert_eq!(filter.default, LevelFilter::Warn);
+        assert_eq!(filter.modules, vec![("window".to_string(), LevelFilter::Debug), ("d3d11".to_string(), LevelFilter::Trace)]);
+
+        assert_eq!(LogFilter::parse("").unwrap(), LogFilter::default());
+        assert_eq!(LogFilter::parse("loud").unwrap_err(), "Unknown log level 'loud'");
+        assert_eq!(LogFilter::parse("window=loud").unwrap_err(), "Unknown log level 'loud'");
+    }
+
+    #[test]
+    fn longest_module_prefix_wins() {
+        let filter = LogFilter::parse("info,window=warn,window::input=trace").unwrap();
+
+        assert_eq!(filter.level_for("window"), LevelFilter::Warn);
+        assert_eq!(filter.level_for("window::placement"), LevelFilter::Warn);
+        assert_eq!(filter.level_for("window::input"), LevelFilter::Trace);
+        assert_eq!(filter.level_for("window::input::keys"), LevelFilter::Trace);
+        assert_eq!(filter.level_for("adapter"), LevelFilter::Info);
+
+        assert!(filter.enabled(Level::Trace, "window::input"));
+        assert!(!filter.enabled(Level::Info, "window"));
+    }
+
+    #[test]
+    fn modules_match_on_path_boundaries() {
+        let filter = LogFilter::parse("info,window=off").unwrap();
+
+        assert_eq!(filter.level_for("window_ui"), LevelFilter::Info);
+        assert_eq!(filter.level_for("windows::core"), LevelFilter::Info);
+        assert_eq!(filter.level_for("window::"), LevelFilter::Off);
+    }
+
+    #[test]
+    fn crate_name_is_stripped() {
+        let crate_name = env!("CARGO_CRATE_NAME");
+        assert_eq!(short_target(&format!("{}::window", crate_name)), "window");
+        assert_eq!(short_target(crate_name), crate_name);
+        assert_eq!(short_target("wgpu::device"), "wgpu::device");
+
+        // Either spelling works in the filter and for the target.
+        let filter = LogFilter::parse(&format!("error,{}::window=debug", crate_name)).unwrap();
+        assert_eq!(filter.modules, vec![("window".to_string(), LevelFilter::Debug)]);
+        assert_eq!(filter.level_for(&format!("{}::window::input", crate_name)), LevelFilter::Debug);
+        assert_eq!(filter.level_for("window"), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn max_level_is_the_most_verbose() {
+        assert_eq!(LogFilter::parse("warn").unwrap().max_level(), LevelFilter::Warn);
+        assert_eq!(LogFilter::parse("warn,window=trace,adapter=error").unwrap().max_level(), LevelFilter::Trace);
+        assert_eq!(LogFilter::parse("debug,window=off").unwrap().max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn rotated_paths() {
+        assert_eq!(rotated_path(Path::new("logs/engine.log"), 0), Path::new("logs/engine.log"));
+        assert_eq!(rotated_path(Path::new("logs/engine.log"), 2), Path::new("logs/engine.2.log"));
+        assert_eq!(rotated_path(Path::new("engine"), 1), Path::new("engine.1"));
+        assert_eq!(rotated_path(Path::new("engine.tar.log"), 1), Path::new("engine.tar.1.log"));
+    }
+
+    #[test]
+    fn file_sink_rotates_and_keeps_max_files() {
+        let dir = temp_dir("rotates");
+        let path = dir.join("logs").join("engine.log");
+        let line_len = format!("{}\n", record(Level::Info, "window", "0")).len() as u64;
+
+        // Room for two lines per file.
+        let mut sink = RotatingFileSink::new(&path, line_len * 2, 2).unwrap();
+        for index in 0..7 {
+            sink.write(&record(Level::Info, "window", &index.to_string())).unwrap();
+        }
+        sink.flush().unwrap();
+
+        let messages = |path: &Path| read(path).lines().map(|line| line.rsplit(' ').next().unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(messages(&path), ["6"]);
+        assert_eq!(messages(&rotated_path(&path, 1)), ["4", "5"]);
+        assert_eq!(messages(&rotated_path(&path, 2)), ["2", "3"]);
+        assert!(!rotated_path(&path, 3).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_sink_appends_to_an_existing_file() {
+        let dir = temp_dir("appends");
+        let path = dir.join("engine.log");
+        std::fs::write(&path, "earlier run\n").unwrap();
+
+        let mut sink = RotatingFileSink::new(&path, 1024, 1).unwrap();
+        sink.write(&record(Level::Warn, "adapter", "later run")).unwrap();
+        sink.flush().unwrap();
+
+        assert_eq!(read(&path), format!("earlier run\n{}\n", record(Level::Warn, "adapter", "later run")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_sink_without_old_files_starts_over() {
+        let dir = temp_dir("truncates");
+        let path = dir.join("engine.log");
+        let first = record(Level::Info, "window", "first");
+        let second = record(Level::Info, "window", "second");
+
+        let mut sink = RotatingFileSink::new(&path, first.to_string().len() as u64 + 1, 0).unwrap();
+        sink.write(&first).unwrap();
+        sink.write(&second).unwrap();
+        sink.flush().unwrap();
+
+        assert_eq!(read(&path), format!("{}\n", second));
+        assert!(!rotated_path(&path, 1).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn history_drops_the_oldest() {
+        let mut history = LogHistory::new(3);
+        assert!(history.is_empty());
+
+        for index in 0..5 {
+            history.push(record(Level::Info, "window", &index.to_string()));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.records().map(|record| record.message.as_str()).collect::<Vec<_>>(), ["2", "3", "4"]);
+
+        history.clear();
+        assert!(history.is_empty());
+
+        let mut disabled = LogHistory::new(0);
+        disabled.push(record(Level::Error, "window", "dropped"));
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn view_filter() {
+        let warning = record(Level::Warn, "window::input", "Unknown Key 'F13'");
+
+        assert!(LogViewFilter::default().matches(&warning));
+        assert!(!LogViewFilter { max_level: LevelFilter::Error, ..Default::default() }.matches(&warning));
+        assert!(LogViewFilter { max_level: LevelFilter::Warn, ..Default::default() }.matches(&warning));
+
+        assert!(LogViewFilter { target: "window".to_string(), ..Default::default() }.matches(&warning));
+        assert!(!LogViewFilter { target: "adapter".to_string(), ..Default::default() }.matches(&warning));
+
+        assert!(LogViewFilter { search: "unknown key".to_string(), ..Default::default() }.matches(&warning));
+        assert!(LogViewFilter { search: "f13".to_string(), ..Default::default() }.matches(&warning));
+        assert!(!LogViewFilter { search: "mouse".to_string(), ..Default::default() }.matches(&warning));
+    }
+
+    #[test]
+    fn record_format() {
+        assert_eq!(record(Level::Info, "window", "Resized").to_string(), "     1.234 #7      INFO  window: Resized");
+    }
+}
@@ -1,23 +1,71 @@
 mod window_application;
 mod window;
+mod window_placement;
+mod display_mode;
 mod window_ui;
+mod adapter;
+mod debug_layer;
+mod config;
 
+mod output_pass;
+
+use dx11_graphics_engine::{
+    color_space, depth_buffer, device_recovery, gamepad, gpu_texture, input, input_recording, logging, mip_generator, pipeline_state, sampler_cache,
+    swap_chain, texture,
+};
+
+use config::{CommandLine, EngineConfig};
 use window::Window;
 use window_application::WindowApplication;
 use window_ui::WindowUi;
 
 mod imgui_glfw_support;
-mod imgui_winit_support;
 // mod imgui_glfw_rs;
 
 mod imgui_dx11_renderer;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let loaded = CommandLine::parse(&args).and_then(|command_line| {
+        let config = EngineConfig::load(command_line.config_path.as_deref(), &command_line.overrides)?;
+        Ok((command_line, config))
+    });
+    let (command_line, config) = match loaded {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }
+    };
+
+    logging::init(&config.log.log_config()).expect("Failed to initialize logging");
+
+    if command_line.list_adapters {
+        let factory: windows::Win32::Graphics::Dxgi::IDXGIFactory2 = unsafe { windows::Win32::Graphics::Dxgi::CreateDXGIFactory1().unwrap() };
+        for (_, adapter) in adapter::enumerate_adapters(&factory).unwrap() {
+            println!("{}", adapter);
+        }
+        return;
+    }
 
-    let mut window = Window::new("Test application!!!").unwrap();
-    let mut application = WindowApplication::new(&window).unwrap();
+    let mut window = Window::new(&config.window).unwrap();
+    window.load_bindings(&config.input.bindings_file);
+    window.init_gamepads(&config.input);
+    if let Some(path) = &config.input.record {
+        if let Err(error) = window.start_recording(path) {
+            log::warn!("Not recording input to {}: {}", path.display(), error);
+        }
+    }
+    if let Some(path) = &config.input.replay {
+        if let Err(error) = window.start_replay(path, config.input.exit_after_replay || !config.window.visible) {
+            log::error!("Failed to replay {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    }
+    let mut application = WindowApplication::new(&window, &config.graphics).unwrap();
 
-    let mut window_ui = WindowUi::new(&window, &application.device, &application.device_context).expect("Window UI failed to init");
+    let mut window_ui = WindowUi::new(&window, &config.ui, &application.device, &application.device_context, &mut application.sampler_cache, &mut application.pipeline_cache).expect("Window UI failed to init");
 
     window.run(
         &mut application,
@@ -25,6 +73,11 @@ fn main() {
         // Box::new(|viewport_size: (u32, u32)| { application.on_resize(viewport_size); }),
         &mut window_ui
     );
+    window.save_placement(&config.window);
 
+    // The UI renderer holds device objects too, release them before the leak report.
+    drop(window_ui);
+    application.cleanup();
+    log::logger().flush();
 
 }
@@ -0,0 +1,190 @@
+//! CPU-side mesh data shared by the importers, the mesh processing passes and the GPU upload code.
+
+/// Vertex layout used by imported meshes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+/// Range of the index buffer drawn with one material.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Submesh {
+    pub index_offset: u32,
+    pub index_count: u32,
+    pub material_index: u32,
+}
+
+/// Indexed triangle list.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+    /// Index ranges per material. Empty means the whole index buffer is a single submesh.
+    pub submeshes: Vec<Submesh>,
+}
+
+/// Index buffer contents in the smallest format that can address all vertices.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IndexData {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl IndexData {
+    pub fn len(&self) -> usize {
+        match self {
+            IndexData::U16(indices) => indices.len(),
+            IndexData::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size of a single index in bytes.
+    pub fn index_size(&self) -> usize {
+        match self {
+            IndexData::U16(_) => 2,
+            IndexData::U32(_) => 4,
+        }
+    }
+
+    /// Raw little-endian bytes ready to be uploaded into an index buffer.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            IndexData::U16(indices) => unsafe {
+                std::slice::from_raw_parts(indices.as_ptr().cast(), indices.len() * 2)
+            },
+            IndexData::U32(indices) => unsafe {
+                std::slice::from_raw_parts(indices.as_ptr().cast(), indices.len() * 4)
+            },
+        }
+    }
+}
+
+impl MeshData {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Submesh ranges, with a single range covering all indices if the mesh has no submeshes.
+    pub fn submesh_ranges(&self) -> Vec<Submesh> {
+        if self.submeshes.is_empty() {
+            vec![Submesh {
+                index_offset: 0,
+                index_count: self.indices.len() as u32,
+                material_index: 0,
+            }]
+        } else {
+            self.submeshes.clone()
+        }
+    }
+
+    /// Returns true if every index fits into 16 bits.
+    pub fn fits_16_bit_indices(&self) -> bool {
+        self.vertices.len() <= u16::MAX as usize + 1
+    }
+
+    /// Picks 16-bit indices when the vertex count allows it, 32-bit otherwise.
+    pub fn index_data(&self) -> IndexData {
+        if self.fits_16_bit_indices() {
+            IndexData::U16(self.indices.iter().map(|&index| index as u16).collect())
+        } else {
+            IndexData::U32(self.indices.clone())
+        }
+    }
+}
+
+/// Axis aligned box and bounding sphere of a mesh.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeshBounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl MeshBounds {
+    /// Largest dimension of the bounding box.
+    pub fn extent(&self) -> f32 {
+        (0..3).map(|axis| self.max[axis] - self.min[axis]).fold(0.0, f32::max)
+    }
+}
+
+impl MeshData {
+    /// Bounds of all vertices. The sphere is centered on the box and encloses every vertex.
+    pub fn bounds(&self) -> MeshBounds {
+        if self.vertices.is_empty() {
+            return MeshBounds::default();
+        }
+
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for vertex in &self.vertices {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(vertex.position[axis]);
+                max[axis] = max[axis].max(vertex.position[axis]);
+            }
+        }
+
+        let center = [
+            (min[0] + max[0]) * 0.5,
+            (min[1] + max[1]) * 0.5,
+            (min[2] + max[2]) * 0.5,
+        ];
+        let radius = self.vertices.iter()
+            .map(|vertex| {
+                let d = [
+                    vertex.position[0] - center[0],
+                    vertex.position[1] - center[1],
+                    vertex.position[2] - center[2],
+                ];
+                d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+            })
+            .fold(0.0, f32::max)
+            .sqrt();
+
+        MeshBounds { min, max, center, radius }
+    }
+}
+
+impl MeshData {
+    /// Replaces all normals with area weighted averages of the adjacent face normals.
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![[0.0f32; 3]; self.vertices.len()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let p0 = self.vertices[triangle[0] as usize].position;
+            let p1 = self.vertices[triangle[1] as usize].position;
+            let p2 = self.vertices[triangle[2] as usize].position;
+
+            let e1 = [p1[0] - p0[0], p1[1] - p0[1], p1[2] - p0[2]];
+            let e2 = [p2[0] - p0[0], p2[1] - p0[1], p2[2] - p0[2]];
+            let face_normal = [
+                e1[1] * e2[2] - e1[2] * e2[1],
+                e1[2] * e2[0] - e1[0] * e2[2],
+                e1[0] * e2[1] - e1[1] * e2[0],
+            ];
+
+            for &index in triangle {
+                let normal = &mut normals[index as usize];
+                normal[0] += face_normal[0];
+                normal[1] += face_normal[1];
+                normal[2] += face_normal[2];
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+            vertex.normal = if length > 0.0 {
+                [normal[0] / length, normal[1] / length, normal[2] / length]
+            } else {
+                [0.0, 0.0, 0.0]
+            };
+        }
+    }
+}
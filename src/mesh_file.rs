@@ -0,0 +1,751 @@
+//! Native binary `.mesh` format.
+//!
+//! All values are little-endian. Every section starts at a 16 byte aligned offset, so a memory
+//! mapped file can be handed to buffer creation without copying.
+//!
+//! | section          | contents                                                        |
+//! |------------------|-----------------------------------------------------------------|
+//! | header           | magic, version, flags, checksum, counts, bounds, file size      |
+//! | attribute table  | semantic, semantic index, format, stream and offset per element |
+//! | stream table     | stride, data offset and size per vertex stream                  |
+//! | LOD table        | index data offset, index count and error per level              |
+//! | submesh table    | index ranges per LOD and submesh                                |
+//! | vertex streams   | raw vertex data                                                 |
+//! | index data       | 16 or 32 bit indices per LOD                                    |
+//!
+//! The checksum is FNV-1a over everything after the header.
+
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+use crate::mesh::{MeshBounds, MeshData, MeshVertex, Submesh};
+use crate::mesh_lod::MeshLod;
+
+pub const MESH_FILE_MAGIC: [u8; 4] = *b"EMSH";
+pub const MESH_FILE_VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = 96;
+const ATTRIBUTE_SIZE: usize = 16;
+const STREAM_SIZE: usize = 24;
+const LOD_SIZE: usize = 16;
+const SUBMESH_SIZE: usize = 16;
+const SECTION_ALIGNMENT: usize = 16;
+
+const FLAG_INDEX_32: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexSemantic {
+    Position = 0,
+    Normal = 1,
+    TexCoord = 2,
+    Color = 3,
+    Tangent = 4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexFormat {
+    Float2 = 0,
+    Float3 = 1,
+    Float4 = 2,
+    UNorm8x4 = 3,
+}
+
+impl VertexFormat {
+    pub fn size(self) -> u32 {
+        match self {
+            VertexFormat::Float2 => 8,
+            VertexFormat::Float3 => 12,
+            VertexFormat::Float4 => 16,
+            VertexFormat::UNorm8x4 => 4,
+        }
+    }
+}
+
+/// One element of the vertex layout descriptor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub semantic: VertexSemantic,
+    pub semantic_index: u16,
+    pub format: VertexFormat,
+    pub stream: u16,
+    pub offset: u32,
+}
+
+/// Layout of [`MeshVertex`] as a single interleaved stream.
+pub const MESH_VERTEX_LAYOUT: [VertexAttribute; 3] = [
+    VertexAttribute { semantic: VertexSemantic::Position, semantic_index: 0, format: VertexFormat::Float3, stream: 0, offset: 0 },
+    VertexAttribute { semantic: VertexSemantic::Normal, semantic_index: 0, format: VertexFormat::Float3, stream: 0, offset: 12 },
+    VertexAttribute { semantic: VertexSemantic::TexCoord, semantic_index: 0, format: VertexFormat::Float2, stream: 0, offset: 24 },
+];
+
+#[derive(Debug)]
+pub enum MeshFileError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// A table entry points outside of the file or is otherwise inconsistent.
+    Corrupt(&'static str),
+    /// The vertex layout cannot be converted into [`MeshVertex`].
+    LayoutMismatch,
+}
+
+impl fmt::Display for MeshFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshFileError::Io(error) => write!(f, "I/O error: {}", error),
+            MeshFileError::BadMagic => write!(f, "not a mesh file"),
+            MeshFileError::UnsupportedVersion(version) => write!(f, "unsupported mesh file version {}", version),
+            MeshFileError::Truncated => write!(f, "mesh file is truncated"),
+            MeshFileError::ChecksumMismatch { expected, actual } => {
+                write!(f, "mesh file checksum mismatch, expected {:08x}, got {:08x}", expected, actual)
+            }
+            MeshFileError::Corrupt(reason) => write!(f, "corrupt mesh file: {}", reason),
+            MeshFileError::LayoutMismatch => write!(f, "mesh file vertex layout does not match MeshVertex"),
+        }
+    }
+}
+
+impl std::error::Error for MeshFileError {}
+
+impl From<std::io::Error> for MeshFileError {
+    fn from(error: std::io::Error) -> Self {
+        MeshFileError::Io(error)
+    }
+}
+
+/// Vertex stream borrowed from the file data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamView<'a> {
+    pub stride: u32,
+    pub data: &'a [u8],
+}
+
+/// Index data of a single LOD borrowed from the file data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LodView<'a> {
+    pub index_count: u32,
+    pub error: f32,
+    pub index_data: &'a [u8],
+    pub submeshes: Vec<Submesh>,
+}
+
+/// Validated view over the bytes of a `.mesh` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshFileView<'a> {
+    pub version: u32,
+    pub vertex_count: u32,
+    pub index_32: bool,
+    pub bounds: MeshBounds,
+    pub attributes: Vec<VertexAttribute>,
+    pub streams: Vec<StreamView<'a>>,
+    pub lods: Vec<LodView<'a>>,
+}
+
+/// Memory mapped `.mesh` file.
+pub struct MeshFile {
+    mmap: memmap2::Mmap,
+}
+
+impl MeshFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MeshFileError> {
+        let file = std::fs::File::open(path)?;
+        // The mapping is read only and the file is not expected to change while the engine runs.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(MeshFile { mmap })
+    }
+
+    pub fn view(&self) -> Result<MeshFileView<'_>, MeshFileError> {
+        MeshFileView::parse(&self.mmap)
+    }
+}
+
+fn put(bytes: &mut [u8], offset: &mut usize, value: &[u8]) {
+    bytes[*offset..*offset + value.len()].copy_from_slice(value);
+    *offset += value.len();
+}
+
+fn align(offset: usize) -> usize {
+    offset.next_multiple_of(SECTION_ALIGNMENT)
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for &byte in bytes {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn at(bytes: &'a [u8], position: usize) -> Self {
+        Reader { bytes, position }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], MeshFileError> {
+        let end = self.position.checked_add(count).ok_or(MeshFileError::Truncated)?;
+        let slice = self.bytes.get(self.position..end).ok_or(MeshFileError::Truncated)?;
+        self.position = end;
+        Ok(slice)
+    }
+
+    /// Fails unless `count` entries of `entry_size` bytes fit into the rest of the data. Table
+    /// counts come from the header, so they are checked before anything is allocated for them.
+    fn expect_entries(&self, count: usize, entry_size: usize) -> Result<(), MeshFileError> {
+        let size = count.checked_mul(entry_size).ok_or(MeshFileError::Truncated)?;
+        if size > self.bytes.len().saturating_sub(self.position) {
+            return Err(MeshFileError::Truncated);
+        }
+        Ok(())
+    }
+
+    fn u16(&mut self) -> Result<u16, MeshFileError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, MeshFileError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, MeshFileError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, MeshFileError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn f32x3(&mut self) -> Result<[f32; 3], MeshFileError> {
+        Ok([self.f32()?, self.f32()?, self.f32()?])
+    }
+}
+
+fn slice_at(bytes: &[u8], offset: u64, size: u64) -> Result<&[u8], MeshFileError> {
+    let start = usize::try_from(offset).map_err(|_| MeshFileError::Corrupt("offset out of range"))?;
+    let size = usize::try_from(size).map_err(|_| MeshFileError::Corrupt("size out of range"))?;
+    if start % SECTION_ALIGNMENT != 0 {
+        return Err(MeshFileError::Corrupt("misaligned section"));
+    }
+    let end = start.checked_add(size).ok_or(MeshFileError::Corrupt("size out of range"))?;
+    bytes.get(start..end).ok_or(MeshFileError::Corrupt("section outside of file"))
+}
+
+impl<'a> MeshFileView<'a> {
+    /// Parses and validates a `.mesh` file. Vertex and index data are borrowed from `bytes`.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, MeshFileError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(if bytes.len() >= 4 && bytes[..4] != MESH_FILE_MAGIC {
+                MeshFileError::BadMagic
+            } else {
+                MeshFileError::Truncated
+            });
+        }
+
+        let mut header = Reader::at(bytes, 0);
+        if header.take(4)? != MESH_FILE_MAGIC {
+            return Err(MeshFileError::BadMagic);
+        }
+        let version = header.u32()?;
+        if version != MESH_FILE_VERSION {
+            return Err(MeshFileError::UnsupportedVersion(version));
+        }
+        let flags = header.u32()?;
+        let checksum = header.u32()?;
+        let vertex_count = header.u32()?;
+        let attribute_count = header.u32()? as usize;
+        let stream_count = header.u32()? as usize;
+        let submesh_count = header.u32()? as usize;
+        let lod_count = header.u32()? as usize;
+        let _reserved = header.u32()?;
+        let bounds = MeshBounds {
+            min: header.f32x3()?,
+            max: header.f32x3()?,
+            center: header.f32x3()?,
+            radius: header.f32()?,
+        };
+        // The file size is not covered by the checksum either, so it is checked before slicing.
+        let file_size = usize::try_from(header.u64()?).map_err(|_| MeshFileError::Truncated)?;
+        if file_size < HEADER_SIZE {
+            return Err(MeshFileError::Corrupt("file size smaller than header"));
+        }
+        if bytes.len() < file_size {
+            return Err(MeshFileError::Truncated);
+        }
+        let bytes = &bytes[..file_size];
+
+        let actual = fnv1a(&bytes[HEADER_SIZE..]);
+        if actual != checksum {
+            return Err(MeshFileError::ChecksumMismatch { expected: checksum, actual });
+        }
+
+        let index_32 = flags & FLAG_INDEX_32 != 0;
+        let index_size = if index_32 { 4 } else { 2 };
+
+        let mut table = Reader::at(bytes, HEADER_SIZE);
+
+        table.expect_entries(attribute_count, ATTRIBUTE_SIZE)?;
+        let mut attributes = Vec::with_capacity(attribute_count);
+        for _ in 0..attribute_count {
+            let semantic = match table.u16()? {
+                0 => VertexSemantic::Position,
+                1 => VertexSemantic::Normal,
+                2 => VertexSemantic::TexCoord,
+                3 => VertexSemantic::Color,
+                4 => VertexSemantic::Tangent,
+                _ => return Err(MeshFileError::Corrupt("unknown vertex semantic")),
+            };
+            let semantic_index = table.u16()?;
+            let format = match table.u16()? {
+                0 => VertexFormat::Float2,
+                1 => VertexFormat::Float3,
+                2 => VertexFormat::Float4,
+                3 => VertexFormat::UNorm8x4,
+                _ => return Err(MeshFileError::Corrupt("unknown vertex format")),
+            };
+            let stream = table.u16()?;
+            let offset = table.u32()?;
+            let _reserved = table.u32()?;
+
+            if stream as usize >= stream_count {
+                return Err(MeshFileError::Corrupt("attribute references missing stream"));
+            }
+            attributes.push(VertexAttribute { semantic, semantic_index, format, stream, offset });
+        }
+        table.position = align(table.position);
+
+        table.expect_entries(stream_count, STREAM_SIZE)?;
+        let mut streams = Vec::with_capacity(stream_count);
+        for _ in 0..stream_count {
+            let stride = table.u32()?;
+            let _reserved = table.u32()?;
+            let offset = table.u64()?;
+            let size = table.u64()?;
+            if size != stride as u64 * vertex_count as u64 {
+                return Err(MeshFileError::Corrupt("stream size does not match vertex count"));
+            }
+            streams.push(StreamView { stride, data: slice_at(bytes, offset, size)? });
+        }
+        for attribute in &attributes {
+            if attribute.offset as u64 + attribute.format.size() as u64 > streams[attribute.stream as usize].stride as u64 {
+                return Err(MeshFileError::Corrupt("attribute outside of vertex stride"));
+            }
+        }
+        table.position = align(table.position);
+
+        table.expect_entries(lod_count, LOD_SIZE)?;
+        let mut lods = Vec::with_capacity(lod_count);
+        for _ in 0..lod_count {
+            let offset = table.u64()?;
+            let index_count = table.u32()?;
+            let error = table.f32()?;
+            let index_data = slice_at(bytes, offset, index_count as u64 * index_size)?;
+            lods.push(LodView { index_count, error, index_data, submeshes: Vec::new() });
+        }
+        table.position = align(table.position);
+
+        table.expect_entries(lod_count.checked_mul(submesh_count).ok_or(MeshFileError::Truncated)?, SUBMESH_SIZE)?;
+
+        for lod in lods.iter_mut() {
+            lod.submeshes.reserve_exact(submesh_count);
+            for _ in 0..submesh_count {
+                let submesh = Submesh {
+                    index_offset: table.u32()?,
+                    index_count: table.u32()?,
+                    material_index: table.u32()?,
+                };
+                let _reserved = table.u32()?;
+                if submesh.index_offset as u64 + submesh.index_count as u64 > lod.index_count as u64 {
+                    return Err(MeshFileError::Corrupt("submesh outside of index data"));
+                }
+                lod.submeshes.push(submesh);
+            }
+        }
+
+        let view = MeshFileView { version, vertex_count, index_32, bounds, attributes, streams, lods };
+        for lod in 0..view.lods.len() {
+            if view.indices(lod).any(|index| index >= vertex_count) {
+                return Err(MeshFileError::Corrupt("index out of range"));
+            }
+        }
+
+        Ok(view)
+    }
+
+    /// Indices of a LOD widened to 32 bits.
+    pub fn indices(&self, lod: usize) -> impl Iterator<Item = u32> + '_ {
+        let data = self.lods[lod].index_data;
+        let index_32 = self.index_32;
+        let index_size = if index_32 { 4 } else { 2 };
+        data.chunks_exact(index_size).map(move |bytes| {
+            if index_32 {
+                u32::from_le_bytes(bytes.try_into().unwrap())
+            } else {
+                u16::from_le_bytes(bytes.try_into().unwrap()) as u32
+            }
+        })
+    }
+
+    /// Copies the file contents back into a [`MeshData`] and its LOD chain. Only files with the
+    /// [`MESH_VERTEX_LAYOUT`] layout can be converted.
+    pub fn to_mesh_data(&self) -> Result<(MeshData, Vec<MeshLod>), MeshFileError> {
+        if self.attributes != MESH_VERTEX_LAYOUT
+            || self.streams.len() != 1
+            || self.streams[0].stride as usize != std::mem::size_of::<MeshVertex>()
+            || self.lods.is_empty()
+        {
+            return Err(MeshFileError::LayoutMismatch);
+        }
+
+        let read_f32 = |bytes: &[u8], offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let vertices = self.streams[0].data
+            .chunks_exact(std::mem::size_of::<MeshVertex>())
+            .map(|bytes| MeshVertex {
+                position: [read_f32(bytes, 0), read_f32(bytes, 4), read_f32(bytes, 8)],
+                normal: [read_f32(bytes, 12), read_f32(bytes, 16), read_f32(bytes, 20)],
+                uv: [read_f32(bytes, 24), read_f32(bytes, 28)],
+            })
+            .collect();
+
+        let lods: Vec<MeshLod> = (0..self.lods.len())
+            .map(|lod| MeshLod {
+                indices: self.indices(lod).collect(),
+                submeshes: self.lods[lod].submeshes.clone(),
+                error: self.lods[lod].error,
+            })
+            .collect();
+
+        let mesh = MeshData {
+            vertices,
+            indices: lods[0].indices.clone(),
+            submeshes: lods[0].submeshes.clone(),
+        };
+
+        Ok((mesh, lods))
+    }
+}
+
+/// Writes `mesh` as a single interleaved stream. `lods` may be empty, otherwise its first level
+/// replaces the index buffer of `mesh`.
+pub fn write_mesh_file(writer: &mut impl Write, mesh: &MeshData, lods: &[MeshLod]) -> Result<(), MeshFileError> {
+    let base_lod;
+    let lods = if lods.is_empty() {
+        base_lod = [MeshLod {
+            indices: mesh.indices.clone(),
+            submeshes: mesh.submesh_ranges(),
+            error: 0.0,
+        }];
+        &base_lod[..]
+    } else {
+        lods
+    };
+
+    let submesh_count = lods[0].submeshes.len();
+    if lods.iter().any(|lod| lod.submeshes.len() != submesh_count) {
+        return Err(MeshFileError::Corrupt("LODs have different submesh counts"));
+    }
+
+    let index_32 = !mesh.fits_16_bit_indices();
+    let index_size = if index_32 { 4 } else { 2 };
+    let stride = std::mem::size_of::<MeshVertex>();
+
+    // Section layout.
+    let attribute_table = HEADER_SIZE;
+    let stream_table = align(attribute_table + MESH_VERTEX_LAYOUT.len() * ATTRIBUTE_SIZE);
+    let lod_table = align(stream_table + STREAM_SIZE);
+    let submesh_table = align(lod_table + lods.len() * LOD_SIZE);
+    let vertex_data = align(submesh_table + lods.len() * submesh_count * SUBMESH_SIZE);
+    let mut index_data = Vec::with_capacity(lods.len());
+    let mut end = align(vertex_data + mesh.vertices.len() * stride);
+    for lod in lods {
+        index_data.push(end);
+        end = align(end + lod.indices.len() * index_size);
+    }
+
+    let mut bytes = vec![0u8; end];
+
+    let mut offset = attribute_table;
+    for attribute in &MESH_VERTEX_LAYOUT {
+        put(&mut bytes, &mut offset, &(attribute.semantic as u16).to_le_bytes());
+        put(&mut bytes, &mut offset, &attribute.semantic_index.to_le_bytes());
+        put(&mut bytes, &mut offset, &(attribute.format as u16).to_le_bytes());
+        put(&mut bytes, &mut offset, &attribute.stream.to_le_bytes());
+        put(&mut bytes, &mut offset, &attribute.offset.to_le_bytes());
+        put(&mut bytes, &mut offset, &0u32.to_le_bytes());
+    }
+
+    let mut offset = stream_table;
+    put(&mut bytes, &mut offset, &(stride as u32).to_le_bytes());
+    put(&mut bytes, &mut offset, &0u32.to_le_bytes());
+    put(&mut bytes, &mut offset, &(vertex_data as u64).to_le_bytes());
+    put(&mut bytes, &mut offset, &((mesh.vertices.len() * stride) as u64).to_le_bytes());
+
+    let mut offset = lod_table;
+    for (lod, &data_offset) in lods.iter().zip(&index_data) {
+        put(&mut bytes, &mut offset, &(data_offset as u64).to_le_bytes());
+        put(&mut bytes, &mut offset, &(lod.indices.len() as u32).to_le_bytes());
+        put(&mut bytes, &mut offset, &lod.error.to_le_bytes());
+    }
+
+    let mut offset = submesh_table;
+    for lod in lods {
+        for submesh in &lod.submeshes {
+            put(&mut bytes, &mut offset, &submesh.index_offset.to_le_bytes());
+            put(&mut bytes, &mut offset, &submesh.index_count.to_le_bytes());
+            put(&mut bytes, &mut offset, &submesh.material_index.to_le_bytes());
+            put(&mut bytes, &mut offset, &0u32.to_le_bytes());
+        }
+    }
+
+    let mut offset = vertex_data;
+    for vertex in &mesh.vertices {
+        for value in vertex.position.iter().chain(&vertex.normal).chain(&vertex.uv) {
+            put(&mut bytes, &mut offset, &value.to_le_bytes());
+        }
+    }
+
+    for (lod, &data_offset) in lods.iter().zip(&index_data) {
+        let mut offset = data_offset;
+        for &index in &lod.indices {
+            if index as usize >= mesh.vertices.len() {
+                return Err(MeshFileError::Corrupt("index out of range"));
+            }
+            if index_32 {
+                put(&mut bytes, &mut offset, &index.to_le_bytes());
+            } else {
+                put(&mut bytes, &mut offset, &(index as u16).to_le_bytes());
+            }
+        }
+    }
+
+    let bounds = mesh.bounds();
+    let checksum = fnv1a(&bytes[HEADER_SIZE..]);
+
+    let mut offset = 0;
+    put(&mut bytes, &mut offset, &MESH_FILE_MAGIC);
+    put(&mut bytes, &mut offset, &MESH_FILE_VERSION.to_le_bytes());
+    put(&mut bytes, &mut offset, &(if index_32 { FLAG_INDEX_32 } else { 0 }).to_le_bytes());
+    put(&mut bytes, &mut offset, &checksum.to_le_bytes());
+    put(&mut bytes, &mut offset, &(mesh.vertices.len() as u32).to_le_bytes());
+    put(&mut bytes, &mut offset, &(MESH_VERTEX_LAYOUT.len() as u32).to_le_bytes());
+    put(&mut bytes, &mut offset, &1u32.to_le_bytes());
+    put(&mut bytes, &mut offset, &(submesh_count as u32).to_le_bytes());
+    put(&mut bytes, &mut offset, &(lods.len() as u32).to_le_bytes());
+    put(&mut bytes, &mut offset, &0u32.to_le_bytes());
+    for value in bounds.min.iter().chain(&bounds.max).chain(&bounds.center).chain([&bounds.radius]) {
+        put(&mut bytes, &mut offset, &value.to_le_bytes());
+    }
+    put(&mut bytes, &mut offset, &(end as u64).to_le_bytes());
+
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad_mesh() -> MeshData {
+        let vertex = |x: f32, y: f32| MeshVertex {
+            position: [x, y, 0.5],
+            normal: [0.0, 0.0, 1.0],
+            uv: [x, 1.0 - y],
+        };
+        MeshData {
+            vertices: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(1.0, 1.0), vertex(0.0, 1.0), vertex(2.0, 0.0)],
+            indices: vec![0, 1, 2, 0, 2, 3, 1, 4, 2],
+            submeshes: vec![
+                Submesh { index_offset: 0, index_count: 6, material_index: 0 },
+                Submesh { index_offset: 6, index_count: 3, material_index: 1 },
+            ],
+        }
+    }
+
+    fn quad_lods() -> Vec<MeshLod> {
+        let mesh = quad_mesh();
+        vec![
+            MeshLod { indices: mesh.indices.clone(), submeshes: mesh.submeshes.clone(), error: 0.0 },
+            MeshLod {
+                indices: vec![0, 2, 3, 1, 4, 2],
+                submeshes: vec![
+                    Submesh { index_offset: 0, index_count: 3, material_index: 0 },
+                    Submesh { index_offset: 3, index_count: 3, material_index: 1 },
+                ],
+                error: 0.25,
+            },
+        ]
+    }
+
+    fn write(mesh: &MeshData, lods: &[MeshLod]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_mesh_file(&mut bytes, mesh, lods).unwrap();
+        bytes
+    }
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Patches a value after the header and updates the checksum so only the edit is detected.
+    fn patch_body(bytes: &mut [u8], offset: usize, value: &[u8]) {
+        bytes[offset..offset + value.len()].copy_from_slice(value);
+        let checksum = fnv1a(&bytes[HEADER_SIZE..]);
+        write_u32(bytes, 12, checksum);
+    }
+
+    fn assert_error(result: Result<MeshFileView<'_>, MeshFileError>, check: impl Fn(&MeshFileError) -> bool) {
+        match result {
+            Err(error) => assert!(check(&error), "unexpected error: {}", error),
+            Ok(_) => panic!("corrupt file was accepted"),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mesh = quad_mesh();
+        let lods = quad_lods();
+        let bytes = write(&mesh, &lods);
+        assert_eq!(bytes.len() % SECTION_ALIGNMENT, 0);
+
+        let view = MeshFileView::parse(&bytes).unwrap();
+        assert_eq!(view.version, MESH_FILE_VERSION);
+        assert_eq!(view.vertex_count, 5);
+        assert!(!view.index_32);
+        assert_eq!(view.bounds, mesh.bounds());
+        assert_eq!(view.attributes, MESH_VERTEX_LAYOUT);
+        assert_eq!(view.streams.len(), 1);
+        assert_eq!(view.streams[0].stride as usize, std::mem::size_of::<MeshVertex>());
+        assert_eq!(view.lods.len(), 2);
+        assert_eq!(view.lods[1].error, 0.25);
+        assert_eq!(view.indices(1).collect::<Vec<_>>(), lods[1].indices);
+
+        let (read_mesh, read_lods) = view.to_mesh_data().unwrap();
+        assert_eq!(read_mesh, mesh);
+        assert_eq!(read_lods, lods);
+    }
+
+    #[test]
+    fn round_trip_without_lods() {
+        let mut mesh = quad_mesh();
+        mesh.submeshes.clear();
+        let bytes = write(&mesh, &[]);
+
+        let (read_mesh, read_lods) = MeshFileView::parse(&bytes).unwrap().to_mesh_data().unwrap();
+        assert_eq!(read_mesh.vertices, mesh.vertices);
+        assert_eq!(read_mesh.indices, mesh.indices);
+        assert_eq!(read_mesh.submeshes, mesh.submesh_ranges());
+        assert_eq!(read_lods.len(), 1);
+    }
+
+    #[test]
+    fn round_trip_32_bit_indices() {
+        let mut mesh = quad_mesh();
+        mesh.vertices.resize(70_000, MeshVertex::default());
+        mesh.indices.extend_from_slice(&[0, 69_999, 1]);
+        mesh.submeshes[1].index_count += 3;
+        let bytes = write(&mesh, &[]);
+
+        let view = MeshFileView::parse(&bytes).unwrap();
+        assert!(view.index_32);
+        assert_eq!(view.to_mesh_data().unwrap().0, mesh);
+    }
+
+    #[test]
+    fn rejects_truncation() {
+        let bytes = write(&quad_mesh(), &quad_lods());
+        for length in [0, 3, 4, HEADER_SIZE - 1, HEADER_SIZE, bytes.len() - 1] {
+            assert_error(MeshFileView::parse(&bytes[..length]), |error| matches!(error, MeshFileError::Truncated));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_version() {
+        let mut bytes = write(&quad_mesh(), &quad_lods());
+        bytes[0] = b'X';
+        assert_error(MeshFileView::parse(&bytes), |error| matches!(error, MeshFileError::BadMagic));
+        assert_error(MeshFileView::parse(&bytes[..8]), |error| matches!(error, MeshFileError::BadMagic));
+
+        let mut bytes = write(&quad_mesh(), &quad_lods());
+        write_u32(&mut bytes, 4, MESH_FILE_VERSION + 1);
+        assert_error(MeshFileView::parse(&bytes), |error| matches!(error, MeshFileError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut bytes = write(&quad_mesh(), &quad_lods());
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x40;
+        assert_error(MeshFileView::parse(&bytes), |error| matches!(error, MeshFileError::ChecksumMismatch { .. }));
+    }
+
+    #[test]
+    fn rejects_huge_table_counts_without_allocating() {
+        // Counts live in the header, which is not covered by the checksum.
+        for offset in [20, 24, 28, 32] {
+            let mut bytes = write(&quad_mesh(), &quad_lods());
+            write_u32(&mut bytes, offset, u32::MAX);
+            assert_error(MeshFileView::parse(&bytes), |error| {
+                matches!(error, MeshFileError::Truncated | MeshFileError::Corrupt(_))
+            });
+        }
+    }
+
+    #[test]
+    fn rejects_bad_file_size() {
+        // After the magic, version, flags, checksum, six counts and the bounds.
+        const FILE_SIZE: usize = 80;
+        let original = write(&quad_mesh(), &quad_lods());
+        for file_size in [0, HEADER_SIZE as u64 - 1, original.len() as u64 + 1, u64::MAX] {
+            let mut bytes = original.clone();
+            bytes[FILE_SIZE..FILE_SIZE + 8].copy_from_slice(&file_size.to_le_bytes());
+            assert_error(MeshFileView::parse(&bytes), |error| {
+                matches!(error, MeshFileError::Truncated | MeshFileError::Corrupt("file size smaller than header"))
+            });
+        }
+
+        // A size that fits but cuts the body off only fails the checksum.
+        let mut bytes = original;
+        bytes[FILE_SIZE..FILE_SIZE + 8].copy_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+        assert_error(MeshFileView::parse(&bytes), |error| matches!(error, MeshFileError::ChecksumMismatch { .. }));
+    }
+
+    #[test]
+    fn rejects_out_of_range_index() {
+        let mut bytes = write(&quad_mesh(), &quad_lods());
+        let lod_table = align(align(HEADER_SIZE + MESH_VERTEX_LAYOUT.len() * ATTRIBUTE_SIZE) + STREAM_SIZE);
+        let index_data = read_u32(&bytes, lod_table) as usize;
+        patch_body(&mut bytes, index_data + 2, &5u16.to_le_bytes());
+        assert_error(MeshFileView::parse(&bytes), |error| matches!(error, MeshFileError::Corrupt("index out of range")));
+    }
+
+    #[test]
+    fn rejects_out_of_range_submesh() {
+        let mut bytes = write(&quad_mesh(), &quad_lods());
+        let lod_table = align(align(HEADER_SIZE + MESH_VERTEX_LAYOUT.len() * ATTRIBUTE_SIZE) + STREAM_SIZE);
+        let submesh_table = align(lod_table + 2 * LOD_SIZE);
+        // Second submesh of the first LOD, index_count 3 -> 4 runs past its 9 indices.
+        patch_body(&mut bytes, submesh_table + SUBMESH_SIZE + 4, &4u32.to_le_bytes());
+        assert_error(MeshFileView::parse(&bytes), |error| {
+            matches!(error, MeshFileError::Corrupt("submesh outside of index data"))
+        });
+    }
+
+    #[test]
+    fn rejects_section_outside_of_file() {
+        let mut bytes = write(&quad_mesh(), &quad_lods());
+        let lod_table = align(align(HEADER_SIZE + MESH_VERTEX_LAYOUT.len() * ATTRIBUTE_SIZE) + STREAM_SIZE);
+        patch_body(&mut bytes, lod_table + 8, &1000u32.to_le_bytes());
+        assert_error(MeshFileView::parse(&bytes), |error| matches!(error, MeshFileError::Corrupt(_)));
+    }
+}
@@ -0,0 +1,301 @@
+//! Level of detail chains built with [`crate::mesh_simplifier`] and runtime LOD selection.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::mesh::{MeshBounds, MeshData, Submesh};
+use crate::mesh_optimizer::optimize_vertex_cache;
+use crate::mesh_simplifier::simplify_with_locked;
+
+/// Triangle ratios used when no explicit ratios are given.
+pub const DEFAULT_LOD_RATIOS: [f32; 4] = [1.0, 0.5, 0.25, 0.125];
+
+/// One level of a LOD chain. All levels index into the vertex buffer of the source mesh.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshLod {
+    pub indices: Vec<u32>,
+    /// Ranges of `indices` matching the submeshes of the source mesh.
+    pub submeshes: Vec<Submesh>,
+    /// Simplification error relative to the mesh extent.
+    pub error: f32,
+}
+
+/// Generates a LOD for each of the target triangle ratios, the first level is usually 1.0.
+///
+/// Submeshes are simplified separately, with the positions they share locked so material
+/// boundaries stay in place and don't crack. Every level is simplified from the previous one, so
+/// triangle counts never increase along the chain.
+/// `max_error` stops simplification early, in which case a level can have more triangles than
+/// its ratio asks for.
+pub fn generate_lod_chain(mesh: &MeshData, ratios: &[f32], max_error: f32) -> Vec<MeshLod> {
+    let mut lods: Vec<MeshLod> = Vec::with_capacity(ratios.len());
+    let locked = shared_positions(mesh);
+
+    for &ratio in ratios {
+        let ratio = ratio.clamp(0.0, 1.0);
+        let mut lod = MeshLod {
+            error: lods.last().map_or(0.0, |lod| lod.error),
+            ..Default::default()
+        };
+
+        for (submesh_index, source) in mesh.submesh_ranges().iter().enumerate() {
+            let previous = match lods.last() {
+                Some(previous) => previous.submeshes[submesh_index],
+                None => *source,
+            };
+            let previous_indices = match lods.last() {
+                Some(previous) => &previous.indices,
+                None => &mesh.indices,
+            };
+            let range = previous.index_offset as usize..(previous.index_offset + previous.index_count) as usize;
+
+            let submesh = MeshData {
+                vertices: mesh.vertices.clone(),
+                indices: previous_indices[range].to_vec(),
+                submeshes: Vec::new(),
+            };
+            let target_index_count = ((source.index_count as f32 * ratio) as usize) / 3 * 3;
+            let result = simplify_with_locked(&submesh, target_index_count, max_error, &locked);
+
+            lod.submeshes.push(Submesh {
+                index_offset: lod.indices.len() as u32,
+                index_count: result.indices.len() as u32,
+                material_index: source.material_index,
+            });
+            lod.indices.extend(optimize_vertex_cache(&result.indices, mesh.vertices.len()));
+            lod.error = lod.error.max(result.error);
+        }
+
+        lods.push(lod);
+    }
+
+    lods
+}
+
+/// Flags the vertices whose position is used by more than one submesh, also through different
+/// vertices with the same position.
+fn shared_positions(mesh: &MeshData) -> Vec<bool> {
+    let mut first_user: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut shared: HashSet<[u32; 3]> = HashSet::new();
+    for (submesh_index, submesh) in mesh.submesh_ranges().iter().enumerate() {
+        let range = submesh.index_offset as usize..(submesh.index_offset + submesh.index_count) as usize;
+        for &index in &mesh.indices[range] {
+            let position = mesh.vertices[index as usize].position.map(f32::to_bits);
+            if *first_user.entry(position).or_insert(submesh_index) != submesh_index {
+                shared.insert(position);
+            }
+        }
+    }
+
+    mesh.vertices.iter().map(|vertex| shared.contains(&vertex.position.map(f32::to_bits))).collect()
+}
+
+/// Picks a LOD from the size of the object's bounding sphere on screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LodSelector {
+    /// Minimum projected size, as a fraction of the viewport height, for each level.
+    /// Level `i` is used while the object covers at least `screen_size_thresholds[i]`.
+    pub screen_size_thresholds: Vec<f32>,
+    /// Multiplies the projected size, values above 1.0 prefer more detailed levels.
+    pub lod_bias: f32,
+}
+
+impl LodSelector {
+    /// Thresholds that halve together with the triangle count of each level.
+    pub fn from_ratios(ratios: &[f32]) -> Self {
+        LodSelector {
+            screen_size_thresholds: ratios.iter().map(|ratio| ratio * 0.5).collect(),
+            lod_bias: 1.0,
+        }
+    }
+
+    /// Returns the LOD index for an object with `bounds` in world space seen from
+    /// `camera_position` with vertical field of view `fov_y` in radians.
+    pub fn select(&self, bounds: &MeshBounds, camera_position: [f32; 3], fov_y: f32) -> usize {
+        let screen_size = projected_screen_size(bounds.center, bounds.radius, camera_position, fov_y) * self.lod_bias;
+
+        self.screen_size_thresholds.iter()
+            .position(|&threshold| screen_size >= threshold)
+            .unwrap_or(self.screen_size_thresholds.len().saturating_sub(1))
+    }
+}
+
+/// Diameter of a bounding sphere after projection, as a fraction of the viewport height.
+///
+/// Returns infinity when the camera is inside the sphere.
+pub fn projected_screen_size(center: [f32; 3], radius: f32, camera_position: [f32; 3], fov_y: f32) -> f32 {
+    let offset = [
+        center[0] - camera_position[0],
+        center[1] - camera_position[1],
+        center[2] - camera_position[2],
+    ];
+    let distance = (offset[0] * offset[0] + offset[1] * offset[1] + offset[2] * offset[2]).sqrt();
+    if distance <= radius {
+        return f32::INFINITY;
+    }
+
+    let view_height = 2.0 * distance * (fov_y * 0.5).tan();
+    2.0 * radius / view_height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::MeshVertex;
+
+    fn sphere_at(center: [f32; 3], radius: f32) -> MeshBounds {
+        MeshBounds {
+            center,
+            radius,
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn projected_size() {
+        let fov_y = std::f32::consts::FRAC_PI_2;
+        // With a 90 degree field of view the view height at distance d is 2d.
+        assert_close(projected_screen_size([0.0, 0.0, -10.0], 1.0, [0.0; 3], fov_y), 0.1);
+        assert_close(projected_screen_size([3.0, 4.0, 0.0], 1.0, [0.0; 3], fov_y), 0.2);
+        assert_close(projected_screen_size([0.0, 0.0, 7.0], 1.0, [0.0, 0.0, 2.0], fov_y), 0.2);
+
+        let narrow = 2.0 * (0.5f32).atan();
+        assert_close(projected_screen_size([0.0, 0.0, 10.0], 1.0, [0.0; 3], narrow), 0.2);
+    }
+
+    #[test]
+    fn projected_size_inside_sphere_is_infinite() {
+        let fov_y = std::f32::consts::FRAC_PI_2;
+        assert_eq!(projected_screen_size([0.0; 3], 2.0, [1.0, 0.0, 0.0], fov_y), f32::INFINITY);
+        assert_eq!(projected_screen_size([0.0; 3], 1.0, [1.0, 0.0, 0.0], fov_y), f32::INFINITY);
+    }
+
+    #[test]
+    fn select_by_screen_size() {
+        let fov_y = std::f32::consts::FRAC_PI_2;
+        let selector = LodSelector::from_ratios(&[1.0, 0.5, 0.25]);
+        assert_eq!(selector.screen_size_thresholds, vec![0.5, 0.25, 0.125]);
+
+        let select = |distance: f32| selector.select(&sphere_at([0.0, 0.0, distance], 1.0), [0.0; 3], fov_y);
+        assert_eq!(select(0.5), 0);
+        // Screen size 2/3, 1/3, 0.2 and 0.1.
+        assert_eq!(select(1.5), 0);
+        assert_eq!(select(3.0), 1);
+        assert_eq!(select(5.0), 2);
+        // Smaller than the last threshold keeps the coarsest level.
+        assert_eq!(select(10.0), 2);
+        // Exactly on a threshold picks the finer level.
+        assert_eq!(select(2.0), 0);
+    }
+
+    #[test]
+    fn select_applies_lod_bias() {
+        let fov_y = std::f32::consts::FRAC_PI_2;
+        let mut selector = LodSelector::from_ratios(&[1.0, 0.5, 0.25]);
+        let bounds = sphere_at([0.0, 0.0, 3.0], 1.0);
+        assert_eq!(selector.select(&bounds, [0.0; 3], fov_y), 1);
+
+        selector.lod_bias = 2.0;
+        assert_eq!(selector.select(&bounds, [0.0; 3], fov_y), 0);
+        selector.lod_bias = 0.5;
+        assert_eq!(selector.select(&bounds, [0.0; 3], fov_y), 2);
+    }
+
+    #[test]
+    fn select_without_levels() {
+        let selector = LodSelector { screen_size_thresholds: Vec::new(), lod_bias: 1.0 };
+        assert_eq!(selector.select(&sphere_at([0.0, 0.0, 5.0], 1.0), [0.0; 3], 1.0), 0);
+    }
+
+    #[test]
+    fn lod_chain_never_grows() {
+        let size = 10u32;
+        let mut mesh = MeshData::default();
+        for y in 0..=size {
+            for x in 0..=size {
+                mesh.vertices.push(MeshVertex {
+                    position: [x as f32, y as f32, ((x * 3 + y * 5) % 4) as f32 * 0.2],
+                    ..Default::default()
+                });
+            }
+        }
+        for y in 0..size {
+            for x in 0..size {
+                let v = y * (size + 1) + x;
+                mesh.indices.extend_from_slice(&[v, v + 1, v + size + 1, v + 1, v + size + 2, v + size + 1]);
+            }
+        }
+        let half = mesh.indices.len() as u32 / 2;
+        mesh.submeshes = vec![
+            Submesh { index_offset: 0, index_count: half, material_index: 3 },
+            Submesh { index_offset: half, index_count: half, material_index: 7 },
+        ];
+
+        let lods = generate_lod_chain(&mesh, &DEFAULT_LOD_RATIOS, 1.0);
+        assert_eq!(lods.len(), DEFAULT_LOD_RATIOS.len());
+        assert_eq!(lods[0].indices.len(), mesh.indices.len());
+        assert_eq!(lods[0].error, 0.0);
+
+        for pair in lods.windows(2) {
+            assert!(pair[1].indices.len() <= pair[0].indices.len());
+            assert!(pair[1].error >= pair[0].error);
+        }
+        for lod in &lods {
+            assert_eq!(lod.submeshes.iter().map(|s| s.material_index).collect::<Vec<_>>(), vec![3, 7]);
+            assert_eq!(lod.submeshes[0].index_offset, 0);
+            assert_eq!(lod.submeshes[1].index_offset, lod.submeshes[0].index_count);
+            assert_eq!((lod.submeshes[1].index_offset + lod.submeshes[1].index_count) as usize, lod.indices.len());
+        }
+        assert!(lods[3].indices.len() < mesh.indices.len() / 2);
+    }
+
+    #[test]
+    fn shared_submesh_edges_survive_every_level() {
+        // Two materials meeting at x = 6, the right one with its own copies of the vertices.
+        let size = 12u32;
+        let row = size + 1;
+        let mut mesh = MeshData::default();
+        for _copy in 0..2 {
+            for y in 0..=size {
+                for x in 0..=size {
+                    mesh.vertices.push(MeshVertex {
+                        position: [x as f32, y as f32, ((x * 7 + y * 13) % 5) as f32 * 0.1],
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        for (material_index, columns, base) in [(0, 0..size / 2, 0), (1, size / 2..size, row * row)] {
+            let index_offset = mesh.indices.len() as u32;
+            for y in 0..size {
+                for x in columns.clone() {
+                    let v = base + y * row + x;
+                    mesh.indices.extend_from_slice(&[v, v + 1, v + row, v + 1, v + row + 1, v + row]);
+                }
+            }
+            mesh.submeshes.push(Submesh { index_offset, index_count: mesh.indices.len() as u32 - index_offset, material_index });
+        }
+        let boundary: Vec<[u32; 3]> = (0..=size)
+            .map(|y| mesh.vertices[(y * row + size / 2) as usize].position.map(f32::to_bits))
+            .collect();
+
+        let lods = generate_lod_chain(&mesh, &DEFAULT_LOD_RATIOS, 1.0);
+        assert!(lods[3].indices.len() < mesh.indices.len() / 2);
+
+        for lod in &lods {
+            for submesh in &lod.submeshes {
+                let range = submesh.index_offset as usize..(submesh.index_offset + submesh.index_count) as usize;
+                let used: HashSet<[u32; 3]> = lod.indices[range]
+                    .iter()
+                    .map(|&index| mesh.vertices[index as usize].position.map(f32::to_bits))
+                    .collect();
+                for position in &boundary {
+                    assert!(used.contains(position), "boundary vertex {:?} lost", position.map(f32::from_bits));
+                }
+            }
+        }
+    }
+}
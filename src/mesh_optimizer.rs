@@ -0,0 +1,683 @@
+//! Optimization passes for imported meshes.
+//!
+//! The passes are meant to be run in order:
+//!
+//! 1. vertex cache reordering (Tom Forsyth's linear-speed algorithm)
+//! 2. overdraw ordering (cluster sorting, similar to Tipsify)
+//! 3. vertex fetch remapping
+//!
+//! [`optimize_mesh`] runs all of them and reports vertex cache statistics before and after.
+
+use std::fmt;
+
+use crate::mesh::{IndexData, MeshData, MeshVertex};
+
+/// Cache size used when reordering triangles.
+const FORSYTH_CACHE_SIZE: usize = 32;
+const FORSYTH_CACHE_DECAY_POWER: f32 = 1.5;
+const FORSYTH_LAST_TRIANGLE_SCORE: f32 = 0.75;
+const FORSYTH_VALENCE_BOOST_SCALE: f32 = 2.0;
+const FORSYTH_VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// Post-transform cache statistics of an index buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VertexCacheStats {
+    /// Vertices transformed by the simulated cache.
+    pub vertices_transformed: usize,
+    /// Average cache miss ratio, transformed vertices per triangle (0.5 is the optimum, 3.0 the worst).
+    pub acmr: f32,
+    /// Average transform to vertex ratio, transformed vertices per referenced vertex (1.0 is the optimum).
+    pub atvr: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OptimizerSettings {
+    /// FIFO cache size used for the ACMR/ATVR statistics and for overdraw cluster boundaries.
+    pub analysis_cache_size: usize,
+    /// How much ACMR may degrade while splitting clusters for overdraw ordering, 1.05 allows 5%.
+    /// Values below 1.0 skip the overdraw pass.
+    pub overdraw_threshold: f32,
+}
+
+impl Default for OptimizerSettings {
+    fn default() -> Self {
+        OptimizerSettings {
+            analysis_cache_size: 16,
+            overdraw_threshold: 1.05,
+        }
+    }
+}
+
+/// Result of [`optimize_mesh`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct OptimizationReport {
+    pub before: VertexCacheStats,
+    pub after: VertexCacheStats,
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub triangles: usize,
+    pub uses_16_bit_indices: bool,
+}
+
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "triangles: {}", self.triangles)?;
+        writeln!(f, "vertices:  {} -> {}", self.vertices_before, self.vertices_after)?;
+        writeln!(f, "ACMR:      {:.3} -> {:.3}", self.before.acmr, self.after.acmr)?;
+        writeln!(f, "ATVR:      {:.3} -> {:.3}", self.before.atvr, self.after.atvr)?;
+        write!(f, "indices:   {} bit", if self.uses_16_bit_indices { 16 } else { 32 })
+    }
+}
+
+/// Reasons [`optimize_mesh`] refuses a mesh. The mesh is left untouched in every case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptimizeError {
+    /// The submesh index count is not a multiple of three.
+    PartialTriangle { submesh: usize, index_count: u32 },
+    /// The submesh reaches past the end of the index buffer.
+    SubmeshOutOfRange { submesh: usize },
+    /// An index references a vertex that does not exist.
+    IndexOutOfRange { index: u32, vertex_count: usize },
+}
+
+impl fmt::Display for OptimizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptimizeError::PartialTriangle { submesh, index_count } => {
+                write!(f, "submesh {} has {} indices, which is not a whole number of triangles", submesh, index_count)
+            }
+            OptimizeError::SubmeshOutOfRange { submesh } => {
+                write!(f, "submesh {} reaches past the end of the index buffer", submesh)
+            }
+            OptimizeError::IndexOutOfRange { index, vertex_count } => {
+                write!(f, "index {} is out of range for {} vertices", index, vertex_count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OptimizeError {}
+
+/// Checks that every submesh is a whole number of triangles inside the index buffer and that
+/// every index references an existing vertex.
+fn validate_mesh(mesh: &MeshData) -> Result<(), OptimizeError> {
+    for (submesh, range) in mesh.submesh_ranges().iter().enumerate() {
+        if range.index_count % 3 != 0 {
+            return Err(OptimizeError::PartialTriangle { submesh, index_count: range.index_count });
+        }
+        let end = range.index_offset as usize + range.index_count as usize;
+        if end > mesh.indices.len() {
+            return Err(OptimizeError::SubmeshOutOfRange { submesh });
+        }
+    }
+    if let Some(&index) = mesh.indices.iter().find(|&&index| index as usize >= mesh.vertices.len()) {
+        return Err(OptimizeError::IndexOutOfRange { index, vertex_count: mesh.vertices.len() });
+    }
+    Ok(())
+}
+
+/// Runs vertex cache, overdraw and vertex fetch optimization on the mesh in place.
+///
+/// Triangles are only reordered within their submesh.
+pub fn optimize_mesh(mesh: &mut MeshData, settings: &OptimizerSettings) -> Result<OptimizationReport, OptimizeError> {
+    validate_mesh(mesh)?;
+
+    let vertices_before = mesh.vertices.len();
+    let before = analyze_vertex_cache(&mesh.indices, mesh.vertices.len(), settings.analysis_cache_size);
+
+    for submesh in mesh.submesh_ranges() {
+        let range = submesh.index_offset as usize..submesh.index_offset as usize + submesh.index_count as usize;
+
+        let mut indices = optimize_vertex_cache(&mesh.indices[range.clone()], mesh.vertices.len());
+        if settings.overdraw_threshold >= 1.0 {
+            indices = optimize_overdraw(
+                &indices,
+                &mesh.vertices,
+                settings.analysis_cache_size,
+                settings.overdraw_threshold,
+            );
+        }
+        mesh.indices[range].copy_from_slice(&indices);
+    }
+    optimize_vertex_fetch(mesh);
+
+    let after = analyze_vertex_cache(&mesh.indices, mesh.vertices.len(), settings.analysis_cache_size);
+
+    Ok(OptimizationReport {
+        before,
+        after,
+        vertices_before,
+        vertices_after: mesh.vertices.len(),
+        triangles: mesh.triangle_count(),
+        uses_16_bit_indices: matches!(mesh.index_data(), IndexData::U16(_)),
+    })
+}
+
+/// Simulates a FIFO post-transform cache of `cache_size` entries.
+pub fn analyze_vertex_cache(indices: &[u32], vertex_count: usize, cache_size: usize) -> VertexCacheStats {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 || cache_size == 0 {
+        return VertexCacheStats::default();
+    }
+
+    // Timestamp based FIFO, a vertex is in cache if it was inserted at most `cache_size` misses ago.
+    let mut inserted_at = vec![u32::MAX; vertex_count];
+    let mut referenced = vec![false; vertex_count];
+    let mut misses = 0u32;
+
+    for &index in &indices[..triangle_count * 3] {
+        let index = index as usize;
+        referenced[index] = true;
+
+        let cached = inserted_at[index] != u32::MAX
+            && ((misses - inserted_at[index]) as usize) <= cache_size;
+        if !cached {
+            inserted_at[index] = misses;
+            misses += 1;
+        }
+    }
+
+    let unique = referenced.iter().filter(|&&r| r).count().max(1);
+
+    VertexCacheStats {
+        vertices_transformed: misses as usize,
+        acmr: misses as f32 / triangle_count as f32,
+        atvr: misses as f32 / unique as f32,
+    }
+}
+
+fn forsyth_vertex_score(cache_position: Option<usize>, remaining_triangles: u32) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+
+    let mut score = match cache_position {
+        // The vertices of the last triangle are scored lower on purpose, so the next
+        // triangle does not reuse exactly the same edge and strip along forever.
+        Some(position) if position < 3 => FORSYTH_LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1.0 / (FORSYTH_CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(FORSYTH_CACHE_DECAY_POWER)
+        }
+        None => 0.0,
+    };
+
+    // Boost vertices with few triangles left so that lone triangles get finished early.
+    score += FORSYTH_VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-FORSYTH_VALENCE_BOOST_POWER);
+    score
+}
+
+/// Reorders triangles to improve post-transform vertex cache hits.
+///
+/// Implementation of Tom Forsyth's "Linear-Speed Vertex Cache Optimisation". The output is
+/// deterministic for a given input.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return Vec::new();
+    }
+
+    // Vertex to triangle adjacency in compressed row form. The first `remaining[v]` entries of
+    // each vertex's range are the triangles that still have to be emitted.
+    let mut remaining = vec![0u32; vertex_count];
+    for &index in &indices[..triangle_count * 3] {
+        remaining[index as usize] += 1;
+    }
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for vertex in 0..vertex_count {
+        offsets[vertex + 1] = offsets[vertex] + remaining[vertex] as usize;
+    }
+    let mut adjacency = vec![0u32; offsets[vertex_count]];
+    let mut fill = offsets.clone();
+    for triangle in 0..triangle_count {
+        for corner in 0..3 {
+            let vertex = indices[triangle * 3 + corner] as usize;
+            adjacency[fill[vertex]] = triangle as u32;
+            fill[vertex] += 1;
+        }
+    }
+
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_score: Vec<f32> = (0..vertex_count)
+        .map(|vertex| forsyth_vertex_score(None, remaining[vertex]))
+        .collect();
+    let mut triangle_score: Vec<f32> = (0..triangle_count)
+        .map(|triangle| {
+            (0..3).map(|corner| vertex_score[indices[triangle * 3 + corner] as usize]).sum()
+        })
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+
+    let mut cache: Vec<u32> = Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+    let mut new_cache: Vec<u32> = Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+    let mut evicted: Vec<u32> = Vec::with_capacity(3);
+    let mut output = Vec::with_capacity(triangle_count * 3);
+
+    let mut best_triangle: Option<usize> = None;
+    let mut scan_cursor = 0;
+
+    loop {
+        let triangle = match best_triangle {
+            Some(triangle) => triangle,
+            None => {
+                // Nothing in the cache is connected to a pending triangle, continue with the
+                // next triangle in input order.
+                while scan_cursor < triangle_count && emitted[scan_cursor] {
+                    scan_cursor += 1;
+                }
+                if scan_cursor == triangle_count {
+                    break;
+                }
+                scan_cursor
+            }
+        };
+
+        emitted[triangle] = true;
+        let triangle_vertices = [
+            indices[triangle * 3],
+            indices[triangle * 3 + 1],
+            indices[triangle * 3 + 2],
+        ];
+        output.extend_from_slice(&triangle_vertices);
+
+        for &vertex in &triangle_vertices {
+            let vertex = vertex as usize;
+            let start = offsets[vertex];
+            let end = start + remaining[vertex] as usize;
+            if let Some(slot) = adjacency[start..end].iter().position(|&t| t as usize == triangle) {
+                adjacency.swap(start + slot, end - 1);
+                remaining[vertex] -= 1;
+            }
+        }
+
+        new_cache.clear();
+        new_cache.extend_from_slice(&triangle_vertices);
+        for &vertex in &cache {
+            if !triangle_vertices.contains(&vertex) {
+                new_cache.push(vertex);
+            }
+        }
+        evicted.clear();
+        for &vertex in new_cache.iter().skip(FORSYTH_CACHE_SIZE) {
+            cache_position[vertex as usize] = None;
+            evicted.push(vertex);
+        }
+        new_cache.truncate(FORSYTH_CACHE_SIZE);
+        std::mem::swap(&mut cache, &mut new_cache);
+
+        for (position, &vertex) in cache.iter().enumerate() {
+            cache_position[vertex as usize] = Some(position);
+        }
+
+        // Rescore everything that entered, moved within or left the cache.
+        best_triangle = None;
+        let mut best_score = f32::MIN;
+        for &vertex in cache.iter().chain(evicted.iter()) {
+            let vertex = vertex as usize;
+            let score = forsyth_vertex_score(cache_position[vertex], remaining[vertex]);
+            let delta = score - vertex_score[vertex];
+            vertex_score[vertex] = score;
+
+            let start = offsets[vertex];
+            for &pending in &adjacency[start..start + remaining[vertex] as usize] {
+                let pending = pending as usize;
+                triangle_score[pending] += delta;
+            }
+        }
+        for &vertex in &cache {
+            let vertex = vertex as usize;
+            let start = offsets[vertex];
+            for &pending in &adjacency[start..start + remaining[vertex] as usize] {
+                let pending = pending as usize;
+                let score = triangle_score[pending];
+                if score > best_score || (score == best_score && Some(pending) < best_triangle) {
+                    best_score = score;
+                    best_triangle = Some(pending);
+                }
+            }
+        }
+    }
+
+    output
+}
+
+/// Reorders clusters of triangles so that triangles facing outwards are drawn first.
+///
+/// `indices` should already be optimized for the vertex cache. The triangle sequence is cut into
+/// clusters at vertex cache restarts, the clusters are split further as long as the ACMR of
+/// each piece stays within `threshold` of the whole cluster, and the pieces are then sorted by
+/// how much they face away from the mesh center.
+pub fn optimize_overdraw(indices: &[u32], vertices: &[MeshVertex], cache_size: usize, threshold: f32) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return Vec::new();
+    }
+
+    let hard_boundaries = cache_restart_boundaries(indices, vertices.len(), cache_size);
+
+    let mut clusters: Vec<(usize, usize)> = Vec::new();
+    for window in hard_boundaries.windows(2) {
+        split_cluster(indices, vertices.len(), cache_size, threshold, window[0], window[1], &mut clusters);
+    }
+
+    let mesh_center = mesh_centroid(indices, vertices);
+
+    let mut sort_keys: Vec<(f32, usize)> = clusters
+        .iter()
+        .enumerate()
+        .map(|(cluster_index, &(start, end))| {
+            (cluster_sort_key(&indices[start * 3..end * 3], vertices, mesh_center), cluster_index)
+        })
+        .collect();
+    // Outward facing clusters first, ties keep the cache optimized order.
+    sort_keys.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut output = Vec::with_capacity(triangle_count * 3);
+    for (_, cluster_index) in sort_keys {
+        let (start, end) = clusters[cluster_index];
+        output.extend_from_slice(&indices[start * 3..end * 3]);
+    }
+    output
+}
+
+/// Triangle offsets at which a FIFO cache of `cache_size` misses all three vertices, including
+/// the start and the end of the triangle list.
+fn cache_restart_boundaries(indices: &[u32], vertex_count: usize, cache_size: usize) -> Vec<usize> {
+    let triangle_count = indices.len() / 3;
+    let mut inserted_at = vec![u32::MAX; vertex_count];
+    let mut misses = 0u32;
+    let mut boundaries = vec![0];
+
+    for triangle in 0..triangle_count {
+        let mut triangle_misses = 0;
+        for corner in 0..3 {
+            let index = indices[triangle * 3 + corner] as usize;
+            let cached = inserted_at[index] != u32::MAX
+                && ((misses - inserted_at[index]) as usize) <= cache_size;
+            if !cached {
+                inserted_at[index] = misses;
+                misses += 1;
+                triangle_misses += 1;
+            }
+        }
+        if triangle_misses == 3 && triangle != 0 {
+            boundaries.push(triangle);
+        }
+    }
+
+    boundaries.push(triangle_count);
+    boundaries
+}
+
+fn split_cluster(
+    indices: &[u32],
+    vertex_count: usize,
+    cache_size: usize,
+    threshold: f32,
+    start: usize,
+    end: usize,
+    clusters: &mut Vec<(usize, usize)>,
+) {
+    let cluster_acmr = analyze_vertex_cache(&indices[start * 3..end * 3], vertex_count, cache_size).acmr;
+
+    let mut piece_start = start;
+    let mut inserted_at = vec![u32::MAX; vertex_count];
+    let mut misses = 0u32;
+
+    for triangle in start..end {
+        for corner in 0..3 {
+            let index = indices[triangle * 3 + corner] as usize;
+            let cached = inserted_at[index] != u32::MAX
+                && ((misses - inserted_at[index]) as usize) <= cache_size;
+            if !cached {
+                inserted_at[index] = misses;
+                misses += 1;
+            }
+        }
+
+        let piece_triangles = triangle + 1 - piece_start;
+        let piece_acmr = misses as f32 / piece_triangles as f32;
+        if triangle + 1 < end && piece_acmr <= cluster_acmr * threshold {
+            clusters.push((piece_start, triangle + 1));
+            piece_start = triangle + 1;
+            inserted_at.iter_mut().for_each(|slot| *slot = u32::MAX);
+            misses = 0;
+        }
+    }
+
+    if piece_start < end {
+        clusters.push((piece_start, end));
+    }
+}
+
+fn mesh_centroid(indices: &[u32], vertices: &[MeshVertex]) -> [f32; 3] {
+    let mut sum = [0.0f32; 3];
+    for &index in indices {
+        let position = vertices[index as usize].position;
+        sum[0] += position[0];
+        sum[1] += position[1];
+        sum[2] += position[2];
+    }
+    let count = indices.len().max(1) as f32;
+    [sum[0] / count, sum[1] / count, sum[2] / count]
+}
+
+fn cluster_sort_key(indices: &[u32], vertices: &[MeshVertex], mesh_center: [f32; 3]) -> f32 {
+    let mut centroid = [0.0f32; 3];
+    let mut normal = [0.0f32; 3];
+    let mut total_area = 0.0f32;
+
+    for triangle in indices.chunks_exact(3) {
+        let p0 = vertices[triangle[0] as usize].position;
+        let p1 = vertices[triangle[1] as usize].position;
+        let p2 = vertices[triangle[2] as usize].position;
+
+        let e1 = [p1[0] - p0[0], p1[1] - p0[1], p1[2] - p0[2]];
+        let e2 = [p2[0] - p0[0], p2[1] - p0[1], p2[2] - p0[2]];
+        // Length of the cross product is twice the area, so the sum is an area weighted normal.
+        let cross = [
+            e1[1] * e2[2] - e1[2] * e2[1],
+            e1[2] * e2[0] - e1[0] * e2[2],
+            e1[0] * e2[1] - e1[1] * e2[0],
+        ];
+        let area = (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+
+        for axis in 0..3 {
+            centroid[axis] += (p0[axis] + p1[axis] + p2[axis]) / 3.0 * area;
+            normal[axis] += cross[axis];
+        }
+        total_area += area;
+    }
+
+    if total_area > 0.0 {
+        for value in centroid.iter_mut() {
+            *value /= total_area;
+        }
+    }
+    let normal_length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    if normal_length > 0.0 {
+        for value in normal.iter_mut() {
+            *value /= normal_length;
+        }
+    }
+
+    (centroid[0] - mesh_center[0]) * normal[0]
+        + (centroid[1] - mesh_center[1]) * normal[1]
+        + (centroid[2] - mesh_center[2]) * normal[2]
+}
+
+/// Reorders vertices in the order the index buffer first references them and drops
+/// unreferenced vertices. Returns the old to new vertex remap table, `u32::MAX` marks
+/// removed vertices.
+pub fn optimize_vertex_fetch(mesh: &mut MeshData) -> Vec<u32> {
+    let mut remap = vec![u32::MAX; mesh.vertices.len()];
+    let mut vertices = Vec::with_capacity(mesh.vertices.len());
+
+    for index in mesh.indices.iter_mut() {
+        let old = *index as usize;
+        if remap[old] == u32::MAX {
+            remap[old] = vertices.len() as u32;
+            vertices.push(mesh.vertices[old]);
+        }
+        *index = remap[old];
+    }
+
+    mesh.vertices = vertices;
+    remap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Submesh;
+
+    /// Regular grid of `size` x `size` quads with its triangles in a scrambled order.
+    fn shuffled_grid(size: u32) -> MeshData {
+        let mut vertices = Vec::new();
+        for y in 0..=size {
+            for x in 0..=size {
+                vertices.push(MeshVertex {
+                    position: [x as f32, y as f32, 0.0],
+                    normal: [0.0, 0.0, 1.0],
+                    uv: [0.0, 0.0],
+                });
+            }
+        }
+
+        let mut triangles = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let v = y * (size + 1) + x;
+                triangles.push([v, v + 1, v + size + 1]);
+                triangles.push([v + 1, v + size + 2, v + size + 1]);
+            }
+        }
+
+        // Fisher-Yates with a fixed LCG so the test input is reproducible.
+        let mut state = 0x2545_f491u32;
+        for i in (1..triangles.len()).rev() {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            triangles.swap(i, (state >> 8) as usize % (i + 1));
+        }
+
+        MeshData {
+            vertices,
+            indices: triangles.into_iter().flatten().collect(),
+            submeshes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn analyze_single_triangle() {
+        let stats = analyze_vertex_cache(&[0, 1, 2], 3, 16);
+        assert_eq!(stats.vertices_transformed, 3);
+        assert_eq!(stats.acmr, 3.0);
+        assert_eq!(stats.atvr, 1.0);
+    }
+
+    #[test]
+    fn analyze_counts_fifo_evictions() {
+        // With a 3 entry cache the second triangle evicts vertex 0 before the third one uses it.
+        let indices = [0, 1, 2, 3, 4, 5, 0, 1, 2];
+        let stats = analyze_vertex_cache(&indices, 6, 3);
+        assert_eq!(stats.vertices_transformed, 9);
+        assert_eq!(stats.acmr, 3.0);
+        assert_eq!(stats.atvr, 1.5);
+
+        let stats = analyze_vertex_cache(&indices, 6, 6);
+        assert_eq!(stats.vertices_transformed, 6);
+        assert_eq!(stats.atvr, 1.0);
+    }
+
+    #[test]
+    fn optimize_improves_acmr_and_atvr() {
+        let mut mesh = shuffled_grid(16);
+        let triangles = mesh.triangle_count();
+        let report = optimize_mesh(&mut mesh, &OptimizerSettings::default()).unwrap();
+
+        assert_eq!(report.triangles, triangles);
+        assert!(report.after.acmr < report.before.acmr, "{}", report);
+        assert!(report.after.atvr <= report.before.atvr, "{}", report);
+        assert!(report.after.acmr >= 0.5 && report.after.acmr < 1.0, "{}", report);
+        assert!(report.after.atvr >= 1.0 && report.after.atvr < 1.5, "{}", report);
+
+        // The report matches an independent analysis of the result.
+        let stats = analyze_vertex_cache(&mesh.indices, mesh.vertices.len(), 16);
+        assert_eq!(stats, report.after);
+    }
+
+    #[test]
+    fn optimize_keeps_triangles() {
+        let original = shuffled_grid(8);
+        let mut mesh = original.clone();
+        optimize_mesh(&mut mesh, &OptimizerSettings::default()).unwrap();
+
+        let canonical = |mesh: &MeshData| {
+            let mut triangles: Vec<[[u32; 3]; 3]> = mesh.indices.chunks(3)
+                .map(|triangle| {
+                    let mut corners = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position.map(|c| c as u32));
+                    // Rotate so the smallest corner comes first, keeping the winding.
+                    let first = (0..3).min_by_key(|&i| corners[i]).unwrap();
+                    corners.rotate_left(first);
+                    corners
+                })
+                .collect();
+            triangles.sort();
+            triangles
+        };
+        assert_eq!(canonical(&mesh), canonical(&original));
+    }
+
+    #[test]
+    fn optimize_keeps_submesh_triangles_in_place() {
+        let mut mesh = shuffled_grid(4);
+        let split = 12;
+        mesh.submeshes = vec![
+            Submesh { index_offset: 0, index_count: split, material_index: 0 },
+            Submesh { index_offset: split, index_count: mesh.indices.len() as u32 - split, material_index: 1 },
+        ];
+        let mut first: Vec<u32> = mesh.indices[..split as usize].to_vec();
+        let positions_before: Vec<_> = first.iter().map(|&i| mesh.vertices[i as usize].position).collect();
+
+        optimize_mesh(&mut mesh, &OptimizerSettings::default()).unwrap();
+
+        first = mesh.indices[..split as usize].to_vec();
+        let mut positions_after: Vec<_> = first.iter().map(|&i| mesh.vertices[i as usize].position).collect();
+        let mut positions_before = positions_before;
+        let key = |p: &[f32; 3]| (p[0] as u32, p[1] as u32);
+        positions_before.sort_by_key(key);
+        positions_after.sort_by_key(key);
+        assert_eq!(positions_after, positions_before);
+    }
+
+    #[test]
+    fn optimize_rejects_partial_triangles() {
+        let mut mesh = shuffled_grid(2);
+        mesh.submeshes = vec![
+            Submesh { index_offset: 0, index_count: 4, material_index: 0 },
+            Submesh { index_offset: 4, index_count: mesh.indices.len() as u32 - 4, material_index: 1 },
+        ];
+        let original = mesh.clone();
+
+        let error = optimize_mesh(&mut mesh, &OptimizerSettings::default()).unwrap_err();
+        assert_eq!(error, OptimizeError::PartialTriangle { submesh: 0, index_count: 4 });
+        assert_eq!(mesh, original);
+    }
+
+    #[test]
+    fn optimize_rejects_out_of_range_submesh_and_index() {
+        let mut mesh = shuffled_grid(2);
+        mesh.submeshes = vec![Submesh { index_offset: 3, index_count: mesh.indices.len() as u32, material_index: 0 }];
+        assert_eq!(
+            optimize_mesh(&mut mesh, &OptimizerSettings::default()),
+            Err(OptimizeError::SubmeshOutOfRange { submesh: 0 })
+        );
+
+        let mut mesh = shuffled_grid(2);
+        mesh.indices[5] = 100;
+        assert_eq!(
+            optimize_mesh(&mut mesh, &OptimizerSettings::default()),
+            Err(OptimizeError::IndexOutOfRange { index: 100, vertex_count: 9 })
+        );
+    }
+}
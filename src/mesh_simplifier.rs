@@ -0,0 +1,585 @@
+//! Quadric error metric mesh simplification.
+//!
+//! Half-edge collapses ordered by the quadric error of Garland and Heckbert. Vertices that share a
+//! position are treated as one, so a collapse never tears the mesh apart at attribute seams:
+//!
+//! * vertices on UV seams (one position, several vertices) and on non-manifold edges never move
+//! * vertices locked by the caller never move
+//! * vertices on open borders only slide along the border
+//! * everything else can collapse onto any neighbor
+//!
+//! The vertex buffer is left untouched, only a new index buffer is produced. Run
+//! [`crate::mesh_optimizer::optimize_vertex_fetch`] afterwards to drop unused vertices.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::mesh::MeshData;
+
+/// Weight of the planes that keep borders in place, relative to the triangle planes.
+const BORDER_PLANE_WEIGHT: f64 = 10.0;
+
+/// Minimum cosine between a triangle normal before and after a collapse.
+const MAX_NORMAL_FLIP_COS: f64 = 0.25;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimplifyResult {
+    pub indices: Vec<u32>,
+    /// Largest collapse error, as a distance relative to the mesh extent.
+    pub error: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VertexKind {
+    Manifold,
+    Border,
+    Locked,
+}
+
+/// Symmetric 4x4 error quadric.
+#[derive(Clone, Copy, Debug, Default)]
+struct Quadric {
+    a2: f64, ab: f64, ac: f64, ad: f64,
+    b2: f64, bc: f64, bd: f64,
+    c2: f64, cd: f64,
+    d2: f64,
+}
+
+impl Quadric {
+    fn from_plane(normal: [f64; 3], d: f64, weight: f64) -> Self {
+        let [a, b, c] = normal;
+        Quadric {
+            a2: a * a * weight, ab: a * b * weight, ac: a * c * weight, ad: a * d * weight,
+            b2: b * b * weight, bc: b * c * weight, bd: b * d * weight,
+            c2: c * c * weight, cd: c * d * weight,
+            d2: d * d * weight,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        self.a2 += other.a2; self.ab += other.ab; self.ac += other.ac; self.ad += other.ad;
+        self.b2 += other.b2; self.bc += other.bc; self.bd += other.bd;
+        self.c2 += other.c2; self.cd += other.cd;
+        self.d2 += other.d2;
+    }
+
+    fn error(&self, p: [f64; 3]) -> f64 {
+        let [x, y, z] = p;
+        let error = self.a2 * x * x + 2.0 * self.ab * x * y + 2.0 * self.ac * x * z + 2.0 * self.ad * x
+            + self.b2 * y * y + 2.0 * self.bc * y * z + 2.0 * self.bd * y
+            + self.c2 * z * z + 2.0 * self.cd * z
+            + self.d2;
+        error.max(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Collapse {
+    /// Position id that is removed.
+    from: u32,
+    /// Vertex that replaces every vertex of `from`.
+    to: u32,
+    cost: f64,
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(a: [f64; 3]) -> Option<[f64; 3]> {
+    let length = dot(a, a).sqrt();
+    if length > 0.0 {
+        Some([a[0] / length, a[1] / length, a[2] / length])
+    } else {
+        None
+    }
+}
+
+/// Simplifies the mesh until it has at most `target_index_count` indices or the next collapse
+/// would exceed `target_error` (relative to the mesh extent, 0.01 is 1%).
+///
+/// The result only depends on the input, the same mesh always produces the same indices.
+pub fn simplify(mesh: &MeshData, target_index_count: usize, target_error: f32) -> SimplifyResult {
+    simplify_with_locked(mesh, target_index_count, target_error, &[])
+}
+
+/// Like [`simplify`], but the vertices set in `locked` never move, for example the ones shared
+/// with another part of the mesh that is simplified separately. `locked` may be shorter than the
+/// vertex buffer.
+pub fn simplify_with_locked(mesh: &MeshData, target_index_count: usize, target_error: f32, locked: &[bool]) -> SimplifyResult {
+    let vertex_count = mesh.vertices.len();
+    let mut indices: Vec<u32> = mesh.indices[..mesh.indices.len() / 3 * 3].to_vec();
+    if indices.len() <= target_index_count || vertex_count == 0 {
+        return SimplifyResult { indices, error: 0.0 };
+    }
+
+    let positions: Vec<[f64; 3]> = mesh.vertices.iter()
+        .map(|vertex| vertex.position.map(|value| value as f64))
+        .collect();
+
+    // Vertices with bitwise equal positions share a position id, which is the lowest vertex index.
+    let mut position_ids: HashMap<[u32; 3], u32> = HashMap::with_capacity(vertex_count);
+    let position_id: Vec<u32> = mesh.vertices.iter()
+        .enumerate()
+        .map(|(index, vertex)| *position_ids.entry(vertex.position.map(f32::to_bits)).or_insert(index as u32))
+        .collect();
+
+    let locked_ids: Vec<usize> = locked.iter()
+        .zip(&position_id)
+        .filter(|(&locked, _)| locked)
+        .map(|(_, &id)| id as usize)
+        .collect();
+
+    let mut group_offsets = vec![0usize; vertex_count + 1];
+    for &id in &position_id {
+        group_offsets[id as usize + 1] += 1;
+    }
+    for id in 0..vertex_count {
+        group_offsets[id + 1] += group_offsets[id];
+    }
+    let mut group_members = vec![0u32; vertex_count];
+    let mut group_fill = group_offsets.clone();
+    for (vertex, &id) in position_id.iter().enumerate() {
+        group_members[group_fill[id as usize]] = vertex as u32;
+        group_fill[id as usize] += 1;
+    }
+
+    let extent = mesh.bounds().extent().max(f32::EPSILON) as f64;
+    let error_limit = target_error as f64 * extent;
+    let error_limit_squared = error_limit * error_limit;
+
+    let mut quadrics = vec![Quadric::default(); vertex_count];
+    accumulate_quadrics(&indices, &positions, &position_id, &mut quadrics);
+
+    let mut result_error_squared = 0.0f64;
+
+    while indices.len() > target_index_count {
+        let mut kinds = classify_vertices(&indices, &position_id, vertex_count);
+        for &id in &locked_ids {
+            kinds[id] = VertexKind::Locked;
+        }
+        let border_edges = border_edges(&indices, &position_id);
+
+        let mut collapses = collapse_candidates(&indices, &position_id, &kinds, &border_edges, &positions, &quadrics);
+        collapses.sort_by(|a, b| {
+            a.cost.total_cmp(&b.cost)
+                .then(a.from.cmp(&b.from))
+                .then(a.to.cmp(&b.to))
+        });
+
+        // Position id to triangle adjacency of the current index buffer.
+        let triangle_count = indices.len() / 3;
+        let mut offsets = vec![0usize; vertex_count + 1];
+        for &index in &indices {
+            offsets[position_id[index as usize] as usize + 1] += 1;
+        }
+        for id in 0..vertex_count {
+            offsets[id + 1] += offsets[id];
+        }
+        let mut adjacency = vec![0u32; indices.len()];
+        let mut fill = offsets.clone();
+        for triangle in 0..triangle_count {
+            for corner in 0..3 {
+                let id = position_id[indices[triangle * 3 + corner] as usize] as usize;
+                adjacency[fill[id]] = triangle as u32;
+                fill[id] += 1;
+            }
+        }
+
+        // Every collapse removes about two triangles.
+        let collapse_budget = ((indices.len() - target_index_count) / 6).max(1);
+        let mut applied = 0;
+        let mut touched = vec![false; vertex_count];
+        let mut remap: Vec<u32> = (0..vertex_count as u32).collect();
+
+        for collapse in &collapses {
+            if applied >= collapse_budget || collapse.cost > error_limit_squared {
+                break;
+            }
+
+            let from = collapse.from as usize;
+            let to_id = position_id[collapse.to as usize] as usize;
+            if touched[from] || touched[to_id] {
+                continue;
+            }
+
+            let fan = &adjacency[offsets[from]..offsets[from + 1]];
+            if flips_triangles(fan, &indices, &position_id, &positions, collapse.from, collapse.to) {
+                continue;
+            }
+
+            // The collapse moves the whole fan, so none of its vertices may take part in another
+            // collapse this pass.
+            for &triangle in fan {
+                for corner in 0..3 {
+                    let id = position_id[indices[triangle as usize * 3 + corner] as usize] as usize;
+                    touched[id] = true;
+                }
+            }
+
+            for &vertex in &group_members[group_offsets[from]..group_offsets[from + 1]] {
+                remap[vertex as usize] = collapse.to;
+            }
+
+            let from_quadric = quadrics[from];
+            quadrics[to_id].add(&from_quadric);
+            result_error_squared = result_error_squared.max(collapse.cost);
+            applied += 1;
+        }
+
+        if applied == 0 {
+            break;
+        }
+
+        let mut simplified = Vec::with_capacity(indices.len());
+        for triangle in indices.chunks_exact(3) {
+            let remapped = [remap[triangle[0] as usize], remap[triangle[1] as usize], remap[triangle[2] as usize]];
+            let ids = remapped.map(|index| position_id[index as usize]);
+            if ids[0] != ids[1] && ids[1] != ids[2] && ids[0] != ids[2] {
+                simplified.extend_from_slice(&remapped);
+            }
+        }
+        indices = simplified;
+    }
+
+    SimplifyResult {
+        indices,
+        error: (result_error_squared.sqrt() / extent) as f32,
+    }
+}
+
+fn accumulate_quadrics(indices: &[u32], positions: &[[f64; 3]], position_id: &[u32], quadrics: &mut [Quadric]) {
+    let border = border_edges(indices, position_id);
+
+    for triangle in indices.chunks_exact(3) {
+        let p = [
+            positions[triangle[0] as usize],
+            positions[triangle[1] as usize],
+            positions[triangle[2] as usize],
+        ];
+        let normal = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+        let area = dot(normal, normal).sqrt() * 0.5;
+        let Some(normal) = normalize(normal) else {
+            continue;
+        };
+
+        let plane = Quadric::from_plane(normal, -dot(normal, p[0]), area);
+        for &index in triangle {
+            quadrics[position_id[index as usize] as usize].add(&plane);
+        }
+
+        for corner in 0..3 {
+            let a = position_id[triangle[corner] as usize];
+            let b = position_id[triangle[(corner + 1) % 3] as usize];
+            if !border.contains(&(a, b)) {
+                continue;
+            }
+
+            // Plane through the border edge, perpendicular to the triangle.
+            let edge = sub(positions[b as usize], positions[a as usize]);
+            let Some(edge_normal) = normalize(cross(edge, normal)) else {
+                continue;
+            };
+            let weight = dot(edge, edge) * BORDER_PLANE_WEIGHT;
+            let plane = Quadric::from_plane(edge_normal, -dot(edge_normal, positions[a as usize]), weight);
+            quadrics[a as usize].add(&plane);
+            quadrics[b as usize].add(&plane);
+        }
+    }
+}
+
+/// Directed edges between position ids without a matching opposite edge.
+fn border_edges(indices: &[u32], position_id: &[u32]) -> HashSet<(u32, u32)> {
+    let mut edges: HashSet<(u32, u32)> = HashSet::new();
+    for triangle in indices.chunks_exact(3) {
+        for corner in 0..3 {
+            let a = position_id[triangle[corner] as usize];
+            let b = position_id[triangle[(corner + 1) % 3] as usize];
+            edges.insert((a, b));
+        }
+    }
+
+    edges.iter()
+        .filter(|&&(a, b)| !edges.contains(&(b, a)))
+        .copied()
+        .collect()
+}
+
+fn classify_vertices(indices: &[u32], position_id: &[u32], vertex_count: usize) -> Vec<VertexKind> {
+    let mut kinds = vec![VertexKind::Manifold; vertex_count];
+
+    // A position used by more than one vertex sits on an attribute seam.
+    let mut first_user: Vec<u32> = vec![u32::MAX; vertex_count];
+    for &index in indices {
+        let id = position_id[index as usize] as usize;
+        if first_user[id] == u32::MAX {
+            first_user[id] = index;
+        } else if first_user[id] != index {
+            kinds[id] = VertexKind::Locked;
+        }
+    }
+
+    let mut directed_edges: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        for corner in 0..3 {
+            let a = position_id[triangle[corner] as usize];
+            let b = position_id[triangle[(corner + 1) % 3] as usize];
+            *directed_edges.entry((a, b)).or_insert(0) += 1;
+        }
+    }
+
+    let mut border_edge_count = vec![0u32; vertex_count];
+    for (&(a, b), &count) in &directed_edges {
+        if count > 1 {
+            kinds[a as usize] = VertexKind::Locked;
+            kinds[b as usize] = VertexKind::Locked;
+        } else if !directed_edges.contains_key(&(b, a)) {
+            border_edge_count[a as usize] += 1;
+            border_edge_count[b as usize] += 1;
+        }
+    }
+
+    for id in 0..vertex_count {
+        match border_edge_count[id] {
+            0 => {}
+            // A simple border vertex has one incoming and one outgoing border edge.
+            2 if kinds[id] == VertexKind::Manifold => kinds[id] = VertexKind::Border,
+            _ => kinds[id] = VertexKind::Locked,
+        }
+    }
+
+    kinds
+}
+
+fn collapse_candidates(
+    indices: &[u32],
+    position_id: &[u32],
+    kinds: &[VertexKind],
+    border_edges: &HashSet<(u32, u32)>,
+    positions: &[[f64; 3]],
+    quadrics: &[Quadric],
+) -> Vec<Collapse> {
+    let mut candidates: HashMap<(u32, u32), Collapse> = HashMap::new();
+
+    for triangle in indices.chunks_exact(3) {
+        for corner in 0..3 {
+            for (from_vertex, to_vertex) in [
+                (triangle[corner], triangle[(corner + 1) % 3]),
+                (triangle[(corner + 1) % 3], triangle[corner]),
+            ] {
+                let from = position_id[from_vertex as usize];
+                let to = position_id[to_vertex as usize];
+                if from == to {
+                    continue;
+                }
+
+                match kinds[from as usize] {
+                    VertexKind::Manifold => {}
+                    // Border vertices may only slide along the border.
+                    VertexKind::Border if border_edges.contains(&(from, to)) || border_edges.contains(&(to, from)) => {}
+                    _ => continue,
+                }
+
+                let mut quadric = quadrics[from as usize];
+                quadric.add(&quadrics[to as usize]);
+                let cost = quadric.error(positions[to_vertex as usize]);
+
+                candidates.entry((from, to))
+                    .and_modify(|existing| {
+                        if to_vertex < existing.to {
+                            existing.to = to_vertex;
+                        }
+                    })
+                    .or_insert(Collapse { from, to: to_vertex, cost });
+            }
+        }
+    }
+
+    candidates.into_values().collect()
+}
+
+/// Returns true if moving position `from` onto vertex `to` would flip or degenerate any triangle
+/// of the fan that is not removed by the collapse.
+fn flips_triangles(fan: &[u32], indices: &[u32], position_id: &[u32], positions: &[[f64; 3]], from: u32, to: u32) -> bool {
+    let to_id = position_id[to as usize];
+    let target = positions[to as usize];
+
+    for &triangle in fan {
+        let corners = [
+            indices[triangle as usize * 3],
+            indices[triangle as usize * 3 + 1],
+            indices[triangle as usize * 3 + 2],
+        ];
+        let ids = corners.map(|index| position_id[index as usize]);
+        if ids.contains(&to_id) {
+            continue;
+        }
+
+        let before = corners.map(|index| positions[index as usize]);
+        let after = [0, 1, 2].map(|corner| if ids[corner] == from { target } else { before[corner] });
+
+        let normal_before = normalize(cross(sub(before[1], before[0]), sub(before[2], before[0])));
+        let normal_after = normalize(cross(sub(after[1], after[0]), sub(after[2], after[0])));
+        match (normal_before, normal_after) {
+            (Some(a), Some(b)) if dot(a, b) >= MAX_NORMAL_FLIP_COS => {}
+            (None, Some(_)) => {}
+            _ => return true,
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::MeshVertex;
+
+    fn vertex(x: f32, y: f32, z: f32, u: f32) -> MeshVertex {
+        MeshVertex {
+            position: [x, y, z],
+            normal: [0.0, 0.0, 1.0],
+            uv: [u, 0.0],
+        }
+    }
+
+    /// `size` x `size` quads on the unit square, `height` gives the z of each grid point.
+    fn grid(size: u32, height: impl Fn(u32, u32) -> f32) -> MeshData {
+        let mut mesh = MeshData::default();
+        for y in 0..=size {
+            for x in 0..=size {
+                mesh.vertices.push(vertex(x as f32 / size as f32, y as f32 / size as f32, height(x, y), 0.0));
+            }
+        }
+        for y in 0..size {
+            for x in 0..size {
+                let v = y * (size + 1) + x;
+                mesh.indices.extend_from_slice(&[v, v + 1, v + size + 1, v + 1, v + size + 2, v + size + 1]);
+            }
+        }
+        mesh
+    }
+
+    fn bumpy(x: u32, y: u32) -> f32 {
+        ((x * 7 + y * 13) % 5) as f32 * 0.05
+    }
+
+    fn area(mesh: &MeshData, indices: &[u32]) -> f64 {
+        let positions: Vec<[f64; 3]> = mesh.vertices.iter().map(|v| v.position.map(|c| c as f64)).collect();
+        indices.chunks_exact(3)
+            .map(|t| {
+                let p = [positions[t[0] as usize], positions[t[1] as usize], positions[t[2] as usize]];
+                let normal = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+                dot(normal, normal).sqrt() * 0.5
+            })
+            .sum()
+    }
+
+    #[test]
+    fn same_input_gives_same_indices() {
+        let mesh = grid(12, bumpy);
+        let first = simplify(&mesh, mesh.indices.len() / 4, 1.0);
+        let second = simplify(&mesh.clone(), mesh.indices.len() / 4, 1.0);
+        assert_eq!(first, second);
+        assert!(first.indices.len() < mesh.indices.len());
+    }
+
+    #[test]
+    fn stops_at_target_index_count() {
+        let mesh = grid(10, |_, _| 0.0);
+        let target = mesh.indices.len() / 2;
+        let result = simplify(&mesh, target, 0.01);
+
+        assert!(result.indices.len() <= target, "{} > {}", result.indices.len(), target);
+        assert!(!result.indices.is_empty());
+        assert_eq!(result.indices.len() % 3, 0);
+        // Collapses on a plane are free.
+        assert!(result.error < 1e-6);
+        assert!((area(&mesh, &result.indices) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn stops_at_max_error() {
+        let mesh = grid(10, bumpy);
+        let max_error = 0.001;
+        let result = simplify(&mesh, 0, max_error);
+
+        assert!(result.error <= max_error, "{}", result.error);
+        assert!(result.indices.len() > mesh.indices.len() / 2);
+
+        let unbounded = simplify(&mesh, 0, 1.0);
+        assert!(unbounded.indices.len() < result.indices.len());
+        assert!(unbounded.error > max_error);
+    }
+
+    #[test]
+    fn target_above_input_returns_input() {
+        let mesh = grid(4, bumpy);
+        let result = simplify(&mesh, mesh.indices.len(), 0.0);
+        assert_eq!(result.indices, mesh.indices);
+        assert_eq!(result.error, 0.0);
+    }
+
+    #[test]
+    fn seam_vertices_are_locked() {
+        // Split the grid at x = 0.5 with separate vertices on each side, like a UV seam.
+        let size = 8;
+        let mut mesh = grid(size, |_, _| 0.0);
+        let seam_column = size / 2;
+        for y in 0..=size {
+            let original = y * (size + 1) + seam_column;
+            let mut copy = mesh.vertices[original as usize];
+            copy.uv[0] = 1.0;
+            let copy_index = mesh.vertices.len() as u32;
+            mesh.vertices.push(copy);
+
+            for triangle in mesh.indices.chunks_exact_mut(3) {
+                let on_right = triangle.iter().any(|&index| index % (size + 1) > seam_column);
+                if on_right {
+                    triangle.iter_mut().filter(|index| **index == original).for_each(|index| *index = copy_index);
+                }
+            }
+        }
+
+        let result = simplify(&mesh, 0, 0.01);
+        assert!(result.indices.len() < mesh.indices.len() / 2);
+
+        for y in 0..=size {
+            let original = y * (size + 1) + seam_column;
+            let copy = (size + 1) * (size + 1) + y;
+            assert!(result.indices.contains(&original), "seam vertex {} was removed", original);
+            assert!(result.indices.contains(&copy), "seam vertex {} was removed", copy);
+        }
+    }
+
+    #[test]
+    fn border_vertices_slide_along_the_border() {
+        let size = 8;
+        let mesh = grid(size, |_, _| 0.0);
+        let result = simplify(&mesh, 0, 0.01);
+        assert!(result.indices.len() < mesh.indices.len() / 4);
+
+        // The outline is unchanged: same area, corners kept and every border edge on the square.
+        assert!((area(&mesh, &result.indices) - 1.0).abs() < 1e-5);
+        for corner in [0, size, size * (size + 1), (size + 1) * (size + 1) - 1] {
+            assert!(result.indices.contains(&corner), "corner {} was removed", corner);
+        }
+
+        let position_id: Vec<u32> = (0..mesh.vertices.len() as u32).collect();
+        for (a, b) in border_edges(&result.indices, &position_id) {
+            let pa = mesh.vertices[a as usize].position;
+            let pb = mesh.vertices[b as usize].position;
+            let same_side = (0..2).any(|axis| {
+                pa[axis] == pb[axis] && (pa[axis] == 0.0 || pa[axis] == 1.0)
+            });
+            assert!(same_side, "border edge {:?} -> {:?} leaves the outline", pa, pb);
+        }
+    }
+}
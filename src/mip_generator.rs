@@ -0,0 +1,551 @@
+//! CPU mip chain generation for uncompressed textures.
+//!
+//! Levels are filtered in linear light, so sRGB textures are decoded before filtering and encoded
+//! again afterwards. Every level is filtered from the previous one with a separable resampler that
+//! handles odd (non power of two) sizes exactly instead of dropping the last row or column.
+
+use crate::texture::{f16_to_f32, f32_to_f16, full_mip_count, mip_size, TextureData, TextureFormat};
+use crate::texture_loader::TextureError;
+
+const KAISER_WIDTH: f32 = 3.0;
+const KAISER_ALPHA: f32 = 4.0;
+
+const ALPHA_COVERAGE_SEARCH_STEPS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipFilter {
+    /// Averages the source pixels covered by each destination pixel.
+    Box,
+    /// Kaiser windowed sinc, keeps distant levels sharper than a box filter.
+    Kaiser,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MipSettings {
+    pub filter: MipFilter,
+    /// Sample across the opposite edge instead of clamping, for tiling textures.
+    pub wrap: bool,
+    /// Alpha test reference value of cutout textures. When set, alpha of every level is scaled so
+    /// the same fraction of pixels passes the test as in the base level.
+    pub alpha_cutoff: Option<f32>,
+    /// Upper limit of levels including the base level, `None` generates the full chain.
+    pub max_levels: Option<u32>,
+}
+
+impl Default for MipSettings {
+    fn default() -> Self {
+        MipSettings {
+            filter: MipFilter::Box,
+            wrap: false,
+            alpha_cutoff: None,
+            max_levels: None,
+        }
+    }
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Replaces the mip chain of `texture` with one generated from its base level.
+pub fn generate_mips(texture: &TextureData, settings: &MipSettings) -> Result<TextureData, TextureError> {
+    let format = texture.format;
+    if !supports_format(format) {
+        return Err(TextureError::Unsupported(format!("mip generation for {:?}", format)));
+    }
+
+    let mip_levels = settings.max_levels
+        .map_or(u32::MAX, |levels| levels.max(1))
+        .min(full_mip_count(texture.width, texture.height));
+
+    let (subresources, size) = TextureData::packed_subresources(
+        format,
+        texture.width,
+        texture.height,
+        mip_levels,
+        texture.array_layers,
+    )?;
+    let mut data = vec![0u8; size];
+
+    for layer in 0..texture.array_layers {
+        let mut pixels = decode_pixels(format, texture.subresource_data(0, layer));
+        let (mut width, mut height) = (texture.width, texture.height);
+        let coverage = settings.alpha_cutoff.map(|cutoff| alpha_coverage(&pixels, cutoff));
+
+        for level in 0..mip_levels {
+            if level > 0 {
+                let (next_width, next_height) = (mip_size(texture.width, level), mip_size(texture.height, level));
+                pixels = resample(&pixels, width, height, next_width, next_height, settings);
+                (width, height) = (next_width, next_height);
+            }
+
+            let subresource = &subresources[(level + layer * mip_levels) as usize];
+            let level_data = &mut data[subresource.offset..subresource.offset + subresource.slice_pitch as usize];
+
+            // Only the stored level is rescaled, the next level is filtered from unscaled alpha so
+            // the correction does not compound down the chain.
+            match (settings.alpha_cutoff, coverage) {
+                (Some(cutoff), Some(coverage)) if level > 0 => {
+                    let mut scaled = pixels.clone();
+                    scale_alpha_to_coverage(&mut scaled, cutoff, coverage);
+                    encode_pixels(format, &scaled, level_data);
+                }
+                _ => encode_pixels(format, &pixels, level_data),
+            }
+        }
+    }
+
+    Ok(TextureData {
+        format,
+        width: texture.width,
+        height: texture.height,
+        mip_levels,
+        array_layers: texture.array_layers,
+        is_cube: texture.is_cube,
+        subresources,
+        data,
+    })
+}
+
+pub fn supports_format(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::R8Unorm
+            | TextureFormat::Rg8Unorm
+            | TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+            | TextureFormat::R16Float
+            | TextureFormat::Rg16Float
+            | TextureFormat::Rgba16Unorm
+            | TextureFormat::Rgba16Float
+            | TextureFormat::R32Float
+            | TextureFormat::Rgba32Float
+    )
+}
+
+/// Converts a tightly packed level to linear RGBA, missing channels read as 0 and alpha as 1.
+pub fn decode_pixels(format: TextureFormat, data: &[u8]) -> Vec<[f32; 4]> {
+    let unorm8 = |byte: u8| byte as f32 / 255.0;
+    let half = |bytes: &[u8]| f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]]));
+    let float = |bytes: &[u8]| f32::from_le_bytes(bytes[0..4].try_into().unwrap());
+
+    let mut pixels: Vec<[f32; 4]> = match format {
+        TextureFormat::R8Unorm => data.iter().map(|&r| [unorm8(r), 0.0, 0.0, 1.0]).collect(),
+        TextureFormat::Rg8Unorm => data.chunks_exact(2)
+            .map(|p| [unorm8(p[0]), unorm8(p[1]), 0.0, 1.0])
+            .collect(),
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => data.chunks_exact(4)
+            .map(|p| [unorm8(p[0]), unorm8(p[1]), unorm8(p[2]), unorm8(p[3])])
+            .collect(),
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => data.chunks_exact(4)
+            .map(|p| [unorm8(p[2]), unorm8(p[1]), unorm8(p[0]), unorm8(p[3])])
+            .collect(),
+        TextureFormat::R16Float => data.chunks_exact(2).map(|p| [half(p), 0.0, 0.0, 1.0]).collect(),
+        TextureFormat::Rg16Float => data.chunks_exact(4)
+            .map(|p| [half(&p[0..]), half(&p[2..]), 0.0, 1.0])
+            .collect(),
+        TextureFormat::Rgba16Unorm => data.chunks_exact(8)
+            .map(|p| {
+                let channel = |i: usize| u16::from_le_bytes([p[i * 2], p[i * 2 + 1]]) as f32 / 65535.0;
+                [channel(0), channel(1), channel(2), channel(3)]
+            })
+            .collect(),
+        TextureFormat::Rgba16Float => data.chunks_exact(8)
+            .map(|p| [half(&p[0..]), half(&p[2..]), half(&p[4..]), half(&p[6..])])
+            .collect(),
+        TextureFormat::R32Float => data.chunks_exact(4).map(|p| [float(p), 0.0, 0.0, 1.0]).collect(),
+        TextureFormat::Rgba32Float => data.chunks_exact(16)
+            .map(|p| [float(&p[0..]), float(&p[4..]), float(&p[8..]), float(&p[12..])])
+            .collect(),
+        _ => panic!("mip generation does not support {:?}", format),
+    };
+
+    if format.is_srgb() {
+        for pixel in &mut pixels {
+            for channel in &mut pixel[0..3] {
+                *channel = srgb_to_linear(*channel);
+            }
+        }
+    }
+
+    pixels
+}
+
+/// Inverse of [`decode_pixels`], clamps to the range of normalized formats.
+pub fn encode_pixels(format: TextureFormat, pixels: &[[f32; 4]], data: &mut [u8]) {
+    let unorm8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+    let srgb8 = |value: f32| unorm8(linear_to_srgb(value.clamp(0.0, 1.0)));
+    let unorm16 = |value: f32| ((value.clamp(0.0, 1.0) * 65535.0 + 0.5) as u16).to_le_bytes();
+    let half = |value: f32| f32_to_f16(value).to_le_bytes();
+
+    let stride = format.block_bytes() as usize;
+    for (pixel, out) in pixels.iter().zip(data.chunks_exact_mut(stride)) {
+        let [r, g, b, a] = *pixel;
+        match format {
+            TextureFormat::R8Unorm => out[0] = unorm8(r),
+            TextureFormat::Rg8Unorm => out.copy_from_slice(&[unorm8(r), unorm8(g)]),
+            TextureFormat::Rgba8Unorm => out.copy_from_slice(&[unorm8(r), unorm8(g), unorm8(b), unorm8(a)]),
+            TextureFormat::Rgba8UnormSrgb => out.copy_from_slice(&[srgb8(r), srgb8(g), srgb8(b), unorm8(a)]),
+            TextureFormat::Bgra8Unorm => out.copy_from_slice(&[unorm8(b), unorm8(g), unorm8(r), unorm8(a)]),
+            TextureFormat::Bgra8UnormSrgb => out.copy_from_slice(&[srgb8(b), srgb8(g), srgb8(r), unorm8(a)]),
+            TextureFormat::R16Float => out.copy_from_slice(&half(r)),
+            TextureFormat::Rg16Float => {
+                out[0..2].copy_from_slice(&half(r));
+                out[2..4].copy_from_slice(&half(g));
+            }
+            TextureFormat::Rgba16Unorm => {
+                for (i, value) in [r, g, b, a].into_iter().enumerate() {
+                    out[i * 2..i * 2 + 2].copy_from_slice(&unorm16(value));
+                }
+            }
+            TextureFormat::Rgba16Float => {
+                for (i, value) in [r, g, b, a].into_iter().enumerate() {
+                    out[i * 2..i * 2 + 2].copy_from_slice(&half(value));
+                }
+            }
+            TextureFormat::R32Float => out.copy_from_slice(&r.to_le_bytes()),
+            TextureFormat::Rgba32Float => {
+                for (i, value) in [r, g, b, a].into_iter().enumerate() {
+                    out[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            _ => panic!("mip generation does not support {:?}", format),
+        }
+    }
+}
+
+/// Resizes an image with the filter from `settings`, horizontally and then vertically.
+pub fn resample(
+    pixels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+    settings: &MipSettings,
+) -> Vec<[f32; 4]> {
+    let horizontal = filter_weights(width, new_width, settings);
+    let vertical = filter_weights(height, new_height, settings);
+    let (width, new_width) = (width as usize, new_width as usize);
+
+    let mut rows = vec![[0.0; 4]; new_width * height as usize];
+    for y in 0..height as usize {
+        for (x, taps) in horizontal.iter().enumerate() {
+            rows[y * new_width + x] = weighted_sum(taps.iter().map(|&(source, weight)| (pixels[y * width + source], weight)));
+        }
+    }
+
+    let mut result = vec![[0.0; 4]; new_width * new_height as usize];
+    for (y, taps) in vertical.iter().enumerate() {
+        for x in 0..new_width {
+            result[y * new_width + x] = weighted_sum(taps.iter().map(|&(source, weight)| (rows[source * new_width + x], weight)));
+        }
+    }
+
+    result
+}
+
+fn weighted_sum(taps: impl Iterator<Item = ([f32; 4], f32)>) -> [f32; 4] {
+    let mut sum = [0.0; 4];
+    for (pixel, weight) in taps {
+        for channel in 0..4 {
+            sum[channel] += pixel[channel] * weight;
+        }
+    }
+    sum
+}
+
+/// Normalized source taps for every destination pixel along one axis.
+fn filter_weights(size: u32, new_size: u32, settings: &MipSettings) -> Vec<Vec<(usize, f32)>> {
+    let scale = size as f32 / new_size as f32;
+
+    (0..new_size)
+        .map(|destination| {
+            let mut taps: Vec<(usize, f32)> = Vec::new();
+            let mut add = |source: i64, weight: f32| {
+                if weight == 0.0 {
+                    return;
+                }
+                let source = if settings.wrap {
+                    source.rem_euclid(size as i64)
+                } else {
+                    source.clamp(0, size as i64 - 1)
+                } as usize;
+                match taps.iter_mut().find(|(existing, _)| *existing == source) {
+                    Some((_, existing_weight)) => *existing_weight += weight,
+                    None => taps.push((source, weight)),
+                }
+            };
+
+            let start = destination as f32 * scale;
+            let end = start + scale;
+            match settings.filter {
+                MipFilter::Box => {
+                    // Exact coverage of each source pixel by the destination footprint.
+                    for source in start.floor() as i64..end.ceil() as i64 {
+                        let overlap = end.min(source as f32 + 1.0) - start.max(source as f32);
+                        add(source, overlap.max(0.0));
+                    }
+                }
+                MipFilter::Kaiser => {
+                    // Kernel coordinates are in destination pixels, so the kernel widens with the scale.
+                    let center = (start + end) * 0.5;
+                    let radius = KAISER_WIDTH * scale.max(1.0);
+                    for source in (center - radius).floor() as i64..=(center + radius).ceil() as i64 {
+                        let distance = (source as f32 + 0.5 - center) / scale.max(1.0);
+                        add(source, kaiser(distance));
+                    }
+                }
+            }
+
+            let total: f32 = taps.iter().map(|(_, weight)| weight).sum();
+            for (_, weight) in &mut taps {
+                *weight /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+fn kaiser(x: f32) -> f32 {
+    if x.abs() >= KAISER_WIDTH {
+        return 0.0;
+    }
+    let t = x / KAISER_WIDTH;
+    sinc(x) * bessel_i0(KAISER_ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(KAISER_ALPHA)
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let quarter_x_squared = x * x * 0.25;
+    for k in 1..32 {
+        term *= quarter_x_squared / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Fraction of pixels whose alpha passes an alpha test against `cutoff`.
+pub fn alpha_coverage(pixels: &[[f32; 4]], cutoff: f32) -> f32 {
+    if pixels.is_empty() {
+        return 0.0;
+    }
+    pixels.iter().filter(|pixel| pixel[3] > cutoff).count() as f32 / pixels.len() as f32
+}
+
+/// Scales alpha so that [`alpha_coverage`] gets as close to `coverage` as possible.
+pub fn scale_alpha_to_coverage(pixels: &mut [[f32; 4]], cutoff: f32, coverage: f32) {
+    let (mut low, mut high) = (0.0f32, 4.0f32);
+    let mut best_scale = 1.0;
+    let mut best_error = f32::MAX;
+
+    for _ in 0..ALPHA_COVERAGE_SEARCH_STEPS {
+        let scale = (low + high) * 0.5;
+        let scaled = pixels.iter().filter(|pixel| (pixel[3] * scale).min(1.0) > cutoff).count() as f32 / pixels.len() as f32;
+
+        let error = (scaled - coverage).abs();
+        if error < best_error {
+            best_error = error;
+            best_scale = scale;
+        }
+
+        if scaled < coverage {
+            low = scale;
+        } else if scaled > coverage {
+            high = scale;
+        } else {
+            break;
+        }
+    }
+
+    for pixel in pixels {
+        pixel[3] = (pixel[3] * best_scale).min(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn box_settings() -> MipSettings {
+        MipSettings::default()
+    }
+
+    fn rgba32f(width: u32, height: u32, pixels: &[[f32; 4]]) -> TextureData {
+        let data = pixels.iter().flatten().flat_map(|value| value.to_le_bytes()).collect();
+        TextureData::from_pixels(TextureFormat::Rgba32Float, width, height, data)
+    }
+
+    fn level_pixels(texture: &TextureData, level: u32) -> Vec<[f32; 4]> {
+        decode_pixels(texture.format, texture.subresource_data(level, 0))
+    }
+
+    fn assert_close(actual: [f32; 4], expected: [f32; 4]) {
+        for channel in 0..4 {
+            assert!((actual[channel] - expected[channel]).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn box_filter_averages_2x2() {
+        let texture = rgba32f(2, 2, &[
+            [1.0, 0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [1.0, 1.0, 1.0, 0.0],
+        ]);
+        let mips = generate_mips(&texture, &box_settings()).unwrap();
+
+        assert_eq!(mips.mip_levels, 2);
+        assert_eq!(level_pixels(&mips, 0), level_pixels(&texture, 0));
+        assert_eq!(level_pixels(&mips, 1), vec![[0.5, 0.5, 0.5, 0.5]]);
+    }
+
+    #[test]
+    fn full_chain_and_level_limit() {
+        let texture = rgba32f(8, 2, &[[0.25; 4]; 16]);
+        let mips = generate_mips(&texture, &box_settings()).unwrap();
+        assert_eq!(mips.mip_levels, 4);
+        let sizes: Vec<_> = (0..4).map(|level| (mips.subresource(level, 0).width, mips.subresource(level, 0).height)).collect();
+        assert_eq!(sizes, vec![(8, 2), (4, 1), (2, 1), (1, 1)]);
+        // A constant image stays constant on every level.
+        for level in 0..4 {
+            for pixel in level_pixels(&mips, level) {
+                assert_close(pixel, [0.25; 4]);
+            }
+        }
+
+        let limited = generate_mips(&texture, &MipSettings { max_levels: Some(2), ..box_settings() }).unwrap();
+        assert_eq!(limited.mip_levels, 2);
+    }
+
+    #[test]
+    fn odd_dimensions_use_every_pixel() {
+        // 3x1 to 1x1: every source pixel contributes a third.
+        let texture = rgba32f(3, 1, &[[0.0; 4], [0.3; 4], [0.9; 4]]);
+        let mips = generate_mips(&texture, &box_settings()).unwrap();
+        assert_close(level_pixels(&mips, 1)[0], [0.4; 4]);
+
+        // 5x1 to 2x1: each destination pixel covers two and a half source pixels.
+        let texture = rgba32f(5, 1, &[[1.0; 4], [1.0; 4], [0.0; 4], [0.0; 4], [0.0; 4]]);
+        let mips = generate_mips(&texture, &box_settings()).unwrap();
+        let level = level_pixels(&mips, 1);
+        assert_eq!(level.len(), 2);
+        assert_close(level[0], [0.8; 4]);
+        assert_close(level[1], [0.0; 4]);
+
+        // The last column of a 3x3 image reaches the 1x1 level.
+        let mut pixels = [[0.0; 4]; 9];
+        for y in 0..3 {
+            pixels[y * 3 + 2] = [0.9; 4];
+        }
+        let mips = generate_mips(&rgba32f(3, 3, &pixels), &box_settings()).unwrap();
+        assert_close(level_pixels(&mips, 1)[0], [0.3; 4]);
+    }
+
+    #[test]
+    fn srgb_is_filtered_in_linear_space() {
+        let black_and_white = vec![0, 0, 0, 255, 255, 255, 255, 255];
+        let srgb = TextureData::from_pixels(TextureFormat::Rgba8UnormSrgb, 2, 1, black_and_white.clone());
+        let mips = generate_mips(&srgb, &box_settings()).unwrap();
+        // Linear 0.5 encodes to 188 in sRGB, averaging the encoded values would give 128.
+        assert_eq!(mips.subresource_data(1, 0), &[188, 188, 188, 255]);
+
+        let linear = TextureData::from_pixels(TextureFormat::Rgba8Unorm, 2, 1, black_and_white);
+        let mips = generate_mips(&linear, &box_settings()).unwrap();
+        assert_eq!(mips.subresource_data(1, 0), &[128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn srgb_conversion_round_trips() {
+        for value in 0..=255u8 {
+            let encoded = value as f32 / 255.0;
+            let round_trip = linear_to_srgb(srgb_to_linear(encoded));
+            assert!((round_trip - encoded).abs() < 1e-5, "{}", value);
+        }
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((linear_to_srgb(0.5) - 0.735_357).abs() < 1e-5);
+    }
+
+    /// Cutout texture: a disc of alpha 0.6 on transparent background, tested against 0.5.
+    fn cutout(size: u32) -> TextureData {
+        let center = size as f32 * 0.5;
+        let pixels: Vec<[f32; 4]> = (0..size * size)
+            .map(|index| {
+                let (x, y) = ((index % size) as f32 + 0.5, (index / size) as f32 + 0.5);
+                let inside = (x - center).powi(2) + (y - center).powi(2) < (size as f32 * 0.4).powi(2);
+                [1.0, 1.0, 1.0, if inside { 0.6 } else { 0.0 }]
+            })
+            .collect();
+        rgba32f(size, size, &pixels)
+    }
+
+    #[test]
+    fn alpha_coverage_is_preserved() {
+        let texture = cutout(32);
+        let cutoff = 0.5;
+        let base_coverage = alpha_coverage(&level_pixels(&texture, 0), cutoff);
+        assert!(base_coverage > 0.4);
+
+        let plain = generate_mips(&texture, &box_settings()).unwrap();
+        let preserved = generate_mips(&texture, &MipSettings { alpha_cutoff: Some(cutoff), ..box_settings() }).unwrap();
+
+        // Without correction the disc shrinks quickly, since edges average to less than the cutoff.
+        let plain_coverage = alpha_coverage(&level_pixels(&plain, 3), cutoff);
+        assert!(plain_coverage < base_coverage - 0.1, "{} vs {}", plain_coverage, base_coverage);
+
+        for level in 1..4 {
+            let error = (alpha_coverage(&level_pixels(&preserved, level), cutoff) - base_coverage).abs();
+            let plain_error = (alpha_coverage(&level_pixels(&plain, level), cutoff) - base_coverage).abs();
+            assert!(error <= plain_error, "level {}: {} vs {}", level, error, plain_error);
+            // The 4x4 level of a symmetric disc can only cover 4, 12 or 16 pixels.
+            if level < 3 {
+                assert!(error < 0.05, "level {}: off by {}", level, error);
+            }
+        }
+        // The base level itself is never rescaled.
+        assert_eq!(preserved.subresource_data(0, 0), texture.subresource_data(0, 0));
+    }
+
+    #[test]
+    fn scale_alpha_matches_coverage() {
+        let mut pixels: Vec<[f32; 4]> = (0..10).map(|i| [0.0, 0.0, 0.0, i as f32 * 0.05]).collect();
+        scale_alpha_to_coverage(&mut pixels, 0.5, 0.5);
+        assert_eq!(alpha_coverage(&pixels, 0.5), 0.5);
+        assert!(pixels.iter().all(|pixel| pixel[3] <= 1.0));
+    }
+
+    #[test]
+    fn rejects_compressed_formats() {
+        let texture = TextureData::from_pixels(TextureFormat::Bc1Unorm, 4, 4, vec![0; 8]);
+        assert!(matches!(generate_mips(&texture, &box_settings()), Err(TextureError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,158 @@
+//! Wavefront OBJ importer.
+//!
+//! Supports positions, texture coordinates, normals, polygonal faces (fan triangulated),
+//! negative indices and `usemtl` material groups, which become submeshes. Texture coordinates are
+//! flipped vertically to the Direct3D convention.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::mesh::{MeshData, MeshVertex, Submesh};
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OBJ line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+pub fn load_obj(path: impl AsRef<Path>) -> Result<MeshData, Box<dyn std::error::Error>> {
+    let source = std::fs::read_to_string(path)?;
+    Ok(parse_obj(&source)?)
+}
+
+pub fn parse_obj(source: &str) -> Result<MeshData, ObjError> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+
+    let mut vertices: Vec<MeshVertex> = Vec::new();
+    let mut vertex_lookup: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+
+    // Triangles per material in order of first use. Faces before the first `usemtl` use the
+    // unnamed material 0.
+    let mut material_names: Vec<String> = vec![String::new()];
+    let mut material_triangles: Vec<Vec<u32>> = vec![Vec::new()];
+    let mut current_material = 0;
+    let mut has_normals = true;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let error = |message: &str| ObjError { line: line_number, message: message.to_string() };
+
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        let mut floats = |count: usize| -> Result<Vec<f32>, ObjError> {
+            let values: Vec<f32> = tokens.by_ref()
+                .take(count)
+                .map(|token| token.parse::<f32>().map_err(|_| error("invalid number")))
+                .collect::<Result<_, _>>()?;
+            if values.len() < count {
+                return Err(error("not enough components"));
+            }
+            Ok(values)
+        };
+
+        match keyword {
+            "v" => {
+                let values = floats(3)?;
+                positions.push([values[0], values[1], values[2]]);
+            }
+            "vt" => {
+                let values = floats(2)?;
+                uvs.push([values[0], 1.0 - values[1]]);
+            }
+            "vn" => {
+                let values = floats(3)?;
+                normals.push([values[0], values[1], values[2]]);
+            }
+            "usemtl" => {
+                let name = tokens.next().unwrap_or("");
+                current_material = match material_names.iter().position(|existing| existing == name) {
+                    Some(index) => index,
+                    None => {
+                        material_names.push(name.to_string());
+                        material_triangles.push(Vec::new());
+                        material_names.len() - 1
+                    }
+                };
+            }
+            "f" => {
+                let mut face: Vec<u32> = Vec::new();
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let resolve = |part: Option<&str>, count: usize| -> Result<Option<usize>, ObjError> {
+                        match part {
+                            None | Some("") => Ok(None),
+                            Some(part) => {
+                                let index: i64 = part.parse().map_err(|_| error("invalid face index"))?;
+                                let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+                                if resolved < 0 || resolved >= count as i64 {
+                                    return Err(error("face index out of range"));
+                                }
+                                Ok(Some(resolved as usize))
+                            }
+                        }
+                    };
+
+                    let position = resolve(parts.next(), positions.len())?.ok_or_else(|| error("face without position"))?;
+                    let uv = resolve(parts.next(), uvs.len())?;
+                    let normal = resolve(parts.next(), normals.len())?;
+                    has_normals &= normal.is_some();
+
+                    let index = *vertex_lookup.entry((position, uv, normal)).or_insert_with(|| {
+                        vertices.push(MeshVertex {
+                            position: positions[position],
+                            normal: normal.map_or([0.0; 3], |normal| normals[normal]),
+                            uv: uv.map_or([0.0; 2], |uv| uvs[uv]),
+                        });
+                        (vertices.len() - 1) as u32
+                    });
+                    face.push(index);
+                }
+
+                if face.len() < 3 {
+                    return Err(error("face with less than 3 vertices"));
+                }
+                for corner in 1..face.len() - 1 {
+                    material_triangles[current_material].extend_from_slice(&[face[0], face[corner], face[corner + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut mesh = MeshData {
+        vertices,
+        ..Default::default()
+    };
+    for (material_index, triangles) in material_triangles.into_iter().enumerate() {
+        if triangles.is_empty() {
+            continue;
+        }
+        mesh.submeshes.push(Submesh {
+            index_offset: mesh.indices.len() as u32,
+            index_count: triangles.len() as u32,
+            material_index: material_index as u32,
+        });
+        mesh.indices.extend(triangles);
+    }
+
+    if !has_normals {
+        mesh.generate_normals();
+    }
+
+    Ok(mesh)
+}
@@ -0,0 +1,63 @@
+// Encodes the linear Rec.709 scene for the swap chain color space, mirrors color_space.rs.
+
+cbuffer OutputConstants: register(b0) {
+    uint mode;
+    float paper_white_nits;
+    float max_nits;
+    float padding;
+};
+
+Texture2D scene: register(t0);
+SamplerState scene_sampler: register(s0);
+
+struct PSInput {
+    float4 position: SV_Position;
+    float2 uv: TEXCOORD0;
+};
+
+static const uint MODE_SRGB = 0;
+static const uint MODE_HDR10 = 1;
+static const uint MODE_SCRGB = 2;
+
+static const float SCRGB_REFERENCE_NITS = 80.0;
+static const float PQ_MAX_NITS = 10000.0;
+
+float3 LinearToSrgb(float3 color) {
+    float3 low = color * 12.92;
+    float3 high = 1.055 * pow(color, 1.0 / 2.4) - 0.055;
+    return color <= 0.0031308 ? low : high;
+}
+
+float3 PqEncode(float3 nits) {
+    const float m1 = 2610.0 / 16384.0;
+    const float m2 = 2523.0 / 4096.0 * 128.0;
+    const float c1 = 3424.0 / 4096.0;
+    const float c2 = 2413.0 / 4096.0 * 32.0;
+    const float c3 = 2392.0 / 4096.0 * 32.0;
+
+    float3 y = pow(saturate(nits / PQ_MAX_NITS), m1);
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), m2);
+}
+
+float3 Rec709ToRec2020(float3 color) {
+    const float3x3 m = {
+        0.6274040, 0.3292820, 0.0433136,
+        0.0690970, 0.9195400, 0.0113612,
+        0.0163916, 0.0880132, 0.8955950
+    };
+    return mul(m, color);
+}
+
+float4 Main(PSInput input): SV_Target {
+    float3 color = scene.Sample(scene_sampler, input.uv).rgb;
+
+    if (mode == MODE_HDR10) {
+        color = PqEncode(clamp(Rec709ToRec2020(color) * paper_white_nits, 0.0, max_nits));
+    } else if (mode == MODE_SCRGB) {
+        color = min(color * paper_white_nits / SCRGB_REFERENCE_NITS, max_nits / SCRGB_REFERENCE_NITS);
+    } else {
+        color = LinearToSrgb(saturate(color));
+    }
+
+    return float4(color, 1.0);
+}
@@ -0,0 +1,12 @@
+struct VSOutput {
+    float4 position: SV_Position;
+    float2 uv: TEXCOORD0;
+};
+
+// A single triangle covering the screen, generated from the vertex index.
+VSOutput Main(uint vertex_id: SV_VertexID) {
+    VSOutput output = (VSOutput)0;
+    output.uv = float2((vertex_id << 1) & 2, vertex_id & 2);
+    output.position = float4(output.uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    return output;
+}
@@ -0,0 +1,155 @@
+//! Linear HDR scene target and the final pass encoding it into the back buffer.
+
+use windows::{core::*, Win32::Graphics::Direct3D11::*, Win32::Graphics::Dxgi::Common::*};
+
+use crate::color_space::{OutputConstants, OutputEncoding};
+use crate::pipeline_state::{BindTracker, BlendDesc, DepthStencilDesc, Pipeline, PipelineCache, PipelineDesc, RasterizerDesc, Topology};
+use crate::sampler_cache::{SamplerCache, SamplerDesc};
+use crate::window_application::WindowApplication;
+
+/// Linear Rec.709 with headroom above paper white and below zero for wide gamut colors.
+pub const SCENE_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R16G16B16A16_FLOAT;
+
+pub struct SceneTarget {
+    pub render_target: ID3D11RenderTargetView,
+    pub view: ID3D11ShaderResourceView,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl SceneTarget {
+    pub fn new(device: &ID3D11Device, width: u32, height: u32) -> Result<Self> {
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: width,
+            Height: height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: SCENE_FORMAT,
+            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: (D3D11_BIND_RENDER_TARGET.0 | D3D11_BIND_SHADER_RESOURCE.0) as u32,
+            ..Default::default()
+        };
+
+        let mut texture = None;
+        unsafe { device.CreateTexture2D(&desc, None, Some(&mut texture))? };
+        let texture = texture.unwrap();
+
+        let mut render_target = None;
+        unsafe { device.CreateRenderTargetView(&texture, None, Some(&mut render_target))? };
+
+        let mut view = None;
+        unsafe { device.CreateShaderResourceView(&texture, None, Some(&mut view))? };
+
+        Ok(SceneTarget { render_target: render_target.unwrap(), view: view.unwrap(), width, height })
+    }
+}
+
+pub struct OutputPass {
+    pub scene: SceneTarget,
+    pipeline: Pipeline,
+    constant_buffer: ID3D11Buffer,
+    constants: OutputConstants,
+    sampler: ID3D11SamplerState,
+}
+
+impl OutputPass {
+    pub fn new(
+        device: &ID3D11Device,
+        pipelines: &mut PipelineCache,
+        samplers: &mut SamplerCache,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let vertex_shader_blob = WindowApplication::compile_shader("output.vs.hlsl", "vs_5_0");
+        let pixel_shader_blob = WindowApplication::compile_shader("output.ps.hlsl", "ps_5_0");
+
+        let mut vertex_shader = None;
+        let mut pixel_shader = None;
+        unsafe {
+            let vertex_data = std::slice::from_raw_parts(vertex_shader_blob.GetBufferPointer() as *const u8, vertex_shader_blob.GetBufferSize());
+            let pixel_data = std::slice::from_raw_parts(pixel_shader_blob.GetBufferPointer() as *const u8, pixel_shader_blob.GetBufferSize());
+            device.CreateVertexShader(vertex_data, None, Some(&mut vertex_shader))?;
+            device.CreatePixelShader(pixel_data, None, Some(&mut pixel_shader))?;
+        }
+
+        // The full screen triangle is generated from vertex ids, no input layout needed.
+        let pipeline = pipelines.create_pipeline(&PipelineDesc {
+            vertex_shader: vertex_shader.unwrap(),
+            pixel_shader,
+            input_layout: None,
+            blend: BlendDesc::OPAQUE,
+            rasterizer: RasterizerDesc::NO_CULL,
+            depth_stencil: DepthStencilDesc::DISABLED,
+            topology: Topology::TriangleList,
+        })?;
+
+        let buffer_desc = D3D11_BUFFER_DESC {
+            ByteWidth: std::mem::size_of::<OutputConstants>() as u32,
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+            ..Default::default()
+        };
+        let constants = OutputEncoding::default().constants();
+        let initial_data = D3D11_SUBRESOURCE_DATA {
+            pSysMem: &constants as *const OutputConstants as *const _,
+            ..Default::default()
+        };
+
+        let mut constant_buffer = None;
+        unsafe { device.CreateBuffer(&buffer_desc, Some(&initial_data), Some(&mut constant_buffer))? };
+
+        Ok(OutputPass {
+            scene: SceneTarget::new(device, width, height)?,
+            pipeline,
+            constant_buffer: constant_buffer.unwrap(),
+            constants,
+            sampler: samplers.get(&SamplerDesc::POINT_CLAMP)?,
+        })
+    }
+
+    pub fn resize(&mut self, device: &ID3D11Device, width: u32, height: u32) -> Result<()> {
+        if (width, height) != (self.scene.width, self.scene.height) {
+            self.scene = SceneTarget::new(device, width, height)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the scene into `back_buffer` with the encoding of the swap chain color space.
+    pub fn encode(
+        &mut self,
+        context: &ID3D11DeviceContext,
+        tracker: &mut BindTracker,
+        back_buffer: &ID3D11RenderTargetView,
+        encoding: &OutputEncoding,
+    ) {
+        let constants = encoding.constants();
+        if constants != self.constants {
+            unsafe { context.UpdateSubresource(&self.constant_buffer, 0, None, &constants as *const OutputConstants as *const _, 0, 0) };
+            self.constants = constants;
+        }
+
+        let viewport = D3D11_VIEWPORT {
+            TopLeftX: 0.0,
+            TopLeftY: 0.0,
+            Width: self.scene.width as f32,
+            Height: self.scene.height as f32,
+            MinDepth: 0.0,
+            MaxDepth: 1.0,
+        };
+
+        self.pipeline.bind(context, tracker);
+
+        unsafe {
+            context.OMSetRenderTargets(Some(&[Some(back_buffer.clone())]), None);
+            context.RSSetViewports(Some(&[viewport]));
+            context.PSSetShaderResources(0, Some(&[Some(self.scene.view.clone())]));
+            context.PSSetSamplers(0, Some(&[Some(self.sampler.clone())]));
+            context.PSSetConstantBuffers(0, Some(&[Some(self.constant_buffer.clone())]));
+            context.Draw(3, 0);
+
+            // The scene is rendered to again next frame, which fails while it is bound as a shader input.
+            context.PSSetShaderResources(0, Some(&[None]));
+        }
+    }
+}
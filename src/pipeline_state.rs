@@ -0,0 +1,720 @@
+//! Declarative pipeline descriptions, deduplicated D3D11 state objects and redundant bind elimination.
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use windows::{core::*, Win32::Graphics::Direct3D::*, Win32::Graphics::Direct3D11::*};
+
+use crate::sampler_cache::{comparison_func, ComparisonFunc};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    InvSrcColor,
+    SrcAlpha,
+    InvSrcAlpha,
+    DestColor,
+    InvDestColor,
+    DestAlpha,
+    InvDestAlpha,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendOp {
+    Add,
+    Subtract,
+    RevSubtract,
+    Min,
+    Max,
+}
+
+/// Blending of the first render target, the same description is used for all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlendDesc {
+    pub enabled: bool,
+    pub src_color: BlendFactor,
+    pub dest_color: BlendFactor,
+    pub color_op: BlendOp,
+    pub src_alpha: BlendFactor,
+    pub dest_alpha: BlendFactor,
+    pub alpha_op: BlendOp,
+    /// `D3D11_COLOR_WRITE_ENABLE` bits.
+    pub write_mask: u8,
+    pub alpha_to_coverage: bool,
+}
+
+impl BlendDesc {
+    pub const OPAQUE: BlendDesc = BlendDesc {
+        enabled: false,
+        src_color: BlendFactor::One,
+        dest_color: BlendFactor::Zero,
+        color_op: BlendOp::Add,
+        src_alpha: BlendFactor::One,
+        dest_alpha: BlendFactor::Zero,
+        alpha_op: BlendOp::Add,
+        write_mask: 0xf,
+        alpha_to_coverage: false,
+    };
+    pub const ALPHA: BlendDesc = BlendDesc {
+        enabled: true,
+        src_color: BlendFactor::SrcAlpha,
+        dest_color: BlendFactor::InvSrcAlpha,
+        src_alpha: BlendFactor::One,
+        dest_alpha: BlendFactor::InvSrcAlpha,
+        ..BlendDesc::OPAQUE
+    };
+    pub const PREMULTIPLIED: BlendDesc = BlendDesc {
+        enabled: true,
+        src_color: BlendFactor::One,
+        dest_color: BlendFactor::InvSrcAlpha,
+        src_alpha: BlendFactor::One,
+        dest_alpha: BlendFactor::InvSrcAlpha,
+        ..BlendDesc::OPAQUE
+    };
+    pub const ADDITIVE: BlendDesc = BlendDesc {
+        enabled: true,
+        src_color: BlendFactor::One,
+        dest_color: BlendFactor::One,
+        src_alpha: BlendFactor::One,
+        dest_alpha: BlendFactor::One,
+        ..BlendDesc::OPAQUE
+    };
+
+    pub fn to_d3d11(self) -> D3D11_BLEND_DESC {
+        D3D11_BLEND_DESC {
+            AlphaToCoverageEnable: self.alpha_to_coverage.into(),
+            IndependentBlendEnable: false.into(),
+            RenderTarget: [D3D11_RENDER_TARGET_BLEND_DESC {
+                BlendEnable: self.enabled.into(),
+                SrcBlend: blend_factor(self.src_color),
+                DestBlend: blend_factor(self.dest_color),
+                BlendOp: blend_op(self.color_op),
+                SrcBlendAlpha: blend_factor(self.src_alpha),
+                DestBlendAlpha: blend_factor(self.dest_alpha),
+                BlendOpAlpha: blend_op(self.alpha_op),
+                RenderTargetWriteMask: self.write_mask,
+            }; 8],
+        }
+    }
+}
+
+fn blend_factor(factor: BlendFactor) -> D3D11_BLEND {
+    match factor {
+        BlendFactor::Zero => D3D11_BLEND_ZERO,
+        BlendFactor::One => D3D11_BLEND_ONE,
+        BlendFactor::SrcColor => D3D11_BLEND_SRC_COLOR,
+        BlendFactor::InvSrcColor => D3D11_BLEND_INV_SRC_COLOR,
+        BlendFactor::SrcAlpha => D3D11_BLEND_SRC_ALPHA,
+        BlendFactor::InvSrcAlpha => D3D11_BLEND_INV_SRC_ALPHA,
+        BlendFactor::DestColor => D3D11_BLEND_DEST_COLOR,
+        BlendFactor::InvDestColor => D3D11_BLEND_INV_DEST_COLOR,
+        BlendFactor::DestAlpha => D3D11_BLEND_DEST_ALPHA,
+        BlendFactor::InvDestAlpha => D3D11_BLEND_INV_DEST_ALPHA,
+    }
+}
+
+fn blend_op(op: BlendOp) -> D3D11_BLEND_OP {
+    match op {
+        BlendOp::Add => D3D11_BLEND_OP_ADD,
+        BlendOp::Subtract => D3D11_BLEND_OP_SUBTRACT,
+        BlendOp::RevSubtract => D3D11_BLEND_OP_REV_SUBTRACT,
+        BlendOp::Min => D3D11_BLEND_OP_MIN,
+        BlendOp::Max => D3D11_BLEND_OP_MAX,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FillMode {
+    Solid,
+    Wireframe,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+}
+
+/// Floats are compared by their bits, like in [`crate::sampler_cache::SamplerDesc`].
+#[derive(Clone, Copy, Debug)]
+pub struct RasterizerDesc {
+    pub fill: FillMode,
+    pub cull: CullMode,
+    pub front_counter_clockwise: bool,
+    pub depth_bias: i32,
+    pub depth_bias_clamp: f32,
+    pub slope_scaled_depth_bias: f32,
+    pub depth_clip: bool,
+    pub scissor: bool,
+}
+
+impl RasterizerDesc {
+    /// The Direct3D defaults, clockwise triangles are front facing.
+    pub const DEFAULT: RasterizerDesc = RasterizerDesc {
+        fill: FillMode::Solid,
+        cull: CullMode::Back,
+        front_counter_clockwise: false,
+        depth_bias: 0,
+        depth_bias_clamp: 0.0,
+        slope_scaled_depth_bias: 0.0,
+        depth_clip: true,
+        scissor: false,
+    };
+    pub const NO_CULL: RasterizerDesc = RasterizerDesc { cull: CullMode::None, ..RasterizerDesc::DEFAULT };
+    pub const WIREFRAME: RasterizerDesc = RasterizerDesc { fill: FillMode::Wireframe, cull: CullMode::None, ..RasterizerDesc::DEFAULT };
+    /// Two sided and clipped to the scissor rectangle, for UI.
+    pub const SCISSOR: RasterizerDesc = RasterizerDesc { cull: CullMode::None, scissor: true, ..RasterizerDesc::DEFAULT };
+
+    fn key(&self) -> (FillMode, CullMode, bool, i32, u32, u32, bool, bool) {
+        (
+            self.fill,
+            self.cull,
+            self.front_counter_clockwise,
+            self.depth_bias,
+            self.depth_bias_clamp.to_bits(),
+            self.slope_scaled_depth_bias.to_bits(),
+            self.depth_clip,
+            self.scissor,
+        )
+    }
+
+    pub fn to_d3d11(self) -> D3D11_RASTERIZER_DESC {
+        D3D11_RASTERIZER_DESC {
+            FillMode: match self.fill {
+                FillMode::Solid => D3D11_FILL_SOLID,
+                FillMode::Wireframe => D3D11_FILL_WIREFRAME,
+            },
+            CullMode: match self.cull {
+                CullMode::None => D3D11_CULL_NONE,
+                CullMode::Front => D3D11_CULL_FRONT,
+                CullMode::Back => D3D11_CULL_BACK,
+            },
+            FrontCounterClockwise: self.front_counter_clockwise.into(),
+            DepthBias: self.depth_bias,
+            DepthBiasClamp: self.depth_bias_clamp,
+            SlopeScaledDepthBias: self.slope_scaled_depth_bias,
+            DepthClipEnable: self.depth_clip.into(),
+            ScissorEnable: self.scissor.into(),
+            MultisampleEnable: false.into(),
+            AntialiasedLineEnable: false.into(),
+        }
+    }
+}
+
+impl PartialEq for RasterizerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for RasterizerDesc {}
+
+impl Hash for RasterizerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// Depth testing only, stencil stays disabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DepthStencilDesc {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_func: ComparisonFunc,
+}
+
+impl DepthStencilDesc {
+    pub const DISABLED: DepthStencilDesc = DepthStencilDesc { depth_test: false, depth_write: false, depth_func: ComparisonFunc::Always };
+    pub const DEFAULT: DepthStencilDesc = DepthStencilDesc { depth_test: true, depth_write: true, depth_func: ComparisonFunc::Less };
+    pub const READ_ONLY: DepthStencilDesc = DepthStencilDesc { depth_write: false, ..DepthStencilDesc::DEFAULT };
+
+    /// The same test for a reversed-Z depth buffer, where nearer means greater.
+    pub fn reversed(self) -> Self {
+        let depth_func = match self.depth_func {
+            ComparisonFunc::Less => ComparisonFunc::Greater,
+            ComparisonFunc::LessEqual => ComparisonFunc::GreaterEqual,
+            ComparisonFunc::Greater => ComparisonFunc::Less,
+            ComparisonFunc::GreaterEqual => ComparisonFunc::LessEqual,
+            func => func,
+        };
+        DepthStencilDesc { depth_func, ..self }
+    }
+
+    pub fn to_d3d11(self) -> D3D11_DEPTH_STENCIL_DESC {
+        let stencil_op = D3D11_DEPTH_STENCILOP_DESC {
+            StencilFailOp: D3D11_STENCIL_OP_KEEP,
+            StencilDepthFailOp: D3D11_STENCIL_OP_KEEP,
+            StencilPassOp: D3D11_STENCIL_OP_KEEP,
+            StencilFunc: D3D11_COMPARISON_ALWAYS,
+        };
+
+        D3D11_DEPTH_STENCIL_DESC {
+            DepthEnable: self.depth_test.into(),
+            DepthWriteMask: if self.depth_write { D3D11_DEPTH_WRITE_MASK_ALL } else { D3D11_DEPTH_WRITE_MASK_ZERO },
+            DepthFunc: comparison_func(self.depth_func),
+            StencilEnable: false.into(),
+            StencilReadMask: 0xff,
+            StencilWriteMask: 0xff,
+            FrontFace: stencil_op,
+            BackFace: stencil_op,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Topology {
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+}
+
+impl Topology {
+    pub fn to_d3d11(self) -> D3D_PRIMITIVE_TOPOLOGY {
+        match self {
+            Topology::PointList => D3D11_PRIMITIVE_TOPOLOGY_POINTLIST,
+            Topology::LineList => D3D11_PRIMITIVE_TOPOLOGY_LINELIST,
+            Topology::LineStrip => D3D11_PRIMITIVE_TOPOLOGY_LINESTRIP,
+            Topology::TriangleList => D3D11_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
+            Topology::TriangleStrip => D3D11_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP,
+        }
+    }
+}
+
+/// Source of [`StateId`]s, shared by every cache.
+static NEXT_STATE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a state object created by a [`StateCache`], equal descriptions get equal ids.
+///
+/// Ids are never reused, not after [`StateCache::clear`] and not by a cache created for a new
+/// device, so a [`BindTracker`] can never mistake a new state for one it saw before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StateId(u64);
+
+/// Creates one `T` per distinct description `D`.
+pub struct StateCache<D, T> {
+    states: HashMap<D, (StateId, T)>,
+}
+
+impl<D, T> Default for StateCache<D, T> {
+    fn default() -> Self {
+        StateCache { states: HashMap::new() }
+    }
+}
+
+impl<D: Hash + Eq + Clone, T: Clone> StateCache<D, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached state for `desc`, calling `create` only for descriptions not seen before.
+    pub fn get_or_create<E>(&mut self, desc: &D, create: impl FnOnce(&D) -> std::result::Result<T, E>) -> std::result::Result<(StateId, T), E> {
+        if let Some((id, state)) = self.states.get(desc) {
+            return Ok((*id, state.clone()));
+        }
+
+        let state = create(desc)?;
+        let id = StateId(NEXT_STATE_ID.fetch_add(1, Ordering::Relaxed));
+        self.states.insert(desc.clone(), (id, state.clone()));
+        Ok((id, state))
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+}
+
+/// Identifies everything a [`Pipeline`] binds.
+///
+/// Shaders and input layouts are interned by [`PipelineCache`], which keeps a reference to each
+/// of them. Their addresses can therefore not be reused by new objects while the cache lives,
+/// and a new cache hands out new ids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub vertex_shader: StateId,
+    pub pixel_shader: Option<StateId>,
+    pub input_layout: Option<StateId>,
+    pub blend: StateId,
+    pub rasterizer: StateId,
+    pub depth_stencil: StateId,
+    pub topology: Topology,
+}
+
+/// Which parts of the pipeline have to be set again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineChanges {
+    pub vertex_shader: bool,
+    pub pixel_shader: bool,
+    pub input_layout: bool,
+    pub blend: bool,
+    pub rasterizer: bool,
+    pub depth_stencil: bool,
+    pub topology: bool,
+}
+
+impl PipelineChanges {
+    pub fn any(&self) -> bool {
+        *self != PipelineChanges::default()
+    }
+}
+
+/// Remembers the last bound [`PipelineKey`] of a context.
+///
+/// Code binding state behind the tracker's back has to call [`BindTracker::invalidate`].
+#[derive(Debug, Default)]
+pub struct BindTracker {
+    bound: Option<PipelineKey>,
+    binds: u64,
+    skipped: u64,
+}
+
+impl BindTracker {
+    pub fn new() -> Self {
+        BindTracker::default()
+    }
+
+    /// Records `key` as bound and returns what differs from the previous one.
+    pub fn changes(&mut self, key: &PipelineKey) -> PipelineChanges {
+        let changes = match &self.bound {
+            Some(bound) => PipelineChanges {
+                vertex_shader: bound.vertex_shader != key.vertex_shader,
+                pixel_shader: bound.pixel_shader != key.pixel_shader,
+                input_layout: bound.input_layout != key.input_layout,
+                blend: bound.blend != key.blend,
+                rasterizer: bound.rasterizer != key.rasterizer,
+                depth_stencil: bound.depth_stencil != key.depth_stencil,
+                topology: bound.topology != key.topology,
+            },
+            None => PipelineChanges {
+                vertex_shader: true,
+                pixel_shader: true,
+                input_layout: true,
+                blend: true,
+                rasterizer: true,
+                depth_stencil: true,
+                topology: true,
+            },
+        };
+
+        self.bound = Some(*key);
+        if changes.any() {
+            self.binds += 1;
+        } else {
+            self.skipped += 1;
+        }
+        changes
+    }
+
+    pub fn invalidate(&mut self) {
+        self.bound = None;
+    }
+
+    /// Number of binds that changed state and that were skipped entirely.
+    pub fn stats(&self) -> (u64, u64) {
+        (self.binds, self.skipped)
+    }
+}
+
+pub struct PipelineDesc {
+    pub vertex_shader: ID3D11VertexShader,
+    pub pixel_shader: Option<ID3D11PixelShader>,
+    pub input_layout: Option<ID3D11InputLayout>,
+    pub blend: BlendDesc,
+    pub rasterizer: RasterizerDesc,
+    pub depth_stencil: DepthStencilDesc,
+    pub topology: Topology,
+}
+
+#[derive(Clone)]
+pub struct Pipeline {
+    pub key: PipelineKey,
+    vertex_shader: ID3D11VertexShader,
+    pixel_shader: Option<ID3D11PixelShader>,
+    input_layout: Option<ID3D11InputLayout>,
+    blend_state: ID3D11BlendState,
+    rasterizer_state: ID3D11RasterizerState,
+    depth_stencil_state: ID3D11DepthStencilState,
+    topology: Topology,
+}
+
+impl Pipeline {
+    /// Binds the parts of the pipeline that differ from what `tracker` saw last.
+    pub fn bind(&self, context: &ID3D11DeviceContext, tracker: &mut BindTracker) {
+        let changes = tracker.changes(&self.key);
+
+        unsafe {
+            if changes.vertex_shader {
+                context.VSSetShader(&self.vertex_shader, None);
+            }
+            if changes.pixel_shader {
+                context.PSSetShader(self.pixel_shader.as_ref(), None);
+            }
+            if changes.input_layout {
+                context.IASetInputLayout(self.input_layout.as_ref());
+            }
+            if changes.blend {
+                context.OMSetBlendState(&self.blend_state, Some(&[0.0; 4]), 0xffffffff);
+            }
+            if changes.rasterizer {
+                context.RSSetState(&self.rasterizer_state);
+            }
+            if changes.depth_stencil {
+                context.OMSetDepthStencilState(&self.depth_stencil_state, 0);
+            }
+            if changes.topology {
+                context.IASetPrimitiveTopology(self.topology.to_d3d11());
+            }
+        }
+    }
+}
+
+/// Device level cache of blend, rasterizer and depth stencil states.
+///
+/// Shaders and input layouts of created pipelines are kept alive by the cache, see [`PipelineKey`].
+pub struct PipelineCache {
+    device: ID3D11Device,
+    blend_states: StateCache<BlendDesc, ID3D11BlendState>,
+    rasterizer_states: StateCache<RasterizerDesc, ID3D11RasterizerState>,
+    depth_stencil_states: StateCache<DepthStencilDesc, ID3D11DepthStencilState>,
+    vertex_shaders: StateCache<usize, ID3D11VertexShader>,
+    pixel_shaders: StateCache<usize, ID3D11PixelShader>,
+    input_layouts: StateCache<usize, ID3D11InputLayout>,
+}
+
+/// Id of a COM object in `cache`. The cache keeps a reference, so the pointer stays unique.
+fn intern<T: Interface + Clone>(cache: &mut StateCache<usize, T>, object: &T) -> StateId {
+    let result: std::result::Result<_, std::convert::Infallible> =
+        cache.get_or_create(&(object.as_raw() as usize), |_| Ok(object.clone()));
+    match result {
+        Ok((id, _)) => id,
+    }
+}
+
+impl PipelineCache {
+    pub fn new(device: &ID3D11Device) -> Self {
+        PipelineCache {
+            device: device.clone(),
+            blend_states: StateCache::new(),
+            rasterizer_states: StateCache::new(),
+            depth_stencil_states: StateCache::new(),
+            vertex_shaders: StateCache::new(),
+            pixel_shaders: StateCache::new(),
+            input_layouts: StateCache::new(),
+        }
+    }
+
+    pub fn blend_state(&mut self, desc: &BlendDesc) -> Result<(StateId, ID3D11BlendState)> {
+        let device = &self.device;
+        self.blend_states.get_or_create(desc, |desc| {
+            let mut state = None;
+            unsafe { device.CreateBlendState(&desc.to_d3d11(), Some(&mut state))? };
+            Ok(state.unwrap())
+        })
+    }
+
+    pub fn rasterizer_state(&mut self, desc: &RasterizerDesc) -> Result<(StateId, ID3D11RasterizerState)> {
+        let device = &self.device;
+        self.rasterizer_states.get_or_create(desc, |desc| {
+            let mut state = None;
+            unsafe { device.CreateRasterizerState(&desc.to_d3d11(), Some(&mut state))? };
+            Ok(state.unwrap())
+        })
+    }
+
+    pub fn depth_stencil_state(&mut self, desc: &DepthStencilDesc) -> Result<(StateId, ID3D11DepthStencilState)> {
+        let device = &self.device;
+        self.depth_stencil_states.get_or_create(desc, |desc| {
+            let mut state = None;
+            unsafe { device.CreateDepthStencilState(&desc.to_d3d11(), Some(&mut state))? };
+            Ok(state.unwrap())
+        })
+    }
+
+    pub fn create_pipeline(&mut self, desc: &PipelineDesc) -> Result<Pipeline> {
+        let (blend, blend_state) = self.blend_state(&desc.blend)?;
+        let (rasterizer, rasterizer_state) = self.rasterizer_state(&desc.rasterizer)?;
+        let (depth_stencil, depth_stencil_state) = self.depth_stencil_state(&desc.depth_stencil)?;
+
+        let key = PipelineKey {
+            vertex_shader: intern(&mut self.vertex_shaders, &desc.vertex_shader),
+            pixel_shader: desc.pixel_shader.as_ref().map(|shader| intern(&mut self.pixel_shaders, shader)),
+            input_layout: desc.input_layout.as_ref().map(|layout| intern(&mut self.input_layouts, layout)),
+            blend,
+            rasterizer,
+            depth_stencil,
+            topology: desc.topology,
+        };
+
+        Ok(Pipeline {
+            key,
+            vertex_shader: desc.vertex_shader.clone(),
+            pixel_shader: desc.pixel_shader.clone(),
+            input_layout: desc.input_layout.clone(),
+            blend_state,
+            rasterizer_state,
+            depth_stencil_state,
+            topology: desc.topology,
+        })
+    }
+
+    /// A copy of `pipeline` with a different depth stencil state.
+    pub fn with_depth_stencil(&mut self, pipeline: &Pipeline, desc: &DepthStencilDesc) -> Result<Pipeline> {
+        let (depth_stencil, depth_stencil_state) = self.depth_stencil_state(desc)?;
+
+        Ok(Pipeline {
+            key: PipelineKey { depth_stencil, ..pipeline.key },
+            depth_stencil_state,
+            ..pipeline.clone()
+        })
+    }
+
+    /// Number of distinct blend, rasterizer and depth stencil states created.
+    pub fn state_counts(&self) -> (usize, usize, usize) {
+        (self.blend_states.len(), self.rasterizer_states.len(), self.depth_stencil_states.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn create_counted<D>(created: &Cell<usize>) -> impl Fn(&D) -> std::result::Result<usize, ()> + '_ {
+        move |_| {
+            created.set(created.get() + 1);
+            Ok(created.get())
+        }
+    }
+
+    fn key(blend: StateId, rasterizer: StateId, depth_stencil: StateId) -> PipelineKey {
+        let mut shaders = StateCache::<usize, ()>::new();
+        let vertex_shader = shaders.get_or_create(&1, |_| Ok::<_, ()>(())).unwrap().0;
+        PipelineKey { vertex_shader, pixel_shader: None, input_layout: None, blend, rasterizer, depth_stencil, topology: Topology::TriangleList }
+    }
+
+    fn ids(count: usize) -> Vec<StateId> {
+        let mut cache = StateCache::<usize, ()>::new();
+        (0..count).map(|index| cache.get_or_create(&index, |_| Ok::<_, ()>(())).unwrap().0).collect()
+    }
+
+    #[test]
+    fn equal_descs_create_one_state() {
+        let created = Cell::new(0);
+        let mut cache = StateCache::new();
+        let create = create_counted(&created);
+
+        let (opaque, first) = cache.get_or_create(&BlendDesc::OPAQUE, &create).unwrap();
+        let (again, second) = cache.get_or_create(&BlendDesc { ..BlendDesc::OPAQUE }, &create).unwrap();
+        let (masked, _) = cache.get_or_create(&BlendDesc { write_mask: 0x7, ..BlendDesc::OPAQUE }, &create).unwrap();
+
+        assert_eq!((opaque, first), (again, second));
+        assert_ne!(opaque, masked);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(created.get(), 2);
+    }
+
+    #[test]
+    fn rasterizer_descs_hash_floats_by_bits() {
+        let mut cache = StateCache::new();
+        let created = Cell::new(0);
+        let create = create_counted(&created);
+        let biased = RasterizerDesc { slope_scaled_depth_bias: 1.5, ..RasterizerDesc::DEFAULT };
+
+        let (default, _) = cache.get_or_create(&RasterizerDesc::DEFAULT, &create).unwrap();
+        let (negative_zero, _) = cache.get_or_create(&RasterizerDesc { depth_bias_clamp: -0.0, ..RasterizerDesc::DEFAULT }, &create).unwrap();
+        let (first, _) = cache.get_or_create(&biased, &create).unwrap();
+        let (second, _) = cache.get_or_create(&RasterizerDesc { slope_scaled_depth_bias: 1.5, ..RasterizerDesc::DEFAULT }, &create).unwrap();
+
+        assert_ne!(default, negative_zero);
+        assert_eq!(first, second);
+        assert_eq!(created.get(), 3);
+        assert_eq!(RasterizerDesc::NO_CULL, RasterizerDesc { cull: CullMode::None, ..RasterizerDesc::DEFAULT });
+        assert_ne!(RasterizerDesc::NO_CULL, RasterizerDesc::SCISSOR);
+    }
+
+    #[test]
+    fn failed_creation_is_not_cached() {
+        let mut cache = StateCache::<DepthStencilDesc, u32>::new();
+
+        assert_eq!(cache.get_or_create(&DepthStencilDesc::DEFAULT, |_| Err("device removed")), Err("device removed"));
+        assert_eq!(cache.len(), 0);
+        assert!(cache.get_or_create(&DepthStencilDesc::DEFAULT, |_| Ok::<_, ()>(7)).is_ok());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn ids_are_not_reused_after_clear() {
+        let mut cache = StateCache::new();
+        let (before, _) = cache.get_or_create(&DepthStencilDesc::DEFAULT, |_| Ok::<_, ()>(1)).unwrap();
+
+        cache.clear();
+        let (after, state) = cache.get_or_create(&DepthStencilDesc::DEFAULT, |_| Ok::<_, ()>(2)).unwrap();
+
+        assert_ne!(before, after);
+        assert_eq!(state, 2);
+    }
+
+    #[test]
+    fn ids_are_unique_across_caches() {
+        let first = ids(4);
+        let second = ids(4);
+
+        for id in &first {
+            assert!(!second.contains(id));
+        }
+    }
+
+    #[test]
+    fn rebinding_the_same_key_is_skipped() {
+        let [blend, rasterizer, depth_stencil]: [StateId; 3] = ids(3).try_into().unwrap();
+        let key = key(blend, rasterizer, depth_stencil);
+        let mut tracker = BindTracker::new();
+
+        let first = tracker.changes(&key);
+        let second = tracker.changes(&key);
+
+        assert!(first.vertex_shader && first.pixel_shader && first.input_layout && first.topology);
+        assert!(!second.any());
+        assert_eq!(tracker.stats(), (1, 1));
+    }
+
+    #[test]
+    fn only_changed_parts_are_bound() {
+        let [blend, rasterizer, depth_stencil, read_only]: [StateId; 4] = ids(4).try_into().unwrap();
+        let key = key(blend, rasterizer, depth_stencil);
+        let mut tracker = BindTracker::new();
+        tracker.changes(&key);
+
+        let changes = tracker.changes(&PipelineKey { depth_stencil: read_only, topology: Topology::LineList, ..key });
+
+        assert_eq!(changes, PipelineChanges { depth_stencil: true, topology: true, ..PipelineChanges::default() });
+        assert_eq!(tracker.stats(), (2, 0));
+    }
+
+    #[test]
+    fn invalidate_forces_a_full_bind() {
+        let [blend, rasterizer, depth_stencil]: [StateId; 3] = ids(3).try_into().unwrap();
+        let key = key(blend, rasterizer, depth_stencil);
+        let mut tracker = BindTracker::new();
+        tracker.changes(&key);
+
+        tracker.invalidate();
+        let changes = tracker.changes(&key);
+
+        assert!(changes.blend && changes.rasterizer && changes.depth_stencil && changes.vertex_shader);
+        assert_eq!(tracker.stats(), (2, 0));
+    }
+}
@@ -0,0 +1,314 @@
+//! Declarative sampler descriptions and a device level cache of the sampler states created from them.
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use windows::{core::*, Win32::Graphics::Direct3D11::*};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SamplerFilter {
+    Point,
+    /// Linear within a level, nearest mip level.
+    Bilinear,
+    Trilinear,
+    /// Uses `SamplerDesc::max_anisotropy` samples.
+    Anisotropic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressMode {
+    Wrap,
+    Mirror,
+    Clamp,
+    Border,
+    MirrorOnce,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ComparisonFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+/// [`SamplerDesc`] fields in declaration order, floats as bits.
+type SamplerKey = (SamplerFilter, [AddressMode; 3], u32, u32, Option<ComparisonFunc>, [u32; 4], u32, u32);
+
+/// Everything that goes into a `D3D11_SAMPLER_DESC`, as a hashable value.
+///
+/// Floats are compared by their bits, so `0.0` and `-0.0` are different descriptions.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDesc {
+    pub filter: SamplerFilter,
+    pub address_u: AddressMode,
+    pub address_v: AddressMode,
+    pub address_w: AddressMode,
+    pub mip_lod_bias: f32,
+    /// Clamped to 1..=16, ignored unless the filter is anisotropic.
+    pub max_anisotropy: u32,
+    /// Makes this a comparison sampler, as used with `SampleCmp` for shadow maps.
+    pub comparison: Option<ComparisonFunc>,
+    pub border_color: [f32; 4],
+    pub min_lod: f32,
+    pub max_lod: f32,
+}
+
+impl SamplerDesc {
+    pub const LINEAR_WRAP: SamplerDesc = SamplerDesc::new(SamplerFilter::Trilinear, AddressMode::Wrap);
+    pub const LINEAR_CLAMP: SamplerDesc = SamplerDesc::new(SamplerFilter::Trilinear, AddressMode::Clamp);
+    pub const LINEAR_MIRROR: SamplerDesc = SamplerDesc::new(SamplerFilter::Trilinear, AddressMode::Mirror);
+    pub const POINT_WRAP: SamplerDesc = SamplerDesc::new(SamplerFilter::Point, AddressMode::Wrap);
+    pub const POINT_CLAMP: SamplerDesc = SamplerDesc::new(SamplerFilter::Point, AddressMode::Clamp);
+    pub const ANISOTROPIC_WRAP: SamplerDesc = SamplerDesc::new(SamplerFilter::Anisotropic, AddressMode::Wrap).with_anisotropy(16);
+    pub const ANISOTROPIC_CLAMP: SamplerDesc = SamplerDesc::new(SamplerFilter::Anisotropic, AddressMode::Clamp).with_anisotropy(16);
+    /// Hardware PCF for shadow maps, everything outside the map counts as lit.
+    pub const SHADOW: SamplerDesc = SamplerDesc::new(SamplerFilter::Bilinear, AddressMode::Border)
+        .with_comparison(ComparisonFunc::LessEqual)
+        .with_border_color([1.0; 4]);
+
+    /// A sampler using the same addressing on every axis and the whole mip chain.
+    pub const fn new(filter: SamplerFilter, address: AddressMode) -> Self {
+        SamplerDesc {
+            filter,
+            address_u: address,
+            address_v: address,
+            address_w: address,
+            mip_lod_bias: 0.0,
+            max_anisotropy: 1,
+            comparison: None,
+            border_color: [0.0; 4],
+            min_lod: 0.0,
+            max_lod: f32::MAX,
+        }
+    }
+
+    pub const fn with_anisotropy(mut self, max_anisotropy: u32) -> Self {
+        self.max_anisotropy = max_anisotropy;
+        self
+    }
+
+    pub const fn with_comparison(mut self, comparison: ComparisonFunc) -> Self {
+        self.comparison = Some(comparison);
+        self
+    }
+
+    pub const fn with_border_color(mut self, border_color: [f32; 4]) -> Self {
+        self.border_color = border_color;
+        self
+    }
+
+    pub const fn with_lod_range(mut self, min_lod: f32, max_lod: f32) -> Self {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+        self
+    }
+
+    pub const fn with_lod_bias(mut self, mip_lod_bias: f32) -> Self {
+        self.mip_lod_bias = mip_lod_bias;
+        self
+    }
+
+    /// The anisotropy D3D11 is given, 1 for the filters that don't use it.
+    fn anisotropy(&self) -> u32 {
+        match self.filter {
+            SamplerFilter::Anisotropic => self.max_anisotropy.clamp(1, 16),
+            _ => 1,
+        }
+    }
+
+    /// The fields that decide equality, with floats as bits.
+    fn key(&self) -> SamplerKey {
+        (
+            self.filter,
+            [self.address_u, self.address_v, self.address_w],
+            self.mip_lod_bias.to_bits(),
+            self.anisotropy(),
+            self.comparison,
+            self.border_color.map(f32::to_bits),
+            self.min_lod.to_bits(),
+            self.max_lod.to_bits(),
+        )
+    }
+
+    pub fn to_d3d11(self) -> D3D11_SAMPLER_DESC {
+        let filter = match (self.filter, self.comparison.is_some()) {
+            (SamplerFilter::Point, false) => D3D11_FILTER_MIN_MAG_MIP_POINT,
+            (SamplerFilter::Bilinear, false) => D3D11_FILTER_MIN_MAG_LINEAR_MIP_POINT,
+            (SamplerFilter::Trilinear, false) => D3D11_FILTER_MIN_MAG_MIP_LINEAR,
+            (SamplerFilter::Anisotropic, false) => D3D11_FILTER_ANISOTROPIC,
+            (SamplerFilter::Point, true) => D3D11_FILTER_COMPARISON_MIN_MAG_MIP_POINT,
+            (SamplerFilter::Bilinear, true) => D3D11_FILTER_COMPARISON_MIN_MAG_LINEAR_MIP_POINT,
+            (SamplerFilter::Trilinear, true) => D3D11_FILTER_COMPARISON_MIN_MAG_MIP_LINEAR,
+            (SamplerFilter::Anisotropic, true) => D3D11_FILTER_COMPARISON_ANISOTROPIC,
+        };
+
+        D3D11_SAMPLER_DESC {
+            Filter: filter,
+            AddressU: address_mode(self.address_u),
+            AddressV: address_mode(self.address_v),
+            AddressW: address_mode(self.address_w),
+            MipLODBias: self.mip_lod_bias,
+            MaxAnisotropy: self.anisotropy(),
+            ComparisonFunc: comparison_func(self.comparison.unwrap_or(ComparisonFunc::Never)),
+            BorderColor: self.border_color,
+            MinLOD: self.min_lod,
+            MaxLOD: self.max_lod,
+        }
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+fn address_mode(mode: AddressMode) -> D3D11_TEXTURE_ADDRESS_MODE {
+    match mode {
+        AddressMode::Wrap => D3D11_TEXTURE_ADDRESS_WRAP,
+        AddressMode::Mirror => D3D11_TEXTURE_ADDRESS_MIRROR,
+        AddressMode::Clamp => D3D11_TEXTURE_ADDRESS_CLAMP,
+        AddressMode::Border => D3D11_TEXTURE_ADDRESS_BORDER,
+        AddressMode::MirrorOnce => D3D11_TEXTURE_ADDRESS_MIRROR_ONCE,
+    }
+}
+
+pub fn comparison_func(func: ComparisonFunc) -> D3D11_COMPARISON_FUNC {
+    match func {
+        ComparisonFunc::Never => D3D11_COMPARISON_NEVER,
+        ComparisonFunc::Less => D3D11_COMPARISON_LESS,
+        ComparisonFunc::Equal => D3D11_COMPARISON_EQUAL,
+        ComparisonFunc::LessEqual => D3D11_COMPARISON_LESS_EQUAL,
+        ComparisonFunc::Greater => D3D11_COMPARISON_GREATER,
+        ComparisonFunc::NotEqual => D3D11_COMPARISON_NOT_EQUAL,
+        ComparisonFunc::GreaterEqual => D3D11_COMPARISON_GREATER_EQUAL,
+        ComparisonFunc::Always => D3D11_COMPARISON_ALWAYS,
+    }
+}
+
+/// Creates each distinct [`SamplerDesc`] once per device and hands out the shared state.
+pub struct SamplerCache {
+    device: ID3D11Device,
+    samplers: HashMap<SamplerDesc, ID3D11SamplerState>,
+}
+
+impl SamplerCache {
+    pub fn new(device: &ID3D11Device) -> Self {
+        SamplerCache { device: device.clone(), samplers: HashMap::new() }
+    }
+
+    pub fn get(&mut self, desc: &SamplerDesc) -> Result<ID3D11SamplerState> {
+        if let Some(sampler) = self.samplers.get(desc) {
+            return Ok(sampler.clone());
+        }
+
+        let mut sampler = None;
+        unsafe { self.device.CreateSamplerState(&desc.to_d3d11(), Some(&mut sampler))? };
+        let sampler = sampler.unwrap();
+
+        self.samplers.insert(*desc, sampler.clone());
+        Ok(sampler)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+
+    /// Releases the cached states, callers holding one keep it alive.
+    pub fn clear(&mut self) {
+        self.samplers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn presets_are_distinct() {
+        let presets = [
+            SamplerDesc::LINEAR_WRAP,
+            SamplerDesc::LINEAR_CLAMP,
+            SamplerDesc::LINEAR_MIRROR,
+            SamplerDesc::POINT_WRAP,
+            SamplerDesc::POINT_CLAMP,
+            SamplerDesc::ANISOTROPIC_WRAP,
+            SamplerDesc::ANISOTROPIC_CLAMP,
+            SamplerDesc::SHADOW,
+        ];
+
+        assert_eq!(presets.iter().collect::<HashSet<_>>().len(), presets.len());
+    }
+
+    #[test]
+    fn equal_descriptions_share_a_key() {
+        let built = SamplerDesc::new(SamplerFilter::Trilinear, AddressMode::Wrap).with_lod_range(0.0, f32::MAX);
+        assert_eq!(built, SamplerDesc::LINEAR_WRAP);
+        assert_eq!(built.key(), SamplerDesc::LINEAR_WRAP.key());
+        assert_eq!([built, SamplerDesc::LINEAR_WRAP].iter().collect::<HashSet<_>>().len(), 1);
+
+        assert_ne!(SamplerDesc::LINEAR_WRAP.with_lod_bias(-0.5), SamplerDesc::LINEAR_WRAP);
+        assert_ne!(SamplerDesc::LINEAR_WRAP.with_lod_bias(-0.0), SamplerDesc::LINEAR_WRAP);
+        assert_ne!(SamplerDesc::LINEAR_WRAP.with_comparison(ComparisonFunc::Less), SamplerDesc::LINEAR_WRAP);
+    }
+
+    #[test]
+    fn anisotropy_is_clamped() {
+        let anisotropic = SamplerDesc::new(SamplerFilter::Anisotropic, AddressMode::Wrap);
+
+        assert_eq!(anisotropic.with_anisotropy(0).to_d3d11().MaxAnisotropy, 1);
+        assert_eq!(anisotropic.with_anisotropy(8).to_d3d11().MaxAnisotropy, 8);
+        assert_eq!(anisotropic.with_anisotropy(64).to_d3d11().MaxAnisotropy, 16);
+        assert_eq!(anisotropic.with_anisotropy(64), SamplerDesc::ANISOTROPIC_WRAP);
+        assert_eq!(anisotropic.with_anisotropy(0), anisotropic.with_anisotropy(1));
+        assert_ne!(anisotropic.with_anisotropy(4), anisotropic.with_anisotropy(8));
+    }
+
+    #[test]
+    fn anisotropy_is_ignored_without_the_anisotropic_filter() {
+        let trilinear = SamplerDesc::LINEAR_WRAP.with_anisotropy(16);
+
+        assert_eq!(trilinear, SamplerDesc::LINEAR_WRAP);
+        assert_eq!(trilinear.to_d3d11().MaxAnisotropy, 1);
+        assert_eq!(SamplerDesc::SHADOW.with_anisotropy(8), SamplerDesc::SHADOW);
+    }
+
+    #[test]
+    fn d3d11_filter_and_comparison() {
+        let desc = |filter| SamplerDesc::new(filter, AddressMode::Clamp);
+
+        assert_eq!(desc(SamplerFilter::Point).to_d3d11().Filter, D3D11_FILTER_MIN_MAG_MIP_POINT);
+        assert_eq!(desc(SamplerFilter::Bilinear).to_d3d11().Filter, D3D11_FILTER_MIN_MAG_LINEAR_MIP_POINT);
+        assert_eq!(desc(SamplerFilter::Trilinear).to_d3d11().Filter, D3D11_FILTER_MIN_MAG_MIP_LINEAR);
+        assert_eq!(desc(SamplerFilter::Anisotropic).to_d3d11().Filter, D3D11_FILTER_ANISOTROPIC);
+        assert_eq!(desc(SamplerFilter::Point).to_d3d11().ComparisonFunc, D3D11_COMPARISON_NEVER);
+
+        let shadow = SamplerDesc::SHADOW.to_d3d11();
+        assert_eq!(shadow.Filter, D3D11_FILTER_COMPARISON_MIN_MAG_LINEAR_MIP_POINT);
+        assert_eq!(shadow.ComparisonFunc, D3D11_COMPARISON_LESS_EQUAL);
+        assert_eq!(shadow.AddressU, D3D11_TEXTURE_ADDRESS_BORDER);
+        assert_eq!(shadow.BorderColor, [1.0; 4]);
+
+        let compared = desc(SamplerFilter::Anisotropic).with_comparison(ComparisonFunc::Greater).to_d3d11();
+        assert_eq!(compared.Filter, D3D11_FILTER_COMPARISON_ANISOTROPIC);
+        assert_eq!(compared.ComparisonFunc, D3D11_COMPARISON_GREATER);
+    }
+}
@@ -0,0 +1,834 @@
+//! Swap chain ownership: presentation settings, output color space, back buffer views, resizing, minimized windows and resize notifications.
+
+use windows::{
+    core::*, Win32::Foundation::*, Win32::Graphics::Direct3D11::*, Win32::Graphics::Dxgi::Common::*,
+    Win32::Graphics::Dxgi::*, Win32::System::Threading::*,
+};
+
+use crate::color_space::{negotiate, OutputColorSpace, SCRGB_REFERENCE_NITS};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PresentConfig {
+    /// Vertical blanks to wait for per present, 0 presents immediately.
+    pub sync_interval: u32,
+    /// Lets immediate presents tear on variable refresh rate displays, when the system supports it.
+    pub allow_tearing: bool,
+    pub buffer_count: u32,
+    /// Frames the CPU may queue ahead of the GPU, enforced through the frame latency waitable object.
+    pub max_frame_latency: u32,
+    /// Used when the display is in HDR mode, otherwise the output falls back to sRGB.
+    pub color_space: OutputColorSpace,
+}
+
+impl Default for PresentConfig {
+    fn default() -> Self {
+        PresentConfig {
+            sync_interval: 1,
+            allow_tearing: false,
+            buffer_count: 2,
+            max_frame_latency: 1,
+            color_space: OutputColorSpace::Srgb,
+        }
+    }
+}
+
+/// How much of the swap chain a configuration change invalidates, from least to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigChange {
+    None,
+    /// Only the arguments of each present change.
+    Present,
+    FrameLatency,
+    /// The buffers have to be resized or change format, which needs the back buffer views released.
+    Buffers,
+    /// The creation flags differ, the swap chain has to be created again.
+    Recreate,
+}
+
+impl PresentConfig {
+    pub const MAX_SYNC_INTERVAL: u32 = 4;
+    pub const MIN_BUFFER_COUNT: u32 = 2;
+    pub const MAX_BUFFER_COUNT: u32 = 16;
+    pub const MAX_FRAME_LATENCY: u32 = 16;
+
+    /// The closest configuration the swap chain accepts.
+    pub fn clamped(&self) -> Self {
+        PresentConfig {
+            sync_interval: self.sync_interval.min(Self::MAX_SYNC_INTERVAL),
+            allow_tearing: self.allow_tearing,
+            buffer_count: self.buffer_count.clamp(Self::MIN_BUFFER_COUNT, Self::MAX_BUFFER_COUNT),
+            max_frame_latency: self.max_frame_latency.clamp(1, Self::MAX_FRAME_LATENCY),
+            color_space: self.color_space,
+        }
+    }
+
+    pub fn uses_tearing(&self, tearing_supported: bool) -> bool {
+        self.allow_tearing && tearing_supported
+    }
+
+    /// `DXGI_SWAP_CHAIN_FLAG` bits the swap chain is created and resized with.
+    pub fn swap_chain_flags(&self, tearing_supported: bool) -> u32 {
+        let tearing = if self.uses_tearing(tearing_supported) { DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING.0 as u32 } else { 0 };
+        DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT.0 as u32 | tearing
+    }
+
+    /// Sync interval and `DXGI_PRESENT` flags for each present, tearing is only allowed without vsync.
+    pub fn present_arguments(&self, tearing_supported: bool) -> (u32, u32) {
+        let flags = if self.sync_interval == 0 && self.uses_tearing(tearing_supported) { DXGI_PRESENT_ALLOW_TEARING } else { 0 };
+        (self.sync_interval, flags)
+    }
+
+    /// What has to be redone to go from `old` to this configuration.
+    pub fn change_from(&self, old: &PresentConfig, tearing_supported: bool) -> ConfigChange {
+        if self.swap_chain_flags(tearing_supported) != old.swap_chain_flags(tearing_supported) {
+            ConfigChange::Recreate
+        } else if self.buffer_count != old.buffer_count || self.color_space != old.color_space {
+            ConfigChange::Buffers
+        } else if self.max_frame_latency != old.max_frame_latency {
+            ConfigChange::FrameLatency
+        } else if self != old {
+            ConfigChange::Present
+        } else {
+            ConfigChange::None
+        }
+    }
+}
+
+/// What the [`SwapChain`] needs from the presentation API.
+pub trait SwapChainBackend {
+    /// The view rendering goes into, recreated after every resize.
+    type Target;
+
+    /// Resizes the buffers, no target may be alive at this point.
+    fn resize_buffers(&mut self, width: u32, height: u32) -> Result<()>;
+    fn create_target(&mut self) -> Result<Self::Target>;
+    /// Applies `config`, no target is alive when `change` is [`ConfigChange::Buffers`] or more.
+    fn configure(&mut self, config: &PresentConfig, change: ConfigChange, width: u32, height: u32) -> Result<()>;
+    fn tearing_supported(&self) -> bool;
+    /// The color space negotiated with the display, which the back buffer has to be encoded for.
+    fn color_space(&self) -> OutputColorSpace;
+    /// Peak luminance of the display in nits.
+    fn display_max_nits(&self) -> f32;
+    /// Blocks until a new frame may be queued.
+    fn wait_for_frame(&mut self) {}
+    fn present(&mut self, sync_interval: u32, flags: u32) -> Result<()>;
+}
+
+pub fn back_buffer_format(color_space: OutputColorSpace) -> DXGI_FORMAT {
+    match color_space {
+        OutputColorSpace::Srgb => DXGI_FORMAT_B8G8R8A8_UNORM,
+        OutputColorSpace::Hdr10 => DXGI_FORMAT_R10G10B10A2_UNORM,
+        OutputColorSpace::ScRgb => DXGI_FORMAT_R16G16B16A16_FLOAT,
+    }
+}
+
+pub fn dxgi_color_space(color_space: OutputColorSpace) -> DXGI_COLOR_SPACE_TYPE {
+    match color_space {
+        OutputColorSpace::Srgb => DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
+        OutputColorSpace::Hdr10 => DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+        OutputColorSpace::ScRgb => DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709,
+    }
+}
+
+/// Presents to a window through DXGI with a flip model swap chain.
+pub struct DxgiSwapChain {
+    /// `None` after [`DxgiSwapChain::release`], or when neither the new nor the old configuration
+    /// could be recreated.
+    swap_chain: Option<IDXGISwapChain3>,
+    factory: IDXGIFactory2,
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    hwnd: HWND,
+    flags: u32,
+    frame_latency_waitable: HANDLE,
+    tearing_supported: bool,
+    config: PresentConfig,
+    color_space: OutputColorSpace,
+    display_max_nits: f32,
+}
+
+impl DxgiSwapChain {
+    pub fn new(
+        factory: &IDXGIFactory2,
+        device: &ID3D11Device,
+        context: &ID3D11DeviceContext,
+        hwnd: HWND,
+        width: u32,
+        height: u32,
+        config: &PresentConfig,
+    ) -> Result<Self> {
+        let tearing_supported = Self::check_tearing_support(factory);
+        let (swap_chain, flags, frame_latency_waitable) =
+            Self::create_swap_chain(factory, device, hwnd, width, height, config, tearing_supported)?;
+
+        let mut swap_chain = DxgiSwapChain {
+            swap_chain: Some(swap_chain),
+            factory: factory.clone(),
+            device: device.clone(),
+            context: context.clone(),
+            hwnd,
+            flags,
+            frame_latency_waitable,
+            tearing_supported,
+            config: *config,
+            color_space: OutputColorSpace::Srgb,
+            display_max_nits: SCRGB_REFERENCE_NITS,
+        };
+        swap_chain.apply_color_space(config.buffer_count, width, height)?;
+        Ok(swap_chain)
+    }
+
+    /// Tearing needs DXGI 1.5 and a driver that supports it.
+    pub fn check_tearing_support(factory: &IDXGIFactory2) -> bool {
+        let Ok(factory) = factory.cast::<IDXGIFactory5>() else {
+            return false;
+        };
+
+        let mut allow_tearing = BOOL(0);
+        let result = unsafe {
+            factory.CheckFeatureSupport(
+                DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+                &mut allow_tearing as *mut BOOL as *mut _,
+                std::mem::size_of::<BOOL>() as u32,
+            )
+        };
+        result.is_ok() && allow_tearing.as_bool()
+    }
+
+    fn create_swap_chain(
+        factory: &IDXGIFactory2,
+        device: &ID3D11Device,
+        hwnd: HWND,
+        width: u32,
+        height: u32,
+        config: &PresentConfig,
+        tearing_supported: bool,
+    ) -> Result<(IDXGISwapChain3, u32, HANDLE)> {
+        let flags = config.swap_chain_flags(tearing_supported);
+
+        let swap_chain_descriptor = DXGI_SWAP_CHAIN_DESC1 {
+            Width: width,
+            Height: height,
+            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+            BufferCount: config.buffer_count,
+            SwapEffect: DXGI_SWAP_EFFECT_FLIP_DISCARD,
+            Flags: flags,
+            ..Default::default()
+        };
+
+        let swap_chain_fullscreen_descriptor = DXGI_SWAP_CHAIN_FULLSCREEN_DESC {
+            Windowed: BOOL(1),
+            ..Default::default()
+        };
+
+        let swap_chain: IDXGISwapChain3 = unsafe {
+            factory.CreateSwapChainForHwnd(
+                device,
+                hwnd,
+                &swap_chain_descriptor,
+                Some(&swap_chain_fullscreen_descriptor),
+                None,
+            )?
+        }.cast()?;
+
+        unsafe { swap_chain.SetMaximumFrameLatency(config.max_frame_latency)? };
+        let frame_latency_waitable = unsafe { swap_chain.GetFrameLatencyWaitableObject() };
+
+        Ok((swap_chain, flags, frame_latency_waitable))
+    }
+
+    /// Releases the swap chain so another one can be created for the window, after the device was lost.
+    /// Nothing but dropping may be done with it afterwards.
+    pub fn release(&mut self) {
+        if !self.frame_latency_waitable.is_invalid() {
+            unsafe { let _ = CloseHandle(self.frame_latency_waitable); }
+            self.frame_latency_waitable = HANDLE::default();
+        }
+        self.swap_chain = None;
+    }
+
+    /// Fails once the swap chain was released or lost while being recreated.
+    pub fn swap_chain(&self) -> Result<&IDXGISwapChain3> {
+        self.swap_chain.as_ref().ok_or_else(|| DXGI_ERROR_INVALID_CALL.into())
+    }
+
+    /// Whether the output showing the window is in HDR mode, and its peak luminance.
+    fn display_color_info(&self) -> (bool, f32) {
+        let desc = self.swap_chain()
+            .and_then(|swap_chain| unsafe { swap_chain.GetContainingOutput() })
+            .and_then(|output| output.cast::<IDXGIOutput6>())
+            .and_then(|output| {
+                let mut desc = DXGI_OUTPUT_DESC1::default();
+                unsafe { output.GetDesc1(&mut desc)? };
+                Ok(desc)
+            });
+
+        match desc {
+            Ok(desc) => (desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020, desc.MaxLuminance),
+            Err(_) => (false, SCRGB_REFERENCE_NITS),
+        }
+    }
+
+    fn set_color_space(&self, color_space: OutputColorSpace) -> bool {
+        let Ok(swap_chain) = self.swap_chain() else {
+            return false;
+        };
+        let color_space = dxgi_color_space(color_space);
+        let support = unsafe { swap_chain.CheckColorSpaceSupport(color_space) }.unwrap_or(0);
+
+        support & DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT.0 as u32 != 0
+            && unsafe { swap_chain.SetColorSpace1(color_space) }.is_ok()
+    }
+
+    /// Resizes the buffers to the format of the negotiated color space, falling back to sRGB when it is refused.
+    fn apply_color_space(&mut self, buffer_count: u32, width: u32, height: u32) -> Result<()> {
+        let (display_hdr, display_max_nits) = self.display_color_info();
+        let mut color_space = negotiate(self.config.color_space, display_hdr);
+
+        unsafe { self.swap_chain()?.ResizeBuffers(buffer_count, width, height, back_buffer_format(color_space), self.flags)? };
+        if !self.set_color_space(color_space) && color_space != OutputColorSpace::Srgb {
+            color_space = OutputColorSpace::Srgb;
+            unsafe { self.swap_chain()?.ResizeBuffers(buffer_count, width, height, back_buffer_format(color_space), self.flags)? };
+            self.set_color_space(color_space);
+        }
+
+        self.color_space = color_space;
+        self.display_max_nits = display_max_nits;
+        Ok(())
+    }
+
+    /// Replaces the swap chain with one created for `config`, applying its color space.
+    fn recreate(&mut self, config: &PresentConfig, width: u32, height: u32) -> Result<()> {
+        if !self.frame_latency_waitable.is_invalid() {
+            unsafe { CloseHandle(self.frame_latency_waitable)? };
+            self.frame_latency_waitable = HANDLE::default();
+        }
+
+        // A window can only have one flip model swap chain, the old one is released first.
+        self.swap_chain = None;
+        let (swap_chain, flags, frame_latency_waitable) =
+            Self::create_swap_chain(&self.factory, &self.device, self.hwnd, width, height, config, self.tearing_supported)?;
+        self.swap_chain = Some(swap_chain);
+        self.flags = flags;
+        self.frame_latency_waitable = frame_latency_waitable;
+        self.config = *config;
+        self.apply_color_space(config.buffer_count, width, height)
+    }
+
+    /// The context may still reference the back buffer, which makes resizing or releasing it fail.
+    fn unbind_back_buffer(&self) {
+        unsafe {
+            self.context.OMSetRenderTargets(None, None);
+            self.context.Flush();
+        }
+    }
+}
+
+impl Drop for DxgiSwapChain {
+    fn drop(&mut self) {
+        if !self.frame_latency_waitable.is_invalid() {
+            unsafe { let _ = CloseHandle(self.frame_latency_waitable); }
+        }
+    }
+}
+
+impl SwapChainBackend for DxgiSwapChain {
+    type Target = ID3D11RenderTargetView;
+
+    fn resize_buffers(&mut self, width: u32, height: u32) -> Result<()> {
+        self.unbind_back_buffer();
+        // The window may have moved to a display with a different HDR mode.
+        self.apply_color_space(0, width, height)
+    }
+
+    fn configure(&mut self, config: &PresentConfig, change: ConfigChange, width: u32, height: u32) -> Result<()> {
+        match change {
+            ConfigChange::None | ConfigChange::Present => (),
+            ConfigChange::FrameLatency => unsafe { self.swap_chain()?.SetMaximumFrameLatency(config.max_frame_latency)? },
+            ConfigChange::Buffers => {
+                self.unbind_back_buffer();
+                let old = std::mem::replace(&mut self.config, *config);
+                if let Err(error) = self.apply_color_space(config.buffer_count, width, height) {
+                    self.config = old;
+                    return Err(error);
+                }
+                unsafe { self.swap_chain()?.SetMaximumFrameLatency(config.max_frame_latency)? };
+            }
+            ConfigChange::Recreate => {
+                self.unbind_back_buffer();
+                let old = self.config;
+                if let Err(error) = self.recreate(config, width, height) {
+                    // Go back to a swap chain with the old settings, the caller still gets the error.
+                    // If even that fails every later call reports that the swap chain is gone.
+                    log::warn!("Recreating the swap chain failed, restoring the previous settings: {}", error);
+                    if let Err(restore_error) = self.recreate(&old, width, height) {
+                        log::error!("Restoring the swap chain failed: {}", restore_error);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        self.config = *config;
+        Ok(())
+    }
+
+    fn tearing_supported(&self) -> bool {
+        self.tearing_supported
+    }
+
+    fn color_space(&self) -> OutputColorSpace {
+        self.color_space
+    }
+
+    fn display_max_nits(&self) -> f32 {
+        self.display_max_nits
+    }
+
+    fn wait_for_frame(&mut self) {
+        unsafe { WaitForSingleObjectEx(self.frame_latency_waitable, 1000, true) };
+    }
+
+    fn create_target(&mut self) -> Result<Self::Target> {
+        let back_buffer: ID3D11Resource = unsafe { self.swap_chain()?.GetBuffer(0)? };
+
+        let mut render_target = None;
+        unsafe { self.device.CreateRenderTargetView(&back_buffer, None, Some(&mut render_target))? };
+        Ok(render_target.unwrap())
+    }
+
+    fn present(&mut self, sync_interval: u32, flags: u32) -> Result<()> {
+        unsafe { self.swap_chain()?.Present(sync_interval, flags).ok() }
+    }
+}
+
+/// A backend without a window or device, which only keeps count of what was asked of it.
+#[derive(Debug, Default)]
+pub struct HeadlessSwapChain {
+    pub width: u32,
+    pub height: u32,
+    pub resizes: u32,
+    pub presents: u32,
+    /// Incremented for every created target.
+    pub generation: u32,
+    pub config: PresentConfig,
+    pub tearing_supported: bool,
+    /// Pretends the display is in HDR mode with this peak luminance.
+    pub hdr_display_nits: Option<f32>,
+    /// The changes passed to `configure`, in order.
+    pub changes: Vec<ConfigChange>,
+    /// Sync interval and flags of the last present.
+    pub last_present: (u32, u32),
+    /// Makes `configure` fail and keep the old configuration, like a swap chain that could not be recreated.
+    pub fail_configure: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeadlessTarget {
+    pub width: u32,
+    pub height: u32,
+    pub generation: u32,
+}
+
+impl SwapChainBackend for HeadlessSwapChain {
+    type Target = HeadlessTarget;
+
+    fn resize_buffers(&mut self, width: u32, height: u32) -> Result<()> {
+        self.width = width;
+        self.height = height;
+        self.resizes += 1;
+        Ok(())
+    }
+
+    fn create_target(&mut self) -> Result<Self::Target> {
+        self.generation += 1;
+        Ok(HeadlessTarget { width: self.width, height: self.height, generation: self.generation })
+    }
+
+    fn configure(&mut self, config: &PresentConfig, change: ConfigChange, _width: u32, _height: u32) -> Result<()> {
+        if self.fail_configure {
+            return Err(DXGI_ERROR_INVALID_CALL.into());
+        }
+        self.config = *config;
+        self.changes.push(change);
+        Ok(())
+    }
+
+    fn tearing_supported(&self) -> bool {
+        self.tearing_supported
+    }
+
+    fn color_space(&self) -> OutputColorSpace {
+        negotiate(self.config.color_space, self.hdr_display_nits.is_some())
+    }
+
+    fn display_max_nits(&self) -> f32 {
+        self.hdr_display_nits.unwrap_or(SCRGB_REFERENCE_NITS)
+    }
+
+    fn present(&mut self, sync_interval: u32, flags: u32) -> Result<()> {
+        self.presents += 1;
+        self.last_present = (sync_interval, flags);
+        Ok(())
+    }
+}
+
+/// Handle returned by [`SwapChain::add_resize_listener`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ListenerId(u32);
+
+type ResizeListener = Box<dyn FnMut(u32, u32)>;
+
+pub struct SwapChain<B: SwapChainBackend> {
+    pub backend: B,
+    target: Option<B::Target>,
+    config: PresentConfig,
+    width: u32,
+    height: u32,
+    minimized: bool,
+    listeners: Vec<(ListenerId, ResizeListener)>,
+    next_listener: u32,
+}
+
+impl<B: SwapChainBackend> SwapChain<B> {
+    /// Takes a backend created with `config` whose buffers are `width` by `height` already.
+    pub fn new(mut backend: B, width: u32, height: u32, config: PresentConfig) -> Result<Self> {
+        let target = backend.create_target()?;
+
+        Ok(SwapChain {
+            backend,
+            target: Some(target),
+            config,
+            width,
+            height,
+            minimized: false,
+            listeners: Vec::new(),
+            next_listener: 0,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// A minimized window has no area, the old buffers are kept until it is restored.
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    /// The target to render into, `None` while nothing should be rendered.
+    pub fn target(&self) -> Option<&B::Target> {
+        if self.minimized { None } else { self.target.as_ref() }
+    }
+
+    /// Handles a new window size, returns whether the buffers were recreated.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<bool> {
+        if width == 0 || height == 0 {
+            self.minimized = true;
+            return Ok(false);
+        }
+
+        self.minimized = false;
+        if (width, height) == (self.width, self.height) && self.target.is_some() {
+            return Ok(false);
+        }
+
+        self.target = None;
+        self.backend.resize_buffers(width, height)?;
+        self.target = Some(self.backend.create_target()?);
+        self.width = width;
+        self.height = height;
+
+        for (_, listener) in self.listeners.iter_mut() {
+            listener(width, height);
+        }
+        Ok(true)
+    }
+
+    pub fn config(&self) -> PresentConfig {
+        self.config
+    }
+
+    pub fn tearing_supported(&self) -> bool {
+        self.backend.tearing_supported()
+    }
+
+    pub fn color_space(&self) -> OutputColorSpace {
+        self.backend.color_space()
+    }
+
+    pub fn display_max_nits(&self) -> f32 {
+        self.backend.display_max_nits()
+    }
+
+    /// Applies a new configuration, releasing and recreating the target only when the buffers change.
+    ///
+    /// On failure the backend keeps the old configuration, which gets a new target when possible.
+    pub fn set_config(&mut self, config: PresentConfig) -> Result<ConfigChange> {
+        let config = config.clamped();
+        let change = config.change_from(&self.config, self.backend.tearing_supported());
+        if change == ConfigChange::None {
+            return Ok(change);
+        }
+
+        if change >= ConfigChange::Buffers {
+            self.target = None;
+            if let Err(error) = self.backend.configure(&config, change, self.width, self.height) {
+                self.target = self.backend.create_target().ok();
+                return Err(error);
+            }
+            self.target = Some(self.backend.create_target()?);
+        } else {
+            self.backend.configure(&config, change, self.width, self.height)?;
+        }
+
+        self.config = config;
+        Ok(change)
+    }
+
+    /// Waits until the frame latency allows another frame, call before recording it.
+    pub fn wait_for_frame(&mut self) {
+        if !self.minimized {
+            self.backend.wait_for_frame();
+        }
+    }
+
+    /// Presents unless minimized, returns whether a frame was presented.
+    pub fn present(&mut self) -> Result<bool> {
+        if self.minimized {
+            return Ok(false);
+        }
+
+        let (sync_interval, flags) = self.config.present_arguments(self.backend.tearing_supported());
+        self.backend.present(sync_interval, flags)?;
+        Ok(true)
+    }
+
+    /// Calls `listener` with the new size after the buffers were recreated.
+    pub fn add_resize_listener(&mut self, listener: impl FnMut(u32, u32) + 'static) -> ListenerId {
+        let id = ListenerId(self.next_listener);
+        self.next_listener += 1;
+        self.listeners.push((id, Box::new(listener)));
+        id
+    }
+
+    pub fn remove_resize_listener(&mut self, id: ListenerId) {
+        self.listeners.retain(|(listener_id, _)| *listener_id != id);
+    }
+
+    /// Moves the resize listeners of `old` over, for a swap chain replacing it.
+    pub fn take_listeners(&mut self, old: &mut SwapChain<B>) {
+        self.listeners.append(&mut old.listeners);
+        self.next_listener = self.next_listener.max(old.next_listener);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn headless(width: u32, height: u32) -> SwapChain<HeadlessSwapChain> {
+        let backend = HeadlessSwapChain { width, height, ..HeadlessSwapChain::default() };
+        SwapChain::new(backend, width, height, PresentConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn resize_recreates_the_target() {
+        let mut swap_chain = headless(640, 480);
+        assert_eq!(swap_chain.target(), Some(&HeadlessTarget { width: 640, height: 480, generation: 1 }));
+
+        assert!(swap_chain.resize(800, 600).unwrap());
+        assert_eq!(swap_chain.size(), (800, 600));
+        assert_eq!(swap_chain.target(), Some(&HeadlessTarget { width: 800, height: 600, generation: 2 }));
+        assert_eq!(swap_chain.backend.resizes, 1);
+    }
+
+    #[test]
+    fn resize_to_the_same_size_does_nothing() {
+        let mut swap_chain = headless(640, 480);
+
+        assert!(!swap_chain.resize(640, 480).unwrap());
+        assert_eq!(swap_chain.backend.resizes, 0);
+        assert_eq!(swap_chain.target().unwrap().generation, 1);
+    }
+
+    #[test]
+    fn minimized_swap_chain_has_no_target_and_does_not_present() {
+        let mut swap_chain = headless(640, 480);
+
+        assert!(!swap_chain.resize(0, 0).unwrap());
+        assert!(swap_chain.is_minimized());
+        assert_eq!(swap_chain.target(), None);
+        assert!(!swap_chain.present().unwrap());
+        assert_eq!(swap_chain.backend.presents, 0);
+        assert_eq!(swap_chain.size(), (640, 480));
+
+        // Restoring to the old size keeps the old buffers.
+        assert!(!swap_chain.resize(640, 480).unwrap());
+        assert!(!swap_chain.is_minimized());
+        assert_eq!(swap_chain.target().unwrap().generation, 1);
+        assert!(swap_chain.present().unwrap());
+        assert_eq!(swap_chain.backend.presents, 1);
+    }
+
+    #[test]
+    fn a_zero_width_or_height_minimizes() {
+        let mut swap_chain = headless(640, 480);
+
+        swap_chain.resize(0, 480).unwrap();
+        assert!(swap_chain.is_minimized());
+        swap_chain.resize(640, 0).unwrap();
+        assert!(swap_chain.is_minimized());
+        assert_eq!(swap_chain.backend.resizes, 0);
+    }
+
+    #[test]
+    fn config_changes_are_ordered_by_cost() {
+        let base = PresentConfig::default();
+
+        assert_eq!(base.change_from(&base, true), ConfigChange::None);
+        assert_eq!(PresentConfig { sync_interval: 0, ..base }.change_from(&base, true), ConfigChange::Present);
+        assert_eq!(PresentConfig { max_frame_latency: 3, ..base }.change_from(&base, true), ConfigChange::FrameLatency);
+        assert_eq!(PresentConfig { buffer_count: 3, ..base }.change_from(&base, true), ConfigChange::Buffers);
+        assert_eq!(PresentConfig { color_space: OutputColorSpace::Hdr10, ..base }.change_from(&base, true), ConfigChange::Buffers);
+        assert_eq!(PresentConfig { allow_tearing: true, ..base }.change_from(&base, true), ConfigChange::Recreate);
+        // Without support the tearing flag changes nothing the swap chain was created with.
+        assert_eq!(PresentConfig { allow_tearing: true, ..base }.change_from(&base, false), ConfigChange::Present);
+        // The most expensive change wins.
+        assert_eq!(PresentConfig { allow_tearing: true, buffer_count: 3, ..base }.change_from(&base, true), ConfigChange::Recreate);
+        assert!(ConfigChange::None < ConfigChange::Present);
+        assert!(ConfigChange::Present < ConfigChange::FrameLatency);
+        assert!(ConfigChange::FrameLatency < ConfigChange::Buffers);
+        assert!(ConfigChange::Buffers < ConfigChange::Recreate);
+    }
+
+    #[test]
+    fn set_config_only_recreates_the_target_for_buffer_changes() {
+        let mut swap_chain = headless(640, 480);
+        swap_chain.backend.tearing_supported = true;
+        let base = swap_chain.config();
+
+        assert_eq!(swap_chain.set_config(base).unwrap(), ConfigChange::None);
+        assert_eq!(swap_chain.set_config(PresentConfig { sync_interval: 0, ..base }).unwrap(), ConfigChange::Present);
+        assert_eq!(swap_chain.target().unwrap().generation, 1);
+        assert_eq!(swap_chain.set_config(PresentConfig { sync_interval: 0, buffer_count: 3, ..base }).unwrap(), ConfigChange::Buffers);
+        assert_eq!(swap_chain.target().unwrap().generation, 2);
+        let recreated = PresentConfig { sync_interval: 0, buffer_count: 3, allow_tearing: true, ..base };
+        assert_eq!(swap_chain.set_config(recreated).unwrap(), ConfigChange::Recreate);
+        assert_eq!(swap_chain.target().unwrap().generation, 3);
+
+        assert_eq!(swap_chain.backend.changes, [ConfigChange::Present, ConfigChange::Buffers, ConfigChange::Recreate]);
+        assert_eq!(swap_chain.config(), recreated);
+        assert_eq!(swap_chain.backend.config, recreated);
+    }
+
+    #[test]
+    fn set_config_clamps() {
+        let mut swap_chain = headless(640, 480);
+        let config = PresentConfig { sync_interval: 9, buffer_count: 1, max_frame_latency: 0, ..PresentConfig::default() };
+
+        swap_chain.set_config(config).unwrap();
+
+        assert_eq!(swap_chain.config().sync_interval, PresentConfig::MAX_SYNC_INTERVAL);
+        assert_eq!(swap_chain.config().buffer_count, PresentConfig::MIN_BUFFER_COUNT);
+        assert_eq!(swap_chain.config().max_frame_latency, 1);
+    }
+
+    #[test]
+    fn failed_set_config_keeps_the_old_config_and_a_target() {
+        let mut swap_chain = headless(640, 480);
+        swap_chain.backend.tearing_supported = true;
+        swap_chain.backend.fail_configure = true;
+        let old = swap_chain.config();
+
+        assert!(swap_chain.set_config(PresentConfig { allow_tearing: true, ..old }).is_err());
+
+        assert_eq!(swap_chain.config(), old);
+        assert_eq!(swap_chain.target().unwrap().generation, 2);
+        assert!(swap_chain.present().unwrap());
+    }
+
+    #[test]
+    fn resize_listeners_get_the_new_size() {
+        let sizes = Rc::new(RefCell::new(Vec::new()));
+        let mut swap_chain = headless(640, 480);
+        let recorded = sizes.clone();
+        let id = swap_chain.add_resize_listener(move |width, height| recorded.borrow_mut().push((width, height)));
+
+        swap_chain.resize(800, 600).unwrap();
+        swap_chain.resize(0, 0).unwrap();
+        swap_chain.resize(800, 600).unwrap();
+        swap_chain.resize(1024, 768).unwrap();
+        swap_chain.remove_resize_listener(id);
+        swap_chain.resize(640, 480).unwrap();
+
+        assert_eq!(*sizes.borrow(), [(800, 600), (1024, 768)]);
+    }
+
+    #[test]
+    fn listeners_move_to_a_replacing_swap_chain() {
+        let calls = Rc::new(RefCell::new(0));
+        let mut old = headless(640, 480);
+        let counted = calls.clone();
+        let first = old.add_resize_listener(move |_, _| *counted.borrow_mut() += 1);
+
+        let mut new = headless(640, 480);
+        new.take_listeners(&mut old);
+        let second = new.add_resize_listener(|_, _| ());
+        new.resize(800, 600).unwrap();
+        old.resize(800, 600).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(*calls.borrow(), 1);
+    }
+
+    #[test]
+    fn present_arguments_only_tear_without_vsync() {
+        let tearing = PresentConfig { allow_tearing: true, sync_interval: 0, ..PresentConfig::default() };
+
+        assert_eq!(tearing.present_arguments(true), (0, DXGI_PRESENT_ALLOW_TEARING));
+        assert_eq!(tearing.present_arguments(false), (0, 0));
+        assert_eq!(PresentConfig { sync_interval: 1, ..tearing }.present_arguments(true), (1, 0));
+        assert_eq!(PresentConfig { allow_tearing: false, ..tearing }.present_arguments(true), (0, 0));
+    }
+
+    #[test]
+    fn swap_chain_flags_include_tearing_when_supported() {
+        let waitable = DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT.0 as u32;
+        let tearing = DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING.0 as u32;
+        let config = PresentConfig { allow_tearing: true, ..PresentConfig::default() };
+
+        assert_eq!(config.swap_chain_flags(true), waitable | tearing);
+        assert_eq!(config.swap_chain_flags(false), waitable);
+        assert_eq!(PresentConfig::default().swap_chain_flags(true), waitable);
+    }
+
+    #[test]
+    fn present_passes_the_arguments_to_the_backend() {
+        let mut swap_chain = headless(640, 480);
+        swap_chain.backend.tearing_supported = true;
+        swap_chain.set_config(PresentConfig { allow_tearing: true, sync_interval: 0, ..PresentConfig::default() }).unwrap();
+
+        swap_chain.present().unwrap();
+
+        assert_eq!(swap_chain.backend.last_present, (0, DXGI_PRESENT_ALLOW_TEARING));
+    }
+
+    #[test]
+    fn color_space_falls_back_to_srgb_without_hdr() {
+        let mut swap_chain = headless(640, 480);
+        swap_chain.set_config(PresentConfig { color_space: OutputColorSpace::Hdr10, ..PresentConfig::default() }).unwrap();
+        assert_eq!(swap_chain.color_space(), OutputColorSpace::Srgb);
+        assert_eq!(swap_chain.display_max_nits(), SCRGB_REFERENCE_NITS);
+
+        swap_chain.backend.hdr_display_nits = Some(1000.0);
+        assert_eq!(swap_chain.color_space(), OutputColorSpace::Hdr10);
+        assert_eq!(swap_chain.display_max_nits(), 1000.0);
+    }
+}
@@ -0,0 +1,481 @@
+//! Platform-neutral texture description shared by the image loaders and GPU texture creation.
+
+use crate::texture_loader::TextureError;
+
+/// Pixel formats the engine can load, mapped to `DXGI_FORMAT` in [`crate::gpu_texture`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+    R8Unorm,
+    Rg8Unorm,
+    Rgba8Unorm,
+    Rgba8UnormSrgb,
+    Bgra8Unorm,
+    Bgra8UnormSrgb,
+    Rgb10A2Unorm,
+    Rg11B10Float,
+    R16Float,
+    Rg16Float,
+    Rgba16Unorm,
+    Rgba16Float,
+    R32Float,
+    Rgba32Float,
+    Bc1Unorm,
+    Bc1UnormSrgb,
+    Bc2Unorm,
+    Bc2UnormSrgb,
+    Bc3Unorm,
+    Bc3UnormSrgb,
+    Bc4Unorm,
+    Bc4Snorm,
+    Bc5Unorm,
+    Bc5Snorm,
+    Bc6hUfloat,
+    Bc6hSfloat,
+    Bc7Unorm,
+    Bc7UnormSrgb,
+}
+
+impl TextureFormat {
+    /// Bytes per block, a block is a single pixel for uncompressed formats and 4x4 pixels otherwise.
+    pub fn block_bytes(self) -> u32 {
+        match self {
+            TextureFormat::R8Unorm => 1,
+            TextureFormat::Rg8Unorm | TextureFormat::R16Float => 2,
+            TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+            | TextureFormat::Rgb10A2Unorm
+            | TextureFormat::Rg11B10Float
+            | TextureFormat::Rg16Float
+            | TextureFormat::R32Float => 4,
+            TextureFormat::Rgba16Unorm | TextureFormat::Rgba16Float => 8,
+            TextureFormat::Rgba32Float => 16,
+            TextureFormat::Bc1Unorm
+            | TextureFormat::Bc1UnormSrgb
+            | TextureFormat::Bc4Unorm
+            | TextureFormat::Bc4Snorm => 8,
+            TextureFormat::Bc2Unorm
+            | TextureFormat::Bc2UnormSrgb
+            | TextureFormat::Bc3Unorm
+            | TextureFormat::Bc3UnormSrgb
+            | TextureFormat::Bc5Unorm
+            | TextureFormat::Bc5Snorm
+            | TextureFormat::Bc6hUfloat
+            | TextureFormat::Bc6hSfloat
+            | TextureFormat::Bc7Unorm
+            | TextureFormat::Bc7UnormSrgb => 16,
+        }
+    }
+
+    /// Width and height of a block in pixels.
+    pub fn block_dimension(self) -> u32 {
+        if self.is_compressed() { 4 } else { 1 }
+    }
+
+    pub fn is_compressed(self) -> bool {
+        matches!(
+            self,
+            TextureFormat::Bc1Unorm
+                | TextureFormat::Bc1UnormSrgb
+                | TextureFormat::Bc2Unorm
+                | TextureFormat::Bc2UnormSrgb
+                | TextureFormat::Bc3Unorm
+                | TextureFormat::Bc3UnormSrgb
+                | TextureFormat::Bc4Unorm
+                | TextureFormat::Bc4Snorm
+                | TextureFormat::Bc5Unorm
+                | TextureFormat::Bc5Snorm
+                | TextureFormat::Bc6hUfloat
+                | TextureFormat::Bc6hSfloat
+                | TextureFormat::Bc7Unorm
+                | TextureFormat::Bc7UnormSrgb
+        )
+    }
+
+    pub fn is_srgb(self) -> bool {
+        matches!(
+            self,
+            TextureFormat::Rgba8UnormSrgb
+                | TextureFormat::Bgra8UnormSrgb
+                | TextureFormat::Bc1UnormSrgb
+                | TextureFormat::Bc2UnormSrgb
+                | TextureFormat::Bc3UnormSrgb
+                | TextureFormat::Bc7UnormSrgb
+        )
+    }
+
+    /// Number of blocks needed to cover `pixels` pixels.
+    fn blocks(self, pixels: u32) -> u32 {
+        (pixels.max(1) - 1) / self.block_dimension() + 1
+    }
+
+    /// Bytes per row of blocks of a `width` pixels wide level, as `D3D11_SUBRESOURCE_DATA::SysMemPitch` expects.
+    ///
+    /// `None` if the pitch does not fit into 32 bits.
+    pub fn row_pitch(self, width: u32) -> Option<u32> {
+        self.blocks(width).checked_mul(self.block_bytes())
+    }
+
+    /// Bytes per 2D slice of a `width` x `height` level, `None` if it does not fit into 32 bits.
+    pub fn slice_pitch(self, width: u32, height: u32) -> Option<u32> {
+        self.row_pitch(width)?.checked_mul(self.blocks(height))
+    }
+}
+
+/// Size of mip level `level` for a base size of `size`.
+pub fn mip_size(size: u32, level: u32) -> u32 {
+    size.checked_shr(level).unwrap_or(0).max(1)
+}
+
+/// Number of levels in a full mip chain down to 1x1.
+pub fn full_mip_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Location of one mip level of one array layer inside [`TextureData::data`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Subresource {
+    pub offset: usize,
+    pub width: u32,
+    pub height: u32,
+    pub row_pitch: u32,
+    pub slice_pitch: u32,
+}
+
+/// CPU side texture with every subresource stored back to back.
+///
+/// Subresources are ordered like Direct3D expects them: all mip levels of layer 0, then all mip
+/// levels of layer 1 and so on. Cube maps store their six faces as consecutive layers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureData {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub is_cube: bool,
+    pub subresources: Vec<Subresource>,
+    pub data: Vec<u8>,
+}
+
+impl TextureData {
+    /// Creates a single level, single layer texture from tightly packed pixels.
+    pub fn from_pixels(format: TextureFormat, width: u32, height: u32, data: Vec<u8>) -> Self {
+        let slice_pitch = format.slice_pitch(width, height).expect("texture is too large");
+        let row_pitch = format.row_pitch(width).unwrap();
+        assert_eq!(data.len(), slice_pitch as usize, "pixel data does not match the texture size");
+
+        TextureData {
+            format,
+            width,
+            height,
+            mip_levels: 1,
+            array_layers: 1,
+            is_cube: false,
+            subresources: vec![Subresource { offset: 0, width, height, row_pitch, slice_pitch }],
+            data,
+        }
+    }
+
+    /// Total number of bytes of tightly packed subresources, without allocating anything.
+    ///
+    /// Fails if the mip count exceeds the full chain or the size overflows, so loaders can check
+    /// untrusted dimensions against the available data first.
+    pub fn packed_size(
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        array_layers: u32,
+    ) -> Result<usize, TextureError> {
+        let full_chain = full_mip_count(width, height);
+        if mip_levels > full_chain {
+            return Err(TextureError::Decode(format!(
+                "{} mip levels for a {}x{} texture, the full chain has {}",
+                mip_levels, width, height, full_chain
+            )));
+        }
+
+        let too_large = || TextureError::Decode(format!("{}x{} texture with {} layers is too large", width, height, array_layers));
+        let mut layer_size = 0usize;
+        for level in 0..mip_levels {
+            let slice_pitch = format.slice_pitch(mip_size(width, level), mip_size(height, level)).ok_or_else(too_large)?;
+            layer_size = layer_size.checked_add(slice_pitch as usize).ok_or_else(too_large)?;
+        }
+        layer_size.checked_mul(array_layers as usize).ok_or_else(too_large)
+    }
+
+    /// Tightly packed subresources in Direct3D order and the total number of bytes they need.
+    pub fn packed_subresources(
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        array_layers: u32,
+    ) -> Result<(Vec<Subresource>, usize), TextureError> {
+        let size = Self::packed_size(format, width, height, mip_levels, array_layers)?;
+
+        // `packed_size` made sure every pitch fits into 32 bits.
+        let mut subresources = Vec::with_capacity(mip_levels as usize * array_layers as usize);
+        let mut offset = 0;
+        for _ in 0..array_layers {
+            for level in 0..mip_levels {
+                let (width, height) = (mip_size(width, level), mip_size(height, level));
+                let subresource = Subresource {
+                    offset,
+                    width,
+                    height,
+                    row_pitch: format.row_pitch(width).unwrap(),
+                    slice_pitch: format.slice_pitch(width, height).unwrap(),
+                };
+                offset += subresource.slice_pitch as usize;
+                subresources.push(subresource);
+            }
+        }
+        Ok((subresources, size))
+    }
+
+    /// Index of a subresource as used by `D3D11CalcSubresource`.
+    pub fn subresource_index(&self, mip_level: u32, array_layer: u32) -> usize {
+        (mip_level + array_layer * self.mip_levels) as usize
+    }
+
+    pub fn subresource(&self, mip_level: u32, array_layer: u32) -> &Subresource {
+        &self.subresources[self.subresource_index(mip_level, array_layer)]
+    }
+
+    /// Bytes of one subresource.
+    pub fn subresource_data(&self, mip_level: u32, array_layer: u32) -> &[u8] {
+        let subresource = self.subresource(mip_level, array_layer);
+        &self.data[subresource.offset..subresource.offset + subresource.slice_pitch as usize]
+    }
+}
+
+/// Converts an `f32` to IEEE 754 half precision bits, rounding to nearest even.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity, NaN keeps a quiet mantissa bit.
+        return sign | 0x7c00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal half, shift in the implicit leading bit.
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && half & 1 == 1);
+        return sign | (half + round_up as u32) as u16;
+    }
+
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity.
+    sign | (half + round_up as u32) as u16
+}
+
+/// Converts IEEE 754 half precision bits to an `f32`.
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x03ff) as u32;
+
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal, renormalize.
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | ((mantissa << shift) & 0x03ff) << 13
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_reference_values() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+    }
+
+    #[test]
+    fn half_rounds_to_nearest_even() {
+        let ulp = 2.0f32.powi(-10);
+        assert_eq!(f32_to_f16(1.0 + ulp * 0.5), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + ulp * 1.5), 0x3c02);
+        assert_eq!(f32_to_f16(1.0 + ulp * 0.5001), 0x3c01);
+        // The carry out of the mantissa moves to the next exponent.
+        assert_eq!(f32_to_f16(2.0 - ulp * 0.25), 0x4000);
+    }
+
+    #[test]
+    fn half_subnormals() {
+        let smallest = 2.0f32.powi(-24);
+        assert_eq!(f32_to_f16(smallest), 0x0001);
+        assert_eq!(f32_to_f16(-smallest), 0x8001);
+        assert_eq!(f32_to_f16(smallest * 1023.0), 0x03ff);
+        assert_eq!(f32_to_f16(2.0f32.powi(-14)), 0x0400);
+        // Halfway cases round to even, including the carry into the smallest normal.
+        assert_eq!(f32_to_f16(smallest * 0.5), 0x0000);
+        assert_eq!(f32_to_f16(smallest * 1.5), 0x0002);
+        assert_eq!(f32_to_f16(smallest * 1023.5), 0x0400);
+        // Too small to be represented.
+        assert_eq!(f32_to_f16(smallest * 0.25), 0x0000);
+        assert_eq!(f32_to_f16(f32::MIN_POSITIVE), 0x0000);
+        assert_eq!(f32_to_f16(-f32::MIN_POSITIVE), 0x8000);
+
+        assert_eq!(f16_to_f32(0x0001), smallest);
+        assert_eq!(f16_to_f32(0x03ff), smallest * 1023.0);
+        assert_eq!(f16_to_f32(0x8200), -smallest * 512.0);
+    }
+
+    #[test]
+    fn half_infinity_and_nan() {
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(1e10), 0x7c00);
+        assert_eq!(f32_to_f16(-1e10), 0xfc00);
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+
+        let nan = f32_to_f16(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x03ff, 0);
+        assert!(f16_to_f32(nan).is_nan());
+        assert!(f16_to_f32(0x7c01).is_nan());
+        assert!(f16_to_f32(0xfe00).is_nan());
+    }
+
+    #[test]
+    fn half_round_trips_every_value() {
+        for half in 0..=u16::MAX {
+            let value = f16_to_f32(half);
+            if value.is_nan() {
+                assert!(f16_to_f32(f32_to_f16(value)).is_nan(), "{:04x}", half);
+            } else {
+                assert_eq!(f32_to_f16(value), half, "{:04x} -> {}", half, value);
+            }
+        }
+    }
+
+    #[test]
+    fn mip_sizes() {
+        assert_eq!(mip_size(256, 0), 256);
+        assert_eq!(mip_size(256, 1), 128);
+        assert_eq!(mip_size(256, 8), 1);
+        assert_eq!(mip_size(256, 9), 1);
+        assert_eq!(mip_size(5, 1), 2);
+        assert_eq!(mip_size(5, 2), 1);
+        assert_eq!(mip_size(1, 0), 1);
+        // Levels past the 32 bit range clamp instead of overflowing the shift.
+        assert_eq!(mip_size(u32::MAX, 31), 1);
+        assert_eq!(mip_size(u32::MAX, 32), 1);
+        assert_eq!(mip_size(u32::MAX, u32::MAX), 1);
+
+        assert_eq!(full_mip_count(1, 1), 1);
+        assert_eq!(full_mip_count(256, 256), 9);
+        assert_eq!(full_mip_count(256, 1), 9);
+        assert_eq!(full_mip_count(5, 3), 3);
+        assert_eq!(full_mip_count(0, 0), 1);
+        assert_eq!(full_mip_count(u32::MAX, 1), 32);
+    }
+
+    #[test]
+    fn block_compressed_pitches() {
+        let cases = [
+            (TextureFormat::Bc1Unorm, 8),
+            (TextureFormat::Bc1UnormSrgb, 8),
+            (TextureFormat::Bc2Unorm, 16),
+            (TextureFormat::Bc3Unorm, 16),
+            (TextureFormat::Bc4Unorm, 8),
+            (TextureFormat::Bc4Snorm, 8),
+            (TextureFormat::Bc5Unorm, 16),
+            (TextureFormat::Bc6hUfloat, 16),
+            (TextureFormat::Bc6hSfloat, 16),
+            (TextureFormat::Bc7Unorm, 16),
+            (TextureFormat::Bc7UnormSrgb, 16),
+        ];
+        for (format, block_bytes) in cases {
+            assert!(format.is_compressed());
+            assert_eq!(format.block_dimension(), 4);
+            assert_eq!(format.block_bytes(), block_bytes);
+            // Partial blocks round up, small mips still take a whole block.
+            assert_eq!(format.row_pitch(1), Some(block_bytes), "{:?}", format);
+            assert_eq!(format.row_pitch(4), Some(block_bytes), "{:?}", format);
+            assert_eq!(format.row_pitch(5), Some(block_bytes * 2), "{:?}", format);
+            assert_eq!(format.row_pitch(256), Some(block_bytes * 64), "{:?}", format);
+            assert_eq!(format.slice_pitch(1, 1), Some(block_bytes), "{:?}", format);
+            assert_eq!(format.slice_pitch(256, 6), Some(block_bytes * 64 * 2), "{:?}", format);
+            assert_eq!(format.slice_pitch(0, 0), Some(block_bytes), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn uncompressed_pitches() {
+        assert_eq!(TextureFormat::R8Unorm.row_pitch(3), Some(3));
+        assert_eq!(TextureFormat::Rgba8Unorm.row_pitch(3), Some(12));
+        assert_eq!(TextureFormat::Rgba16Float.slice_pitch(3, 5), Some(120));
+        assert_eq!(TextureFormat::Rgba32Float.slice_pitch(0, 0), Some(16));
+    }
+
+    #[test]
+    fn pitches_that_overflow_are_rejected() {
+        assert_eq!(TextureFormat::Rgba32Float.row_pitch(u32::MAX), None);
+        assert_eq!(TextureFormat::R8Unorm.row_pitch(u32::MAX), Some(u32::MAX));
+        assert_eq!(TextureFormat::R8Unorm.slice_pitch(u32::MAX, 2), None);
+        assert_eq!(TextureFormat::Bc7Unorm.row_pitch(u32::MAX), None);
+        assert_eq!(TextureFormat::Bc1Unorm.row_pitch(u32::MAX), None);
+        assert_eq!(TextureFormat::Bc1Unorm.row_pitch(0x4000_0000), Some(0x1000_0000 * 8));
+    }
+
+    #[test]
+    fn packed_size_is_checked() {
+        assert_eq!(TextureData::packed_size(TextureFormat::Rgba8Unorm, 4, 4, 3, 1).unwrap(), 64 + 16 + 4);
+        assert_eq!(TextureData::packed_size(TextureFormat::Bc1Unorm, 8, 8, 4, 6).unwrap(), (32 + 8 + 8 + 8) * 6);
+        // More levels than the full chain.
+        assert!(TextureData::packed_size(TextureFormat::Rgba8Unorm, 4, 4, 4, 1).is_err());
+        assert!(TextureData::packed_size(TextureFormat::Rgba8Unorm, 1, 1, 33, 1).is_err());
+        assert!(TextureData::packed_size(TextureFormat::Rgba8Unorm, 1, 1, u32::MAX, 1).is_err());
+        // Sizes that do not fit.
+        assert!(TextureData::packed_size(TextureFormat::Rgba32Float, u32::MAX, 1, 1, 1).is_err());
+        assert!(TextureData::packed_size(TextureFormat::Rgba32Float, 1 << 20, 1 << 20, 1, 1).is_err());
+        assert!(TextureData::packed_subresources(TextureFormat::Rgba8Unorm, 4, 4, 4, 1).is_err());
+    }
+
+    #[test]
+    fn packed_subresources_follow_direct3d_order() {
+        let (subresources, size) = TextureData::packed_subresources(TextureFormat::Rgba8Unorm, 4, 2, 3, 2).unwrap();
+        let sizes: Vec<_> = subresources.iter().map(|s| (s.width, s.height, s.offset)).collect();
+        assert_eq!(sizes, vec![(4, 2, 0), (2, 1, 32), (1, 1, 40), (4, 2, 44), (2, 1, 76), (1, 1, 84)]);
+        assert_eq!(size, 88);
+    }
+}
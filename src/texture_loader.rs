@@ -0,0 +1,207 @@
+//! Decodes PNG, JPEG, TGA and Radiance HDR images into [`TextureData`].
+//!
+//! DDS and KTX2 containers are handed to [`crate::dds_loader`] and [`crate::ktx2_loader`], they
+//! already store GPU formats so the load options do not apply to them.
+
+use std::fmt;
+use std::path::Path;
+
+use image::DynamicImage;
+
+use crate::dds_loader::{parse_dds, DDS_MAGIC};
+use crate::ktx2_loader::{parse_ktx2, KTX2_IDENTIFIER};
+use crate::mip_generator::{generate_mips, MipSettings};
+use crate::texture::{f32_to_f16, TextureData, TextureFormat};
+
+/// How the color channels of an 8 bit image should be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Color textures (albedo, emissive, UI), sampled through an `_SRGB` format.
+    Srgb,
+    /// Data textures (normal maps, roughness, masks).
+    Linear,
+}
+
+/// Storage precision of floating point images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HdrPrecision {
+    /// `R32G32B32A32_FLOAT`.
+    Full,
+    /// `R16G16B16A16_FLOAT`, half the memory of `Full`.
+    Half,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureLoadOptions {
+    pub color_space: ColorSpace,
+    pub hdr_precision: HdrPrecision,
+    /// Generates a mip chain for images that only have a base level, `None` keeps a single level.
+    pub mips: Option<MipSettings>,
+}
+
+impl Default for TextureLoadOptions {
+    fn default() -> Self {
+        TextureLoadOptions {
+            color_space: ColorSpace::Srgb,
+            hdr_precision: HdrPrecision::Half,
+            mips: Some(MipSettings::default()),
+        }
+    }
+}
+
+/// Channel storage of a decoded image, independent of the decoder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourcePixels {
+    Unorm8,
+    Unorm16,
+    Float32,
+}
+
+/// Picks the GPU format for a decoded image.
+///
+/// 16 bit images keep their precision only when linear, there is no 16 bit sRGB format so sRGB
+/// images are reduced to 8 bits per channel.
+pub fn select_format(source: SourcePixels, options: &TextureLoadOptions) -> TextureFormat {
+    match (source, options.color_space) {
+        (SourcePixels::Float32, _) => match options.hdr_precision {
+            HdrPrecision::Full => TextureFormat::Rgba32Float,
+            HdrPrecision::Half => TextureFormat::Rgba16Float,
+        },
+        (SourcePixels::Unorm16, ColorSpace::Linear) => TextureFormat::Rgba16Unorm,
+        (_, ColorSpace::Srgb) => TextureFormat::Rgba8UnormSrgb,
+        (SourcePixels::Unorm8, ColorSpace::Linear) => TextureFormat::Rgba8Unorm,
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Decode(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Io(error) => write!(f, "failed to read texture: {}", error),
+            TextureError::Decode(message) => write!(f, "failed to decode texture: {}", message),
+            TextureError::Unsupported(message) => write!(f, "unsupported texture: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<std::io::Error> for TextureError {
+    fn from(error: std::io::Error) -> Self {
+        TextureError::Io(error)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(error) => TextureError::Io(error),
+            image::ImageError::Unsupported(error) => TextureError::Unsupported(error.to_string()),
+            error => TextureError::Decode(error.to_string()),
+        }
+    }
+}
+
+pub fn load_image(path: impl AsRef<Path>, options: &TextureLoadOptions) -> Result<TextureData, TextureError> {
+    let bytes = std::fs::read(path)?;
+    decode_image(&bytes, options)
+}
+
+/// Decodes an image from memory, the container is detected from its contents.
+pub fn decode_image(bytes: &[u8], options: &TextureLoadOptions) -> Result<TextureData, TextureError> {
+    if bytes.starts_with(&DDS_MAGIC) {
+        return parse_dds(bytes);
+    }
+    if bytes.starts_with(&KTX2_IDENTIFIER) {
+        return parse_ktx2(bytes);
+    }
+
+    let image = image::load_from_memory(bytes)?;
+    let texture = image_to_texture(image, options);
+
+    match &options.mips {
+        Some(settings) => generate_mips(&texture, settings),
+        None => Ok(texture),
+    }
+}
+
+/// Expands any channel layout to RGBA, since Direct3D has no three channel formats to sample from.
+pub fn image_to_texture(image: DynamicImage, options: &TextureLoadOptions) -> TextureData {
+    let source = match image {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => SourcePixels::Float32,
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_) => SourcePixels::Unorm16,
+        _ => SourcePixels::Unorm8,
+    };
+
+    let (width, height) = (image.width(), image.height());
+    let format = select_format(source, options);
+
+    let data = match format {
+        TextureFormat::Rgba32Float => image.into_rgba32f().into_raw()
+            .into_iter()
+            .flat_map(f32::to_le_bytes)
+            .collect(),
+        TextureFormat::Rgba16Float => image.into_rgba32f().into_raw()
+            .into_iter()
+            .flat_map(|value| f32_to_f16(value).to_le_bytes())
+            .collect(),
+        TextureFormat::Rgba16Unorm => image.into_rgba16().into_raw()
+            .into_iter()
+            .flat_map(u16::to_le_bytes)
+            .collect(),
+        _ => image.into_rgba8().into_raw(),
+    };
+
+    TextureData::from_pixels(format, width, height, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(color_space: ColorSpace, hdr_precision: HdrPrecision) -> TextureLoadOptions {
+        TextureLoadOptions { color_space, hdr_precision, mips: None }
+    }
+
+    #[test]
+    fn format_selection() {
+        let srgb = options(ColorSpace::Srgb, HdrPrecision::Half);
+        let linear = options(ColorSpace::Linear, HdrPrecision::Half);
+        let full = options(ColorSpace::Linear, HdrPrecision::Full);
+
+        assert_eq!(select_format(SourcePixels::Unorm8, &srgb), TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(select_format(SourcePixels::Unorm8, &linear), TextureFormat::Rgba8Unorm);
+        assert_eq!(select_format(SourcePixels::Unorm16, &srgb), TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(select_format(SourcePixels::Unorm16, &linear), TextureFormat::Rgba16Unorm);
+        // Float images ignore the color space, they are always linear.
+        assert_eq!(select_format(SourcePixels::Float32, &srgb), TextureFormat::Rgba16Float);
+        assert_eq!(select_format(SourcePixels::Float32, &linear), TextureFormat::Rgba16Float);
+        assert_eq!(select_format(SourcePixels::Float32, &full), TextureFormat::Rgba32Float);
+    }
+
+    #[test]
+    fn images_are_expanded_to_rgba() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_raw(2, 1, vec![1, 2, 3, 4, 5, 6]).unwrap());
+        let texture = image_to_texture(image, &options(ColorSpace::Srgb, HdrPrecision::Half));
+        assert_eq!(texture.format, TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(texture.data, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+        assert_eq!(texture.subresources[0].row_pitch, 8);
+    }
+
+    #[test]
+    fn float_images_are_stored_as_half() {
+        let image = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_raw(1, 1, vec![1.0, 0.5, -2.0, 65504.0]).unwrap());
+        let texture = image_to_texture(image, &options(ColorSpace::Linear, HdrPrecision::Half));
+        assert_eq!(texture.format, TextureFormat::Rgba16Float);
+        assert_eq!(texture.data, [0x3c00u16, 0x3800, 0xc000, 0x7bff].iter().flat_map(|h| h.to_le_bytes()).collect::<Vec<_>>());
+    }
+}
@@ -1,6 +1,12 @@
 use glfw::Context;
 
-use crate::{window_ui::WindowUi, window_application::WindowApplication};
+use crate::{config::WindowConfig, logging, window_ui::WindowUi, window_application::WindowApplication};
+use crate::config::InputConfig;
+use crate::gamepad::{self, GamepadEvent, Gamepads};
+use crate::input::{self, ActionMap, Input, InputState};
+use crate::input_recording::{is_input_event, InputLogError, InputRecorder, InputReplay, RecordedFrame};
+use crate::display_mode::{connected_monitor, pick_monitor, pick_video_mode, DisplayMode, DisplaySettings, MonitorModes};
+use crate::window_placement::{best_monitor, restore_placement, MonitorRect, WindowPlacement};
 
 
 pub struct Window {
@@ -10,43 +16,263 @@ pub struct Window {
 
     pub window_width: u32,
     pub window_height: u32,
-    pub is_fullscreen: bool,
-
+    pub display: DisplaySettings,
+    /// The connected monitors, updated whenever the display mode changes.
+    pub monitors: Vec<MonitorModes>,
+    /// What toggling fullscreen switches to from windowed.
+    fullscreen_mode: DisplayMode,
+
+    pub input: InputState,
+    pub actions: ActionMap,
+    pub gamepads: Gamepads,
+    bindings_file: Option<std::path::PathBuf>,
+    recorder: Option<InputRecorder>,
+    replay: Option<InputReplay>,
+    exit_after_replay: bool,
+
+    /// The windowed rectangle, kept while maximized or fullscreen to go back to and to save.
     last_window_position: (i32, i32),
     last_window_size: (u32, u32),
 }
 
 impl Window {
 
-    pub fn new(title: &'static str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(config: &WindowConfig) -> Result<Self, Box<dyn std::error::Error>> {
         let mut glfw = glfw::init_no_callbacks()?;
 
         let primary_monitor = glfw::Monitor::from_primary();
         let video_mode = primary_monitor.get_video_mode()
             .expect("Failed to find primary monitor video mode");
 
-        let window_width = (video_mode.width as f32 * 0.8).round() as u32;
-        let window_height = (video_mode.height as f32 * 0.8).round() as u32;
+        let monitors = glfw.with_connected_monitors(|_, monitors| monitors.iter().map(MonitorRect::from_glfw).collect::<Vec<_>>());
+        let saved_placement = if config.remember_placement { WindowPlacement::load(&config.placement_file) } else { None };
+
+        let display = config.display_settings();
+        let placement = saved_placement.and_then(|placement| restore_placement(&placement, &monitors)).unwrap_or_else(|| {
+            let size = config.size((video_mode.width, video_mode.height));
+            let position = (video_mode.width as i32 / 2 - size.0 as i32 / 2, video_mode.height as i32 / 2 - size.1 as i32 / 2);
+            WindowPlacement {
+                monitor: display.monitor.clone().or_else(|| primary_monitor.get_name()),
+                position,
+                size,
+                maximized: false,
+                mode: display.mode,
+                video_mode: display.video_mode,
+            }
+        });
+        log::debug!("Window placement: {:?}", placement);
+
+        let (window_width, window_height) = placement.size;
 
         glfw.window_hint(glfw::WindowHint::ScaleToMonitor(false));
         glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
+        glfw.window_hint(glfw::WindowHint::Visible(config.visible));
 
         let (mut window, events) = glfw
-            .create_window(window_width, window_height, title, glfw::WindowMode::Windowed)
+            .create_window(window_width, window_height, &config.title, glfw::WindowMode::Windowed)
             .expect("Failed to create GLFW window");
 
-        let window_left = video_mode.width / 2 - window_width / 2;
-        let window_top = video_mode.height / 2 - window_height / 2;
-        
-        window.set_pos(window_left as i32, window_top as i32);
+        window.set_pos(placement.position.0, placement.position.1);
+
+        if placement.maximized {
+            window.maximize();
+        }
         
         window.set_key_polling(true);
         window.set_framebuffer_size_polling(true);
+        window.set_pos_polling(true);
+        window.set_size_polling(true);
+        window.set_mouse_button_polling(true);
+        window.set_focus_polling(true);
+        window.set_cursor_pos_polling(true);
+        window.set_cursor_enter_polling(true);
+        window.set_scroll_polling(true);
+        window.set_char_polling(true);
+        window.set_content_scale_polling(true);
+
+        let fullscreen_mode = if placement.mode == DisplayMode::Windowed { DisplayMode::Exclusive } else { placement.mode };
+        let display = DisplaySettings { mode: DisplayMode::Windowed, monitor: display.monitor, video_mode: display.video_mode.or(placement.video_mode) };
+
+        let mut window = Window { glfw, window, events, window_width, window_height, display, monitors: Vec::new(), fullscreen_mode,
+            input: InputState::default(),
+            actions: ActionMap::with_defaults(),
+            gamepads: Gamepads::default(),
+            bindings_file: None,
+            recorder: None,
+            replay: None,
+            exit_after_replay: false,
+            last_window_position: placement.position,
+            last_window_size: placement.size,
+        };
+
+        window.refresh_monitors();
+        // A hidden window stays windowed, fullscreen would show it.
+        if placement.mode != DisplayMode::Windowed && config.visible {
+            window.set_display(DisplaySettings { mode: placement.mode, monitor: placement.monitor, ..window.display.clone() });
+        }
+
+        Ok(window)
+    }
+
+    pub fn refresh_monitors(&mut self) {
+        self.monitors = self.glfw.with_connected_monitors(|_, monitors| monitors.iter().filter_map(MonitorModes::from_glfw).collect());
+    }
 
-        Ok(Window { glfw, window, events, window_width, window_height, is_fullscreen: false, 
-            last_window_position: (window_left as i32, window_top as i32),
-            last_window_size: (window_width, window_height)
-        })
+    /// Switches to windowed, borderless or exclusive fullscreen. Borderless and exclusive go to the
+    /// chosen monitor, or the one the window is on.
+    pub fn set_display(&mut self, settings: DisplaySettings) {
+        if self.display.mode == DisplayMode::Windowed && !self.window.is_maximized() {
+            let window_size = self.window.get_size();
+            self.last_window_position = self.window.get_pos();
+            self.last_window_size = (window_size.0 as u32, window_size.1 as u32);
+        }
+
+        self.refresh_monitors();
+
+        if settings.mode == DisplayMode::Windowed {
+            self.window.set_decorated(true);
+            self.window.set_monitor(glfw::WindowMode::Windowed,
+                self.last_window_position.0, self.last_window_position.1,
+                self.last_window_size.0, self.last_window_size.1,
+                None,
+            );
+            self.display = settings;
+            return;
+        }
+
+        let rects: Vec<MonitorRect> = self.monitors.iter().map(MonitorModes::rect).collect();
+        let Some(index) = pick_monitor(&rects, settings.monitor.as_deref(), self.last_window_position, self.last_window_size) else {
+            log::warn!("No monitor to go fullscreen on");
+            return;
+        };
+        let monitor = &self.monitors[index];
+        log::info!("{} on {:?}", settings.mode.name(), monitor.name);
+
+        match settings.mode {
+            DisplayMode::Borderless => {
+                self.window.set_decorated(false);
+                self.window.set_monitor(glfw::WindowMode::Windowed,
+                    monitor.position.0, monitor.position.1,
+                    monitor.current.width, monitor.current.height,
+                    None,
+                );
+            }
+            _ => {
+                let video_mode = pick_video_mode(&monitor.modes, monitor.current, settings.video_mode);
+                log::info!("Video mode {}", video_mode);
+
+                let known_count = self.monitors.len();
+                let window = &mut self.window;
+                let switched = self.glfw.with_connected_monitors(|_, monitors| {
+                    let names: Vec<Option<String>> = monitors.iter().map(glfw::Monitor::get_name).collect();
+                    let Some(connected) = connected_monitor(index, monitor.name.as_deref(), known_count, &names) else {
+                        return false;
+                    };
+
+                    window.set_decorated(true);
+                    window.set_monitor(glfw::WindowMode::FullScreen(&monitors[connected]),
+                        0, 0,
+                        video_mode.width, video_mode.height,
+                        Some(video_mode.refresh_rate)
+                    );
+                    true
+                });
+                if !switched {
+                    log::warn!("{:?} is no longer connected, staying {}", monitor.name, self.display.mode.name());
+                    return;
+                }
+            }
+        }
+
+        self.fullscreen_mode = settings.mode;
+        self.display = settings;
+    }
+
+    /// The current placement, with the windowed rectangle when maximized or fullscreen.
+    pub fn placement(&mut self) -> WindowPlacement {
+        let windowed = self.display.mode == DisplayMode::Windowed;
+        let maximized = windowed && self.window.is_maximized();
+
+        let fullscreen_monitor = self.window.with_window_mode(|mode| match mode {
+            glfw::WindowMode::FullScreen(monitor) => Some(monitor.get_name()),
+            glfw::WindowMode::Windowed => None,
+        });
+        let monitor = fullscreen_monitor.unwrap_or_else(|| {
+            let (position, size) = if windowed && !maximized {
+                (self.last_window_position, (self.last_window_size.0 as i32, self.last_window_size.1 as i32))
+            } else {
+                (self.window.get_pos(), self.window.get_size())
+            };
+            let monitors = self.glfw.with_connected_monitors(|_, monitors| monitors.iter().map(MonitorRect::from_glfw).collect::<Vec<_>>());
+            best_monitor(&monitors, position, (size.0 as u32, size.1 as u32)).and_then(|index| monitors[index].name.clone())
+        });
+
+        WindowPlacement {
+            monitor,
+            position: self.last_window_position,
+            size: self.last_window_size,
+            maximized,
+            mode: self.display.mode,
+            video_mode: self.display.video_mode,
+        }
+    }
+
+    pub fn save_placement(&mut self, config: &WindowConfig) {
+        if !config.remember_placement {
+            return;
+        }
+
+        if let Err(error) = self.placement().save(&config.placement_file) {
+            log::warn!("Failed to save window placement to {}: {}", config.placement_file.display(), error);
+        }
+    }
+
+    /// Loads the action bindings from `path`, which the bindings window also saves to.
+    pub fn load_bindings(&mut self, path: &std::path::Path) {
+        match ActionMap::load(path) {
+            Ok(actions) => self.actions = actions,
+            Err(error) => log::warn!("Using the default bindings, failed to load {}: {}", path.display(), error),
+        }
+        self.bindings_file = Some(path.to_path_buf());
+    }
+
+    pub fn init_gamepads(&mut self, config: &InputConfig) {
+        if let Some(path) = &config.gamepad_mappings {
+            match Gamepads::load_mappings(&self.glfw, path) {
+                Ok(()) => log::info!("Loaded gamepad mappings from {}", path.display()),
+                Err(error) => log::warn!("Failed to load gamepad mappings from {}: {}", path.display(), error),
+            }
+        }
+        self.gamepads = Gamepads::new(config.gamepad_settings());
+    }
+
+    /// Writes the window events of every frame to `path`.
+    pub fn start_recording(&mut self, path: &std::path::Path) -> Result<(), InputLogError> {
+        self.recorder = Some(InputRecorder::create(path)?);
+        // Everything is polled so the log holds all events, not only the ones handled here.
+        self.window.set_all_polling(true);
+        log::info!("Recording input to {}", path.display());
+        Ok(())
+    }
+
+    /// Replays the events recorded in `path` in place of live input, one recorded frame per frame.
+    pub fn start_replay(&mut self, path: &std::path::Path, exit_when_done: bool) -> Result<(), InputLogError> {
+        let replay = InputReplay::load(path)?;
+        log::info!("Replaying {} frames of input from {}", replay.remaining(), path.display());
+        self.replay = Some(replay);
+        self.exit_after_replay = exit_when_done;
+        Ok(())
+    }
+
+    fn save_bindings(&self) {
+        let Some(path) = &self.bindings_file else {
+            return;
+        };
+
+        match self.actions.save(path) {
+            Ok(()) => log::info!("Saved bindings to {}", path.display()),
+            Err(error) => log::warn!("Failed to save bindings to {}: {}", path.display(), error),
+        }
     }
 
     pub fn get_win32(&self) -> windows::Win32::Foundation::HWND {
@@ -57,81 +283,119 @@ impl Window {
     // render_callback: Box<dyn Fn((u32, u32)) + 'a>, resize_callback: Box<dyn Fn((u32, u32)) + 'a>
     pub fn run(&mut self, window_application: &mut WindowApplication, window_ui: &mut WindowUi) {
         let mut last_frame = std::time::Instant::now();
+        let mut frame = 0;
 
         while !self.window.should_close() {
+            frame += 1;
+            logging::set_frame(frame);
+
             let now = std::time::Instant::now();
+            let mut delta = now.duration_since(last_frame);
+            last_frame = now;
+
+            // Collected first, handling them may need the whole window.
+            let mut events: Vec<_> = glfw::flush_messages(&self.events).map(|(_, event)| event).collect();
+
+            if let Some(replay) = &mut self.replay {
+                match replay.next_frame() {
+                    Some(recorded) => {
+                        delta = recorded.delta;
+                        events.retain(|event| !is_input_event(event));
+                        events.extend(recorded.events.into_iter().filter(is_input_event));
+                    }
+                    None => {
+                        log::info!("Replay finished at frame {}", frame);
+                        self.replay = None;
+                        if self.exit_after_replay {
+                            self.window.set_should_close(true);
+                        }
+                    }
+                }
+            }
+
             window_ui.imgui
                 .io_mut()
-                .update_delta_time(now.duration_since(last_frame));
-            last_frame = now;
+                .update_delta_time(delta);
+
+            if let Some(recorder) = &mut self.recorder {
+                if let Err(error) = recorder.record(&RecordedFrame { frame, delta, events: events.clone() }) {
+                    log::warn!("Stopped recording input: {}", error);
+                    self.recorder = None;
+                }
+            }
 
-            for (_, event) in glfw::flush_messages(&self.events) {
-                println!("Got window event: {:?}", event);
+            for event in events {
+                log::trace!("Got window event: {:?}", event);
     
-                window_ui.platform.handle_event(window_ui.imgui.io_mut(), &self.window, &event);
+                window_ui.platform.handle_event(window_ui.imgui.io_mut(), &event);
+
+                let io = window_ui.imgui.io();
+                let capturing = window_ui.bindings_editor.capture.is_some();
+                if !input::taken_by_ui(&event, io.want_capture_keyboard, io.want_capture_mouse, capturing) {
+                    self.input.handle_event(&event);
+                }
 
                 match event {
                     glfw::WindowEvent::FramebufferSize(width, height) => {
                         self.window_width = width as u32;
                         self.window_height = height as u32;
 
-                        window_application.on_resize((self.window_width, self.window_height));
-                    }
-                    glfw::WindowEvent::Key(glfw::Key::Enter, _, glfw::Action::Press, _) => {
-                        if self.is_fullscreen {
-                            self.window.set_monitor(glfw::WindowMode::Windowed,
-                                self.last_window_position.0, self.last_window_position.1,
-                                self.last_window_size.0, self.last_window_size.1,
-                                None,
-                            );
-                        } else {
-                            let window_position = self.window.get_pos();
-                            let window_size = self.window.get_size();
-
-                            self.last_window_position = window_position;
-                            self.last_window_size = (window_size.0 as u32, window_size.1 as u32);
-
-                            self.glfw.with_connected_monitors(|_, monitors| {
-                                let mut highest_overlap = 0;
-                                let mut best_monitor: Option<&glfw::Monitor> = None;
-                    
-                                for monitor in monitors.iter() {
-                                    let video_mode = monitor.get_video_mode().unwrap();
-                                    let monitor_position = monitor.get_pos();
-
-                                    let overlap_area = Self::intersecting_area(window_position, window_size, monitor_position, (video_mode.width as i32, video_mode.height as i32));
-                                    println!("Intersecting area for monitor {:?}: {:?}", monitor.get_name(), overlap_area);
-
-                                    if overlap_area > highest_overlap {
-                                        highest_overlap = overlap_area;
-                                        best_monitor = Some(monitor);
-                                    }
-                                }
-    
-                                let monitor = best_monitor.unwrap();
-                                let video_mode = monitor.get_video_mode().unwrap();
-
-                                self.window.set_monitor(glfw::WindowMode::FullScreen(monitor),
-                                    0, 0,
-                                    video_mode.width, video_mode.height,
-                                    Some(video_mode.refresh_rate)
-                                );
-                            });
+                        if let Err(error) = window_application.on_resize((self.window_width, self.window_height)) {
+                            log::error!("Failed to resize to {}x{}: {}", self.window_width, self.window_height, error);
                         }
-
-                        self.is_fullscreen = !self.is_fullscreen;
                     }
-                    glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
-                        self.window.set_should_close(true);
+                    glfw::WindowEvent::Pos(x, y) if self.display.mode == DisplayMode::Windowed && !self.window.is_maximized() => {
+                        self.last_window_position = (x, y);
+                    }
+                    glfw::WindowEvent::Size(width, height)
+                        if self.display.mode == DisplayMode::Windowed && !self.window.is_maximized() && width > 0 && height > 0 =>
+                    {
+                        self.last_window_size = (width as u32, height as u32);
                     }
                     _ => {}
                 };
             }
+            // A content scale change needs the fonts at the new size.
+            window_ui.update_fonts(&mut window_application.sampler_cache);
 
             
 
 
 
+            // Gamepads aren't recorded, a replay runs without them.
+            let gamepad_events = if self.replay.is_none() { self.gamepads.poll(&self.glfw) } else { Vec::new() };
+            for event in gamepad_events {
+                match event {
+                    GamepadEvent::Connected { id, name } => log::info!("Gamepad {:?} connected: {}", id, name),
+                    GamepadEvent::Disconnected { id, name } => log::info!("Gamepad {:?} disconnected: {}", id, name),
+                }
+            }
+            for button in gamepad::BUTTONS {
+                self.input.set(Input::Gamepad(button), self.gamepads.button(button));
+            }
+            window_ui.platform.handle_gamepad(window_ui.imgui.io_mut(), self.gamepads.combined().as_ref());
+
+            if let Some(action) = window_ui.bindings_editor.capture.clone() {
+                if let Some(binding) = self.input.captured_binding() {
+                    log::info!("Bound {} to {}", binding, action);
+                    self.actions.bind(&action, binding);
+                    window_ui.bindings_editor.capture = None;
+                }
+                // Nothing triggers while the input is being picked.
+                self.actions.update(&InputState::default());
+            } else {
+                self.actions.update(&self.input);
+            }
+            self.input.end_frame();
+
+            if self.actions.pressed(input::TOGGLE_FULLSCREEN) {
+                let mode = if self.display.mode == DisplayMode::Windowed { self.fullscreen_mode } else { DisplayMode::Windowed };
+                self.set_display(DisplaySettings { mode, ..self.display.clone() });
+            }
+            if self.actions.pressed(input::QUIT) {
+                self.window.set_should_close(true);
+            }
+
             window_ui.platform.prepare_frame(window_ui.imgui.io_mut(), &mut self.window).unwrap();
             let ui = window_ui.imgui.frame();
 
@@ -148,37 +412,46 @@ impl Window {
             //     });
 
             ui.show_demo_window(&mut true);
+
+            let mut present_config = window_application.present_config();
+            let encoding = window_application.output_encoding();
+            if WindowUi::present_settings(ui, &mut present_config, window_application.tearing_supported(), &encoding, &mut window_application.paper_white_nits) {
+                if let Err(error) = window_application.set_present_config(present_config) {
+                    log::error!("Failed to apply the present settings, keeping the old ones: {}", error);
+                }
+            }
+
+            let mut display = self.display.clone();
+            let display_changed = WindowUi::display_settings(ui, &mut display, &self.monitors);
+
+            let save_bindings = WindowUi::bindings_window(ui, &mut self.actions, &mut window_ui.bindings_editor);
+
+            WindowUi::log_window(ui, &mut window_ui.log_viewer);
+
             window_ui.platform.prepare_render(ui, &mut self.window);
             
-            window_application.render((self.window_width, self.window_height), window_ui);
+            if let Err(error) = window_application.render((self.window_width, self.window_height), window_ui) {
+                log::error!("Giving up on the lost device: {}", error);
+                self.window.set_should_close(true);
+            }
             
             self.window.swap_buffers();
 
+            if save_bindings {
+                self.save_bindings();
+            }
+            if display_changed {
+                self.set_display(display);
+            }
+
             self.glfw.poll_events();
         }
-    }
-
-
 
-    /// Calculates shared intersecting area between 2 rectangles defined by their top left points and sizes.
-    fn intersecting_area(rectangle_1_position: (i32, i32), rectangle_1_size: (i32, i32), rectangle_2_position: (i32, i32), rectangle_2_size: (i32, i32)) -> i32 {
-        use std::cmp::{min, max};
-
-        let rectangle_1_bottom_right = (
-            rectangle_1_position.0 + rectangle_1_size.0,
-            rectangle_1_position.1 + rectangle_1_size.1
-        );
-        let rectangle_2_bottom_right = (
-            rectangle_2_position.0 + rectangle_2_size.0,
-            rectangle_2_position.1 + rectangle_2_size.1
-        );
-
-        max(0,
-            min(rectangle_2_bottom_right.0, rectangle_1_bottom_right.0) 
-            - max(rectangle_2_position.0, rectangle_1_position.0))
-        * max(0,
-            min(rectangle_2_bottom_right.1, rectangle_1_bottom_right.1)
-            - max(rectangle_2_position.1, rectangle_1_position.1))
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(error) = recorder.flush() {
+                log::warn!("Failed to write the input recording: {}", error);
+            }
+        }
     }
 
-}
\ No newline at end of file
+}
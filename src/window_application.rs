@@ -1,6 +1,4 @@
 
-use std::{ptr, borrow::BorrowMut};
-
 use windows::{
     core::*, Win32::Foundation::*, Win32::Graphics::Direct3D::*, Win32::Graphics::Direct3D11::*,
     Win32::Graphics::Dxgi::Common::*, Win32::Graphics::Dxgi::*,
@@ -14,8 +12,21 @@ use std::mem::*;
 
 extern crate glfw;
 
+use crate::adapter::{create_device, feature_level_name, AdapterPreference, CreatedDevice};
+use crate::color_space::OutputEncoding;
+use crate::config::GraphicsConfig;
+use crate::debug_layer::{DebugLayer, DebugLayerSettings};
+use crate::device_recovery::{is_device_lost, retry_while_lost, DeviceLostReason, RecoveryBackoff, ResourceRegistry, MAX_RECOVERY_ATTEMPTS};
+use crate::depth_buffer::{DepthBuffer, DepthSettings};
+use crate::mip_generator::srgb_to_linear;
+use crate::output_pass::OutputPass;
+use crate::pipeline_state::{BindTracker, BlendDesc, DepthStencilDesc, Pipeline, PipelineCache, PipelineDesc, RasterizerDesc, Topology};
+use crate::sampler_cache::ComparisonFunc;
+use crate::sampler_cache::SamplerCache;
+use crate::swap_chain::{DxgiSwapChain, PresentConfig, SwapChain};
 use crate::window::Window;
 
+#[repr(C)]
 pub struct VertexPositionColor {
     pub position: XMFLOAT3,
     pub color: XMFLOAT3,
@@ -27,21 +38,32 @@ pub struct VertexPositionColor {
 pub struct WindowApplication {
     // window: Window,
 
-    dxgi_factory: IDXGIFactory2,
     pub device: ID3D11Device,
     pub device_context: ID3D11DeviceContext,
-    swap_chain: IDXGISwapChain1,
-    render_target: Option<ID3D11RenderTargetView>,
+    debug_layer: Option<DebugLayer>,
+    swap_chain: SwapChain<DxgiSwapChain>,
+    depth_buffer: DepthBuffer,
+    output_pass: OutputPass,
+    /// Luminance of scene white on HDR displays.
+    pub paper_white_nits: f32,
+    pub sampler_cache: SamplerCache,
+    pub pipeline_cache: PipelineCache,
+    bind_tracker: BindTracker,
 
-    vertex_layout: ID3D11InputLayout,
     triangle_vertices: ID3D11Buffer,
-
-    vertex_shader: ID3D11VertexShader,
-    pixel_shader: ID3D11PixelShader,
+    triangle_pipeline: Pipeline,
+
+    hwnd: HWND,
+    adapter_preference: AdapterPreference,
+    debug_settings: DebugLayerSettings,
+    /// Resources created from outside, recreated together with the ones above when the device is lost.
+    pub resources: ResourceRegistry<ID3D11Device>,
+    device_lost: bool,
+    recovery_backoff: RecoveryBackoff,
 }
 
 impl WindowApplication {
-    pub fn new(window: &Window) -> Result<Self> {
+    pub fn new(window: &Window, config: &GraphicsConfig) -> Result<Self> {
         
 
         // let mut glfw = glfw::init(glfw::LOG_ERRORS).unwrap();
@@ -76,102 +98,76 @@ impl WindowApplication {
         //     }
         // }
 
-        let dxgi_factory: IDXGIFactory2 = unsafe { CreateDXGIFactory1()? };
-    
-        // let dxgi_factory: IDXGIFactory2 = unsafe { CreateDXGIFactory2(DXGI_CREATE_FACTORY_DEBUG )? };
-
-        let mut device: Option<ID3D11Device> = None;
-
-        // pub unsafe fn D3D11CreateDevice<P0, P1>(
-        //     padapter: P0,
-        //     drivertype: super::Direct3D::D3D_DRIVER_TYPE,
-        //     software: P1,
-        //     flags: D3D11_CREATE_DEVICE_FLAG,
-        //     pfeaturelevels: ::core::option::Option<&[super::Direct3D::D3D_FEATURE_LEVEL]>,
-        //     sdkversion: u32,
-        //     ppdevice: ::core::option::Option<*mut ::core::option::Option<ID3D11Device>>,
-        //     pfeaturelevel: ::core::option::Option<*mut super::Direct3D::D3D_FEATURE_LEVEL>,
-        //     ppimmediatecontext: ::core::option::Option<*mut ::core::option::Option<ID3D11DeviceContext>>
-        // ) -> ::windows_core::Result<()>
-
-        let mut device_context: Option<ID3D11DeviceContext> = Default::default();
-
-        unsafe {
-            D3D11CreateDevice(
-                None,
-                D3D_DRIVER_TYPE_HARDWARE,
-                HINSTANCE::default(),
-                D3D11_CREATE_DEVICE_DEBUG | D3D11_CREATE_DEVICE_BGRA_SUPPORT,
-                Some(&[D3D_FEATURE_LEVEL_11_1]),
-                D3D11_SDK_VERSION,
-                Some(&mut device),
-                None,
-                Some(&mut device_context),
-            ).unwrap();
-        };
-
-        let device_unwrapped = device.unwrap();
-
-        let debug: &ID3D11Debug = unsafe { ID3D11Debug::from_raw_borrowed(&device_unwrapped.abi()).unwrap() };
-
-        // println!("{:?}", debug);
-
-        // (2560, 1440)
-        let swap_chain_descriptor = DXGI_SWAP_CHAIN_DESC1 {
-            Width: window.window_width,
-            Height: window.window_height,
-            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
-            SampleDesc: DXGI_SAMPLE_DESC {
-                Count: 1,
-                Quality: 0,
-            },
-            BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
-            BufferCount: 2,
-            SwapEffect: DXGI_SWAP_EFFECT_FLIP_DISCARD,
-            ..Default::default()
-        };
-
-        let swap_chain_fullscreen_descriptor = DXGI_SWAP_CHAIN_FULLSCREEN_DESC {
-            Windowed: BOOL(1),
-            ..Default::default()
-        };
-
+        let adapter_preference = config.adapter_preference();
+        let debug_settings = config.debug_layer_settings();
+        let created = Self::create_device(&adapter_preference, &debug_settings)?;
 
         // let window_handle = window.get_win32_window();
         // let hwnd: HWND = unsafe { std::mem::transmute(window_handle) };
 
-        let hwnd = window.get_win32();
+        Self::from_device(
+            created,
+            window.get_win32(),
+            (window.window_width, window.window_height),
+            adapter_preference,
+            debug_settings,
+            config.present_config(),
+            config.depth_settings(),
+        )
+    }
 
-        let swap_chain: IDXGISwapChain1;
+    /// Creates a device on the preferred adapter, without the debug layer when it is not installed.
+    fn create_device(adapter_preference: &AdapterPreference, debug_settings: &DebugLayerSettings) -> Result<CreatedDevice> {
+        let dxgi_factory: IDXGIFactory2 = unsafe { CreateDXGIFactory1()? };
+    
+        // let dxgi_factory: IDXGIFactory2 = unsafe { CreateDXGIFactory2(DXGI_CREATE_FACTORY_DEBUG )? };
 
-        unsafe { 
-            swap_chain = dxgi_factory.CreateSwapChainForHwnd(
-                &device_unwrapped,
-                hwnd,
-                &swap_chain_descriptor,
-                Some(&swap_chain_fullscreen_descriptor),
-                None
-            ).unwrap();
+        let created = match create_device(&dxgi_factory, adapter_preference, debug_settings.device_flags() | D3D11_CREATE_DEVICE_BGRA_SUPPORT) {
+            Err(error) if error.code() == DXGI_ERROR_SDK_COMPONENT_MISSING => {
+                log::warn!("D3D11 debug layer is not installed, continuing without it");
+                create_device(&dxgi_factory, adapter_preference, D3D11_CREATE_DEVICE_BGRA_SUPPORT)?
+            }
+            result => result?,
         };
+        log::info!("Using adapter {} at feature level {}", created.adapter, feature_level_name(created.feature_level));
 
-        // CreateSwapchainResources
-
-        let back_buffer: ID3D11Resource = unsafe { swap_chain.GetBuffer(0).unwrap() };
-
-        let mut render_target: Option<ID3D11RenderTargetView> = Default::default();
+        Ok(created)
+    }
 
-        // windows::core::IntoParam<windows::Win32::Graphics::Direct3D11::ID3D11Resource
+    /// Creates the swap chain for `hwnd` and everything rendered with the device.
+    fn from_device(
+        created: CreatedDevice,
+        hwnd: HWND,
+        size: (u32, u32),
+        adapter_preference: AdapterPreference,
+        debug_settings: DebugLayerSettings,
+        present_config: PresentConfig,
+        depth_settings: DepthSettings,
+    ) -> Result<Self> {
+        // A WARP device lives on its own factory, swap chains have to be created with that one.
+        let dxgi_factory = created.factory;
+        let device_unwrapped = created.device;
+        let device_context = created.device_context;
+
+        let debug_layer = DebugLayer::new(&device_unwrapped, debug_settings);
 
-        // expected raw pointer `*mut std::option::Option<windows::Win32::Graphics::Direct3D11::ID3D11RenderTargetView>`
-        // found reference `&windows::Win32::Graphics::Direct3D11::ID3D11RenderTargetView`
+        // CreateSwapchainResources
 
-        unsafe { 
-            device_unwrapped.CreateRenderTargetView(
-                &back_buffer,
-                None,
-                Some(&mut render_target),
-            ).unwrap();
-        };
+        let (width, height) = size;
+        let swap_chain = SwapChain::new(
+            DxgiSwapChain::new(
+                &dxgi_factory,
+                &device_unwrapped,
+                &device_context,
+                hwnd,
+                width,
+                height,
+                &present_config,
+            )?,
+            width,
+            height,
+            present_config,
+        )?;
 
 
         // let vertex_shader = Self::create_vertex_shader(&device_unwrapped, "main.vs.hlsl");
@@ -284,9 +280,6 @@ impl WindowApplication {
             Usage: D3D11_USAGE_IMMUTABLE,
             BindFlags: 1, //D3D11_BIND_VERTEX_BUFFER,
             ..Default::default()
-            // CPUAccessFlags: todo!(),
-            // MiscFlags: todo!(),
-            // StructureByteStride: todo!(),
         };
 
         let resource_data = D3D11_SUBRESOURCE_DATA {
@@ -305,25 +298,60 @@ impl WindowApplication {
             ).unwrap();
         }
 
+        let depth_buffer = DepthBuffer::new(&device_unwrapped, width, height, depth_settings)?;
+
+        let mut sampler_cache = SamplerCache::new(&device_unwrapped);
+        let mut pipeline_cache = PipelineCache::new(&device_unwrapped);
+
+        let output_pass = OutputPass::new(&device_unwrapped, &mut pipeline_cache, &mut sampler_cache, width, height)?;
+
+        let triangle_pipeline = pipeline_cache.create_pipeline(&PipelineDesc {
+            vertex_shader,
+            pixel_shader: Some(pixel_shader),
+            input_layout: Some(vertex_layout.unwrap()),
+            blend: BlendDesc::OPAQUE,
+            rasterizer: RasterizerDesc::NO_CULL,
+            depth_stencil: depth_settings.depth_stencil(Self::triangle_depth_stencil()),
+            topology: Topology::TriangleList,
+        })?;
+
         let application = WindowApplication { 
             // window,
 
-            dxgi_factory,
             device: device_unwrapped,
-            device_context: device_context.unwrap(),
+            device_context,
+            debug_layer,
             swap_chain,
-            render_target: render_target,
+            depth_buffer,
+            output_pass,
+            paper_white_nits: OutputEncoding::default().paper_white_nits,
+            sampler_cache,
+            pipeline_cache,
+            bind_tracker: BindTracker::new(),
 
-            vertex_layout: vertex_layout.as_ref().unwrap().to_owned(),
             triangle_vertices: triangle_vertices.as_ref().unwrap().to_owned(),
-
-            vertex_shader,
-            pixel_shader,
+            triangle_pipeline,
+
+            hwnd,
+            adapter_preference,
+            debug_settings,
+            resources: ResourceRegistry::new(),
+            device_lost: false,
+            recovery_backoff: RecoveryBackoff::default(),
         };
 
         Ok(application)
     }
 
+    /// The triangle sits at depth 0, which only passes against a reversed-Z clear when equal passes too.
+    fn triangle_depth_stencil() -> DepthStencilDesc {
+        DepthStencilDesc { depth_func: ComparisonFunc::LessEqual, ..DepthStencilDesc::DEFAULT }
+    }
+
+    pub fn depth_settings(&self) -> DepthSettings {
+        self.depth_buffer.settings
+    }
+
     pub fn compile_shader(file_name: &str, profile: &str) -> ID3DBlob {
         let exe_path = std::env::current_exe().ok().unwrap();
         let asset_path = exe_path.parent().unwrap();
@@ -350,18 +378,20 @@ impl WindowApplication {
         .map(|()| shader.unwrap());
 
 
-        match error {
-            Some(err) => {
-                unsafe { 
-                    let ptr = err.GetBufferPointer() as *const u8;
-                    let size = err.GetBufferSize();
+        if let Some(err) = error {
+            unsafe {
+                let ptr = err.GetBufferPointer() as *const u8;
+                let size = err.GetBufferSize();
 
-                    let msg = String::from_utf8_lossy(std::slice::from_raw_parts(ptr, size));
-                    println!("aaa {:?}", msg);
+                let msg = String::from_utf8_lossy(std::slice::from_raw_parts(ptr, size));
+                // The messages are warnings when compiling succeeded.
+                if shader.is_ok() {
+                    log::warn!("{}: {}", file_name, msg);
+                } else {
+                    log::error!("{}: {}", file_name, msg);
                 }
             }
-            None => println!("None i guess"),
-        };
+        }
 
         shader.unwrap()
 
@@ -429,7 +459,35 @@ impl WindowApplication {
     //     unsafe { pixel_shader.as_ref().unwrap().to_owned().unwrap() }
     // }
 
-    pub fn render(&self, viewport_size: (u32, u32), window_ui: &mut crate::window_ui::WindowUi) {
+    /// Fails only when the device was lost and could not be recovered, the application has to exit then.
+    pub fn render(&mut self, viewport_size: (u32, u32), window_ui: &mut crate::window_ui::WindowUi) -> Result<()> {
+        if self.device_lost {
+            // The UI frame is ended first, recreating the renderer reads the font atlas.
+            window_ui.imgui.render();
+
+            let now = std::time::Instant::now();
+            if self.recovery_backoff.ready(now) {
+                if let Err(error) = self.recover_device(window_ui) {
+                    if !self.recovery_backoff.failed(now) {
+                        return Err(error);
+                    }
+                    log::error!(
+                        "Failed to recover from a lost device, retrying in {:?}: {}",
+                        RecoveryBackoff::delay(self.recovery_backoff.failures()),
+                        error
+                    );
+                }
+            }
+            return Ok(());
+        }
+
+        self.swap_chain.wait_for_frame();
+
+        let Some(render_target) = self.swap_chain.target().cloned() else {
+            // The UI frame was started already and has to be ended even when nothing is drawn.
+            window_ui.imgui.render();
+            return Ok(());
+        };
 
         let viewport = D3D11_VIEWPORT {
             TopLeftX: 0f32,
@@ -443,10 +501,14 @@ impl WindowApplication {
         };
 
         unsafe { 
-            self.device_context.ClearRenderTargetView(&self.render_target.clone().unwrap(), &[0.1f32, 0.1f32, 0.1f32, 0.1f32]);
+            // The scene is linear, the clear color is given in sRGB.
+            let clear_color = srgb_to_linear(0.1);
+            let scene_target = self.output_pass.scene.render_target.clone();
+            self.device_context.ClearRenderTargetView(&scene_target, &[clear_color, clear_color, clear_color, 1.0]);
+            self.depth_buffer.clear(&self.device_context);
 
 
-            self.device_context.IASetInputLayout(&self.vertex_layout);
+            self.triangle_pipeline.bind(&self.device_context, &mut self.bind_tracker);
 
             let vertex_stride: u32 = size_of::<VertexPositionColor>() as u32;
             let vertex_offset: u32 = 0;
@@ -459,105 +521,164 @@ impl WindowApplication {
                 Some(&vertex_offset)
             );
 
-            self.device_context.IASetPrimitiveTopology(D3D11_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
-
             self.device_context.RSSetViewports(Some(&[viewport]));
 
-            self.device_context.VSSetShader(&self.vertex_shader, None);
-            self.device_context.PSSetShader(&self.pixel_shader, None);
-
-            self.device_context.OMSetRenderTargets(Some(&[self.render_target.clone()]), None);
+            self.device_context.OMSetRenderTargets(Some(&[Some(scene_target)]), &self.depth_buffer.view);
 
             self.device_context.Draw(3, 0);
 
 
 
 
-            // let draw_data = window_ui.imgui.render();
-            // window_ui.renderer.render(draw_data).expect("error rendering imgui");
+            let draw_data = window_ui.imgui.render();
+            if let Err(error) = window_ui.renderer.render(draw_data) {
+                self.handle_error(error);
+            }
 
+            let encoding = self.output_encoding();
+            self.output_pass.encode(&self.device_context, &mut self.bind_tracker, &render_target, &encoding);
 
-            let _ = self.swap_chain.Present(0, 0); 
+            if let Err(error) = self.swap_chain.present() {
+                self.handle_error(error);
+            }
         };
 
+        if let Some(debug_layer) = &self.debug_layer {
+            debug_layer.flush_messages();
+        }
+
+        Ok(())
     }
 
-    pub fn on_resize(&mut self, swap_chain_buffer_size: (u32, u32)) {
+    /// Resizes the swap chain and the targets sized like it. Losing the device is handled here,
+    /// other errors are returned.
+    pub fn on_resize(&mut self, swap_chain_buffer_size: (u32, u32)) -> Result<()> {
+        if self.device_lost {
+            return Ok(());
+        }
 
-        self.render_target = None;
+        match self.resize_buffers(swap_chain_buffer_size.0, swap_chain_buffer_size.1) {
+            Err(error) if is_device_lost(error.code()) => {
+                self.handle_error(error);
+                Ok(())
+            }
+            result => result,
+        }
+    }
 
-        unsafe { 
+    fn resize_buffers(&mut self, width: u32, height: u32) -> Result<()> {
+        if self.swap_chain.resize(width, height)? {
+            self.depth_buffer.resize(&self.device, width, height)?;
+            self.output_pass.resize(&self.device, width, height)?;
+        }
+        Ok(())
+    }
 
-            self.device_context.OMSetRenderTargets(None, None);
-            self.device_context.Flush();
+    /// Marks the device as lost so it is recreated next frame, other errors only cost the current frame.
+    fn handle_error(&mut self, error: Error) {
+        if !is_device_lost(error.code()) {
+            log::error!("Skipping the frame: {}", error);
+            return;
+        }
 
-        };
+        let reason = unsafe { self.device.GetDeviceRemovedReason() }.err().map(|error| error.code()).unwrap_or(error.code());
+        log::error!("Device lost: {:?}", DeviceLostReason::from_hresult(reason));
+        self.device_lost = true;
+    }
 
-        unsafe { 
-            self.swap_chain.ResizeBuffers(
-                0,
-                swap_chain_buffer_size.0,
-                swap_chain_buffer_size.1,
-                DXGI_FORMAT_UNKNOWN,
-                0,
-            ).unwrap();
+    /// Creates a new device and recreates everything on it, keeping the current settings.
+    pub fn recover_device(&mut self, window_ui: &mut crate::window_ui::WindowUi) -> Result<()> {
+        retry_while_lost(MAX_RECOVERY_ATTEMPTS, |attempt| {
+            log::info!("Recreating the device, attempt {}", attempt);
+            self.try_recover_device(window_ui)
+        })?;
+
+        self.device_lost = false;
+        Ok(())
+    }
+
+    /// Recreation order: device, swap chain and the application's resources, registered resources, UI renderer.
+    fn try_recover_device(&mut self, window_ui: &mut crate::window_ui::WindowUi) -> Result<()> {
+        self.resources.release_all();
+        self.debug_layer = None;
+        // A window can only have one swap chain, the old one goes before the new one is created.
+        self.swap_chain.backend.release();
+
+        let created = Self::create_device(&self.adapter_preference, &self.debug_settings)?;
+        let mut recovered = Self::from_device(
+            created,
+            self.hwnd,
+            self.swap_chain.size(),
+            self.adapter_preference.clone(),
+            self.debug_settings,
+            self.present_config(),
+            self.depth_settings(),
+        )?;
+
+        self.resources.recreate_all(&recovered.device)?;
+        unsafe {
+            window_ui.renderer.recreate_device_objects(
+                &mut window_ui.imgui,
+                &recovered.device,
+                &recovered.device_context,
+                &mut recovered.sampler_cache,
+                &mut recovered.pipeline_cache,
+            )?
         };
+
+        recovered.paper_white_nits = self.paper_white_nits;
+        recovered.resources = std::mem::take(&mut self.resources);
+        recovered.swap_chain.take_listeners(&mut self.swap_chain);
+        *self = recovered;
+        Ok(())
     }
 
-    // pub fn run(&mut self) {
-    //     while !self.window.window.should_close() {
-    //         for (_, event) in glfw::flush_messages(&self.window.events) {
-    //             println!("Got window event: {:?}", event);
-    
-    //             match event {
-    //                 glfw::WindowEvent::FramebufferSize(_width, _height) => {
-                        
-    //                 }
-    //                 glfw::WindowEvent::Key(glfw::Key::Enter, _, glfw::Action::Press, _) => {
-    //                     self.window.window.with_window_mode(|mode| {
-    //                         match mode {
-    //                             glfw::WindowMode::Windowed => println!("Windowed"),
-    //                             glfw::WindowMode::FullScreen(monitor) => println!("FullScreen({:?})", monitor.get_name()),
-    //                         }
-    //                     });
-
-    //                     let monitor = glfw::Monitor::from_window(&self.window.window);
-    //                     println!("got monitor {:?}", monitor);
-
-    //                     self.window.glfw.with_connected_monitors(|_, monitors| {
-    //                         for monitor in monitors.iter() {
-    //                             println!("{:?}: {:?}", monitor.get_name(), monitor.get_video_mode());
-    //                         }
-    //                     });
-
-    //                     // let video_mode = monitor.get_video_mode();
-    //                     // println!("got video mode");
-    //                     // let video_mode = video_mode.expect("asdsd");
-    //                     // println!("got video mode2");
-                        
-    //                     // self.window.window.set_pos(0, 0);
-    //                     // self.window.window.set_size(2560, 1440);
-
-    //                     // self.window.window.set_monitor(glfw::WindowMode::FullScreen(&monitor), 0, 0, video_mode.width, video_mode.height, Some(video_mode.refresh_rate));
-    //                     // println!("got set monitor");
-    //                 }
-    //                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
-    //                     println!("Closing the window.");
-    //                     self.window.window.set_should_close(true);
-    //                 }
-    //                 _ => {}
-    //             };
-    //         }
-        
-    //         self.render();
-        
-    //         // window.swap_buffers();
-    //         self.window.glfw.poll_events();
-    //     }
-    // }
+    /// How the scene is encoded for the color space the swap chain negotiated.
+    pub fn output_encoding(&self) -> OutputEncoding {
+        OutputEncoding {
+            color_space: self.swap_chain.color_space(),
+            paper_white_nits: self.paper_white_nits,
+            max_nits: self.swap_chain.display_max_nits(),
+        }
+    }
+
+    pub fn present_config(&self) -> PresentConfig {
+        self.swap_chain.config()
+    }
 
-    fn _cleanup(&self) {
+    /// Reconfigures the swap chain, keeping the old config when that fails. Losing the device is
+    /// handled here, other errors are returned.
+    pub fn set_present_config(&mut self, config: PresentConfig) -> Result<()> {
+        if self.device_lost {
+            return Ok(());
+        }
 
+        match self.swap_chain.set_config(config) {
+            Err(error) if is_device_lost(error.code()) => {
+                self.handle_error(error);
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
     }
 
-}
\ No newline at end of file
+    pub fn tearing_supported(&self) -> bool {
+        self.swap_chain.tearing_supported()
+    }
+
+    /// Releases all device objects, then reports the ones that leaked when the debug layer is enabled.
+    /// Everything else holding device objects, like the UI renderer, has to be dropped before.
+    pub fn cleanup(mut self) {
+        unsafe {
+            self.device_context.ClearState();
+            self.device_context.Flush();
+        }
+
+        let debug_layer = self.debug_layer.take();
+        drop(self);
+
+        if let Some(debug_layer) = debug_layer {
+            debug_layer.report_live_objects();
+        }
+    }
+}
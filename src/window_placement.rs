@@ -0,0 +1,249 @@
+//! Saving the window's position, size and state on exit and putting it back on the next start.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::display_mode::{DisplayMode, VideoMode};
+
+/// Where and how the window was shown. Position and size are of the client area in screen
+/// coordinates and describe the windowed rectangle, also while maximized or fullscreen.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowPlacement {
+    /// Name of the monitor the window was on, as reported by glfw.
+    pub monitor: Option<String>,
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    #[serde(default)]
+    pub maximized: bool,
+    #[serde(default)]
+    pub mode: DisplayMode,
+    /// The chosen exclusive fullscreen mode, kept while windowed too.
+    pub video_mode: Option<VideoMode>,
+}
+
+impl WindowPlacement {
+    /// Reads a placement saved by [`WindowPlacement::save`], `None` when there is none or it can't be read.
+    pub fn load(path: &Path) -> Option<Self> {
+        let text = std::fs::read_to_string(path).ok()?;
+        match toml::from_str(&text) {
+            Ok(placement) => Some(placement),
+            Err(error) => {
+                log::warn!("Ignoring window placement in {}: {}", path.display(), error);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// A connected monitor's name and the part of the desktop it shows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MonitorRect {
+    pub name: Option<String>,
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+}
+
+impl MonitorRect {
+    pub fn from_glfw(monitor: &glfw::Monitor) -> Self {
+        // The work area leaves out the taskbar.
+        let (x, y, width, height) = monitor.get_workarea();
+        MonitorRect { name: monitor.get_name(), position: (x, y), size: (width.max(0) as u32, height.max(0) as u32) }
+    }
+
+    /// Where a window of `size` is centered on this monitor.
+    pub fn centered(&self, size: (u32, u32)) -> (i32, i32) {
+        (
+            self.position.0 + (self.size.0 as i32 - size.0 as i32) / 2,
+            self.position.1 + (self.size.1 as i32 - size.1 as i32) / 2,
+        )
+    }
+}
+
+/// Calculates shared intersecting area between 2 rectangles defined by their top left points and sizes.
+pub fn intersecting_area(rectangle_1_position: (i32, i32), rectangle_1_size: (i32, i32), rectangle_2_position: (i32, i32), rectangle_2_size: (i32, i32)) -> i32 {
+    use std::cmp::{min, max};
+
+    let rectangle_1_bottom_right = (
+        rectangle_1_position.0 + rectangle_1_size.0,
+        rectangle_1_position.1 + rectangle_1_size.1
+    );
+    let rectangle_2_bottom_right = (
+        rectangle_2_position.0 + rectangle_2_size.0,
+        rectangle_2_position.1 + rectangle_2_size.1
+    );
+
+    max(0,
+        min(rectangle_2_bottom_right.0, rectangle_1_bottom_right.0)
+        - max(rectangle_2_position.0, rectangle_1_position.0))
+    * max(0,
+        min(rectangle_2_bottom_right.1, rectangle_1_bottom_right.1)
+        - max(rectangle_2_position.1, rectangle_1_position.1))
+}
+
+/// Index of the monitor sharing the largest area with the rectangle, `None` when it is on none of them.
+pub fn best_monitor(monitors: &[MonitorRect], position: (i32, i32), size: (u32, u32)) -> Option<usize> {
+    monitors
+        .iter()
+        .map(|monitor| intersecting_area(position, (size.0 as i32, size.1 as i32), monitor.position, (monitor.size.0 as i32, monitor.size.1 as i32)))
+        .enumerate()
+        .filter(|(_, area)| *area > 0)
+        .max_by_key(|(index, area)| (*area, std::cmp::Reverse(*index)))
+        .map(|(index, _)| index)
+}
+
+/// Fits a saved placement onto the monitors connected now, the first of which is the primary one.
+///
+/// The window goes back to the monitor it was saved on. When that monitor is gone it is centered
+/// on the primary one, and when it is no longer fully on screen it is shrunk to fit and moved
+/// onto its monitor. `None` when there are no monitors at all.
+pub fn restore_placement(placement: &WindowPlacement, monitors: &[MonitorRect]) -> Option<WindowPlacement> {
+    let saved_monitor = placement
+        .monitor
+        .as_ref()
+        .and_then(|name| monitors.iter().position(|monitor| monitor.name.as_ref() == Some(name)));
+
+    let Some(index) = saved_monitor else {
+        let primary = monitors.first()?;
+        let size = (placement.size.0.min(primary.size.0), placement.size.1.min(primary.size.1));
+        return Some(WindowPlacement { monitor: primary.name.clone(), position: primary.centered(size), size, ..placement.clone() });
+    };
+
+    let monitor = &monitors[index];
+    let size = (placement.size.0.min(monitor.size.0), placement.size.1.min(monitor.size.1));
+    let area = size.0 as i64 * size.1 as i64;
+
+    // Monitors don't overlap, so a window spanning several of them is fully visible when the parts add up.
+    let visible: i64 = monitors
+        .iter()
+        .map(|other| intersecting_area(placement.position, (size.0 as i32, size.1 as i32), other.position, (other.size.0 as i32, other.size.1 as i32)) as i64)
+        .sum();
+
+    let position = if visible >= area {
+        placement.position
+    } else {
+        (
+            placement.position.0.clamp(monitor.position.0, monitor.position.0 + (monitor.size.0 - size.0) as i32),
+            placement.position.1.clamp(monitor.position.1, monitor.position.1 + (monitor.size.1 - size.1) as i32),
+        )
+    };
+
+    Some(WindowPlacement { position, size, ..placement.clone() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, position: (i32, i32), size: (u32, u32)) -> MonitorRect {
+        MonitorRect { name: Some(name.to_string()), position, size }
+    }
+
+    /// A 1920x1080 primary monitor with a 2560x1440 one to its left.
+    fn two_monitors() -> Vec<MonitorRect> {
+        vec![monitor("Primary", (0, 0), (1920, 1080)), monitor("Left", (-2560, 0), (2560, 1440))]
+    }
+
+    fn placement(monitor: &str, position: (i32, i32), size: (u32, u32)) -> WindowPlacement {
+        WindowPlacement { monitor: Some(monitor.to_string()), position, size, maximized: false, mode: DisplayMode::Windowed, video_mode: None }
+    }
+
+    #[test]
+    fn a_visible_placement_is_kept() {
+        let saved = WindowPlacement { maximized: true, ..placement("Left", (-2000, 100), (1280, 720)) };
+        assert_eq!(restore_placement(&saved, &two_monitors()), Some(saved));
+    }
+
+    #[test]
+    fn missing_monitor_centers_on_the_primary() {
+        let saved = placement("Gone", (4000, 200), (1280, 720));
+        let restored = restore_placement(&saved, &two_monitors()).unwrap();
+
+        assert_eq!(restored.monitor.as_deref(), Some("Primary"));
+        assert_eq!(restored.position, (320, 180));
+        assert_eq!(restored.size, (1280, 720));
+    }
+
+    #[test]
+    fn missing_monitor_name_centers_on_the_primary() {
+        let saved = WindowPlacement { monitor: None, ..placement("", (-2000, 100), (800, 600)) };
+        assert_eq!(restore_placement(&saved, &two_monitors()).unwrap().position, (560, 240));
+    }
+
+    #[test]
+    fn no_monitors_gives_nothing() {
+        assert_eq!(restore_placement(&placement("Primary", (0, 0), (800, 600)), &[]), None);
+    }
+
+    #[test]
+    fn off_screen_rect_is_moved_onto_its_monitor() {
+        let monitors = two_monitors();
+
+        let below = restore_placement(&placement("Primary", (100, 2000), (800, 600)), &monitors).unwrap();
+        assert_eq!(below.position, (100, 480));
+
+        let partly_right = restore_placement(&placement("Primary", (1500, 100), (800, 600)), &monitors).unwrap();
+        assert_eq!(partly_right.position, (1120, 100));
+
+        let above_left = restore_placement(&placement("Left", (-3000, -500), (800, 600)), &monitors).unwrap();
+        assert_eq!(above_left.position, (-2560, 0));
+        assert_eq!(above_left.size, (800, 600));
+    }
+
+    #[test]
+    fn window_larger_than_its_monitor_is_shrunk() {
+        let restored = restore_placement(&placement("Primary", (-100, -50), (2560, 1600)), &two_monitors()).unwrap();
+
+        assert_eq!(restored.size, (1920, 1080));
+        assert_eq!(restored.position, (0, 0));
+    }
+
+    #[test]
+    fn window_spanning_two_monitors_stays() {
+        let saved = placement("Primary", (-400, 100), (1280, 720));
+        assert_eq!(restore_placement(&saved, &two_monitors()), Some(saved));
+    }
+
+    #[test]
+    fn window_partly_outside_both_monitors_is_moved() {
+        // The left monitor is taller, below the primary there is no desktop.
+        let restored = restore_placement(&placement("Primary", (-400, 600), (1280, 720)), &two_monitors()).unwrap();
+        assert_eq!(restored.position, (0, 360));
+    }
+
+    #[test]
+    fn best_monitor_has_the_largest_overlap() {
+        let monitors = two_monitors();
+
+        assert_eq!(best_monitor(&monitors, (-400, 100), (1280, 720)), Some(0));
+        assert_eq!(best_monitor(&monitors, (-1000, 100), (1280, 720)), Some(1));
+        assert_eq!(best_monitor(&monitors, (5000, 5000), (100, 100)), None);
+        // Equal overlap goes to the earlier monitor.
+        assert_eq!(best_monitor(&monitors, (-100, 0), (200, 100)), Some(0));
+    }
+
+    #[test]
+    fn intersecting_areas() {
+        assert_eq!(intersecting_area((0, 0), (10, 10), (5, 5), (10, 10)), 25);
+        assert_eq!(intersecting_area((0, 0), (10, 10), (10, 0), (10, 10)), 0);
+        assert_eq!(intersecting_area((-10, -10), (5, 5), (0, 0), (10, 10)), 0);
+        assert_eq!(intersecting_area((-5, -5), (20, 20), (0, 0), (10, 10)), 100);
+    }
+
+    #[test]
+    fn placement_round_trips_through_toml() {
+        let saved = WindowPlacement {
+            mode: DisplayMode::Exclusive,
+            video_mode: Some(VideoMode { width: 1920, height: 1080, refresh_rate: 144 }),
+            ..placement("Left", (-2000, 100), (1280, 720))
+        };
+
+        let text = toml::to_string(&saved).unwrap();
+        assert_eq!(toml::from_str::<WindowPlacement>(&text).unwrap(), saved);
+    }
+}
@@ -1,18 +1,51 @@
 
-use imgui::{Context, FontConfig, FontGlyphRanges, FontSource, Ui, ConfigFlags};
+use imgui::{Context, FontConfig, FontSource, Ui, ConfigFlags};
 use windows::Win32::Graphics::Direct3D11::{ID3D11Device, ID3D11DeviceContext};
 use crate::imgui_dx11_renderer::Renderer;
-use crate::imgui_glfw_support::{HiDpiMode, GlfwPlatform};
+use crate::config::UiConfig;
+use crate::imgui_glfw_support::GlfwPlatform;
+use crate::pipeline_state::PipelineCache;
+use crate::sampler_cache::SamplerCache;
+use crate::color_space::{OutputColorSpace, OutputEncoding};
+use crate::swap_chain::PresentConfig;
+use crate::display_mode::{DisplayMode, DisplaySettings, MonitorModes};
+use crate::input::ActionMap;
+use crate::logging::{self, LogViewFilter};
+use log::{Level, LevelFilter};
 
 pub struct WindowUi {
     pub imgui: Context,
     pub platform: GlfwPlatform,
     pub renderer: Renderer,
+    pub log_viewer: LogViewer,
+    pub bindings_editor: BindingsEditor,
+    /// The DPI factor the fonts were rasterized at.
+    font_scale: f64,
+}
+
+/// State of the log window.
+pub struct LogViewer {
+    pub filter: LogViewFilter,
+    /// Keeps the newest record in view while scrolled to the bottom.
+    pub auto_scroll: bool,
+}
+
+impl Default for LogViewer {
+    fn default() -> Self {
+        LogViewer { filter: LogViewFilter::default(), auto_scroll: true }
+    }
+}
+
+/// State of the input bindings window.
+#[derive(Default)]
+pub struct BindingsEditor {
+    /// The action the next pressed input gets bound to.
+    pub capture: Option<String>,
 }
 
 impl WindowUi {
     
-    pub fn new(window: &crate::window::Window, device: &ID3D11Device, device_context: &ID3D11DeviceContext) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(window: &crate::window::Window, config: &UiConfig, device: &ID3D11Device, device_context: &ID3D11DeviceContext, samplers: &mut SamplerCache, pipelines: &mut PipelineCache) -> Result<Self, Box<dyn std::error::Error>> {
         // return Ok(WindowUi {  });
 
         let mut imgui = imgui::Context::create();
@@ -26,14 +59,210 @@ impl WindowUi {
 
         let mut platform = GlfwPlatform::init(&mut imgui);
 
-        let dpi_mode = HiDpiMode::Default;
+        let dpi_mode = config.hidpi_mode();
 
         platform.attach_window(imgui.io_mut(), &window.window, dpi_mode);
+        let font_scale = platform.hidpi_factor();
+        Self::load_fonts(&mut imgui, font_scale);
 
-        let renderer = unsafe { Renderer::new(&mut imgui, device, device_context).expect("Failed to initialize renderer") };
+        let renderer = unsafe { Renderer::new(&mut imgui, device, device_context, samplers, pipelines).expect("Failed to initialize renderer") };
         
-        Ok(WindowUi { imgui, platform, renderer })
+        Ok(WindowUi { imgui, platform, renderer, log_viewer: LogViewer::default(), bindings_editor: BindingsEditor::default(), font_scale })
+    }
+
+    /// Rasterizes the fonts at `scale` times their size and draws them scaled back down, so text
+    /// stays sharp on high DPI monitors.
+    fn load_fonts(imgui: &mut Context, scale: f64) {
+        let fonts = imgui.fonts();
+        fonts.clear();
+        fonts.add_font(&[FontSource::DefaultFontData {
+            config: Some(FontConfig { size_pixels: (13.0 * scale) as f32, ..FontConfig::default() }),
+        }]);
+        imgui.io_mut().font_global_scale = (1.0 / scale) as f32;
     }
 
+    /// Rebuilds the fonts when the DPI factor changed, e.g. after the window moved to another
+    /// monitor. Call before starting a frame.
+    pub fn update_fonts(&mut self, samplers: &mut SamplerCache) {
+        let scale = self.platform.hidpi_factor();
+        if scale == self.font_scale {
+            return;
+        }
+
+        log::debug!("Rebuilding fonts for DPI factor {}", scale);
+        Self::load_fonts(&mut self.imgui, scale);
+        if let Err(error) = self.renderer.rebuild_font_texture(&mut self.imgui, samplers) {
+            log::error!("Failed to rebuild the font texture: {}", error);
+        }
+        self.font_scale = scale;
+    }
+
+    /// Edits `config` and the paper white luminance in a settings window, returns whether `config` changed.
+    pub fn present_settings(ui: &Ui, config: &mut PresentConfig, tearing_supported: bool, encoding: &OutputEncoding, paper_white_nits: &mut f32) -> bool {
+        let mut changed = false;
+
+        ui.window("Presentation")
+            .always_auto_resize(true)
+            .build(|| {
+                changed |= ui.slider("Sync interval", 0, PresentConfig::MAX_SYNC_INTERVAL, &mut config.sync_interval);
+                ui.disabled(!tearing_supported, || {
+                    changed |= ui.checkbox("Allow tearing", &mut config.allow_tearing);
+                });
+                if !tearing_supported {
+                    ui.text_disabled("Tearing is not supported on this system");
+                }
+                changed |= ui.slider("Buffer count", PresentConfig::MIN_BUFFER_COUNT, PresentConfig::MAX_BUFFER_COUNT, &mut config.buffer_count);
+                changed |= ui.slider("Max frame latency", 1, PresentConfig::MAX_FRAME_LATENCY, &mut config.max_frame_latency);
+
+                ui.separator();
+                changed |= ui.radio_button("sRGB", &mut config.color_space, OutputColorSpace::Srgb);
+                ui.same_line();
+                changed |= ui.radio_button("HDR10", &mut config.color_space, OutputColorSpace::Hdr10);
+                ui.same_line();
+                changed |= ui.radio_button("scRGB", &mut config.color_space, OutputColorSpace::ScRgb);
+                ui.text(format!("Output: {:?}, display peak {:.0} nits", encoding.color_space, encoding.max_nits));
+                ui.disabled(!encoding.color_space.is_hdr(), || {
+                    ui.slider("Paper white (nits)", 80.0, 500.0, paper_white_nits);
+                });
+            });
+
+        changed
+    }
+
+    /// Display mode, monitor and exclusive fullscreen video mode. Returns whether anything was changed.
+    pub fn display_settings(ui: &Ui, settings: &mut DisplaySettings, monitors: &[MonitorModes]) -> bool {
+        let mut changed = false;
+
+        ui.window("Display")
+            .always_auto_resize(true)
+            .build(|| {
+                for mode in DisplayMode::ALL {
+                    changed |= ui.radio_button(mode.name(), &mut settings.mode, mode);
+                }
 
-}
\ No newline at end of file
+                let mut monitor_names = vec!["Current".to_string()];
+                monitor_names.extend(monitors.iter().enumerate().map(|(index, monitor)| match &monitor.name {
+                    Some(name) => name.clone(),
+                    None => format!("Monitor {}", index + 1),
+                }));
+                let mut selected_monitor = settings
+                    .monitor
+                    .as_ref()
+                    .and_then(|name| monitors.iter().position(|monitor| monitor.name.as_ref() == Some(name)))
+                    .map_or(0, |index| index + 1);
+                if ui.combo_simple_string("Monitor", &mut selected_monitor, &monitor_names) {
+                    settings.monitor = selected_monitor.checked_sub(1).and_then(|index| monitors[index].name.clone());
+                    changed = true;
+                }
+
+                // The modes of the chosen monitor, or the primary one for the current.
+                let Some(monitor) = monitors.get(selected_monitor.saturating_sub(1)) else {
+                    return;
+                };
+                let mut mode_names = vec![format!("Desktop ({})", monitor.current)];
+                mode_names.extend(monitor.modes.iter().map(|mode| mode.to_string()));
+                let mut selected_mode = settings
+                    .video_mode
+                    .and_then(|video_mode| monitor.modes.iter().position(|mode| *mode == video_mode))
+                    .map_or(0, |index| index + 1);
+                ui.disabled(settings.mode != DisplayMode::Exclusive, || {
+                    if ui.combo_simple_string("Video mode", &mut selected_mode, &mode_names) {
+                        settings.video_mode = selected_mode.checked_sub(1).map(|index| monitor.modes[index]);
+                        changed = true;
+                    }
+                });
+            });
+
+        changed
+    }
+
+    /// Lists the actions with their bindings. Clicking a binding removes it and `+` binds the next
+    /// pressed input. Returns whether saving was asked for.
+    pub fn bindings_window(ui: &Ui, actions: &mut ActionMap, editor: &mut BindingsEditor) -> bool {
+        let mut save = false;
+
+        ui.window("Input bindings")
+            .size([450.0, 350.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                for (action, bindings) in actions.bindings.clone() {
+                    ui.text(&action);
+                    ui.same_line_with_pos(160.0);
+
+                    for binding in bindings {
+                        if ui.small_button(format!("{}##{}", binding, action)) {
+                            actions.unbind(&action, binding);
+                        }
+                        ui.same_line();
+                    }
+
+                    if editor.capture.as_ref() == Some(&action) {
+                        ui.text_colored([1.0, 0.8, 0.2, 1.0], "Press an input...");
+                        ui.same_line();
+                        if ui.small_button(format!("Cancel##{}", action)) {
+                            editor.capture = None;
+                        }
+                    } else if ui.small_button(format!("+##{}", action)) {
+                        editor.capture = Some(action.clone());
+                    }
+                }
+
+                ui.separator();
+                save = ui.button("Save");
+                ui.same_line();
+                if ui.button("Reset to defaults") {
+                    *actions = ActionMap::with_defaults();
+                    editor.capture = None;
+                }
+            });
+
+        save
+    }
+
+    /// Shows the logged records with level, module and text filters.
+    pub fn log_window(ui: &Ui, viewer: &mut LogViewer) {
+        const LEVELS: [LevelFilter; 5] = [LevelFilter::Error, LevelFilter::Warn, LevelFilter::Info, LevelFilter::Debug, LevelFilter::Trace];
+
+        let Some(history) = logging::history() else {
+            return;
+        };
+
+        ui.window("Log")
+            .size([800.0, 300.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let mut level = LEVELS.iter().position(|level| *level == viewer.filter.max_level).unwrap_or(LEVELS.len() - 1);
+                ui.set_next_item_width(100.0);
+                if ui.combo_simple_string("Level", &mut level, &LEVELS.map(|level| level.as_str())) {
+                    viewer.filter.max_level = LEVELS[level];
+                }
+                ui.same_line();
+                ui.set_next_item_width(150.0);
+                ui.input_text("Module", &mut viewer.filter.target).build();
+                ui.same_line();
+                ui.set_next_item_width(200.0);
+                ui.input_text("Search", &mut viewer.filter.search).build();
+                ui.same_line();
+                ui.checkbox("Auto-scroll", &mut viewer.auto_scroll);
+                ui.same_line();
+                if ui.button("Clear") {
+                    history.lock().unwrap().clear();
+                }
+
+                ui.separator();
+                ui.child_window("Records").horizontal_scrollbar(true).build(|| {
+                    for record in history.lock().unwrap().records().filter(|record| viewer.filter.matches(record)) {
+                        let color = match record.level {
+                            Level::Error => [1.0, 0.4, 0.4, 1.0],
+                            Level::Warn => [1.0, 0.8, 0.3, 1.0],
+                            Level::Info => [0.9, 0.9, 0.9, 1.0],
+                            Level::Debug | Level::Trace => [0.6, 0.6, 0.6, 1.0],
+                        };
+                        ui.text_colored(color, record.to_string());
+                    }
+
+                    if viewer.auto_scroll && ui.scroll_y() >= ui.scroll_max_y() {
+                        ui.set_scroll_here_y_with_ratio(1.0);
+                    }
+                });
+            });
+    }
+}